@@ -71,6 +71,12 @@ fn main() -> anyhow::Result<()> {
                 auxide_midi::MidiEvent::PitchBend(bend) => {
                     println!("PitchBend: {}", bend);
                 }
+                auxide_midi::MidiEvent::ChannelPressure(pressure) => {
+                    println!("Pressure: {}", pressure);
+                }
+                auxide_midi::MidiEvent::ProgramChange(program) => {
+                    println!("Program: {}", program);
+                }
             }
         }
 
@@ -0,0 +1,251 @@
+//! Per-channel MIDI activity meters for monitoring UIs
+//!
+//! An [`ActivityMeter`] counts events per channel as they arrive; a
+//! [`ActivitySnapshot`] is a plain copy of the counters that can be handed
+//! to a UI thread. Rates are measured over fixed one-second windows.
+//! Keep one meter per device (see [`ActivityMonitor`]) to tell
+//! controllers apart.
+
+use crate::midi_input::{ChannelEvent, MidiEvent};
+
+/// Length of the window rates are measured over, µs
+pub const ACTIVITY_WINDOW: u64 = 1_000_000;
+
+/// What one channel has been doing
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ChannelActivity {
+    /// Events since the meter was created or reset
+    pub events: u64,
+    pub events_per_second: f32,
+    /// Note-ons per second
+    pub notes_per_second: f32,
+    /// Notes currently held down
+    pub notes_held: u32,
+    /// Arrival time of the last event, µs
+    pub last_event: Option<u64>,
+}
+
+/// Activity of all 16 channels at one moment
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ActivitySnapshot {
+    pub channels: [ChannelActivity; 16],
+}
+
+impl ActivitySnapshot {
+    pub fn channel(&self, channel: u8) -> &ChannelActivity {
+        &self.channels[(channel & 0x0F) as usize]
+    }
+
+    /// Events per second over every channel
+    pub fn events_per_second(&self) -> f32 {
+        self.channels.iter().map(|c| c.events_per_second).sum()
+    }
+
+    /// Channels that received anything since `since` (µs)
+    pub fn active_channels(&self, since: u64) -> impl Iterator<Item = u8> + '_ {
+        (0..16u8).filter(move |&channel| {
+            self.channel(channel)
+                .last_event
+                .is_some_and(|time| time >= since)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ChannelMeter {
+    events: u64,
+    last_event: Option<u64>,
+    held: u128,
+    window_start: u64,
+    window_events: u32,
+    window_notes: u32,
+    // Rates from the last complete window
+    event_rate: f32,
+    note_rate: f32,
+}
+
+impl ChannelMeter {
+    /// Rates as of `now`: the last complete window, or zero once a whole
+    /// window has passed without the meter hearing anything
+    fn rates(&self, now: u64) -> (f32, f32) {
+        let elapsed = now.saturating_sub(self.window_start);
+        let per_second = 1_000_000.0 / ACTIVITY_WINDOW as f32;
+        if elapsed >= 2 * ACTIVITY_WINDOW {
+            (0.0, 0.0)
+        } else if elapsed >= ACTIVITY_WINDOW {
+            (
+                self.window_events as f32 * per_second,
+                self.window_notes as f32 * per_second,
+            )
+        } else {
+            (self.event_rate, self.note_rate)
+        }
+    }
+
+    fn roll(&mut self, now: u64) {
+        let elapsed = now.saturating_sub(self.window_start);
+        if elapsed >= ACTIVITY_WINDOW {
+            (self.event_rate, self.note_rate) = self.rates(now);
+            self.window_start += elapsed - elapsed % ACTIVITY_WINDOW;
+            self.window_events = 0;
+            self.window_notes = 0;
+        }
+    }
+}
+
+/// Counts events per channel for one device
+#[derive(Debug, Clone, Default)]
+pub struct ActivityMeter {
+    channels: [ChannelMeter; 16],
+}
+
+impl ActivityMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count `event`, received at `time` (µs)
+    pub fn record(&mut self, time: u64, event: &ChannelEvent) {
+        let meter = &mut self.channels[(event.channel & 0x0F) as usize];
+        if meter.last_event.is_none() {
+            meter.window_start = time;
+        }
+        meter.roll(time);
+        meter.events += 1;
+        meter.window_events += 1;
+        meter.last_event = Some(time);
+        match event.event {
+            MidiEvent::NoteOn(note, _) => {
+                meter.window_notes += 1;
+                meter.held |= 1 << (note & 0x7F);
+            }
+            MidiEvent::NoteOff(note, _) => meter.held &= !(1 << (note & 0x7F)),
+            _ if event.event.is_all_notes_off() => meter.held = 0,
+            _ => {}
+        }
+    }
+
+    /// Copy of every channel's counters as of `now` (µs)
+    pub fn snapshot(&self, now: u64) -> ActivitySnapshot {
+        let mut snapshot = ActivitySnapshot::default();
+        for (activity, meter) in snapshot.channels.iter_mut().zip(&self.channels) {
+            let (events_per_second, notes_per_second) = match meter.last_event {
+                Some(_) => meter.rates(now),
+                None => (0.0, 0.0),
+            };
+            *activity = ChannelActivity {
+                events: meter.events,
+                events_per_second,
+                notes_per_second,
+                notes_held: meter.held.count_ones(),
+                last_event: meter.last_event,
+            };
+        }
+        snapshot
+    }
+
+    pub fn reset(&mut self) {
+        self.channels = Default::default();
+    }
+}
+
+/// Activity meters for several devices, by name
+#[derive(Debug, Clone, Default)]
+pub struct ActivityMonitor {
+    devices: Vec<(String, ActivityMeter)>,
+}
+
+impl ActivityMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count `event` from `device`, adding a meter the first time it is seen
+    pub fn record(&mut self, device: &str, time: u64, event: &ChannelEvent) {
+        let index = match self.devices.iter().position(|(name, _)| name == device) {
+            Some(index) => index,
+            None => {
+                self.devices
+                    .push((device.to_string(), ActivityMeter::new()));
+                self.devices.len() - 1
+            }
+        };
+        self.devices[index].1.record(time, event);
+    }
+
+    pub fn meter(&self, device: &str) -> Option<&ActivityMeter> {
+        self.devices
+            .iter()
+            .find(|(name, _)| name == device)
+            .map(|(_, meter)| meter)
+    }
+
+    /// Snapshots of every device as of `now` (µs)
+    pub fn snapshot(&self, now: u64) -> Vec<(String, ActivitySnapshot)> {
+        self.devices
+            .iter()
+            .map(|(name, meter)| (name.clone(), meter.snapshot(now)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on(channel: u8, note: u8) -> ChannelEvent {
+        ChannelEvent {
+            channel,
+            event: MidiEvent::NoteOn(note, 100),
+        }
+    }
+
+    #[test]
+    fn rates_come_from_complete_windows() {
+        let mut meter = ActivityMeter::new();
+        // Ten notes and one CC in the first second on channel 2
+        for i in 0..10u8 {
+            meter.record(i as u64 * 100_000, &on(2, 60 + i));
+        }
+        let cc = ChannelEvent {
+            channel: 2,
+            event: MidiEvent::ControlChange(1, 64),
+        };
+        meter.record(950_000, &cc);
+
+        let early = meter.snapshot(990_000);
+        assert_eq!(early.channel(2).events, 11);
+        assert_eq!(early.channel(2).notes_held, 10);
+        assert_eq!(early.channel(2).events_per_second, 0.0); // Window not complete
+
+        let later = meter.snapshot(1_500_000);
+        assert_eq!(later.channel(2).events_per_second, 11.0);
+        assert_eq!(later.channel(2).notes_per_second, 10.0);
+        assert_eq!(later.active_channels(0).collect::<Vec<_>>(), [2]);
+        assert_eq!(later.events_per_second(), 11.0);
+
+        // Silence decays to zero
+        assert_eq!(meter.snapshot(2_500_000).channel(2).events_per_second, 0.0);
+    }
+
+    #[test]
+    fn monitor_keeps_devices_apart() {
+        let mut monitor = ActivityMonitor::new();
+        monitor.record("Keys", 0, &on(0, 60));
+        monitor.record("Pads", 0, &on(9, 36));
+        monitor.record(
+            "Keys",
+            10,
+            &ChannelEvent {
+                channel: 0,
+                event: MidiEvent::NoteOff(60, 0),
+            },
+        );
+        let snapshots = monitor.snapshot(20);
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].1.channel(0).events, 2);
+        assert_eq!(snapshots[0].1.channel(0).notes_held, 0);
+        assert_eq!(snapshots[1].1.channel(9).notes_held, 1);
+        assert!(monitor.meter("Drums").is_none());
+    }
+}
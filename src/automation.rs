@@ -0,0 +1,256 @@
+//! CC automation: record parameter moves against a sample clock and play
+//! them back, interpolated, into the parameter bridge
+
+use crate::cc_mapping::ParamTarget;
+use crate::param_bridge::ParamUpdateQueue;
+
+/// Timestamped values of one parameter, in sample time
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AutomationLane {
+    target: ParamTarget,
+    points: Vec<(u64, f32)>, // (sample position, normalized value), sorted by position
+}
+
+impl AutomationLane {
+    pub fn new(target: ParamTarget) -> Self {
+        Self {
+            target,
+            points: Vec::new(),
+        }
+    }
+
+    pub fn target(&self) -> ParamTarget {
+        self.target
+    }
+
+    pub fn points(&self) -> &[(u64, f32)] {
+        &self.points
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Position of the last point
+    pub fn end(&self) -> u64 {
+        self.points.last().map_or(0, |&(time, _)| time)
+    }
+
+    /// Add a point; recording over earlier material replaces everything
+    /// from `time` onwards (punch-in)
+    pub fn record(&mut self, time: u64, value: f32) {
+        let keep = self.points.partition_point(|&(t, _)| t < time);
+        self.points.truncate(keep);
+        self.points.push((time, value));
+    }
+
+    /// Value at `time`, linearly interpolated between points and held
+    /// before the first and after the last
+    pub fn value_at(&self, time: u64) -> Option<f32> {
+        let next = self.points.partition_point(|&(t, _)| t <= time);
+        match (next.checked_sub(1), self.points.get(next)) {
+            (None, Some(&(_, first))) => Some(first),
+            (Some(prev), None) => Some(self.points[prev].1),
+            (Some(prev), Some(&(t1, v1))) => {
+                let (t0, v0) = self.points[prev];
+                let frac = (time - t0) as f32 / (t1 - t0) as f32;
+                Some(v0 + (v1 - v0) * frac)
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+/// Records and plays back automation lanes against a running sample position
+///
+/// Record from the audio thread with `record` (for example with the output
+/// of `CCMap::handle_cc`), call `advance` once per block, and while playing
+/// call `play` before each block to push the interpolated values into a
+/// [`ParamUpdateQueue`]. Recording appends to the lanes, so reserve space
+/// with `with_capacity` if it must not allocate.
+#[derive(Debug, Clone, Default)]
+pub struct AutomationRecorder {
+    lanes: Vec<AutomationLane>,
+    position: u64,
+    recording: bool,
+    playing: bool,
+    loop_length: Option<u64>,
+    capacity: usize, // Points reserved per new lane
+}
+
+impl AutomationRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reserve room for `points` per lane so recording stays allocation-free
+    /// once each lane exists
+    pub fn with_capacity(points: usize) -> Self {
+        Self {
+            capacity: points,
+            ..Self::default()
+        }
+    }
+
+    pub fn lanes(&self) -> &[AutomationLane] {
+        &self.lanes
+    }
+
+    pub fn lane(&self, target: ParamTarget) -> Option<&AutomationLane> {
+        self.lanes.iter().find(|lane| lane.target == target)
+    }
+
+    /// Add or replace a lane (e.g. one loaded from a preset)
+    pub fn set_lane(&mut self, lane: AutomationLane) {
+        match self.lanes.iter_mut().find(|l| l.target == lane.target) {
+            Some(existing) => *existing = lane,
+            None => self.lanes.push(lane),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lanes.clear();
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn seek(&mut self, position: u64) {
+        self.position = position;
+    }
+
+    /// Wrap the position back to 0 every `length` samples (None plays straight through)
+    pub fn set_loop_length(&mut self, length: Option<u64>) {
+        self.loop_length = length.filter(|&length| length > 0);
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    /// Capture a parameter value at the current position while recording
+    /// Returns true if the value was recorded
+    pub fn record(&mut self, target: ParamTarget, value: f32) -> bool {
+        if !self.recording {
+            return false;
+        }
+        let position = self.position;
+        match self.lanes.iter_mut().find(|lane| lane.target == target) {
+            Some(lane) => lane.record(position, value),
+            None => {
+                let mut lane = AutomationLane::new(target);
+                lane.points.reserve(self.capacity);
+                lane.record(position, value);
+                self.lanes.push(lane);
+            }
+        }
+        true
+    }
+
+    /// Send every lane's value at the current position into `queue`
+    /// Lanes being recorded are skipped so playback does not fight the
+    /// performer. Returns the number of updates queued.
+    pub fn play(&self, queue: &mut ParamUpdateQueue) -> usize {
+        if !self.playing {
+            return 0;
+        }
+        let mut sent = 0;
+        for lane in &self.lanes {
+            if self.recording && lane.end() >= self.position {
+                continue;
+            }
+            if let Some(value) = lane.value_at(self.position) {
+                sent += queue.send(lane.target, value);
+            }
+        }
+        sent
+    }
+
+    /// Move the position forward by one block
+    pub fn advance(&mut self, samples: u64) {
+        self.position += samples;
+        if let Some(length) = self.loop_length {
+            self.position %= length;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param_bridge::{NodeParam, ParamScale};
+    use auxide::control::ControlMsg;
+    use auxide::graph::NodeId;
+
+    #[test]
+    fn lane_interpolates_and_punches_in() {
+        let mut lane = AutomationLane::new(ParamTarget::FilterCutoff);
+        assert_eq!(lane.value_at(10), None);
+        lane.record(100, 0.0);
+        lane.record(200, 1.0);
+        lane.record(300, 0.5);
+        assert_eq!(lane.value_at(0), Some(0.0));
+        assert_eq!(lane.value_at(150), Some(0.5));
+        assert_eq!(lane.value_at(250), Some(0.75));
+        assert_eq!(lane.value_at(1000), Some(0.5));
+
+        lane.record(150, 0.2); // Overdub from 150
+        assert_eq!(lane.points(), &[(100, 0.0), (150, 0.2)]);
+    }
+
+    #[test]
+    fn recorded_moves_play_back_into_the_bridge() {
+        let mut recorder = AutomationRecorder::with_capacity(16);
+        recorder.set_recording(true);
+        for (block, value) in [0.0, 0.5, 1.0].into_iter().enumerate() {
+            recorder.seek(block as u64 * 64);
+            assert!(recorder.record(ParamTarget::FilterCutoff, value));
+        }
+        recorder.set_recording(false);
+        assert!(!recorder.record(ParamTarget::FilterCutoff, 0.0));
+
+        let (mut queue, mut receiver) = ParamUpdateQueue::new();
+        queue.bind(
+            ParamTarget::FilterCutoff,
+            NodeId(4),
+            NodeParam::Index(0),
+            ParamScale::Raw,
+        );
+        recorder.set_playing(true);
+        recorder.set_loop_length(Some(128));
+        recorder.seek(96);
+        assert_eq!(recorder.play(&mut queue), 1);
+        recorder.advance(64); // Wraps to 32
+
+        assert_eq!(recorder.play(&mut queue), 1);
+        let mut values = Vec::new();
+        receiver.drain(&mut |msg| {
+            if let ControlMsg::SetParam { value, .. } = msg {
+                values.push(value);
+            }
+        });
+        assert_eq!(values, [0.75, 0.25]);
+    }
+}
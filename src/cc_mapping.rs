@@ -1,56 +1,1016 @@
 //! MIDI CC parameter mapping
+//!
+//! With `std`, [`CCMap`] preallocates [`DEFAULT_CC_SLOTS`] mapping slots and
+//! grows when they run out. Without it the map needs no allocator: its slots
+//! are a fixed array whose size is a const parameter (`CCMap::<8>::fixed()`),
+//! and new mappings are refused once every slot is in use. Loading and saving
+//! through [`CCMapping`] lists needs `std`.
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+use crate::slot_table::SlotTable;
+use crate::smoother::ParamSmoother;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ParamTarget {
     FilterCutoff,
     FilterResonance,
     AttackTime,
     ReleaseTime,
+    Volume,
+    Pan,
+    Expression,
+    Breath,
+    PortamentoTime,
+    /// Application-defined parameter (e.g. an auxide graph node parameter ID)
+    Custom(u32),
     Unused,
 }
 
-#[derive(Debug)]
-pub struct CCMap {
-    mappings: [(u8, ParamTarget); 16], // Fixed size for RT-safety
+/// How a controller's values are interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CCMode {
+    /// The value is the position (regular knobs and faders)
+    #[default]
+    Absolute,
+    /// Relative: 1-63 increment, 127 down to 64 decrement (127 = -1)
+    TwosComplement,
+    /// Relative: 64 is no change, 65 = +1, 63 = -1
+    BinaryOffset,
+    /// Relative: bit 6 is the sign, bits 0-5 the magnitude (65 = -1)
+    SignMagnitude,
+    /// Switch: on (1.0) while the value is >= 64, off (0.0) otherwise
+    Momentary,
+    /// Switch: each press (value crossing up to >= 64) flips on/off
+    Toggle,
+    /// Switch: emits 1.0 once per press, nothing on release
+    Trigger,
+}
+
+impl CCMode {
+    /// Check whether the mode accumulates relative steps
+    pub fn is_relative(self) -> bool {
+        matches!(
+            self,
+            CCMode::TwosComplement | CCMode::BinaryOffset | CCMode::SignMagnitude
+        )
+    }
+
+    /// Check whether the mode treats the controller as an on/off switch
+    pub fn is_switch(self) -> bool {
+        matches!(self, CCMode::Momentary | CCMode::Toggle | CCMode::Trigger)
+    }
+
+    /// Decode a relative step; absolute and switch modes return 0
+    pub fn delta(self, value: u8) -> i8 {
+        let value = value & 0x7F;
+        match self {
+            CCMode::Absolute | CCMode::Momentary | CCMode::Toggle | CCMode::Trigger => 0,
+            CCMode::TwosComplement => {
+                if value >= 64 {
+                    (value as i16 - 128) as i8
+                } else {
+                    value as i8
+                }
+            }
+            CCMode::BinaryOffset => value as i8 - 64,
+            CCMode::SignMagnitude => {
+                let magnitude = (value & 0x3F) as i8;
+                if value & 0x40 != 0 {
+                    -magnitude
+                } else {
+                    magnitude
+                }
+            }
+        }
+    }
+}
+
+/// Number of mapping slots preallocated by `CCMap::new`, and all it has
+/// without `std`
+pub const DEFAULT_CC_SLOTS: usize = 16;
+
+/// Most discrete outputs one mapping snaps to
+pub const MAX_SNAP_VALUES: usize = 16;
+
+/// Most targets of one CC the synth engines apply per message
+pub const MAX_CC_TARGETS: usize = 8;
+
+/// Output range of a mapping without an explicit range (plain normalized value)
+pub const DEFAULT_RANGE: (f32, f32) = (0.0, 1.0);
+
+/// Offset from a 14-bit controller's MSB (CC 0-31) to its LSB (CC 32-63)
+pub const CC_LSB_OFFSET: u8 = 32;
+
+const MAX_14BIT: f32 = 16383.0;
+
+#[derive(Debug, Clone, Copy)]
+struct SlotState {
+    range: (f32, f32),      // Output (min, max)
+    high_res: bool,         // 14-bit MSB/LSB pair
+    inverted: bool,         // 127 -> min, 0 -> max
+    steps: u16,             // Quantize to N evenly spaced values (0 = continuous)
+    hysteresis: u8,         // Ignore absolute changes of this many raw steps or fewer
+    smoothing: Option<f32>, // Smoother time constant in seconds
+    last_raw: Option<u8>,   // Last accepted absolute value
+    mode: CCMode,
+    position: f32, // Current normalized parameter value (accumulated for relative modes)
+    pickup: bool,  // Soft takeover: ignore the knob until it crosses `position`
+    engaged: bool,
+    knob: Option<f32>, // Last normalized knob value seen while not engaged
+    pressed: bool,     // Switch modes: last value was >= 64
+    msb: u8,
+    lsb: Option<u8>, // None until the LSB following the latest MSB arrives
+}
+
+impl SlotState {
+    /// Dead band check for absolute values; the ends of travel always pass
+    fn passes_hysteresis(&self, value: u8) -> bool {
+        match self.last_raw {
+            _ if self.hysteresis == 0 => true,
+            Some(last) if value != 0 && value != 127 => value.abs_diff(last) > self.hysteresis,
+            Some(last) => value != last,
+            None => true,
+        }
+    }
+
+    /// Update switch state; returns true if the change should be reported
+    fn switch(&mut self, value: u8) -> bool {
+        let pressed = value >= 64;
+        let rising = pressed && !self.pressed;
+        self.pressed = pressed;
+
+        match self.mode {
+            CCMode::Momentary => {
+                self.position = if pressed { 1.0 } else { 0.0 };
+                true
+            }
+            CCMode::Toggle => {
+                if rising {
+                    self.position = 1.0 - self.position;
+                }
+                rising
+            }
+            CCMode::Trigger => {
+                self.position = if rising { 1.0 } else { 0.0 };
+                rising
+            }
+            _ => true,
+        }
+    }
+
+    /// Soft takeover check; returns true once the knob controls the parameter
+    fn pick_up(&mut self, normalized: f32) -> bool {
+        if !self.pickup || self.engaged {
+            return true;
+        }
+        let crossed = self
+            .knob
+            .is_some_and(|prev| (prev - self.position) * (normalized - self.position) <= 0.0);
+        self.knob = Some(normalized);
+        self.engaged = crossed || (normalized - self.position).abs() <= 0.5 / 127.0;
+        self.engaged
+    }
+
+    fn advance(&mut self, value: u8) {
+        let step = self.mode.delta(value) as f32 / 127.0;
+        self.position = (self.position + step).clamp(0.0, 1.0);
+    }
+}
+
+/// A mapping's discrete outputs (empty = continuous)
+#[derive(Debug, Clone, Copy)]
+struct SnapValues {
+    values: [f32; MAX_SNAP_VALUES],
+    len: usize,
+}
+
+impl SnapValues {
+    const EMPTY: Self = Self {
+        values: [0.0; MAX_SNAP_VALUES],
+        len: 0,
+    };
+
+    /// Keep the first `MAX_SNAP_VALUES` of `values`
+    fn from_slice(values: &[f32]) -> Self {
+        let mut snap = Self::EMPTY;
+        snap.len = values.len().min(MAX_SNAP_VALUES);
+        snap.values[..snap.len].copy_from_slice(&values[..snap.len]);
+        snap
+    }
+
+    fn as_slice(&self) -> &[f32] {
+        &self.values[..self.len]
+    }
+}
+
+const DEFAULT_SLOT: SlotState = SlotState {
+    range: DEFAULT_RANGE,
+    high_res: false,
+    inverted: false,
+    steps: 0,
+    hysteresis: 0,
+    smoothing: None,
+    last_raw: None,
+    mode: CCMode::Absolute,
+    position: 0.0,
+    pickup: false,
+    engaged: false,
+    knob: None,
+    pressed: false,
+    msb: 0,
+    lsb: None,
+};
+
+/// Most recent raw value received on each of the 128 controllers
+#[derive(Debug, Clone)]
+pub struct ControllerState {
+    values: [Option<u8>; 128], // Fixed size for RT-safety
+}
+
+impl ControllerState {
+    pub fn new() -> Self {
+        Self {
+            values: [None; 128],
+        }
+    }
+
+    /// Record a received value
+    pub fn update(&mut self, cc_num: u8, value: u8) {
+        if let Some(slot) = self.values.get_mut(cc_num as usize) {
+            *slot = Some(value & 0x7F);
+        }
+    }
+
+    /// Last raw value (0-127), or None if the controller hasn't been received
+    pub fn raw(&self, cc_num: u8) -> Option<u8> {
+        self.values.get(cc_num as usize).copied().flatten()
+    }
+
+    /// Last value normalized to 0.0-1.0
+    pub fn normalized(&self, cc_num: u8) -> Option<f32> {
+        self.raw(cc_num).map(|value| value as f32 / 127.0)
+    }
+
+    /// Forget all received values
+    pub fn reset(&mut self) {
+        self.values = [None; 128];
+    }
+}
+
+impl Default for ControllerState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A single controller mapping, as stored in preset files
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct CCMapping {
+    pub cc: u8,
+    pub target: ParamTarget,
+    pub min: f32,
+    pub max: f32,
+    pub high_res: bool,
+    pub inverted: bool,
+    pub steps: u16,
+    pub snap_values: Vec<f32>,
+    pub hysteresis: u8,
+    pub smoothing: Option<f32>,
+    pub mode: CCMode,
+    pub pickup: bool,
+}
+
+#[cfg(feature = "std")]
+impl CCMapping {
+    pub fn new(cc: u8, target: ParamTarget) -> Self {
+        Self {
+            cc,
+            target,
+            ..Self::default()
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for CCMapping {
+    fn default() -> Self {
+        Self {
+            cc: 0,
+            target: ParamTarget::Unused,
+            min: DEFAULT_RANGE.0,
+            max: DEFAULT_RANGE.1,
+            high_res: false,
+            inverted: false,
+            steps: 0,
+            snap_values: Vec::new(),
+            hysteresis: 0,
+            smoothing: None,
+            mode: CCMode::Absolute,
+            pickup: false,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CCMapPreset {
+    mappings: Vec<CCMapping>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "CCMapPreset", into = "CCMapPreset")
+)]
+pub struct CCMap<const N: usize = DEFAULT_CC_SLOTS> {
+    mappings: SlotTable<(u8, ParamTarget), N>, // Grows on set_mapping; lookups never allocate
+    slots: SlotTable<SlotState, N>,            // Per-slot range and 14-bit state
+    snap_values: SlotTable<SnapValues, N>,     // Per-slot discrete outputs
+    learning: Option<ParamTarget>,
+    controllers: ControllerState,
 }
 
 impl CCMap {
+    /// Create a map with the default mappings (mod wheel, volume, pan, ...)
     pub fn new() -> Self {
-        let mut mappings = [(0, ParamTarget::Unused); 16];
+        Self::with_defaults()
+    }
+
+    /// Create an empty map with `slots` preallocated mapping slots
+    #[cfg(feature = "std")]
+    pub fn with_capacity(slots: usize) -> Self {
+        Self::with_slots(slots).expect("std maps have no slot limit")
+    }
+
+    /// Build a map from a list of mappings (no defaults)
+    #[cfg(feature = "std")]
+    pub fn from_mappings(mappings: &[CCMapping]) -> Self {
+        let mut map = Self::fixed();
+        map.add_mappings(mappings);
+        map
+    }
+
+    /// Load mappings from a JSON preset file
+    #[cfg(feature = "serde")]
+    pub fn load_from(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+impl<const N: usize> CCMap<N> {
+    /// Create an empty map with `N` mapping slots (preallocated with `std`,
+    /// the whole map without it)
+    pub fn fixed() -> Self {
+        Self::with_slots(N).expect("N slots always fit")
+    }
+
+    fn with_slots(slots: usize) -> Option<Self> {
+        Some(Self {
+            mappings: SlotTable::filled((0, ParamTarget::Unused), slots)?,
+            slots: SlotTable::filled(DEFAULT_SLOT, slots)?,
+            snap_values: SlotTable::filled(SnapValues::EMPTY, slots)?,
+            learning: None,
+            controllers: ControllerState::new(),
+        })
+    }
+
+    /// Create a map of `N` slots holding the default mappings; without
+    /// `std`, those beyond the `N` slots are left out
+    pub fn with_defaults() -> Self {
+        let mut map = Self::fixed();
+        let defaults = [
+            (1, ParamTarget::FilterCutoff),     // Mod wheel -> cutoff
+            (74, ParamTarget::FilterResonance), // Filter Q -> resonance
+            (7, ParamTarget::Volume),
+            (10, ParamTarget::Pan), // 0.0 left, 0.5 centre, 1.0 right
+            (11, ParamTarget::Expression),
+            (2, ParamTarget::Breath),
+            (5, ParamTarget::PortamentoTime),
+        ];
+        for (cc_num, target) in defaults {
+            if !map.set_mapping(cc_num, target) {
+                break;
+            }
+        }
+        // Expression and breath scale the output; start fully open
+        map.set_target_value(ParamTarget::Expression, 1.0);
+        map.set_target_value(ParamTarget::Breath, 1.0);
+        map
+    }
 
-        // Default mappings
-        mappings[0] = (1, ParamTarget::FilterCutoff); // Mod wheel -> cutoff
-        mappings[1] = (74, ParamTarget::FilterResonance); // Filter Q -> resonance
+    /// Add every mapping of a list to the free slots
+    /// Unused targets and 14-bit mappings without an LSB partner are skipped
+    #[cfg(feature = "std")]
+    pub fn add_mappings(&mut self, mappings: &[CCMapping]) {
+        for mapping in mappings {
+            if mapping.target == ParamTarget::Unused
+                || (mapping.high_res && mapping.cc >= CC_LSB_OFFSET)
+            {
+                continue;
+            }
+            let Some(slot) = self.free_slot() else {
+                break; // Only without std
+            };
+            self.mappings[slot] = (mapping.cc, mapping.target);
+            self.slots[slot] = SlotState {
+                range: (mapping.min, mapping.max),
+                high_res: mapping.high_res,
+                inverted: mapping.inverted,
+                steps: mapping.steps,
+                hysteresis: mapping.hysteresis,
+                smoothing: mapping.smoothing,
+                mode: mapping.mode,
+                pickup: mapping.pickup,
+                ..DEFAULT_SLOT
+            };
+            self.snap_values[slot] = SnapValues::from_slice(&mapping.snap_values);
+        }
+    }
 
-        Self { mappings }
+    /// Get the configuration of every active mapping
+    #[cfg(feature = "std")]
+    pub fn to_mappings(&self) -> Vec<CCMapping> {
+        self.mappings
+            .iter()
+            .zip(self.slots.iter().zip(self.snap_values.iter()))
+            .filter(|((_, target), _)| *target != ParamTarget::Unused)
+            .map(|(&(cc, target), (state, snap_values))| CCMapping {
+                cc,
+                target,
+                min: state.range.0,
+                max: state.range.1,
+                high_res: state.high_res,
+                inverted: state.inverted,
+                steps: state.steps,
+                snap_values: snap_values.as_slice().to_vec(),
+                hysteresis: state.hysteresis,
+                smoothing: state.smoothing,
+                mode: state.mode,
+                pickup: state.pickup,
+            })
+            .collect()
     }
 
-    /// Map a CC number and value to a parameter target and normalized value
+    /// Save the mappings to a JSON preset file
+    #[cfg(feature = "serde")]
+    pub fn save_to(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Map a CC number and value to a parameter target and value
+    /// The value is scaled into the mapping's output range (0.0-1.0 by default).
+    /// 14-bit mappings treat the value as the MSB; use `handle_cc` to combine the LSB.
+    /// Relative and switch mappings report their stored value; only `handle_cc` moves it.
     pub fn map_cc(&self, cc_num: u8, value: u8) -> Option<(ParamTarget, f32)> {
-        for (mapped_cc, target) in &self.mappings {
-            if *mapped_cc == cc_num && *target != ParamTarget::Unused {
-                let normalized = value as f32 / 127.0;
-                return Some((*target, normalized));
+        let slot = self.find(cc_num)?;
+        Some(self.slot_value(slot, value))
+    }
+
+    /// Map a CC to every target it drives
+    pub fn map_cc_all(
+        &self,
+        cc_num: u8,
+        value: u8,
+    ) -> impl Iterator<Item = (ParamTarget, f32)> + '_ {
+        (0..self.mappings.len())
+            .filter(move |&slot| self.is_mapped(slot, cc_num))
+            .map(move |slot| self.slot_value(slot, value))
+    }
+
+    /// Map a CC to every target it drives, writing into a caller-provided buffer
+    /// Returns the number of entries written; extra targets beyond `out.len()` are dropped
+    pub fn map_cc_into(&self, cc_num: u8, value: u8, out: &mut [(ParamTarget, f32)]) -> usize {
+        let mut written = 0;
+        for (entry, mapped) in out.iter_mut().zip(self.map_cc_all(cc_num, value)) {
+            *entry = mapped;
+            written += 1;
+        }
+        written
+    }
+
+    /// Set a mapping for a CC number with the default 0.0-1.0 output range
+    /// Replaces the CC's first existing target, otherwise fills the first
+    /// unused slot, otherwise adds one (allocates; not for the audio thread).
+    /// Returns false if no slot can be added, which only happens without `std`.
+    pub fn set_mapping(&mut self, cc_num: u8, target: ParamTarget) -> bool {
+        self.set_mapping_with_range(cc_num, target, DEFAULT_RANGE.0, DEFAULT_RANGE.1)
+    }
+
+    /// Set a mapping for a CC number that outputs values in `min..=max`
+    /// `min` may be greater than `max` to invert the controller
+    pub fn set_mapping_with_range(
+        &mut self,
+        cc_num: u8,
+        target: ParamTarget,
+        min: f32,
+        max: f32,
+    ) -> bool {
+        let Some(slot) = self.find(cc_num).or_else(|| self.free_slot()) else {
+            return false;
+        };
+        self.fill_slot(slot, cc_num, target, (min, max));
+        true
+    }
+
+    /// Add another target for a CC, keeping the targets it already drives
+    /// Adding an existing CC/target pair updates its range; returns false
+    /// if no slot can be added, as for `set_mapping`
+    pub fn add_mapping(&mut self, cc_num: u8, target: ParamTarget, min: f32, max: f32) -> bool {
+        let Some(slot) = (0..self.mappings.len())
+            .find(|&slot| self.is_mapped(slot, cc_num) && self.mappings[slot].1 == target)
+            .or_else(|| self.free_slot())
+        else {
+            return false;
+        };
+        self.fill_slot(slot, cc_num, target, (min, max));
+        true
+    }
+
+    /// Set a 14-bit mapping: `msb_cc` (0-31) is paired with LSB `msb_cc + 32`
+    /// Returns false if `msb_cc` has no LSB partner or no slot can be added
+    pub fn set_mapping_14bit(&mut self, msb_cc: u8, target: ParamTarget) -> bool {
+        if msb_cc >= CC_LSB_OFFSET || !self.set_mapping(msb_cc, target) {
+            return false;
+        }
+        let slot = self.find(msb_cc).expect("mapping was just set");
+        self.slots[slot].high_res = true;
+        true
+    }
+
+    /// Check whether a CC is mapped as the MSB of a 14-bit pair
+    pub fn is_14bit(&self, cc_num: u8) -> bool {
+        self.find(cc_num)
+            .is_some_and(|slot| self.slots[slot].high_res)
+    }
+
+    /// Change the output range of an existing mapping
+    /// Returns false if the CC is not mapped
+    pub fn set_range(&mut self, cc_num: u8, min: f32, max: f32) -> bool {
+        match self.find(cc_num) {
+            Some(slot) => {
+                self.slots[slot].range = (min, max);
+                true
             }
+            None => false,
         }
-        None
     }
 
-    /// Set a mapping for a CC number
-    pub fn set_mapping(&mut self, cc_num: u8, target: ParamTarget) {
-        // Find first unused slot or replace existing
-        for mapping in &mut self.mappings {
-            if mapping.1 == ParamTarget::Unused || mapping.0 == cc_num {
-                *mapping = (cc_num, target);
-                break;
+    /// Flip the polarity of every target driven by a CC
+    /// Returns false if the CC is not mapped
+    pub fn set_inverted(&mut self, cc_num: u8, inverted: bool) -> bool {
+        let mut found = false;
+        for slot in 0..self.mappings.len() {
+            if self.is_mapped(slot, cc_num) {
+                self.slots[slot].inverted = inverted;
+                found = true;
             }
         }
+        found
+    }
+
+    /// Check whether a mapped CC is inverted
+    pub fn is_inverted(&self, cc_num: u8) -> bool {
+        self.find(cc_num)
+            .is_some_and(|slot| self.slots[slot].inverted)
     }
 
-    /// Get all current mappings
-    pub fn get_mappings(&self) -> &[(u8, ParamTarget); 16] {
+    /// Set how every target of a CC interprets incoming values
+    /// Switching mode resets the accumulated relative or switch value to 0.0
+    pub fn set_mode(&mut self, cc_num: u8, mode: CCMode) -> bool {
+        let mut found = false;
+        for slot in 0..self.mappings.len() {
+            if self.is_mapped(slot, cc_num) {
+                self.slots[slot].mode = mode;
+                self.slots[slot].position = 0.0;
+                self.slots[slot].pressed = false;
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Get the mode of a mapped CC
+    pub fn mode(&self, cc_num: u8) -> Option<CCMode> {
+        self.find(cc_num).map(|slot| self.slots[slot].mode)
+    }
+
+    /// Get the current output value of a relative or switch mapping
+    pub fn value(&self, cc_num: u8) -> Option<f32> {
+        let slot = self.find(cc_num)?;
+        let state = &self.slots[slot];
+        (state.mode != CCMode::Absolute).then(|| self.scale(slot, state.position))
+    }
+
+    /// Enable soft takeover for every target of an absolute CC
+    /// After `set_target_value` the controller is ignored until it crosses the new value
+    pub fn set_pickup(&mut self, cc_num: u8, pickup: bool) -> bool {
+        let mut found = false;
+        for slot in 0..self.mappings.len() {
+            if self.is_mapped(slot, cc_num) {
+                self.slots[slot].pickup = pickup;
+                self.slots[slot].engaged = false;
+                self.slots[slot].knob = None;
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Tell the map a target's value changed elsewhere (e.g. a preset load)
+    /// `value` is in the mapping's output units; returns false if the target isn't mapped
+    pub fn set_target_value(&mut self, target: ParamTarget, value: f32) -> bool {
+        let mut found = false;
+        for slot in 0..self.mappings.len() {
+            if self.mappings[slot].1 != target || target == ParamTarget::Unused {
+                continue;
+            }
+            let state = &mut self.slots[slot];
+            let (min, max) = state.range;
+            let normalized = if max != min {
+                ((value - min) / (max - min)).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            state.position = if state.inverted {
+                1.0 - normalized
+            } else {
+                normalized
+            };
+            state.engaged = false;
+            state.knob = None;
+            found = true;
+        }
+        found
+    }
+
+    /// Quantize every target of a CC to `steps` evenly spaced values in its range
+    /// 0 or 1 restores continuous output; returns false if the CC is not mapped
+    pub fn set_steps(&mut self, cc_num: u8, steps: u16) -> bool {
+        let mut found = false;
+        for slot in 0..self.mappings.len() {
+            if self.is_mapped(slot, cc_num) {
+                self.slots[slot].steps = steps;
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Ignore absolute changes of `amount` raw steps or fewer on every target of a CC
+    /// Filters jitter from noisy pots in `handle_cc`; 0 disables
+    pub fn set_hysteresis(&mut self, cc_num: u8, amount: u8) -> bool {
+        let mut found = false;
+        for slot in 0..self.mappings.len() {
+            if self.is_mapped(slot, cc_num) {
+                self.slots[slot].hysteresis = amount;
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Set the smoothing time constant (seconds) for every target of a CC
+    /// None leaves smoothing to the integration layer's default
+    pub fn set_smoothing(&mut self, cc_num: u8, seconds: Option<f32>) -> bool {
+        let mut found = false;
+        for slot in 0..self.mappings.len() {
+            if self.is_mapped(slot, cc_num) {
+                self.slots[slot].smoothing = seconds;
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Get the smoothing time constant of a mapped CC
+    pub fn smoothing(&self, cc_num: u8) -> Option<f32> {
+        self.find(cc_num)
+            .and_then(|slot| self.slots[slot].smoothing)
+    }
+
+    /// Create a smoother configured with the CC's smoothing time
+    /// Returns None if the CC is unmapped or has no smoothing time
+    pub fn smoother_for(&self, cc_num: u8, sample_rate: f32) -> Option<ParamSmoother> {
+        self.smoothing(cc_num)
+            .map(|seconds| ParamSmoother::with_time_constant(seconds, sample_rate))
+    }
+
+    /// Snap every target of a CC to one of `values`, spread evenly across the
+    /// controller's travel (replaces the output range; empty restores it)
+    /// Only the first `MAX_SNAP_VALUES` values are kept
+    pub fn set_snap_values(&mut self, cc_num: u8, values: &[f32]) -> bool {
+        let mut found = false;
+        for slot in 0..self.mappings.len() {
+            if self.is_mapped(slot, cc_num) {
+                self.snap_values[slot] = SnapValues::from_slice(values);
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Get the output range of a mapped CC
+    pub fn range(&self, cc_num: u8) -> Option<(f32, f32)> {
+        self.find(cc_num).map(|slot| self.slots[slot].range)
+    }
+
+    /// Remove every mapping for a CC number, returning its first previous target
+    pub fn remove_mapping(&mut self, cc_num: u8) -> Option<ParamTarget> {
+        let target = self.find(cc_num).map(|slot| self.mappings[slot].1);
+        for slot in 0..self.mappings.len() {
+            if self.is_mapped(slot, cc_num) {
+                self.clear_slot(slot);
+            }
+        }
+        target
+    }
+
+    /// Remove a single CC/target pair, keeping the CC's other targets
+    pub fn remove_target(&mut self, cc_num: u8, target: ParamTarget) -> bool {
+        let slot = (0..self.mappings.len())
+            .find(|&slot| self.is_mapped(slot, cc_num) && self.mappings[slot].1 == target);
+        match slot {
+            Some(slot) => {
+                self.clear_slot(slot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove all mappings, including the defaults (slots are kept for reuse)
+    pub fn clear(&mut self) {
+        self.mappings.fill((0, ParamTarget::Unused));
+        self.slots.fill(DEFAULT_SLOT);
+        self.snap_values.fill(SnapValues::EMPTY);
+    }
+
+    /// Enter learn mode: the next CC passed to `handle_cc` is bound to `target`
+    pub fn begin_learn(&mut self, target: ParamTarget) {
+        self.learning = Some(target);
+    }
+
+    /// Leave learn mode without binding anything
+    pub fn cancel_learn(&mut self) {
+        self.learning = None;
+    }
+
+    /// Get the target waiting to be learned, if any
+    pub fn learning(&self) -> Option<ParamTarget> {
+        self.learning
+    }
+
+    /// Handle an incoming CC, binding it first if learn mode is active
+    /// Learning replaces any controller previously bound to the target; a
+    /// full fixed-size map stays in learn mode instead.
+    /// 14-bit mappings combine MSB and LSB; a new MSB alone is scaled like
+    /// a 7-bit value until its LSB arrives.
+    /// Every target of the CC is updated, but only the first change is
+    /// returned; use `handle_cc_into` for CCs mapped to several targets.
+    pub fn handle_cc(&mut self, cc_num: u8, value: u8) -> Option<(ParamTarget, f32)> {
+        let mut out = [(ParamTarget::Unused, 0.0)];
+        (self.handle_cc_into(cc_num, value, &mut out) > 0).then_some(out[0])
+    }
+
+    /// Handle an incoming CC like `handle_cc`, writing the new value of
+    /// every target it drives into `out`
+    /// Returns how many were written; targets beyond `out.len()` still
+    /// update their pickup, hysteresis and switch state. Doesn't allocate.
+    pub fn handle_cc_into(
+        &mut self,
+        cc_num: u8,
+        value: u8,
+        out: &mut [(ParamTarget, f32)],
+    ) -> usize {
+        self.controllers.update(cc_num, value);
+        if let Some(target) = self.learning.take() {
+            for slot in 0..self.mappings.len() {
+                if self.mappings[slot].1 == target {
+                    self.clear_slot(slot);
+                }
+            }
+            if !self.set_mapping(cc_num, target) {
+                self.learning = Some(target); // No free slot; keep waiting
+            }
+        }
+
+        let value = value & 0x7F;
+        let mut written = 0;
+        let mut report = |change: Option<(ParamTarget, f32)>| {
+            if let (Some(change), Some(entry)) = (change, out.get_mut(written)) {
+                *entry = change;
+                written += 1;
+            }
+        };
+        if self.find(cc_num).is_some() {
+            for slot in 0..self.mappings.len() {
+                if self.is_mapped(slot, cc_num) {
+                    report(self.handle_slot(slot, value));
+                }
+            }
+            return written;
+        }
+
+        // LSB of a 14-bit pair
+        let Some(msb_cc) = cc_num.checked_sub(CC_LSB_OFFSET) else {
+            return 0;
+        };
+        if msb_cc >= CC_LSB_OFFSET {
+            return 0;
+        }
+        for slot in 0..self.mappings.len() {
+            if self.is_mapped(slot, msb_cc) && self.slots[slot].high_res {
+                self.slots[slot].lsb = Some(value);
+                report(Some((self.mappings[slot].1, self.scale_14bit(slot))));
+            }
+        }
+        written
+    }
+
+    /// Apply a CC value to one slot's state, returning its new output
+    fn handle_slot(&mut self, slot: usize, value: u8) -> Option<(ParamTarget, f32)> {
+        let state = &mut self.slots[slot];
+        if state.mode.is_relative() {
+            state.advance(value);
+            return Some(self.slot_value(slot, value));
+        }
+        if state.mode.is_switch() {
+            return state.switch(value).then(|| self.slot_value(slot, value));
+        }
+        if state.high_res {
+            state.msb = value;
+            state.lsb = None;
+            return Some((self.mappings[slot].1, self.scale_14bit(slot)));
+        }
+        if !state.passes_hysteresis(value) {
+            return None;
+        }
+        let normalized = value as f32 / 127.0;
+        if !state.pick_up(normalized) {
+            return None;
+        }
+        state.last_raw = Some(value);
+        state.position = normalized;
+        Some(self.slot_value(slot, value))
+    }
+
+    /// Get the last values received through `handle_cc`, mapped or not
+    pub fn controller_state(&self) -> &ControllerState {
+        &self.controllers
+    }
+
+    /// Get the current output of a mapped CC without receiving a new value
+    /// Reflects the last accepted value (after pickup, hysteresis and switch
+    /// handling), or a value set with `set_target_value`
+    pub fn current_value(&self, cc_num: u8) -> Option<(ParamTarget, f32)> {
+        let slot = self.find(cc_num)?;
+        let state = &self.slots[slot];
+        let value = if state.high_res && state.mode == CCMode::Absolute {
+            self.scale_14bit(slot)
+        } else {
+            self.scale(slot, state.position)
+        };
+        Some((self.mappings[slot].1, value))
+    }
+
+    /// Get all current mapping slots, including unused ones
+    pub fn get_mappings(&self) -> &[(u8, ParamTarget)] {
         &self.mappings
     }
+
+    /// Iterate over active (CC, target) mappings, skipping unused slots
+    pub fn iter_active(&self) -> impl Iterator<Item = (u8, ParamTarget)> + '_ {
+        self.mappings
+            .iter()
+            .copied()
+            .filter(|(_, target)| *target != ParamTarget::Unused)
+    }
+
+    /// Number of active mappings
+    pub fn len(&self) -> usize {
+        self.iter_active().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter_active().next().is_none()
+    }
+
+    fn find(&self, cc_num: u8) -> Option<usize> {
+        (0..self.mappings.len()).find(|&slot| self.is_mapped(slot, cc_num))
+    }
+
+    fn is_mapped(&self, slot: usize, cc_num: u8) -> bool {
+        let (mapped_cc, target) = self.mappings[slot];
+        mapped_cc == cc_num && target != ParamTarget::Unused
+    }
+
+    fn clear_slot(&mut self, slot: usize) {
+        self.mappings[slot] = (0, ParamTarget::Unused);
+        self.slots[slot] = DEFAULT_SLOT;
+        self.snap_values[slot] = SnapValues::EMPTY;
+    }
+
+    /// Point a slot at a target with a fresh state
+    fn fill_slot(&mut self, slot: usize, cc_num: u8, target: ParamTarget, range: (f32, f32)) {
+        self.mappings[slot] = (cc_num, target);
+        self.snap_values[slot] = SnapValues::EMPTY;
+        self.slots[slot] = SlotState {
+            range,
+            ..DEFAULT_SLOT
+        };
+    }
+
+    /// The first unused slot, or a freshly pushed one
+    fn free_slot(&mut self) -> Option<usize> {
+        if let Some(slot) = self
+            .mappings
+            .iter()
+            .position(|m| m.1 == ParamTarget::Unused)
+        {
+            return Some(slot);
+        }
+        if !self.mappings.push((0, ParamTarget::Unused)) {
+            return None;
+        }
+        self.slots.push(DEFAULT_SLOT);
+        self.snap_values.push(SnapValues::EMPTY);
+        Some(self.mappings.len() - 1)
+    }
+
+    fn slot_value(&self, slot: usize, value: u8) -> (ParamTarget, f32) {
+        let state = &self.slots[slot];
+        let normalized = if state.mode != CCMode::Absolute {
+            state.position
+        } else {
+            // A 14-bit MSB alone covers the same 0.0-1.0 as a 7-bit value
+            value as f32 / 127.0
+        };
+        (self.mappings[slot].1, self.scale(slot, normalized))
+    }
+
+    /// Apply inversion, quantization and the output range (or snap list)
+    fn scale(&self, slot: usize, normalized: f32) -> f32 {
+        let state = &self.slots[slot];
+        let normalized = if state.inverted {
+            1.0 - normalized
+        } else {
+            normalized
+        };
+
+        let snap = self.snap_values[slot].as_slice();
+        if !snap.is_empty() {
+            let index = libm::roundf(normalized * (snap.len() - 1) as f32) as usize;
+            return snap[index.min(snap.len() - 1)];
+        }
+
+        let normalized = if state.steps >= 2 {
+            let intervals = (state.steps - 1) as f32;
+            libm::roundf(normalized * intervals) / intervals
+        } else {
+            normalized
+        };
+        let (min, max) = state.range;
+        min + normalized * (max - min)
+    }
+
+    fn scale_14bit(&self, slot: usize) -> f32 {
+        let state = &self.slots[slot];
+        let normalized = match state.lsb {
+            Some(lsb) => (((state.msb as u16) << 7) | lsb as u16) as f32 / MAX_14BIT,
+            // A 14-bit MSB alone covers the same 0.0-1.0 as a 7-bit value
+            None => state.msb as f32 / 127.0,
+        };
+        self.scale(slot, normalized)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<const N: usize> From<CCMapPreset> for CCMap<N> {
+    fn from(preset: CCMapPreset) -> Self {
+        let mut map = Self::fixed();
+        map.add_mappings(&preset.mappings);
+        map
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<const N: usize> From<CCMap<N>> for CCMapPreset {
+    fn from(map: CCMap<N>) -> Self {
+        Self {
+            mappings: map.to_mappings(),
+        }
+    }
 }
 
 impl Default for CCMap {
@@ -93,6 +1053,407 @@ mod tests {
         assert_eq!(result, Some((ParamTarget::AttackTime, 1.0)));
     }
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn map_grows_past_default_slots() {
+        let mut map = CCMap::new();
+        for cc in 20..40 {
+            assert!(map.set_mapping(cc, ParamTarget::AttackTime));
+        }
+
+        assert!(map.get_mappings().len() > DEFAULT_CC_SLOTS);
+        assert_eq!(map.map_cc(39, 127), Some((ParamTarget::AttackTime, 1.0)));
+        assert_eq!(map.map_cc(1, 0), Some((ParamTarget::FilterCutoff, 0.0)));
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[test]
+    fn full_map_rejects_new_mappings() {
+        let mut map = CCMap::<4>::fixed();
+        for cc in 20..24 {
+            assert!(map.set_mapping(cc, ParamTarget::AttackTime));
+        }
+        assert!(!map.set_mapping(24, ParamTarget::AttackTime));
+        assert!(!map.add_mapping(20, ParamTarget::Pan, 0.0, 1.0));
+        assert!(!map.set_mapping_14bit(1, ParamTarget::FilterCutoff));
+        assert_eq!(map.map_cc(23, 127), Some((ParamTarget::AttackTime, 1.0)));
+        assert_eq!(map.map_cc(24, 127), None);
+
+        // Existing CCs can still be remapped, and removing frees a slot
+        assert!(map.set_mapping(23, ParamTarget::ReleaseTime));
+        map.remove_mapping(20);
+        assert!(map.set_mapping(24, ParamTarget::AttackTime));
+
+        // Learning onto a full map waits for a free slot
+        map.begin_learn(ParamTarget::Volume);
+        assert_eq!(map.handle_cc(30, 64), None);
+        assert_eq!(map.learning(), Some(ParamTarget::Volume));
+
+        // Small maps keep the defaults that fit
+        let small = CCMap::<2>::with_defaults();
+        assert_eq!(small.len(), 2);
+        assert_eq!(
+            small.map_cc(74, 127),
+            Some((ParamTarget::FilterResonance, 1.0))
+        );
+        assert_eq!(small.map_cc(7, 127), None);
+    }
+
+    #[test]
+    fn remove_and_clear() {
+        let mut map = CCMap::new();
+        assert_eq!(map.remove_mapping(1), Some(ParamTarget::FilterCutoff));
+        assert_eq!(map.remove_mapping(1), None);
+        assert_eq!(map.map_cc(1, 64), None);
+
+        map.clear();
+        assert_eq!(map.map_cc(74, 64), None);
+        assert_eq!(map.get_mappings().len(), DEFAULT_CC_SLOTS);
+    }
+
+    #[test]
+    fn learn_binds_next_cc() {
+        let mut map = CCMap::new();
+        map.begin_learn(ParamTarget::FilterCutoff);
+        assert_eq!(map.learning(), Some(ParamTarget::FilterCutoff));
+
+        assert_eq!(
+            map.handle_cc(21, 127),
+            Some((ParamTarget::FilterCutoff, 1.0))
+        );
+        assert_eq!(map.learning(), None);
+        assert_eq!(map.map_cc(1, 64), None); // old controller unbound
+
+        // Only the first CC is learned
+        assert_eq!(map.handle_cc(22, 127), None);
+    }
+
+    #[test]
+    fn mapping_range_scales_output() {
+        let mut map = CCMap::new();
+        map.set_mapping_with_range(7, ParamTarget::FilterCutoff, 200.0, 8000.0);
+
+        assert_eq!(map.map_cc(7, 0), Some((ParamTarget::FilterCutoff, 200.0)));
+        assert_eq!(
+            map.map_cc(7, 127),
+            Some((ParamTarget::FilterCutoff, 8000.0))
+        );
+        assert_eq!(map.range(7), Some((200.0, 8000.0)));
+
+        // Inverted range
+        assert!(map.set_range(7, 1.0, 0.0));
+        assert_eq!(map.map_cc(7, 127), Some((ParamTarget::FilterCutoff, 0.0)));
+        assert!(!map.set_range(42, 0.0, 1.0));
+    }
+
+    #[test]
+    fn fourteen_bit_pair_combined() {
+        let mut map = CCMap::new();
+        assert!(map.set_mapping_14bit(1, ParamTarget::FilterCutoff));
+        assert!(map.is_14bit(1));
+        assert!(!map.set_mapping_14bit(40, ParamTarget::AttackTime));
+
+        let (_, coarse) = map.handle_cc(1, 64).unwrap();
+        assert_eq!(coarse, 64.0 / 127.0);
+        let (_, fine) = map.handle_cc(33, 127).unwrap();
+        assert_eq!(fine, 8319.0 / 16383.0);
+
+        // Full scale reaches 1.0 on every path, with or without the LSB
+        assert_eq!(map.map_cc(1, 127), Some((ParamTarget::FilterCutoff, 1.0)));
+        assert_eq!(
+            map.handle_cc(1, 127),
+            Some((ParamTarget::FilterCutoff, 1.0))
+        );
+        assert_eq!(map.current_value(1), Some((ParamTarget::FilterCutoff, 1.0)));
+        assert_eq!(
+            map.handle_cc(33, 127),
+            Some((ParamTarget::FilterCutoff, 1.0))
+        );
+
+        // A new MSB drops the old LSB
+        map.handle_cc(33, 127);
+        let (_, reset) = map.handle_cc(1, 0).unwrap();
+        assert_eq!(reset, 0.0);
+    }
+
+    #[test]
+    fn custom_targets_are_distinct() {
+        let mut map = CCMap::new();
+        map.set_mapping(20, ParamTarget::Custom(7));
+        map.set_mapping(21, ParamTarget::Custom(8));
+
+        assert_eq!(map.map_cc(20, 127), Some((ParamTarget::Custom(7), 1.0)));
+        assert_eq!(map.map_cc(21, 0), Some((ParamTarget::Custom(8), 0.0)));
+    }
+
+    #[test]
+    fn one_cc_drives_several_targets() {
+        let mut map = CCMap::new();
+        map.add_mapping(1, ParamTarget::Custom(1), 0.0, 10.0);
+
+        let mut out = [(ParamTarget::Unused, 0.0); 4];
+        assert_eq!(map.map_cc_into(1, 127, &mut out), 2);
+        assert_eq!(out[0], (ParamTarget::FilterCutoff, 1.0));
+        assert_eq!(out[1], (ParamTarget::Custom(1), 10.0));
+
+        // Buffer too small keeps the first targets
+        assert_eq!(map.map_cc_into(1, 127, &mut out[..1]), 1);
+
+        assert!(map.remove_target(1, ParamTarget::FilterCutoff));
+        assert_eq!(map.map_cc(1, 127), Some((ParamTarget::Custom(1), 10.0)));
+        assert_eq!(map.remove_mapping(1), Some(ParamTarget::Custom(1)));
+        assert_eq!(map.map_cc_all(1, 127).count(), 0);
+    }
+
+    #[test]
+    fn handling_a_cc_updates_every_target() {
+        let mut map = CCMap::new();
+        map.add_mapping(1, ParamTarget::Custom(1), 0.0, 10.0);
+
+        let mut out = [(ParamTarget::Unused, 0.0); 4];
+        assert_eq!(map.handle_cc_into(1, 127, &mut out), 2);
+        assert_eq!(
+            out[..2],
+            [
+                (ParamTarget::FilterCutoff, 1.0),
+                (ParamTarget::Custom(1), 10.0)
+            ]
+        );
+
+        // handle_cc reports the first target but moves both
+        assert_eq!(map.handle_cc(1, 0), Some((ParamTarget::FilterCutoff, 0.0)));
+        let positions: Vec<_> = (0..map.mappings.len())
+            .filter(|&slot| map.is_mapped(slot, 1))
+            .map(|slot| map.slots[slot].position)
+            .collect();
+        assert_eq!(positions, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn inverted_mapping_flips_polarity() {
+        let mut map = CCMap::new();
+        assert!(map.set_inverted(1, true));
+        assert!(map.is_inverted(1));
+        assert!(!map.set_inverted(42, true));
+
+        assert_eq!(map.map_cc(1, 127), Some((ParamTarget::FilterCutoff, 0.0)));
+        assert_eq!(map.map_cc(1, 0), Some((ParamTarget::FilterCutoff, 1.0)));
+
+        // Inversion applies within the output range
+        map.set_range(1, 100.0, 200.0);
+        assert_eq!(map.map_cc(1, 0), Some((ParamTarget::FilterCutoff, 200.0)));
+    }
+
+    #[test]
+    fn relative_modes_decode() {
+        assert_eq!(CCMode::TwosComplement.delta(1), 1);
+        assert_eq!(CCMode::TwosComplement.delta(127), -1);
+        assert_eq!(CCMode::BinaryOffset.delta(65), 1);
+        assert_eq!(CCMode::BinaryOffset.delta(63), -1);
+        assert_eq!(CCMode::SignMagnitude.delta(1), 1);
+        assert_eq!(CCMode::SignMagnitude.delta(65), -1);
+        assert_eq!(CCMode::Absolute.delta(100), 0);
+    }
+
+    #[test]
+    fn relative_mapping_accumulates() {
+        let mut map = CCMap::new();
+        assert!(map.set_mode(1, CCMode::BinaryOffset));
+        assert_eq!(map.value(1), Some(0.0));
+
+        map.handle_cc(1, 74); // +10
+        map.handle_cc(1, 62); // -2
+        let expected = 8.0 / 127.0;
+        assert_eq!(map.value(1), Some(expected));
+        assert_eq!(
+            map.map_cc(1, 0),
+            Some((ParamTarget::FilterCutoff, expected))
+        );
+
+        // Clamped at the ends
+        map.handle_cc(1, 0);
+        assert_eq!(map.value(1), Some(0.0));
+        assert_eq!(map.value(74), None); // absolute mapping
+    }
+
+    #[test]
+    fn pickup_waits_for_crossing() {
+        let mut map = CCMap::new();
+        map.set_mapping_with_range(20, ParamTarget::FilterCutoff, 0.0, 127.0);
+        assert!(map.set_pickup(20, true));
+        assert!(map.set_target_value(ParamTarget::FilterCutoff, 64.0));
+
+        // Knob below the stored value doesn't jump the parameter
+        assert_eq!(map.handle_cc(20, 10), None);
+        assert_eq!(map.handle_cc(20, 40), None);
+
+        // Crossing 64 engages the knob
+        assert_eq!(
+            map.handle_cc(20, 70),
+            Some((ParamTarget::FilterCutoff, 70.0))
+        );
+        assert_eq!(map.handle_cc(20, 0), Some((ParamTarget::FilterCutoff, 0.0)));
+    }
+
+    #[test]
+    fn switch_modes() {
+        let mut map = CCMap::new();
+        map.set_mapping(64, ParamTarget::Custom(0));
+        map.set_mapping(65, ParamTarget::Custom(1));
+        map.set_mapping(66, ParamTarget::Custom(2));
+        map.set_mode(64, CCMode::Momentary);
+        map.set_mode(65, CCMode::Toggle);
+        map.set_mode(66, CCMode::Trigger);
+
+        assert_eq!(map.handle_cc(64, 127), Some((ParamTarget::Custom(0), 1.0)));
+        assert_eq!(map.handle_cc(64, 0), Some((ParamTarget::Custom(0), 0.0)));
+
+        assert_eq!(map.handle_cc(65, 127), Some((ParamTarget::Custom(1), 1.0)));
+        assert_eq!(map.handle_cc(65, 100), None); // still held
+        assert_eq!(map.handle_cc(65, 0), None);
+        assert_eq!(map.handle_cc(65, 127), Some((ParamTarget::Custom(1), 0.0)));
+
+        assert_eq!(map.handle_cc(66, 127), Some((ParamTarget::Custom(2), 1.0)));
+        assert_eq!(map.handle_cc(66, 0), None);
+        assert_eq!(map.value(66), Some(0.0));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn mappings_round_trip() {
+        let mut map = CCMap::new();
+        map.set_mapping_14bit(7, ParamTarget::Custom(5));
+        map.set_range(7, 20.0, 20000.0);
+        map.set_inverted(74, true);
+        map.set_mode(74, CCMode::BinaryOffset);
+
+        let restored = CCMap::from_mappings(&map.to_mappings());
+        assert_eq!(restored.to_mappings(), map.to_mappings());
+        assert!(restored.is_14bit(7));
+        assert_eq!(restored.range(7), Some((20.0, 20000.0)));
+        assert_eq!(restored.mode(74), Some(CCMode::BinaryOffset));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn preset_file_round_trip() {
+        let mut map = CCMap::new();
+        map.set_mapping_with_range(20, ParamTarget::AttackTime, 0.001, 2.0);
+        map.set_pickup(20, true);
+
+        let path = std::env::temp_dir().join("auxide_midi_cc_map_test.json");
+        map.save_to(&path).unwrap();
+        let loaded = CCMap::load_from(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.to_mappings(), map.to_mappings());
+    }
+
+    #[test]
+    fn stepped_and_snapped_output() {
+        let mut map = CCMap::new();
+        map.set_mapping_with_range(20, ParamTarget::Custom(0), 0.0, 3.0);
+        assert!(map.set_steps(20, 4));
+        assert_eq!(map.map_cc(20, 0), Some((ParamTarget::Custom(0), 0.0)));
+        assert_eq!(map.map_cc(20, 50), Some((ParamTarget::Custom(0), 1.0)));
+        assert_eq!(map.map_cc(20, 127), Some((ParamTarget::Custom(0), 3.0)));
+
+        assert!(map.set_snap_values(20, &[-12.0, -7.0, 0.0, 7.0, 12.0]));
+        assert_eq!(map.map_cc(20, 0), Some((ParamTarget::Custom(0), -12.0)));
+        assert_eq!(map.map_cc(20, 64), Some((ParamTarget::Custom(0), 0.0)));
+        assert_eq!(map.map_cc(20, 127), Some((ParamTarget::Custom(0), 12.0)));
+
+        // Long lists are cut to MAX_SNAP_VALUES
+        let values: Vec<f32> = (0..40).map(|value| value as f32).collect();
+        map.set_snap_values(20, &values);
+        let last = (MAX_SNAP_VALUES - 1) as f32;
+        assert_eq!(map.map_cc(20, 127), Some((ParamTarget::Custom(0), last)));
+
+        // Empty list restores the range
+        map.set_snap_values(20, &[]);
+        assert_eq!(map.map_cc(20, 127), Some((ParamTarget::Custom(0), 3.0)));
+    }
+
+    #[test]
+    fn hysteresis_filters_jitter() {
+        let mut map = CCMap::new();
+        assert!(map.set_hysteresis(1, 1));
+
+        assert!(map.handle_cc(1, 64).is_some());
+        assert_eq!(map.handle_cc(1, 65), None);
+        assert_eq!(map.handle_cc(1, 63), None);
+        assert!(map.handle_cc(1, 66).is_some());
+
+        // Ends of travel are always reachable
+        map.handle_cc(1, 126);
+        assert_eq!(
+            map.handle_cc(1, 127),
+            Some((ParamTarget::FilterCutoff, 1.0))
+        );
+        assert_eq!(map.handle_cc(1, 127), None);
+    }
+
+    #[test]
+    fn last_values_queryable() {
+        let mut map = CCMap::new();
+        map.set_range(1, 100.0, 200.0);
+        assert_eq!(map.controller_state().raw(1), None);
+
+        map.handle_cc(1, 127);
+        map.handle_cc(42, 64); // unmapped CCs are still recorded
+        assert_eq!(map.controller_state().raw(1), Some(127));
+        assert_eq!(map.controller_state().normalized(42), Some(64.0 / 127.0));
+        assert_eq!(
+            map.current_value(1),
+            Some((ParamTarget::FilterCutoff, 200.0))
+        );
+        assert_eq!(map.current_value(42), None);
+    }
+
+    #[test]
+    fn per_mapping_smoothing() {
+        let mut map = CCMap::new();
+        assert!(map.set_smoothing(1, Some(0.005)));
+        assert!(map.set_smoothing(74, Some(0.2)));
+
+        assert_eq!(map.smoothing(1), Some(0.005));
+        assert_eq!(map.smoothing(42), None);
+        assert!(map.smoother_for(74, 48000.0).is_some());
+
+        map.set_smoothing(74, None);
+        assert!(map.smoother_for(74, 48000.0).is_none());
+    }
+
+    #[test]
+    fn active_mappings_skip_unused() {
+        let mut map = CCMap::<4>::fixed();
+        map.set_mapping(1, ParamTarget::FilterCutoff);
+        map.set_mapping(74, ParamTarget::FilterResonance);
+        assert_eq!(map.len(), 2);
+        assert_eq!(
+            map.iter_active().collect::<Vec<_>>(),
+            vec![
+                (1, ParamTarget::FilterCutoff),
+                (74, ParamTarget::FilterResonance)
+            ]
+        );
+
+        map.remove_mapping(1);
+        assert_eq!(map.len(), 1);
+        map.clear();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn common_controllers_mapped_by_default() {
+        let map = CCMap::new();
+        assert_eq!(map.map_cc(7, 127), Some((ParamTarget::Volume, 1.0)));
+        assert_eq!(map.map_cc(10, 0), Some((ParamTarget::Pan, 0.0)));
+        assert_eq!(map.map_cc(11, 0), Some((ParamTarget::Expression, 0.0)));
+        assert_eq!(map.map_cc(2, 127), Some((ParamTarget::Breath, 1.0)));
+        assert_eq!(map.map_cc(5, 127), Some((ParamTarget::PortamentoTime, 1.0)));
+    }
+
     #[test]
     fn cc_value_normalization() {
         let map = CCMap::new();
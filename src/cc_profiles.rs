@@ -0,0 +1,172 @@
+//! Named controller mapping profiles, selectable by MIDI device name
+
+use crate::cc_mapping::{CCMap, CCMapping, ParamTarget};
+
+/// A named set of CC mappings for a hardware controller
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ControllerProfile {
+    pub name: String,
+    /// Case-insensitive substring of the device name reported by midir
+    /// None means the profile is only selected by name
+    pub device_pattern: Option<String>,
+    pub mappings: Vec<CCMapping>,
+}
+
+impl ControllerProfile {
+    pub fn new(name: &str, device_pattern: Option<&str>, mappings: Vec<CCMapping>) -> Self {
+        Self {
+            name: name.to_string(),
+            device_pattern: device_pattern.map(str::to_string),
+            mappings,
+        }
+    }
+
+    /// Check whether this profile applies to a MIDI device
+    pub fn matches_device(&self, device_name: &str) -> bool {
+        self.device_pattern
+            .as_ref()
+            .is_some_and(|pattern| device_name.to_lowercase().contains(&pattern.to_lowercase()))
+    }
+
+    /// Build a CC map from the profile
+    pub fn cc_map(&self) -> CCMap {
+        CCMap::from_mappings(&self.mappings)
+    }
+
+    /// General MIDI 2 sound controllers (CC 71-74)
+    pub fn generic_gm() -> Self {
+        Self::new(
+            "Generic GM",
+            None,
+            vec![
+                CCMapping::new(74, ParamTarget::FilterCutoff),
+                CCMapping::new(71, ParamTarget::FilterResonance),
+                CCMapping::new(73, ParamTarget::AttackTime),
+                CCMapping::new(72, ParamTarget::ReleaseTime),
+            ],
+        )
+    }
+
+    /// Arturia MicroFreak factory CC assignments
+    pub fn arturia_microfreak() -> Self {
+        Self::new(
+            "Arturia MicroFreak",
+            Some("MicroFreak"),
+            vec![
+                CCMapping::new(23, ParamTarget::FilterCutoff),
+                CCMapping::new(83, ParamTarget::FilterResonance),
+                CCMapping::new(105, ParamTarget::AttackTime),
+                CCMapping::new(106, ParamTarget::ReleaseTime),
+            ],
+        )
+    }
+}
+
+/// Built-in and user-registered controller profiles
+///
+/// Profiles registered later take precedence, so user profiles override
+/// the built-in ones for the same device.
+#[derive(Debug, Clone, Default)]
+pub struct ProfileLibrary {
+    profiles: Vec<ControllerProfile>,
+}
+
+impl ProfileLibrary {
+    /// Create an empty library
+    pub fn new() -> Self {
+        Self {
+            profiles: Vec::new(),
+        }
+    }
+
+    /// Create a library containing the built-in profiles
+    pub fn with_builtin() -> Self {
+        let mut library = Self::new();
+        library.register(ControllerProfile::generic_gm());
+        library.register(ControllerProfile::arturia_microfreak());
+        library
+    }
+
+    /// Add a profile, replacing any existing profile with the same name
+    pub fn register(&mut self, profile: ControllerProfile) {
+        self.profiles.retain(|p| p.name != profile.name);
+        self.profiles.push(profile);
+    }
+
+    /// Remove a profile by name
+    pub fn unregister(&mut self, name: &str) -> Option<ControllerProfile> {
+        let index = self.profiles.iter().position(|p| p.name == name)?;
+        Some(self.profiles.remove(index))
+    }
+
+    pub fn profiles(&self) -> &[ControllerProfile] {
+        &self.profiles
+    }
+
+    /// Find a profile by name
+    pub fn profile(&self, name: &str) -> Option<&ControllerProfile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    /// Find the most recently registered profile matching a device name
+    pub fn profile_for_device(&self, device_name: &str) -> Option<&ControllerProfile> {
+        self.profiles
+            .iter()
+            .rev()
+            .find(|p| p.matches_device(device_name))
+    }
+
+    /// Build a CC map for a device, if a profile matches it
+    pub fn cc_map_for_device(&self, device_name: &str) -> Option<CCMap> {
+        self.profile_for_device(device_name)
+            .map(ControllerProfile::cc_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_name_selects_profile() {
+        let library = ProfileLibrary::with_builtin();
+        let profile = library
+            .profile_for_device("Arturia MicroFreak:Arturia MicroFreak MIDI 1 20:0")
+            .unwrap();
+        assert_eq!(profile.name, "Arturia MicroFreak");
+
+        let map = library.cc_map_for_device("ARTURIA MICROFREAK").unwrap();
+        assert_eq!(map.map_cc(23, 127), Some((ParamTarget::FilterCutoff, 1.0)));
+
+        // Name-only profiles never match devices
+        assert!(library.profile_for_device("Generic GM").is_none());
+        assert!(library.profile("Generic GM").is_some());
+    }
+
+    #[test]
+    fn user_profile_overrides_builtin() {
+        let mut library = ProfileLibrary::with_builtin();
+        library.register(ControllerProfile::new(
+            "My Freak",
+            Some("microfreak"),
+            vec![CCMapping::new(1, ParamTarget::Custom(1))],
+        ));
+
+        assert_eq!(
+            library
+                .profile_for_device("Arturia MicroFreak")
+                .unwrap()
+                .name,
+            "My Freak"
+        );
+        assert!(library.unregister("My Freak").is_some());
+        assert_eq!(
+            library
+                .profile_for_device("Arturia MicroFreak")
+                .unwrap()
+                .name,
+            "Arturia MicroFreak"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Channel mode messages: Omni On/Off and Mono/Poly (CC 124-127)
+//!
+//! MIDI 1.0 receivers run in one of four modes, set per basic channel:
+//!
+//! | Mode | Omni | Voices |
+//! |------|------|--------|
+//! | 1    | On   | Poly   |
+//! | 2    | On   | Mono   |
+//! | 3    | Off  | Poly   |
+//! | 4    | Off  | Mono   |
+//!
+//! Every mode message also acts as All Notes Off (see
+//! [`MidiEvent::is_all_notes_off`]). [`ChannelModes`] tracks the mode of
+//! each channel; a `MultiTimbralAllocator` can apply it to its parts.
+
+use crate::midi_input::{ChannelEvent, MidiEvent};
+
+/// CC 124: Omni Off
+pub const CC_OMNI_OFF: u8 = 124;
+/// CC 125: Omni On
+pub const CC_OMNI_ON: u8 = 125;
+/// CC 126: Mono On (value = number of channels, 0 for as many as voices)
+pub const CC_MONO_ON: u8 = 126;
+/// CC 127: Poly On
+pub const CC_POLY_ON: u8 = 127;
+
+/// A channel mode message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelModeMessage {
+    OmniOff,
+    OmniOn,
+    /// Mono On over this many channels from the basic channel (0 = all)
+    MonoOn(u8),
+    PolyOn,
+}
+
+impl ChannelModeMessage {
+    pub fn from_event(event: &MidiEvent) -> Option<Self> {
+        match *event {
+            MidiEvent::ControlChange(CC_OMNI_OFF, _) => Some(Self::OmniOff),
+            MidiEvent::ControlChange(CC_OMNI_ON, _) => Some(Self::OmniOn),
+            MidiEvent::ControlChange(CC_MONO_ON, channels) => Some(Self::MonoOn(channels & 0x7F)),
+            MidiEvent::ControlChange(CC_POLY_ON, _) => Some(Self::PolyOn),
+            _ => None,
+        }
+    }
+
+    pub fn to_event(self) -> MidiEvent {
+        match self {
+            Self::OmniOff => MidiEvent::ControlChange(CC_OMNI_OFF, 0),
+            Self::OmniOn => MidiEvent::ControlChange(CC_OMNI_ON, 0),
+            Self::MonoOn(channels) => MidiEvent::ControlChange(CC_MONO_ON, channels & 0x7F),
+            Self::PolyOn => MidiEvent::ControlChange(CC_POLY_ON, 0),
+        }
+    }
+}
+
+/// Receive mode of one channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelMode {
+    pub omni: bool,
+    pub mono: bool,
+}
+
+impl ChannelMode {
+    /// MIDI 1.0 mode number, 1-4
+    pub fn number(&self) -> u8 {
+        match (self.omni, self.mono) {
+            (true, false) => 1,
+            (true, true) => 2,
+            (false, false) => 3,
+            (false, true) => 4,
+        }
+    }
+}
+
+impl Default for ChannelMode {
+    /// Mode 1 (Omni On, Poly), the power-on mode
+    fn default() -> Self {
+        Self {
+            omni: true,
+            mono: false,
+        }
+    }
+}
+
+/// Tracks the mode of all 16 channels from the mode messages they receive
+#[derive(Debug, Clone, Default)]
+pub struct ChannelModes {
+    modes: [ChannelMode; 16],
+}
+
+impl ChannelModes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(&self, channel: u8) -> ChannelMode {
+        self.modes[(channel & 0x0F) as usize]
+    }
+
+    pub fn set_mode(&mut self, channel: u8, mode: ChannelMode) {
+        self.modes[(channel & 0x0F) as usize] = mode;
+    }
+
+    /// Apply a mode message arriving on `event.channel` and return it
+    ///
+    /// In Omni Off, Mono On with a channel count puts that many channels
+    /// from the basic channel into mono (0 = through channel 16); with
+    /// Omni On it affects the basic channel alone.
+    pub fn handle_event(&mut self, event: &ChannelEvent) -> Option<ChannelModeMessage> {
+        let message = ChannelModeMessage::from_event(&event.event)?;
+        let channel = (event.channel & 0x0F) as usize;
+        let mode = &mut self.modes[channel];
+        match message {
+            ChannelModeMessage::OmniOff => mode.omni = false,
+            ChannelModeMessage::OmniOn => mode.omni = true,
+            ChannelModeMessage::PolyOn => mode.mono = false,
+            ChannelModeMessage::MonoOn(count) => {
+                let last = match (mode.omni, count) {
+                    (true, _) => channel,
+                    (false, 0) => 15,
+                    (false, count) => (channel + count as usize - 1).min(15),
+                };
+                let omni = mode.omni;
+                for mode in &mut self.modes[channel..=last] {
+                    *mode = ChannelMode { omni, mono: true };
+                }
+            }
+        }
+        Some(message)
+    }
+
+    /// Back to mode 1 on every channel
+    pub fn reset(&mut self) {
+        self.modes = Default::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cc(channel: u8, cc: u8, value: u8) -> ChannelEvent {
+        ChannelEvent {
+            channel,
+            event: MidiEvent::ControlChange(cc, value),
+        }
+    }
+
+    #[test]
+    fn messages_round_trip_and_imply_notes_off() {
+        for message in [
+            ChannelModeMessage::OmniOff,
+            ChannelModeMessage::OmniOn,
+            ChannelModeMessage::MonoOn(4),
+            ChannelModeMessage::PolyOn,
+        ] {
+            let event = message.to_event();
+            assert!(event.is_all_notes_off());
+            assert_eq!(ChannelModeMessage::from_event(&event), Some(message));
+        }
+        assert_eq!(
+            ChannelModeMessage::from_event(&MidiEvent::ControlChange(123, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn modes_follow_the_spec() {
+        let mut modes = ChannelModes::new();
+        assert_eq!(modes.mode(0).number(), 1);
+        assert_eq!(
+            modes.handle_event(&cc(0, CC_MONO_ON, 1)),
+            Some(ChannelModeMessage::MonoOn(1))
+        );
+        assert_eq!(modes.mode(0).number(), 2);
+        assert_eq!(modes.mode(1).number(), 1);
+
+        // Mode 4 over three channels from channel 2
+        modes.handle_event(&cc(2, CC_OMNI_OFF, 0));
+        modes.handle_event(&cc(2, CC_MONO_ON, 3));
+        assert_eq!(
+            (0..6).map(|ch| modes.mode(ch).number()).collect::<Vec<_>>(),
+            [2, 1, 4, 4, 4, 1]
+        );
+        modes.handle_event(&cc(3, CC_POLY_ON, 0));
+        assert_eq!(modes.mode(3).number(), 3);
+        assert_eq!(modes.handle_event(&cc(3, 7, 100)), None);
+    }
+}
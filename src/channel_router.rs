@@ -0,0 +1,225 @@
+//! Channel routing: remap, duplicate and split MIDI channels
+//!
+//! A [`ChannelRouter`] holds a list of [`ChannelRoute`]s, each taking events
+//! from one source channel (or any) within a key and velocity range and
+//! sending them to a destination channel. Several routes from the same
+//! source duplicate it; routes with different key ranges split it.
+
+use crate::midi_input::{ChannelEvent, MidiEvent};
+use std::ops::RangeInclusive;
+
+/// Most routes a `ChannelRouter` can hold
+pub const MAX_CHANNEL_ROUTES: usize = 32;
+
+/// One routing rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelRoute {
+    /// Source channel, or None for every channel
+    pub from: Option<u8>,
+    pub to: u8,
+    /// Notes this route takes (note events only)
+    pub notes: RangeInclusive<u8>,
+    /// Note-on velocities this route takes
+    pub velocities: RangeInclusive<u8>,
+}
+
+impl ChannelRoute {
+    /// Send everything on `from` to `to`
+    pub fn remap(from: u8, to: u8) -> Self {
+        Self {
+            from: Some(from & 0x0F),
+            to: to & 0x0F,
+            notes: 0..=127,
+            velocities: 0..=127,
+        }
+    }
+
+    /// Send the notes in `notes` on `from` to `to`
+    pub fn split(from: u8, notes: RangeInclusive<u8>, to: u8) -> Self {
+        Self {
+            notes,
+            ..Self::remap(from, to)
+        }
+    }
+
+    /// Only take note-ons within `velocities` (velocity layering)
+    pub fn with_velocities(mut self, velocities: RangeInclusive<u8>) -> Self {
+        self.velocities = velocities;
+        self
+    }
+
+    fn takes_channel(&self, channel: u8) -> bool {
+        self.from.is_none_or(|from| from == channel)
+    }
+}
+
+/// Routes channel events to destination channels
+///
+/// Note-offs follow their note-on: they go wherever the note-on went,
+/// whatever their own velocity, even if the routes changed in between.
+/// Other channel messages (controllers, bend, pressure) go to each
+/// destination of their source channel once. Events no route takes are
+/// passed through unchanged unless pass-through is turned off.
+#[derive(Debug, Clone)]
+pub struct ChannelRouter {
+    routes: Vec<ChannelRoute>,
+    held: Vec<u16>, // Destination channels of each held note, by source channel and note
+    passthrough: bool,
+}
+
+impl ChannelRouter {
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::with_capacity(MAX_CHANNEL_ROUTES),
+            held: vec![0; 16 * 128],
+            passthrough: true,
+        }
+    }
+
+    /// Add a route; returns false once `MAX_CHANNEL_ROUTES` are in use
+    pub fn add_route(&mut self, route: ChannelRoute) -> bool {
+        if self.routes.len() >= MAX_CHANNEL_ROUTES {
+            return false;
+        }
+        self.routes.push(route);
+        true
+    }
+
+    /// Copy everything on `from` to each of `to`
+    pub fn duplicate(&mut self, from: u8, to: &[u8]) -> usize {
+        to.iter()
+            .take_while(|&&to| self.add_route(ChannelRoute::remap(from, to)))
+            .count()
+    }
+
+    pub fn routes(&self) -> &[ChannelRoute] {
+        &self.routes
+    }
+
+    /// Remove every route (held notes still receive their note-offs)
+    pub fn clear(&mut self) {
+        self.routes.clear();
+    }
+
+    /// Pass events no route takes through unchanged (the default)
+    pub fn set_passthrough(&mut self, passthrough: bool) {
+        self.passthrough = passthrough;
+    }
+
+    /// Route `event`, calling `emit` for each resulting event
+    /// Returns the number of events emitted.
+    pub fn route(&mut self, event: ChannelEvent, mut emit: impl FnMut(ChannelEvent)) -> usize {
+        let channel = event.channel & 0x0F;
+        let destinations = match event.event {
+            MidiEvent::NoteOn(note, velocity) => {
+                let destinations = self
+                    .routes
+                    .iter()
+                    .filter(|route| {
+                        route.takes_channel(channel)
+                            && route.notes.contains(&note)
+                            && route.velocities.contains(&velocity)
+                    })
+                    .fold(0u16, |mask, route| mask | 1 << route.to);
+                self.held[held_slot(channel, note)] = destinations;
+                destinations
+            }
+            MidiEvent::NoteOff(note, _) => std::mem::take(&mut self.held[held_slot(channel, note)]),
+            _ => self
+                .routes
+                .iter()
+                .filter(|route| route.takes_channel(channel))
+                .fold(0u16, |mask, route| mask | 1 << route.to),
+        };
+
+        if destinations == 0 {
+            // Untaken note-ons pass through, and so do their note-offs
+            if self.passthrough {
+                emit(event);
+                return 1;
+            }
+            return 0;
+        }
+        let mut emitted = 0;
+        for to in (0..16u8).filter(|to| destinations & (1 << to) != 0) {
+            emit(ChannelEvent {
+                channel: to,
+                event: event.event.clone(),
+            });
+            emitted += 1;
+        }
+        emitted
+    }
+}
+
+impl Default for ChannelRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn held_slot(channel: u8, note: u8) -> usize {
+    channel as usize * 128 + (note & 0x7F) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(channel: u8, event: MidiEvent) -> ChannelEvent {
+        ChannelEvent { channel, event }
+    }
+
+    fn routed(router: &mut ChannelRouter, input: ChannelEvent) -> Vec<ChannelEvent> {
+        let mut out = Vec::new();
+        router.route(input, |e| out.push(e));
+        out
+    }
+
+    #[test]
+    fn remaps_duplicates_and_passes_through() {
+        let mut router = ChannelRouter::new();
+        router.add_route(ChannelRoute::remap(0, 5));
+        assert_eq!(router.duplicate(1, &[2, 3]), 2);
+
+        let bend = MidiEvent::PitchBend(9000);
+        assert_eq!(
+            routed(&mut router, event(0, bend.clone())),
+            [event(5, bend.clone())]
+        );
+        assert_eq!(
+            routed(&mut router, event(1, bend.clone())),
+            [event(2, bend.clone()), event(3, bend.clone())]
+        );
+        assert_eq!(
+            routed(&mut router, event(9, bend.clone())),
+            [event(9, bend.clone())]
+        );
+
+        router.set_passthrough(false);
+        assert!(routed(&mut router, event(9, bend)).is_empty());
+    }
+
+    #[test]
+    fn splits_by_key_and_velocity_and_note_offs_follow() {
+        let mut router = ChannelRouter::new();
+        router.add_route(ChannelRoute::split(0, 0..=59, 1)); // Bass
+        router.add_route(ChannelRoute::split(0, 60..=127, 2).with_velocities(0..=99));
+        router.add_route(ChannelRoute::split(0, 60..=127, 3).with_velocities(100..=127));
+
+        assert_eq!(
+            routed(&mut router, event(0, MidiEvent::NoteOn(40, 90))),
+            [event(1, MidiEvent::NoteOn(40, 90))]
+        );
+        assert_eq!(
+            routed(&mut router, event(0, MidiEvent::NoteOn(72, 110))),
+            [event(3, MidiEvent::NoteOn(72, 110))]
+        );
+        // The release velocity would pick the soft layer; the note-off follows its note-on
+        router.clear();
+        assert_eq!(
+            routed(&mut router, event(0, MidiEvent::NoteOff(72, 10))),
+            [event(3, MidiEvent::NoteOff(72, 10))]
+        );
+    }
+}
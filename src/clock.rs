@@ -0,0 +1,402 @@
+//! Following an external MIDI clock, or generating one
+//!
+//! [`ClockFollower`] turns the System Real-Time and Song Position messages
+//! of a sequencer or drum machine into a transport: playing or stopped, the
+//! song position in clock ticks and a smoothed tempo estimate.
+//! [`ClockMaster`] is the standalone equivalent, ticking at a set tempo.
+
+use crate::tempo::{
+    clock_interval_to_bpm, clock_tick_samples, clock_ticks_to_beats, MIDI_CLOCK, MIDI_CONTINUE,
+    MIDI_SONG_POSITION, MIDI_START, MIDI_STOP,
+};
+
+/// MIDI clock ticks per Song Position "MIDI beat" (a sixteenth note)
+pub const CLOCKS_PER_MIDI_BEAT: u64 = 6;
+
+/// Default weight of each new tick interval in the tempo estimate
+pub const DEFAULT_CLOCK_SMOOTHING: f64 = 0.1;
+
+/// Ticks missing for this many estimated intervals mean the clock stopped
+const CLOCK_TIMEOUT_INTERVALS: f64 = 8.0;
+
+/// Snapshot of the transport for tempo-synced features
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Transport {
+    pub playing: bool,
+    /// Song position in MIDI clock ticks (24 per quarter note)
+    pub ticks: u64,
+    /// Estimated tempo, once two ticks have been seen
+    pub bpm: Option<f64>,
+}
+
+impl Transport {
+    /// Song position in quarter-note beats
+    pub fn beats(&self) -> f64 {
+        clock_ticks_to_beats(self.ticks as f64)
+    }
+}
+
+/// What a message did to the transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportEvent {
+    Tick,
+    Started,
+    Continued,
+    Stopped,
+    /// Song position moved to this many clock ticks
+    Located(u64),
+}
+
+/// Follows an external MIDI clock
+///
+/// Feed it every real-time byte and Song Position message with its arrival
+/// time in microseconds. Tick intervals are smoothed exponentially; an
+/// interval more than twice the estimate (a paused clock) restarts the
+/// estimate instead of dragging it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockFollower {
+    playing: bool,
+    ticks: u64,
+    last_tick: Option<u64>,
+    interval: Option<f64>, // Smoothed µs per tick
+    smoothing: f64,
+}
+
+impl ClockFollower {
+    pub fn new() -> Self {
+        Self {
+            playing: false,
+            ticks: 0,
+            last_tick: None,
+            interval: None,
+            smoothing: DEFAULT_CLOCK_SMOOTHING,
+        }
+    }
+
+    /// Weight of each new interval, 0.0 (frozen) to 1.0 (no smoothing)
+    pub fn set_smoothing(&mut self, smoothing: f64) {
+        self.smoothing = smoothing.clamp(0.001, 1.0);
+    }
+
+    pub fn transport(&self) -> Transport {
+        Transport {
+            playing: self.playing,
+            ticks: self.ticks,
+            bpm: self.bpm(),
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Song position in MIDI clock ticks
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn bpm(&self) -> Option<f64> {
+        self.interval
+            .map(|interval| clock_interval_to_bpm(interval / 1_000_000.0))
+    }
+
+    /// True if a tick arrived recently enough at `now` (µs)
+    pub fn has_clock(&self, now: u64) -> bool {
+        match (self.last_tick, self.interval) {
+            (Some(last), Some(interval)) => {
+                (now.saturating_sub(last) as f64) < interval * CLOCK_TIMEOUT_INTERVALS
+            }
+            _ => false,
+        }
+    }
+
+    /// Handle a MIDI message received at `time` (µs)
+    /// Returns what it did, or None for messages that aren't transport related.
+    pub fn handle_message(&mut self, bytes: &[u8], time: u64) -> Option<TransportEvent> {
+        match *bytes {
+            [MIDI_CLOCK, ..] => {
+                self.tick(time);
+                Some(TransportEvent::Tick)
+            }
+            [MIDI_START, ..] => {
+                self.start();
+                Some(TransportEvent::Started)
+            }
+            [MIDI_CONTINUE, ..] => {
+                self.resume();
+                Some(TransportEvent::Continued)
+            }
+            [MIDI_STOP, ..] => {
+                self.stop();
+                Some(TransportEvent::Stopped)
+            }
+            [MIDI_SONG_POSITION, lsb, msb] => {
+                self.set_song_position(((msb as u16 & 0x7F) << 7) | (lsb as u16 & 0x7F));
+                Some(TransportEvent::Located(self.ticks))
+            }
+            _ => None,
+        }
+    }
+
+    /// A clock tick at `time` (µs); advances the song position while playing
+    pub fn tick(&mut self, time: u64) {
+        if let Some(last) = self.last_tick {
+            let measured = time.saturating_sub(last) as f64;
+            self.interval = match self.interval {
+                Some(interval) if measured <= interval * 2.0 => {
+                    Some(interval + (measured - interval) * self.smoothing)
+                }
+                Some(_) => None, // Clock paused; start over from the next tick
+                None if measured > 0.0 => Some(measured),
+                None => None,
+            };
+        }
+        self.last_tick = Some(time);
+        if self.playing {
+            self.ticks += 1;
+        }
+    }
+
+    /// Start from the top of the song
+    pub fn start(&mut self) {
+        self.ticks = 0;
+        self.playing = true;
+    }
+
+    /// Continue from the current song position
+    pub fn resume(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+
+    /// Move to a Song Position Pointer value, in sixteenth notes
+    pub fn set_song_position(&mut self, midi_beats: u16) {
+        self.ticks = midi_beats as u64 * CLOCKS_PER_MIDI_BEAT;
+    }
+}
+
+impl Default for ClockFollower {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Default tempo of a `ClockMaster`
+pub const DEFAULT_MASTER_BPM: f64 = 120.0;
+
+/// Receives the MIDI clock bytes a `ClockMaster` generates
+pub type ClockOutput = Box<dyn FnMut(u8) + Send>;
+
+/// Internal MIDI clock generator
+///
+/// Call [`process`](Self::process) once per audio block; it reports every
+/// tick falling inside the block with its frame offset, so tempo-synced
+/// features run without an external clock. With an output set, the
+/// matching Clock, Start, Stop and Continue bytes are also sent, ready to
+/// forward to a MIDI output port.
+pub struct ClockMaster {
+    bpm: f64,
+    playing: bool,
+    ticks: u64,
+    until_tick: f64, // Frames until the next tick
+    output: Option<ClockOutput>,
+}
+
+impl ClockMaster {
+    pub fn new(bpm: f64) -> Self {
+        Self {
+            bpm: bpm.max(1.0),
+            playing: false,
+            ticks: 0,
+            until_tick: 0.0,
+            output: None,
+        }
+    }
+
+    pub fn bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    /// Change the tempo; takes effect from the next tick
+    pub fn set_tempo(&mut self, bpm: f64) {
+        self.bpm = bpm.max(1.0);
+    }
+
+    /// Send generated MIDI clock bytes to `output` (None to stop sending)
+    pub fn set_output(&mut self, output: Option<ClockOutput>) {
+        self.output = output;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Song position in MIDI clock ticks
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn transport(&self) -> Transport {
+        Transport {
+            playing: self.playing,
+            ticks: self.ticks,
+            bpm: Some(self.bpm),
+        }
+    }
+
+    /// Start from the top; the first tick falls on the next processed frame
+    pub fn start(&mut self) {
+        self.ticks = 0;
+        self.until_tick = 0.0;
+        self.playing = true;
+        self.send(MIDI_START);
+    }
+
+    /// Continue from the current song position
+    pub fn resume(&mut self) {
+        if !self.playing {
+            self.playing = true;
+            self.send(MIDI_CONTINUE);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        if self.playing {
+            self.playing = false;
+            self.send(MIDI_STOP);
+        }
+    }
+
+    /// Advance by a block of `frames`, calling `on_tick(frame_offset, tick)`
+    /// for every tick in it; returns the number of ticks
+    pub fn process(
+        &mut self,
+        frames: usize,
+        sample_rate: f64,
+        mut on_tick: impl FnMut(usize, u64),
+    ) -> usize {
+        if !self.playing {
+            return 0;
+        }
+        let tick_frames = clock_tick_samples(self.bpm, sample_rate).max(1.0);
+        let mut count = 0;
+        while self.until_tick < frames as f64 {
+            let offset = self.until_tick.max(0.0) as usize;
+            self.send(MIDI_CLOCK);
+            on_tick(offset, self.ticks);
+            self.ticks += 1;
+            self.until_tick += tick_frames;
+            count += 1;
+        }
+        self.until_tick -= frames as f64;
+        count
+    }
+
+    fn send(&mut self, status: u8) {
+        if let Some(output) = &mut self.output {
+            output(status);
+        }
+    }
+}
+
+impl Default for ClockMaster {
+    fn default() -> Self {
+        Self::new(DEFAULT_MASTER_BPM)
+    }
+}
+
+impl std::fmt::Debug for ClockMaster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClockMaster")
+            .field("bpm", &self.bpm)
+            .field("playing", &self.playing)
+            .field("ticks", &self.ticks)
+            .field("output", &self.output.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tempo_estimate_tracks_a_jittery_clock() {
+        let mut clock = ClockFollower::new();
+        assert_eq!(clock.bpm(), None);
+        // 120 BPM is a tick every 20833 µs; alternate ±1 ms of jitter
+        let mut time = 0;
+        for i in 0..200 {
+            let jitter = if i % 2 == 0 { 1000 } else { -1000 };
+            clock.tick((time + jitter) as u64);
+            time += 20_833;
+        }
+        let bpm = clock.bpm().unwrap();
+        assert!((bpm - 120.0).abs() < 1.0, "{}", bpm);
+        assert!(clock.has_clock(time as u64));
+        assert!(!clock.has_clock(time as u64 + 1_000_000));
+    }
+
+    #[test]
+    fn master_ticks_at_its_tempo_and_drives_a_follower() {
+        use std::sync::{Arc, Mutex};
+
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let mut master = ClockMaster::new(125.0); // A tick every 960 frames at 48 kHz
+        let log = sent.clone();
+        master.set_output(Some(Box::new(move |status| {
+            log.lock().unwrap().push(status)
+        })));
+        assert_eq!(master.process(512, 48000.0, |_, _| {}), 0); // Not started
+
+        master.start();
+        let mut offsets = Vec::new();
+        let mut follower = ClockFollower::new();
+        let mut frame = 0;
+        for _ in 0..15 {
+            master.process(512, 48000.0, |offset, tick| {
+                offsets.push((offset, tick));
+                follower.tick((frame + offset) as u64 * 1_000_000 / 48000);
+            });
+            frame += 512;
+        }
+        assert_eq!(offsets[..3], [(0, 0), (448, 1), (384, 2)]);
+        assert_eq!(master.ticks(), 8);
+        assert!((follower.bpm().unwrap() - 125.0).abs() < 0.1);
+
+        master.stop();
+        master.resume();
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[0], MIDI_START);
+        assert_eq!(sent.iter().filter(|&&s| s == MIDI_CLOCK).count(), 8);
+        assert_eq!(sent[sent.len() - 2..], [MIDI_STOP, MIDI_CONTINUE]);
+    }
+
+    #[test]
+    fn transport_follows_start_stop_and_song_position() {
+        let mut clock = ClockFollower::new();
+        clock.tick(0); // Clock runs while stopped
+        assert_eq!(clock.ticks(), 0);
+
+        assert_eq!(
+            clock.handle_message(&[MIDI_START], 10),
+            Some(TransportEvent::Started)
+        );
+        for i in 1..=24 {
+            clock.handle_message(&[MIDI_CLOCK], i * 20_000);
+        }
+        assert_eq!(clock.transport().beats(), 1.0);
+
+        clock.handle_message(&[MIDI_STOP], 500_000);
+        clock.handle_message(&[MIDI_SONG_POSITION, 8, 0], 500_000); // Bar 1, beat 3
+        assert_eq!(clock.ticks(), 48);
+        clock.handle_message(&[MIDI_CONTINUE], 510_000);
+        clock.handle_message(&[MIDI_CLOCK], 520_000);
+        assert!(clock.is_playing());
+        assert_eq!(clock.ticks(), 49);
+        assert_eq!(clock.handle_message(&[0x90, 60, 100], 0), None);
+    }
+}
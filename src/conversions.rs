@@ -1,22 +1,429 @@
 //! MIDI note and parameter conversions
 
-/// Convert MIDI note number to frequency in Hz
+use crate::midi_input::MidiEvent;
+use crate::rpn::{ParameterChange, ParameterNumber, RPN_PITCH_BEND_SENSITIVITY};
+use crate::scala::{KeyboardMapping, Scale};
+use std::path::Path;
+
+/// Standard concert pitch for A4 in Hz
+pub const CONCERT_A4: f32 = 440.0;
+
+/// MIDI note number of A4
+pub const A4_NOTE: u8 = 69;
+
+/// Maps MIDI notes to frequencies: equal temperament relative to a reference
+/// note and pitch (12-TET at A4 by default), or an arbitrary scale loaded
+/// from Scala files
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "TuningPreset", into = "TuningPreset")
+)]
+pub struct Tuning {
+    reference_pitch: f32,
+    reference_note: u8,
+    divisions: u16,                         // Equal steps per octave
+    table: Option<Box<[Option<f32>; 128]>>, // Per-note frequencies for scale tunings
+}
+
+impl Tuning {
+    /// Create a tuning with A4 at `reference_pitch` Hz (e.g. 432, 442, 443)
+    pub fn new(reference_pitch: f32) -> Self {
+        Self::equal_temperament(12, A4_NOTE, reference_pitch)
+    }
+
+    /// Equal division of the octave into `divisions` steps (e.g. 19, 24, 31),
+    /// one step per MIDI note, with `reference_note` at `reference_pitch` Hz
+    pub fn equal_temperament(divisions: u16, reference_note: u8, reference_pitch: f32) -> Self {
+        Self {
+            reference_pitch,
+            reference_note,
+            divisions: divisions.max(1),
+            table: None,
+        }
+    }
+
+    /// Standard A4 = 440 Hz tuning
+    pub const fn concert() -> Self {
+        Self {
+            reference_pitch: CONCERT_A4,
+            reference_note: A4_NOTE,
+            divisions: 12,
+            table: None,
+        }
+    }
+
+    /// Tune notes through `scale`, laid out on the keyboard by `mapping`
+    pub fn from_scale(scale: &Scale, mapping: &KeyboardMapping) -> Self {
+        Self {
+            reference_pitch: mapping.reference_freq() as f32,
+            reference_note: mapping.reference_note(),
+            divisions: scale.len() as u16,
+            table: Some(Box::new(mapping.frequencies(scale))),
+        }
+    }
+
+    /// Load a Scala `.scl` file, with an optional `.kbm` keyboard mapping
+    /// (default: root on middle C, A4 = 440 Hz)
+    pub fn load_scala(
+        scale_path: impl AsRef<Path>,
+        mapping_path: Option<impl AsRef<Path>>,
+    ) -> anyhow::Result<Self> {
+        let scale = Scale::load(scale_path)?;
+        let mapping = match mapping_path {
+            Some(path) => KeyboardMapping::load(path)?,
+            None => KeyboardMapping::default(),
+        };
+        Ok(Self::from_scale(&scale, &mapping))
+    }
+
+    /// Check whether this tuning uses a scale rather than equal temperament
+    pub fn is_microtonal(&self) -> bool {
+        self.table.is_some()
+    }
+
+    /// Check whether `note` has a pitch (keyboard mappings can leave keys unmapped)
+    pub fn is_mapped(&self, note: u8) -> bool {
+        self.table_freq(note as usize).is_some()
+    }
+
+    /// Notes per octave (per period for scale tunings)
+    pub fn divisions(&self) -> u16 {
+        self.divisions
+    }
+
+    /// Note that sounds at the reference pitch
+    pub fn reference_note(&self) -> u8 {
+        self.reference_note
+    }
+
+    /// Frequency of the reference note
+    pub fn reference_pitch(&self) -> f32 {
+        self.reference_pitch
+    }
+
+    /// Move the reference pitch, transposing scale tunings with it
+    pub fn set_reference_pitch(&mut self, reference_pitch: f32) {
+        if let Some(table) = &mut self.table {
+            let ratio = reference_pitch / self.reference_pitch;
+            for freq in table.iter_mut().flatten() {
+                *freq *= ratio;
+            }
+        }
+        self.reference_pitch = reference_pitch;
+    }
+
+    /// Convert MIDI note number to frequency in Hz
+    /// Formula (N-TET): reference * 2^((note - reference_note) / N)
+    /// Unmapped keys return 0 Hz; check `is_mapped` to skip them
+    pub fn note_to_freq(&self, note: u8) -> f32 {
+        self.fractional_note_to_freq(note as f32)
+    }
+
+    /// Convert a fractional note number (e.g. note plus bend in steps)
+    /// to frequency in Hz. Scale tunings glide between neighbouring keys.
+    pub fn fractional_note_to_freq(&self, note: f32) -> f32 {
+        if self.table.is_none() {
+            let steps = note - self.reference_note as f32;
+            return self.reference_pitch * 2.0_f32.powf(steps / self.divisions as f32);
+        }
+        let note = note.clamp(0.0, 127.0);
+        let low = note.floor() as usize;
+        let fraction = note - low as f32;
+        match (self.table_freq(low), self.table_freq(low + 1)) {
+            (Some(a), Some(b)) if fraction > 0.0 => a * (b / a).powf(fraction),
+            (Some(a), _) => a,
+            _ => 0.0,
+        }
+    }
+
+    /// Convert frequency in Hz to a fractional note number
+    pub fn freq_to_note(&self, freq: f32) -> f32 {
+        let Some(table) = &self.table else {
+            let octaves = (freq / self.reference_pitch).log2();
+            return self.reference_note as f32 + self.divisions as f32 * octaves;
+        };
+        let mut mapped = table
+            .iter()
+            .enumerate()
+            .filter_map(|(note, freq)| freq.map(|freq| (note as f32, freq)));
+        let Some(mut below) = mapped.next() else {
+            return A4_NOTE as f32;
+        };
+        if freq <= below.1 {
+            return below.0;
+        }
+        for above in mapped {
+            if freq <= above.1 {
+                let fraction = (freq / below.1).log2() / (above.1 / below.1).log2();
+                return below.0 + fraction * (above.0 - below.0);
+            }
+            below = above;
+        }
+        below.0
+    }
+
+    fn table_freq(&self, note: usize) -> Option<f32> {
+        match &self.table {
+            Some(table) => table.get(note).copied().flatten(),
+            None => (note < 128).then(|| self.fractional_note_to_freq(note as f32)),
+        }
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::concert()
+    }
+}
+
+/// Serialized form of a [`Tuning`]; scale tunings keep their note table
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TuningPreset {
+    reference_pitch: f32,
+    reference_note: u8,
+    divisions: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    table: Option<Vec<Option<f32>>>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<TuningPreset> for Tuning {
+    type Error = String;
+
+    fn try_from(preset: TuningPreset) -> Result<Self, String> {
+        let table = match preset.table {
+            Some(table) => {
+                let len = table.len();
+                let table: [Option<f32>; 128] = table
+                    .try_into()
+                    .map_err(|_| format!("tuning table has {len} notes, expected 128"))?;
+                Some(Box::new(table))
+            }
+            None => None,
+        };
+        Ok(Self {
+            reference_pitch: preset.reference_pitch,
+            reference_note: preset.reference_note,
+            divisions: preset.divisions.max(1),
+            table,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Tuning> for TuningPreset {
+    fn from(tuning: Tuning) -> Self {
+        Self {
+            reference_pitch: tuning.reference_pitch,
+            reference_note: tuning.reference_note,
+            divisions: tuning.divisions,
+            table: tuning.table.map(|table| table.to_vec()),
+        }
+    }
+}
+
+/// Convert MIDI note number to frequency in Hz at standard concert pitch
 /// Formula: 440.0 * 2^((note - 69) / 12.0)
 pub fn note_to_freq(note: u8) -> f32 {
-    440.0 * 2.0_f32.powf((note as f32 - 69.0) / 12.0)
+    Tuning::concert().note_to_freq(note)
+}
+
+/// Convert a MIDI note plus a detune in cents to frequency in Hz at
+/// standard concert pitch
+pub fn note_with_cents_to_freq(note: u8, cents: f32) -> f32 {
+    Tuning::concert().fractional_note_to_freq(note as f32 + cents / 100.0)
+}
+
+/// Map a normalized 0.0-1.0 control to a frequency between `min_hz` and
+/// `max_hz` on a logarithmic scale, so equal control travel covers equal
+/// musical intervals (e.g. a CC sweeping a filter cutoff)
+/// Formula: min * (max / min)^norm
+pub fn normalized_to_freq(norm: f32, min_hz: f32, max_hz: f32) -> f32 {
+    min_hz * (max_hz / min_hz).powf(norm.clamp(0.0, 1.0))
+}
+
+/// Inverse of `normalized_to_freq`; frequencies outside the range clamp to 0.0/1.0
+pub fn freq_to_normalized(freq: f32, min_hz: f32, max_hz: f32) -> f32 {
+    ((freq / min_hz).ln() / (max_hz / min_hz).ln()).clamp(0.0, 1.0)
+}
+
+/// Convert an interval in cents to a frequency ratio
+/// Formula: 2^(cents / 1200)
+pub fn cents_to_ratio(cents: f32) -> f32 {
+    2.0_f32.powf(cents / 1200.0)
+}
+
+/// Convert a frequency ratio to an interval in cents
+/// Formula: 1200 * log2(ratio)
+pub fn ratio_to_cents(ratio: f32) -> f32 {
+    1200.0 * ratio.log2()
 }
 
 /// Convert MIDI velocity to linear gain
 /// Formula: (velocity / 127)^2 for natural feel
 pub fn velocity_to_gain(velocity: u8) -> f32 {
-    (velocity as f32 / 127.0).powf(2.0)
+    VelocityCurve::Squared.gain(velocity)
+}
+
+/// Convert decibels to linear gain
+/// Formula: 10^(db / 20)
+pub fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+/// Convert linear gain to decibels (silence is negative infinity)
+/// Formula: 20 * log10(gain)
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.abs().log10()
+}
+
+/// Convert MIDI velocity to decibels through the default squared curve
+/// (127 = 0 dB, 0 = negative infinity)
+pub fn velocity_to_db(velocity: u8) -> f32 {
+    gain_to_db(velocity_to_gain(velocity))
+}
+
+/// Response curve from MIDI velocity to linear gain
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VelocityCurve {
+    /// Gain proportional to velocity
+    Linear,
+    /// Square root: louder at low velocities, for heavy keybeds
+    Soft,
+    /// Cubic: quieter at low velocities, for light keybeds
+    Hard,
+    /// (velocity / 127)^2
+    #[default]
+    Squared,
+    /// Gains at evenly spaced velocities from 0 to 127, linearly interpolated
+    /// (e.g. `vec![0.0, 0.5, 1.0]` puts velocity 64 at about half gain)
+    Custom(Vec<f32>),
+}
+
+impl VelocityCurve {
+    /// Convert MIDI velocity to linear gain through this curve
+    pub fn gain(&self, velocity: u8) -> f32 {
+        let x = velocity.min(127) as f32 / 127.0;
+        match self {
+            VelocityCurve::Linear => x,
+            VelocityCurve::Soft => x.sqrt(),
+            VelocityCurve::Hard => x * x * x,
+            VelocityCurve::Squared => x * x,
+            VelocityCurve::Custom(table) => match table.len() {
+                0 => x,
+                1 => table[0],
+                len => {
+                    let position = x * (len - 1) as f32;
+                    let index = (position as usize).min(len - 2);
+                    let fraction = position - index as f32;
+                    table[index] + (table[index + 1] - table[index]) * fraction
+                }
+            },
+        }
+    }
 }
 
 /// Convert MIDI pitch bend to frequency ratio
 /// Range: ±2 semitones (8192 = center, 0 = -2, 16383 = +2)
 pub fn pitch_bend_to_ratio(bend: i16) -> f32 {
-    let semitones = ((bend - 8192) as f32 / 8192.0) * 2.0;
-    2.0_f32.powf(semitones / 12.0)
+    pitch_bend_to_ratio_with_range(bend, DEFAULT_BEND_RANGE)
+}
+
+/// Convert MIDI pitch bend to frequency ratio with a bend range of
+/// ±`semitones` (e.g. 12 or 48)
+pub fn pitch_bend_to_ratio_with_range(bend: i16, semitones: f32) -> f32 {
+    2.0_f32.powf(pitch_bend_to_semitones(bend, semitones) / 12.0)
+}
+
+fn pitch_bend_to_semitones(bend: i16, range: f32) -> f32 {
+    ((bend - 8192) as f32 / 8192.0) * range
+}
+
+/// Default pitch bend range in semitones
+pub const DEFAULT_BEND_RANGE: f32 = 2.0;
+
+/// Centre (no bend) position of the 14-bit pitch wheel
+pub const PITCH_BEND_CENTER: i16 = 8192;
+
+/// Tracks the pitch wheel and its configured range for one channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchBendState {
+    bend: i16,
+    range: f32, // Semitones
+}
+
+impl PitchBendState {
+    /// Create a centred pitch wheel with a range of ±`semitones`
+    pub fn new(semitones: f32) -> Self {
+        Self {
+            bend: PITCH_BEND_CENTER,
+            range: semitones,
+        }
+    }
+
+    pub fn range(&self) -> f32 {
+        self.range
+    }
+
+    pub fn set_range(&mut self, semitones: f32) {
+        self.range = semitones;
+    }
+
+    /// Raw 14-bit bend value
+    pub fn bend(&self) -> i16 {
+        self.bend
+    }
+
+    pub fn set_bend(&mut self, bend: i16) {
+        self.bend = bend.clamp(0, 16383);
+    }
+
+    /// Feed an event; pitch bend messages update the wheel position
+    /// Returns true if the event was a pitch bend
+    pub fn handle_event(&mut self, event: &MidiEvent) -> bool {
+        match *event {
+            MidiEvent::PitchBend(bend) => {
+                self.set_bend(bend);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Apply a pitch bend sensitivity RPN (MSB semitones, LSB cents)
+    /// Returns true if the change set the range
+    pub fn handle_parameter_change(&mut self, change: &ParameterChange) -> bool {
+        if change.number != ParameterNumber::Registered(RPN_PITCH_BEND_SENSITIVITY) {
+            return false;
+        }
+        self.range = change.msb() as f32 + change.lsb() as f32 / 100.0;
+        true
+    }
+
+    /// Current bend in semitones
+    pub fn semitones(&self) -> f32 {
+        pitch_bend_to_semitones(self.bend, self.range)
+    }
+
+    /// Current bend as a frequency ratio
+    pub fn ratio(&self) -> f32 {
+        pitch_bend_to_ratio_with_range(self.bend, self.range)
+    }
+
+    /// Re-centre the wheel, keeping the range
+    pub fn reset(&mut self) {
+        self.bend = PITCH_BEND_CENTER;
+    }
+}
+
+impl Default for PitchBendState {
+    fn default() -> Self {
+        Self::new(DEFAULT_BEND_RANGE)
+    }
 }
 
 #[cfg(test)]
@@ -29,6 +436,31 @@ mod tests {
         assert!((note_to_freq(69) - 440.0).abs() < 0.01);
     }
 
+    #[test]
+    fn reference_pitch_moves_every_note() {
+        let tuning = Tuning::new(432.0);
+        assert!((tuning.note_to_freq(69) - 432.0).abs() < 0.01);
+        assert!((tuning.note_to_freq(81) - 864.0).abs() < 0.01);
+        assert!((tuning.freq_to_note(432.0) - 69.0).abs() < 1e-4);
+        assert_eq!(Tuning::default().reference_pitch(), 440.0);
+    }
+
+    #[test]
+    fn quarter_tone_temperament() {
+        let tuning = Tuning::equal_temperament(24, 69, 440.0);
+        assert!((tuning.note_to_freq(93) - 880.0).abs() < 0.01);
+        assert!((tuning.note_to_freq(70) - note_with_cents_to_freq(69, 50.0)).abs() < 0.01);
+        assert_eq!(tuning.divisions(), 24);
+        assert!((tuning.freq_to_note(880.0) - 93.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn cents_math() {
+        assert!((cents_to_ratio(1200.0) - 2.0).abs() < 1e-6);
+        assert!((ratio_to_cents(1.5) - 701.955).abs() < 1e-3);
+        assert!((note_with_cents_to_freq(69, 100.0) - note_to_freq(70)).abs() < 0.01);
+    }
+
     #[test]
     fn velocity_gain_curve() {
         // Velocity 0 should be silent
@@ -39,12 +471,63 @@ mod tests {
         assert!((velocity_to_gain(64) - 0.25).abs() < 0.01);
     }
 
+    #[test]
+    fn velocity_curves_order_at_mid_velocity() {
+        let soft = VelocityCurve::Soft.gain(64);
+        let linear = VelocityCurve::Linear.gain(64);
+        let hard = VelocityCurve::Hard.gain(64);
+        assert!(soft > linear && linear > hard);
+        assert_eq!(VelocityCurve::default().gain(64), velocity_to_gain(64));
+
+        let custom = VelocityCurve::Custom(vec![0.2, 0.6, 1.0]);
+        assert!((custom.gain(0) - 0.2).abs() < 1e-6);
+        assert!((custom.gain(127) - 1.0).abs() < 1e-6);
+        assert!((custom.gain(96) - 0.8).abs() < 0.01);
+    }
+
+    #[test]
+    fn decibel_conversions() {
+        assert_eq!(db_to_gain(0.0), 1.0);
+        assert!((db_to_gain(-6.0206) - 0.5).abs() < 1e-4);
+        assert!((gain_to_db(0.1) + 20.0).abs() < 1e-4);
+        assert_eq!(gain_to_db(0.0), f32::NEG_INFINITY);
+        assert_eq!(velocity_to_db(127), 0.0);
+        assert!(velocity_to_db(64) < -5.0);
+    }
+
+    #[test]
+    fn normalized_frequency_is_logarithmic() {
+        assert_eq!(normalized_to_freq(0.0, 20.0, 20000.0), 20.0);
+        assert!((normalized_to_freq(1.0, 20.0, 20000.0) - 20000.0).abs() < 0.1);
+        // Halfway covers half the octaves: geometric mean
+        assert!((normalized_to_freq(0.5, 100.0, 10000.0) - 1000.0).abs() < 0.1);
+        assert!((freq_to_normalized(1000.0, 100.0, 10000.0) - 0.5).abs() < 1e-5);
+        assert_eq!(freq_to_normalized(10.0, 100.0, 10000.0), 0.0);
+    }
+
     #[test]
     fn pitch_bend_neutral() {
         // Center position (8192) should be ratio 1.0
         assert!((pitch_bend_to_ratio(8192) - 1.0).abs() < 0.01);
     }
 
+    #[test]
+    fn bend_state_follows_wheel_and_rpn() {
+        let mut state = PitchBendState::default();
+        assert_eq!(state.ratio(), 1.0);
+        assert!(state.handle_event(&MidiEvent::PitchBend(0)));
+        assert!((state.semitones() + 2.0).abs() < 1e-6);
+
+        let change = ParameterChange {
+            number: ParameterNumber::Registered(RPN_PITCH_BEND_SENSITIVITY),
+            value: 12 << 7,
+        };
+        assert!(state.handle_parameter_change(&change));
+        assert_eq!(state.range(), 12.0);
+        assert!((state.ratio() - 0.5).abs() < 1e-6);
+        assert!(!state.handle_event(&MidiEvent::NoteOn(60, 100)));
+    }
+
     #[test]
     fn pitch_bend_range() {
         // Minimum (0) should be -2 semitones
@@ -0,0 +1,147 @@
+//! Deterministic per-voice analog drift
+
+use crate::voice_allocator::VoiceId;
+
+/// Default maximum drift either side of the true pitch, in cents
+pub const DEFAULT_DRIFT_CENTS: f32 = 3.0;
+
+/// Default rate at which the drift picks a new point to wander towards
+pub const DEFAULT_DRIFT_RATE_HZ: f32 = 0.5;
+
+/// Slowly wandering pitch offset that keeps stacked voices from phase-locking
+///
+/// Each voice gets a fixed detune plus a smooth random walk, both drawn from a
+/// small PRNG seeded by the voice handle, so the same performance always
+/// drifts the same way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VoiceDrift {
+    rng: u32,
+    amount_cents: f32,
+    rate_hz: f32,
+    detune: f32, // Fixed offset, cents
+    current: f32,
+    target: f32,
+    remaining: u32, // Samples until `target` is reached
+}
+
+impl VoiceDrift {
+    /// Create a drift generator wandering up to `amount_cents` either side,
+    /// picking a new destination `rate_hz` times per second
+    pub fn new(seed: u32, amount_cents: f32, rate_hz: f32) -> Self {
+        let mut drift = Self {
+            rng: seed | 1, // xorshift state must be non-zero
+            amount_cents,
+            rate_hz,
+            detune: 0.0,
+            current: 0.0,
+            target: 0.0,
+            remaining: 0,
+        };
+        drift.detune = drift.random() * amount_cents * 0.5;
+        drift.current = drift.random() * amount_cents * 0.5;
+        drift.target = drift.current;
+        drift
+    }
+
+    /// A generator that never drifts
+    pub fn disabled() -> Self {
+        Self::new(1, 0.0, 0.0)
+    }
+
+    /// Create a drift generator seeded from a voice handle
+    pub fn for_voice(voice_id: VoiceId, amount_cents: f32, rate_hz: f32) -> Self {
+        Self::new(seed_for(voice_id), amount_cents, rate_hz)
+    }
+
+    pub fn amount_cents(&self) -> f32 {
+        self.amount_cents
+    }
+
+    pub fn rate_hz(&self) -> f32 {
+        self.rate_hz
+    }
+
+    /// Current offset in cents (fixed detune plus wander)
+    pub fn cents(&self) -> f32 {
+        (self.detune + self.current).clamp(-self.amount_cents, self.amount_cents)
+    }
+
+    /// Current offset as a frequency ratio
+    pub fn ratio(&self) -> f32 {
+        2.0_f32.powf(self.cents() / 1200.0)
+    }
+
+    /// Advance by `samples` (1 per sample, or the block length per block)
+    /// and return the offset in cents
+    pub fn advance(&mut self, samples: u32, sample_rate: f32) -> f32 {
+        let mut samples = samples;
+        while samples > 0 && self.amount_cents != 0.0 {
+            if self.remaining == 0 {
+                self.target = self.random() * self.amount_cents * 0.5;
+                let period = sample_rate / self.rate_hz.max(f32::EPSILON);
+                self.remaining = (period as u32).max(1);
+            }
+            let step = samples.min(self.remaining);
+            self.current += (self.target - self.current) * step as f32 / self.remaining as f32;
+            self.remaining -= step;
+            samples -= step;
+        }
+        self.cents()
+    }
+
+    /// Uniform value in -1.0..1.0 (xorshift32)
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
+
+impl Default for VoiceDrift {
+    fn default() -> Self {
+        Self::new(1, DEFAULT_DRIFT_CENTS, DEFAULT_DRIFT_RATE_HZ)
+    }
+}
+
+/// Mix a voice handle into a well-spread PRNG seed
+fn seed_for(voice_id: VoiceId) -> u32 {
+    let mut x = (voice_id.0 as u32).wrapping_mul(0x9E37_79B9) ^ voice_id.1;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 13;
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drift_is_deterministic_per_voice() {
+        let mut a = VoiceDrift::for_voice(VoiceId(0, 1), 5.0, 2.0);
+        let mut b = VoiceDrift::for_voice(VoiceId(0, 1), 5.0, 2.0);
+        let mut other = VoiceDrift::for_voice(VoiceId(1, 1), 5.0, 2.0);
+        let mut differs = false;
+        for _ in 0..100 {
+            let cents = a.advance(441, 44100.0);
+            assert_eq!(cents, b.advance(441, 44100.0));
+            differs |= cents != other.advance(441, 44100.0);
+            assert!(cents.abs() <= 5.0);
+        }
+        assert!(differs);
+    }
+
+    #[test]
+    fn drift_moves_smoothly() {
+        let mut drift = VoiceDrift::new(42, 10.0, 1.0);
+        let mut last = drift.cents();
+        for _ in 0..4410 {
+            let cents = drift.advance(1, 44100.0);
+            assert!((cents - last).abs() < 0.01);
+            last = cents;
+        }
+        assert_eq!(VoiceDrift::new(42, 0.0, 1.0).advance(100, 44100.0), 0.0);
+    }
+}
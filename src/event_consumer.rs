@@ -0,0 +1,166 @@
+//! Audio-thread MIDI event consumption
+//!
+//! [`MidiEventConsumer`] is the only MIDI type meant to be touched from the
+//! audio callback. It reads a fixed-size lock-free ring filled by a
+//! [`MidiEventProducer`] on the MIDI thread; draining never allocates,
+//! locks or blocks, and the consumer holds no `Vec` or mutex that could.
+//! Timestamps are sample frames on the audio clock, so each event can be
+//! applied at its offset within the block.
+
+use crate::midi_input::ChannelEvent;
+use crate::scheduler::TimedEvent;
+use rtrb::{Consumer, Producer, RingBuffer};
+
+/// Default number of events the ring between the threads can hold
+pub const DEFAULT_EVENT_QUEUE_CAPACITY: usize = 512;
+
+/// MIDI-thread side: pushes timestamped events for the audio thread
+#[derive(Debug)]
+pub struct MidiEventProducer {
+    producer: Producer<TimedEvent>,
+    dropped: usize,
+}
+
+/// Audio-thread side: drains events due before the end of each block
+///
+/// Events must be pushed in time order; an event stamped before the block
+/// being drained is delivered at offset 0 rather than lost.
+#[derive(Debug)]
+pub struct MidiEventConsumer {
+    consumer: Consumer<TimedEvent>,
+}
+
+/// Create a producer/consumer pair holding up to `capacity` events
+pub fn midi_event_queue(capacity: usize) -> (MidiEventProducer, MidiEventConsumer) {
+    let (producer, consumer) = RingBuffer::new(capacity);
+    (
+        MidiEventProducer {
+            producer,
+            dropped: 0,
+        },
+        MidiEventConsumer { consumer },
+    )
+}
+
+impl MidiEventProducer {
+    /// Queue `event` for sample frame `time`; returns false (and counts a
+    /// drop) when the audio thread has fallen behind and the ring is full
+    pub fn push(&mut self, time: u64, event: ChannelEvent) -> bool {
+        match self.producer.push(TimedEvent { time, event }) {
+            Ok(()) => true,
+            Err(_) => {
+                self.dropped += 1;
+                false
+            }
+        }
+    }
+
+    /// Events refused because the ring was full
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Free slots in the ring
+    pub fn free(&self) -> usize {
+        self.producer.slots()
+    }
+}
+
+impl MidiEventConsumer {
+    /// Events waiting in the ring, due or not
+    pub fn pending(&self) -> usize {
+        self.consumer.slots()
+    }
+
+    /// Timestamp of the next event
+    pub fn next_time(&self) -> Option<u64> {
+        self.consumer.peek().ok().map(|next| next.time)
+    }
+
+    /// Hand every event stamped before `block_end` to `handle`, in order
+    /// Returns the number of events delivered.
+    pub fn drain_until(&mut self, block_end: u64, mut handle: impl FnMut(TimedEvent)) -> usize {
+        let mut delivered = 0;
+        while self.next_time().is_some_and(|time| time < block_end) {
+            if let Ok(event) = self.consumer.pop() {
+                handle(event);
+                delivered += 1;
+            }
+        }
+        delivered
+    }
+
+    /// Drain the block of `frames` samples starting at `block_start`,
+    /// passing each event with its sample offset into the block
+    pub fn drain_block(
+        &mut self,
+        block_start: u64,
+        frames: usize,
+        mut handle: impl FnMut(usize, ChannelEvent),
+    ) -> usize {
+        self.drain_until(block_start + frames as u64, |due| {
+            let offset = due.time.saturating_sub(block_start) as usize;
+            handle(offset, due.event)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_input::MidiEvent;
+
+    fn note(note: u8) -> ChannelEvent {
+        ChannelEvent {
+            channel: 0,
+            event: MidiEvent::NoteOn(note, 100),
+        }
+    }
+
+    #[test]
+    fn events_are_split_across_blocks_with_offsets() {
+        let (mut producer, mut consumer) = midi_event_queue(8);
+        producer.push(10, note(60));
+        producer.push(70, note(62));
+        producer.push(128, note(64));
+
+        let mut seen = Vec::new();
+        assert_eq!(
+            consumer.drain_block(0, 64, |offset, e| seen.push((offset, e))),
+            1
+        );
+        assert_eq!(
+            consumer.drain_block(64, 64, |offset, e| seen.push((offset, e))),
+            1
+        );
+        assert_eq!(seen, [(10, note(60)), (6, note(62))]);
+        assert_eq!(consumer.next_time(), Some(128));
+
+        // A late event lands at the start of the next block
+        let mut late = Vec::new();
+        consumer.drain_block(200, 64, |offset, e| late.push((offset, e)));
+        assert_eq!(late, [(0, note(64))]);
+        assert_eq!(consumer.pending(), 0);
+    }
+
+    #[test]
+    fn full_ring_drops_instead_of_blocking() {
+        let (mut producer, _consumer) = midi_event_queue(1);
+        assert!(producer.push(0, note(60)));
+        assert!(!producer.push(1, note(61)));
+        assert_eq!(producer.dropped(), 1);
+        assert_eq!(producer.free(), 0);
+    }
+
+    #[test]
+    fn consumer_runs_on_another_thread() {
+        let (mut producer, mut consumer) = midi_event_queue(DEFAULT_EVENT_QUEUE_CAPACITY);
+        for time in 0..100 {
+            producer.push(time, note(60));
+        }
+        let delivered = std::thread::spawn(move || consumer.drain_until(50, |_| {}))
+            .join()
+            .unwrap();
+        assert_eq!(delivered, 50);
+    }
+}
@@ -0,0 +1,94 @@
+//! Expression (CC11) and breath (CC2) as channel output gain
+//!
+//! The default `CCMap` maps CC11 to [`ParamTarget::Expression`] and CC2 to
+//! [`ParamTarget::Breath`]; [`ChannelGain`] multiplies whatever values those
+//! targets receive into one gain for the channel. Remap either controller
+//! to another target to take it out of the gain, or map another controller
+//! (e.g. aftertouch via CC) to one of them to put it in.
+
+use crate::cc_mapping::ParamTarget;
+
+/// Breath controller number
+pub const CC_BREATH: u8 = 2;
+/// Expression pedal controller number
+pub const CC_EXPRESSION: u8 = 11;
+
+/// Output gain of one channel from its expression and breath levels
+///
+/// Both start fully open, so a channel that never receives them plays at
+/// full level.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelGain {
+    expression: f32,
+    breath: f32,
+}
+
+impl ChannelGain {
+    pub fn new() -> Self {
+        Self {
+            expression: 1.0,
+            breath: 1.0,
+        }
+    }
+
+    /// Feed a mapped parameter value (0.0-1.0)
+    /// Returns true if `target` is expression or breath.
+    pub fn handle_param(&mut self, target: ParamTarget, value: f32) -> bool {
+        let value = value.clamp(0.0, 1.0);
+        match target {
+            ParamTarget::Expression => self.expression = value,
+            ParamTarget::Breath => self.breath = value,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn expression(&self) -> f32 {
+        self.expression
+    }
+
+    pub fn breath(&self) -> f32 {
+        self.breath
+    }
+
+    /// Linear gain to apply to the channel's output
+    pub fn gain(&self) -> f32 {
+        self.expression * self.breath
+    }
+
+    /// Open both fully
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for ChannelGain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cc_mapping::CCMap;
+
+    #[test]
+    fn default_map_feeds_the_gain() {
+        let mut map = CCMap::new();
+        let mut gain = ChannelGain::new();
+        assert_eq!(gain.gain(), 1.0);
+
+        for (cc, value) in [(CC_EXPRESSION, 127), (CC_BREATH, 0), (1, 127)] {
+            if let Some((target, value)) = map.handle_cc(cc, value) {
+                gain.handle_param(target, value);
+            }
+        }
+        assert_eq!(gain.expression(), 1.0);
+        assert_eq!(gain.gain(), 0.0);
+        assert!(!gain.handle_param(ParamTarget::FilterCutoff, 0.5));
+
+        gain.reset();
+        assert_eq!(gain.breath(), 1.0);
+    }
+}
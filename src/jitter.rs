@@ -0,0 +1,118 @@
+//! Timestamp jitter smoothing
+//!
+//! MIDI callbacks are delivered in bursts when the OS scheduler gets
+//! around to the MIDI thread, so arrival times are lumpy even when the
+//! driver's own timestamps are evenly spaced. [`JitterFilter`] maps the
+//! driver timestamps onto the local clock with a fixed delay, restoring the
+//! original spacing at the cost of a bounded amount of added latency.
+
+use std::time::Duration;
+
+/// Default latency budget for absorbing jitter
+pub const DEFAULT_JITTER_BUDGET: Duration = Duration::from_millis(3);
+
+/// How quickly the clock offset drifts upwards, as a shift of the error
+const OFFSET_RELAX_SHIFT: u32 = 8;
+
+/// Re-times source timestamps onto a steady local timeline
+///
+/// The filter tracks the smallest observed transit time (local arrival
+/// minus source timestamp) and schedules each event at its source time
+/// plus that transit plus a fixed budget. Events delayed by less than the
+/// budget come out evenly spaced; later ones are released on arrival, so
+/// the added latency never exceeds the budget. Output times never go
+/// backwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JitterFilter {
+    budget: u64,         // µs
+    offset: Option<i64>, // Local minus source clock, µs
+    last: u64,           // Last output time
+}
+
+impl JitterFilter {
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget: budget.as_micros() as u64,
+            offset: None,
+            last: 0,
+        }
+    }
+
+    pub fn budget(&self) -> Duration {
+        Duration::from_micros(self.budget)
+    }
+
+    pub fn set_budget(&mut self, budget: Duration) {
+        self.budget = budget.as_micros() as u64;
+    }
+
+    /// Local time for an event stamped `source` by the driver that arrived
+    /// at local time `arrival` (both in µs)
+    pub fn retime(&mut self, source: u64, arrival: u64) -> u64 {
+        let transit = arrival as i64 - source as i64;
+        let offset = match self.offset {
+            Some(offset) if transit >= offset => {
+                // Creep up slowly so clock drift can't leave us too early
+                offset + ((transit - offset) >> OFFSET_RELAX_SHIFT)
+            }
+            _ => transit,
+        };
+        self.offset = Some(offset);
+
+        let steady = (source as i64 + offset).max(0) as u64 + self.budget;
+        let time = steady.clamp(arrival, arrival + self.budget).max(self.last);
+        self.last = time;
+        time
+    }
+
+    /// Forget the clock offset (e.g. after reconnecting a device)
+    pub fn reset(&mut self) {
+        self.offset = None;
+        self.last = 0;
+    }
+}
+
+impl Default for JitterFilter {
+    fn default() -> Self {
+        Self::new(DEFAULT_JITTER_BUDGET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursty_arrivals_are_spread_back_out() {
+        let mut filter = JitterFilter::new(Duration::from_micros(2000));
+        // Notes every 1 ms at the source; the third and fourth arrive together
+        let arrivals = [
+            (0, 500),
+            (1000, 1500),
+            (2000, 3900),
+            (3000, 3910),
+            (4000, 4520),
+        ];
+        let times: Vec<u64> = arrivals
+            .iter()
+            .map(|&(source, arrival)| filter.retime(source, arrival))
+            .collect();
+        assert_eq!(times[..2], [2500, 3500]);
+        for pair in times.windows(2) {
+            assert!((990..=1010).contains(&(pair[1] - pair[0])), "{:?}", times);
+        }
+    }
+
+    #[test]
+    fn added_latency_is_bounded() {
+        let mut filter = JitterFilter::new(Duration::from_micros(1000));
+        filter.retime(0, 100);
+        // Delayed far beyond the budget: released on arrival
+        assert_eq!(filter.retime(1000, 5000), 5000);
+        // Never earlier than arrival, never later than arrival + budget
+        let time = filter.retime(2000, 5100);
+        assert!((5100..=6100).contains(&time));
+        // Never backwards
+        assert!(filter.retime(2000, 2100) >= time);
+    }
+}
@@ -0,0 +1,138 @@
+//! Keyboard split zones: key ranges routed to separate voice allocators
+
+use crate::voice_allocator::{VoiceAllocator, VoiceId};
+use std::ops::RangeInclusive;
+
+/// A key range driving its own voice allocator
+#[derive(Debug)]
+pub struct KeyZone {
+    pub low: u8,
+    pub high: u8,
+    pub allocator: VoiceAllocator,
+}
+
+impl KeyZone {
+    pub fn new(keys: RangeInclusive<u8>, allocator: VoiceAllocator) -> Self {
+        Self {
+            low: *keys.start(),
+            high: *keys.end(),
+            allocator,
+        }
+    }
+
+    pub fn contains(&self, note: u8) -> bool {
+        (self.low..=self.high).contains(&note)
+    }
+}
+
+/// Splits the keyboard into zones, each with its own voice pool
+///
+/// Zones are checked in the order they were added; the first zone whose
+/// range contains the note wins, so overlapping ranges are allowed.
+#[derive(Debug, Default)]
+pub struct KeySplit {
+    zones: Vec<KeyZone>,
+}
+
+impl KeySplit {
+    pub fn new() -> Self {
+        Self { zones: Vec::new() }
+    }
+
+    /// Convenience for the classic two-way split: notes below `split_note`
+    /// go to the lower allocator, `split_note` and above to the upper one
+    /// A split at note 0 leaves no lower zone; the upper one is zone 0.
+    pub fn two_way(split_note: u8, lower: VoiceAllocator, upper: VoiceAllocator) -> Self {
+        let mut split = Self::new();
+        if let Some(highest_lower) = split_note.checked_sub(1) {
+            split.add_zone(0..=highest_lower, lower);
+        }
+        split.add_zone(split_note..=127, upper);
+        split
+    }
+
+    /// Add a zone, returning its index
+    pub fn add_zone(&mut self, keys: RangeInclusive<u8>, allocator: VoiceAllocator) -> usize {
+        self.zones.push(KeyZone::new(keys, allocator));
+        self.zones.len() - 1
+    }
+
+    pub fn zones(&self) -> &[KeyZone] {
+        &self.zones
+    }
+
+    pub fn zone(&self, index: usize) -> Option<&KeyZone> {
+        self.zones.get(index)
+    }
+
+    pub fn zone_mut(&mut self, index: usize) -> Option<&mut KeyZone> {
+        self.zones.get_mut(index)
+    }
+
+    /// Find the zone index responsible for a note
+    pub fn zone_for_note(&self, note: u8) -> Option<usize> {
+        self.zones.iter().position(|z| z.contains(note))
+    }
+
+    /// Allocate a voice in the zone covering the note
+    /// Returns the zone index and voice, or None if no zone covers the note
+    pub fn allocate_voice(&mut self, note: u8) -> Option<(usize, VoiceId)> {
+        let index = self.zone_for_note(note)?;
+        let voice_id = self.zones[index].allocator.allocate_voice(note)?;
+        Some((index, voice_id))
+    }
+
+    /// Release the voice playing a note in the zone covering it
+    pub fn release_voice(&mut self, note: u8) -> Option<(usize, VoiceId)> {
+        let index = self.zone_for_note(note)?;
+        let voice_id = self.zones[index].allocator.release_voice(note)?;
+        Some((index, voice_id))
+    }
+
+    /// Get the number of active voices across all zones
+    pub fn active_voice_count(&self) -> usize {
+        self.zones
+            .iter()
+            .map(|z| z.allocator.active_voice_count())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_way_split_routes_by_key() {
+        let mut split =
+            KeySplit::two_way(60, VoiceAllocator::with_voices(1), VoiceAllocator::new());
+
+        assert_eq!(split.allocate_voice(40).unwrap().0, 0);
+        assert_eq!(split.allocate_voice(59).unwrap().0, 0); // mono bass steals
+        assert_eq!(split.allocate_voice(60).unwrap().0, 1);
+        assert_eq!(split.allocate_voice(72).unwrap().0, 1);
+
+        assert_eq!(split.zone(0).unwrap().allocator.active_voice_count(), 1);
+        assert_eq!(split.zone(1).unwrap().allocator.active_voice_count(), 2);
+    }
+
+    #[test]
+    fn split_at_zero_has_no_lower_zone() {
+        let mut split = KeySplit::two_way(0, VoiceAllocator::new(), VoiceAllocator::new());
+        assert_eq!(split.zones().len(), 1);
+        assert_eq!(split.zone_for_note(0), Some(0));
+        assert_eq!(split.allocate_voice(0).unwrap().0, 0);
+        assert_eq!(split.zone(0).unwrap().low, 0);
+    }
+
+    #[test]
+    fn uncovered_notes_ignored() {
+        let mut split = KeySplit::new();
+        split.add_zone(36..=47, VoiceAllocator::new());
+
+        assert_eq!(split.allocate_voice(30), None);
+        let (zone, voice) = split.allocate_voice(36).unwrap();
+        assert_eq!(split.release_voice(36), Some((zone, voice)));
+        assert_eq!(split.active_voice_count(), 0);
+    }
+}
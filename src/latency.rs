@@ -0,0 +1,178 @@
+//! Note-on to audio latency measurement
+//!
+//! The MIDI thread marks when a note-on arrives and the audio thread marks
+//! the first block that reflects it; the difference is accumulated into
+//! min/average/max/jitter statistics. One note is timed at a time (notes
+//! arriving while one is in flight are skipped), which keeps both marks a
+//! single atomic operation: no locks, no allocation.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Summary of the latencies measured so far
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    /// Standard deviation of the measurements
+    pub jitter: Duration,
+}
+
+#[derive(Debug)]
+struct Accumulator {
+    epoch: Instant,
+    pending: AtomicU64, // Arrival time + 1 in µs since epoch, 0 when idle
+    count: AtomicU64,
+    sum: AtomicU64,    // µs
+    sum_sq: AtomicU64, // µs²
+    min: AtomicU64,
+    max: AtomicU64,
+}
+
+/// Shared latency probe; clone it into the MIDI and audio threads
+#[derive(Debug, Clone)]
+pub struct LatencyMonitor {
+    inner: Arc<Accumulator>,
+}
+
+impl LatencyMonitor {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Accumulator {
+                epoch: Instant::now(),
+                pending: AtomicU64::new(0),
+                count: AtomicU64::new(0),
+                sum: AtomicU64::new(0),
+                sum_sq: AtomicU64::new(0),
+                min: AtomicU64::new(u64::MAX),
+                max: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Microseconds since the monitor was created
+    pub fn now_micros(&self) -> u64 {
+        self.inner.epoch.elapsed().as_micros() as u64
+    }
+
+    /// Note-on arrival (MIDI thread); returns false if a note is already
+    /// being timed
+    pub fn mark_note_on(&self) -> bool {
+        self.mark_note_on_at(self.now_micros())
+    }
+
+    pub fn mark_note_on_at(&self, micros: u64) -> bool {
+        self.inner
+            .pending
+            .compare_exchange(0, micros + 1, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// First block reflecting the note (audio thread); returns the measured
+    /// latency, or None if no note was being timed
+    pub fn mark_audio(&self) -> Option<Duration> {
+        self.mark_audio_at(self.now_micros())
+    }
+
+    pub fn mark_audio_at(&self, micros: u64) -> Option<Duration> {
+        let arrived = self
+            .inner
+            .pending
+            .swap(0, Ordering::AcqRel)
+            .checked_sub(1)?;
+        let latency = micros.saturating_sub(arrived);
+        let inner = &self.inner;
+        inner.count.fetch_add(1, Ordering::Relaxed);
+        inner.sum.fetch_add(latency, Ordering::Relaxed);
+        inner
+            .sum_sq
+            .fetch_add(latency.saturating_mul(latency), Ordering::Relaxed);
+        inner.min.fetch_min(latency, Ordering::Relaxed);
+        inner.max.fetch_max(latency, Ordering::Relaxed);
+        Some(Duration::from_micros(latency))
+    }
+
+    /// True while a note-on is waiting for its audio mark
+    pub fn is_pending(&self) -> bool {
+        self.inner.pending.load(Ordering::Relaxed) != 0
+    }
+
+    pub fn stats(&self) -> LatencyStats {
+        let inner = &self.inner;
+        let count = inner.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return LatencyStats::default();
+        }
+        let mean = inner.sum.load(Ordering::Relaxed) as f64 / count as f64;
+        let mean_sq = inner.sum_sq.load(Ordering::Relaxed) as f64 / count as f64;
+        let jitter = (mean_sq - mean * mean).max(0.0).sqrt();
+        LatencyStats {
+            count,
+            min: Duration::from_micros(inner.min.load(Ordering::Relaxed)),
+            max: Duration::from_micros(inner.max.load(Ordering::Relaxed)),
+            mean: Duration::from_secs_f64(mean / 1e6),
+            jitter: Duration::from_secs_f64(jitter / 1e6),
+        }
+    }
+
+    /// Forget every measurement (e.g. after changing the block size)
+    pub fn reset(&self) {
+        let inner = &self.inner;
+        inner.pending.store(0, Ordering::Relaxed);
+        inner.count.store(0, Ordering::Relaxed);
+        inner.sum.store(0, Ordering::Relaxed);
+        inner.sum_sq.store(0, Ordering::Relaxed);
+        inner.min.store(u64::MAX, Ordering::Relaxed);
+        inner.max.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Default for LatencyMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_cover_min_mean_max_and_jitter() {
+        let monitor = LatencyMonitor::new();
+        assert_eq!(monitor.mark_audio_at(100), None);
+        for (arrival, latency) in [(0, 2000), (10_000, 4000), (20_000, 6000)] {
+            assert!(monitor.mark_note_on_at(arrival));
+            assert!(!monitor.mark_note_on_at(arrival + 1)); // Already timing one
+            assert_eq!(
+                monitor.mark_audio_at(arrival + latency),
+                Some(Duration::from_micros(latency))
+            );
+        }
+        let stats = monitor.stats();
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.min, Duration::from_micros(2000));
+        assert_eq!(stats.max, Duration::from_micros(6000));
+        assert_eq!(stats.mean, Duration::from_micros(4000));
+        // Population standard deviation of 2, 4 and 6 ms
+        assert!((stats.jitter.as_secs_f64() * 1e6 - 1632.99).abs() < 0.1);
+
+        monitor.reset();
+        assert_eq!(monitor.stats(), LatencyStats::default());
+    }
+
+    #[test]
+    fn marks_cross_threads() {
+        let monitor = LatencyMonitor::new();
+        let audio = monitor.clone();
+        assert!(monitor.mark_note_on());
+        let latency = std::thread::spawn(move || audio.mark_audio())
+            .join()
+            .unwrap();
+        assert!(latency.is_some());
+        assert!(!monitor.is_pending());
+    }
+}
@@ -0,0 +1,172 @@
+//! Layered allocation: one note-on allocates a voice in several groups
+
+use crate::voice_allocator::{VoiceAllocator, VoiceId};
+
+pub const MAX_LAYERS: usize = 8;
+
+/// Voices allocated (or released) across layers for a single note
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayeredVoices {
+    voices: [(usize, VoiceId); MAX_LAYERS], // Fixed size for RT-safety
+    len: usize,
+}
+
+impl LayeredVoices {
+    fn new() -> Self {
+        Self {
+            voices: [(0, VoiceId(0, 0)); MAX_LAYERS],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, layer: usize, voice_id: VoiceId) {
+        if self.len < MAX_LAYERS {
+            self.voices[self.len] = (layer, voice_id);
+            self.len += 1;
+        }
+    }
+
+    /// (layer index, voice) pairs
+    pub fn as_slice(&self) -> &[(usize, VoiceId)] {
+        &self.voices[..self.len]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(usize, VoiceId)> + '_ {
+        self.as_slice().iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[derive(Debug)]
+struct Layer {
+    allocator: VoiceAllocator,
+    enabled: bool,
+}
+
+/// Stacks several voice groups so each note sounds in all of them
+/// (e.g. saw + sub oscillator layers with separate voice budgets)
+#[derive(Debug, Default)]
+pub struct VoiceLayers {
+    layers: Vec<Layer>,
+}
+
+impl VoiceLayers {
+    pub fn new() -> Self {
+        Self { layers: Vec::new() }
+    }
+
+    /// Add a layer, returning its index (at most MAX_LAYERS layers)
+    pub fn add_layer(&mut self, allocator: VoiceAllocator) -> Option<usize> {
+        if self.layers.len() >= MAX_LAYERS {
+            return None;
+        }
+        self.layers.push(Layer {
+            allocator,
+            enabled: true,
+        });
+        Some(self.layers.len() - 1)
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Enable or disable a layer; disabled layers don't receive new notes
+    pub fn set_layer_enabled(&mut self, layer: usize, enabled: bool) {
+        if let Some(layer) = self.layers.get_mut(layer) {
+            layer.enabled = enabled;
+        }
+    }
+
+    pub fn allocator(&self, layer: usize) -> Option<&VoiceAllocator> {
+        self.layers.get(layer).map(|l| &l.allocator)
+    }
+
+    pub fn allocator_mut(&mut self, layer: usize) -> Option<&mut VoiceAllocator> {
+        self.layers.get_mut(layer).map(|l| &mut l.allocator)
+    }
+
+    /// Allocate a voice for the note in every enabled layer
+    pub fn allocate_voice(&mut self, note: u8) -> LayeredVoices {
+        let mut voices = LayeredVoices::new();
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            if !layer.enabled {
+                continue;
+            }
+            if let Some(voice_id) = layer.allocator.allocate_voice(note) {
+                voices.push(i, voice_id);
+            }
+        }
+        voices
+    }
+
+    /// Release the note in every layer (including disabled ones, so notes
+    /// started before a layer was disabled don't hang)
+    pub fn release_voice(&mut self, note: u8) -> LayeredVoices {
+        let mut voices = LayeredVoices::new();
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            if let Some(voice_id) = layer.allocator.release_voice(note) {
+                voices.push(i, voice_id);
+            }
+        }
+        voices
+    }
+
+    /// Get the number of active voices across all layers
+    pub fn active_voice_count(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|l| l.allocator.active_voice_count())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn note_allocates_in_every_layer() {
+        let mut layers = VoiceLayers::new();
+        layers.add_layer(VoiceAllocator::new());
+        layers.add_layer(VoiceAllocator::with_voices(2));
+
+        let voices = layers.allocate_voice(60);
+        assert_eq!(voices.len(), 2);
+        let layer_ids: Vec<_> = voices.iter().map(|(layer, _)| *layer).collect();
+        assert_eq!(layer_ids, vec![0, 1]);
+
+        let released = layers.release_voice(60);
+        assert_eq!(released, voices);
+        assert_eq!(layers.active_voice_count(), 0);
+    }
+
+    #[test]
+    fn disabled_layer_skipped_but_released() {
+        let mut layers = VoiceLayers::new();
+        layers.add_layer(VoiceAllocator::new());
+        layers.add_layer(VoiceAllocator::new());
+
+        layers.allocate_voice(60);
+        layers.set_layer_enabled(1, false);
+
+        assert_eq!(layers.allocate_voice(64).len(), 1);
+        assert_eq!(layers.release_voice(60).len(), 2);
+    }
+
+    #[test]
+    fn layer_count_limited() {
+        let mut layers = VoiceLayers::new();
+        for _ in 0..MAX_LAYERS {
+            assert!(layers.add_layer(VoiceAllocator::with_voices(1)).is_some());
+        }
+        assert_eq!(layers.add_layer(VoiceAllocator::with_voices(1)), None);
+    }
+}
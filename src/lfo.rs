@@ -0,0 +1,246 @@
+//! Low-frequency oscillator shapes and a tempo-synced LFO
+
+use crate::tempo::{
+    clock_interval_to_bpm, NoteDivision, NoteLength, MIDI_CLOCK, MIDI_CLOCKS_PER_QUARTER,
+    MIDI_CONTINUE, MIDI_START, MIDI_STOP,
+};
+use std::f32::consts::TAU;
+
+/// Tempo a synced LFO assumes until it has heard two clock ticks
+pub const DEFAULT_LFO_BPM: f64 = 120.0;
+
+/// Weight of each new tick interval in the running tempo estimate
+const TEMPO_SMOOTHING: f64 = 0.25;
+
+/// Waveform of a low-frequency oscillator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    Triangle,
+    Square,
+    SawUp,
+    SawDown,
+}
+
+impl LfoShape {
+    /// Bipolar (-1.0 to 1.0) value at `phase` (0.0 to 1.0, wrapped)
+    pub fn value(&self, phase: f32) -> f32 {
+        let phase = phase.rem_euclid(1.0);
+        match self {
+            LfoShape::Sine => (phase * TAU).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            LfoShape::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            LfoShape::SawUp => 2.0 * phase - 1.0,
+            LfoShape::SawDown => 1.0 - 2.0 * phase,
+        }
+    }
+}
+
+/// LFO whose cycle is a note length, locked to incoming MIDI clock
+///
+/// Each clock tick hard-syncs the phase to the tick count since the last
+/// Start, so the LFO stays aligned with the sequencer; between ticks it runs
+/// smoothly at the tempo measured from the tick spacing. Without a clock it
+/// free-runs at `set_tempo`'s tempo. Feed it MIDI real-time bytes with
+/// `handle_realtime` and read it once per block (or sample) with `advance`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SyncedLfo {
+    shape: LfoShape,
+    length: NoteLength,
+    bpm: f64,
+    position: f64, // Clock ticks since Start, fractional between ticks
+    ticks: u64,
+    samples_since_tick: f64,
+    tick_samples: Option<f64>, // Measured tick spacing
+    sample_rate: f64,
+}
+
+impl SyncedLfo {
+    pub fn new(shape: LfoShape, length: NoteLength) -> Self {
+        Self {
+            shape,
+            length,
+            bpm: DEFAULT_LFO_BPM,
+            position: 0.0,
+            ticks: 0,
+            samples_since_tick: 0.0,
+            tick_samples: None,
+            sample_rate: 44100.0,
+        }
+    }
+
+    pub fn shape(&self) -> LfoShape {
+        self.shape
+    }
+
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.shape = shape;
+    }
+
+    pub fn length(&self) -> NoteLength {
+        self.length
+    }
+
+    /// Change the cycle length, keeping the phase aligned to the clock
+    pub fn set_length(&mut self, length: NoteLength) {
+        self.length = length;
+    }
+
+    /// Current tempo, measured from the clock or set by hand
+    pub fn bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    /// Set the tempo used while no clock is arriving
+    pub fn set_tempo(&mut self, bpm: f64) {
+        self.bpm = bpm;
+    }
+
+    /// Cycles per second at the current tempo
+    pub fn rate_hz(&self) -> f64 {
+        self.length.to_hz(self.bpm)
+    }
+
+    /// Position within the cycle, 0.0 to 1.0
+    pub fn phase(&self) -> f32 {
+        (self.position / self.length.to_clock_ticks()).fract() as f32
+    }
+
+    /// Bipolar value at the current phase
+    pub fn value(&self) -> f32 {
+        self.shape.value(self.phase())
+    }
+
+    /// Handle a MIDI real-time status byte (Clock, Start, Continue, Stop)
+    /// Returns true if the byte was one of them
+    pub fn handle_realtime(&mut self, status: u8) -> bool {
+        match status {
+            MIDI_CLOCK => self.clock_tick(),
+            MIDI_START => self.start(),
+            MIDI_CONTINUE | MIDI_STOP => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// Restart the cycle on the downbeat (MIDI Start)
+    pub fn start(&mut self) {
+        self.ticks = 0;
+        self.position = 0.0;
+        self.samples_since_tick = 0.0;
+    }
+
+    /// Advance by one MIDI clock tick, re-syncing the phase and the tempo
+    pub fn clock_tick(&mut self) {
+        if self.ticks > 0 || self.samples_since_tick > 0.0 {
+            let interval = self.samples_since_tick;
+            let smoothed = match self.tick_samples {
+                Some(previous) => previous + (interval - previous) * TEMPO_SMOOTHING,
+                None => interval,
+            };
+            if smoothed > 0.0 {
+                self.tick_samples = Some(smoothed);
+                self.bpm = clock_interval_to_bpm(smoothed / self.sample_rate);
+            }
+        }
+        self.ticks += 1;
+        self.position = self.ticks as f64;
+        self.samples_since_tick = 0.0;
+    }
+
+    /// Advance by `samples` and return the new value
+    pub fn advance(&mut self, samples: u32, sample_rate: f32) -> f32 {
+        self.sample_rate = sample_rate as f64;
+        self.samples_since_tick += samples as f64;
+        let ticks_per_sample = self.bpm / 60.0 * MIDI_CLOCKS_PER_QUARTER as f64 / self.sample_rate;
+        self.position += samples as f64 * ticks_per_sample;
+        // While the clock is running, never run ahead of the next tick
+        let clock_running = self
+            .tick_samples
+            .is_some_and(|tick| self.samples_since_tick < 2.0 * tick);
+        if clock_running {
+            self.position = self.position.min(self.ticks as f64 + 1.0);
+        }
+        self.value()
+    }
+}
+
+impl Default for SyncedLfo {
+    fn default() -> Self {
+        Self::new(LfoShape::Sine, NoteLength::straight(NoteDivision::Quarter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clock ticks at 120 BPM and 48 kHz arrive every 1000 samples
+    fn run_ticks(lfo: &mut SyncedLfo, ticks: usize) {
+        for _ in 0..ticks {
+            lfo.advance(1000, 48000.0);
+            lfo.handle_realtime(MIDI_CLOCK);
+        }
+    }
+
+    #[test]
+    fn synced_lfo_follows_clock_divisions() {
+        let mut lfo = SyncedLfo::default();
+        lfo.set_tempo(90.0);
+        lfo.handle_realtime(MIDI_START);
+        run_ticks(&mut lfo, 6);
+        assert!((lfo.bpm() - 120.0).abs() < 1e-6);
+        assert!((lfo.phase() - 0.25).abs() < 1e-6);
+
+        lfo.set_length(NoteLength::dotted(NoteDivision::Eighth)); // 18 ticks
+        run_ticks(&mut lfo, 3);
+        assert!((lfo.phase() - 0.5).abs() < 1e-6);
+
+        lfo.set_length(NoteLength::triplet(NoteDivision::Eighth)); // 8 ticks
+        lfo.handle_realtime(MIDI_START);
+        run_ticks(&mut lfo, 2);
+        // Halfway to the next tick, a quarter of the way through tick 3 of 8
+        lfo.advance(500, 48000.0);
+        assert!((lfo.phase() - 2.5 / 8.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn synced_lfo_waits_for_a_late_tick() {
+        let mut lfo = SyncedLfo::new(LfoShape::SawUp, NoteDivision::Quarter.into());
+        run_ticks(&mut lfo, 4);
+        // The sequencer slows down: the phase holds at the next tick
+        lfo.advance(1500, 48000.0);
+        assert!((lfo.phase() - 5.0 / 24.0).abs() < 1e-6);
+        // With the clock gone for good it free-runs at the last tempo
+        lfo.advance(1000, 48000.0);
+        assert!(lfo.phase() > 5.0 / 24.0);
+    }
+
+    #[test]
+    fn shapes_are_bipolar() {
+        for shape in [
+            LfoShape::Sine,
+            LfoShape::Triangle,
+            LfoShape::Square,
+            LfoShape::SawUp,
+            LfoShape::SawDown,
+        ] {
+            for i in 0..100 {
+                let value = shape.value(i as f32 / 100.0);
+                assert!((-1.0..=1.0).contains(&value), "{:?} {}", shape, value);
+            }
+        }
+        assert!((LfoShape::Sine.value(0.25) - 1.0).abs() < 1e-6);
+        assert_eq!(LfoShape::SawUp.value(0.0), -1.0);
+        assert_eq!(LfoShape::Square.value(1.75), -1.0);
+    }
+}
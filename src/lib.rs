@@ -3,14 +3,27 @@
 //! MIDI input integration and polyphonic synthesizer for Auxide DSP graphs.
 //!
 //! This crate provides:
-//! - MIDI input handling with midir
+//! - MIDI input handling with midir (the default `midir` feature)
 //! - Voice allocation and management for polyphonic synthesis
 //! - Real-time-safe parameter updates
 //! - Integration with auxide-dsp nodes
 //!
+//! Parsing, conversions, voice allocation, CC mapping and smoothing have no
+//! OS dependencies; build with `default-features = false, features = ["std"]`
+//! to leave out midir, `MidiInputHandler`, `MidiOutputHandler` and
+//! `MidiSynthController` for headless servers, WASM or plugin hosts.
+//!
+//! Without `std` the crate is `no_std` and keeps the parts that need neither
+//! `std` nor an allocator: the event model and parser (`ChannelEvent`,
+//! `MidiEvent`, `StreamParser`), `VoiceAllocator` and `CCMap` (fixed
+//! capacity, e.g. `VoiceAllocator::<8>::fixed()`), `Smoother`,
+//! `ChannelModes` and `SustainPedal`.
+//!
 //! ## Example
 //!
 //! ```rust
+//! # #[cfg(feature = "midir")]
+//! # mod example {
 //! use auxide_midi::{MidiInputHandler, VoiceAllocator, MidiEvent};
 //!
 //! fn example() -> Result<(), Box<dyn std::error::Error>> {
@@ -44,20 +57,222 @@
 //!     }
 //!     Ok(())
 //! }
+//! # }
 //! ```
+//!
+//! ## Per-voice pitch and gate
+//!
+//! `VoiceDriver` allocates voices and sends each one its own frequency and
+//! gate. Build one `MidiVoiceSource` node per voice into the graph and feed
+//! events from the MIDI thread:
+//!
+//! ```rust
+//! # #[cfg(feature = "std")]
+//! # {
+//! use auxide_midi::{midi_voice_sources, MidiEvent, VoiceAllocator, VoiceDriver};
+//!
+//! let (mut sender, sources) = midi_voice_sources(8);
+//! // ... add each of `sources` to the graph, wired to an oscillator's
+//! // frequency input and an envelope's gate input
+//! let mut driver = VoiceDriver::new(VoiceAllocator::with_voices(sources.len()));
+//! driver.handle_event(MidiEvent::NoteOn(60, 100), &mut sender);
+//! driver.handle_event(MidiEvent::PitchBend(12288), &mut sender); // Retunes voice 0
+//! # }
+//! ```
+//!
+//! Graphs with fixed oscillator nodes can use `GraphVoiceControl` instead,
+//! which sends `SetFrequency` and `TriggerGate` through a `ParamUpdateQueue`.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![forbid(unsafe_code)]
 
+#[cfg(feature = "std")]
+pub mod activity;
+#[cfg(feature = "std")]
+pub mod automation;
 pub mod cc_mapping;
+#[cfg(feature = "std")]
+pub mod cc_profiles;
+pub mod channel_mode;
+#[cfg(feature = "std")]
+pub mod channel_router;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
 pub mod conversions;
+#[cfg(feature = "std")]
+pub mod drift;
+#[cfg(feature = "std")]
+pub mod event_consumer;
+#[cfg(feature = "std")]
+pub mod expression;
+#[cfg(feature = "std")]
+pub mod jitter;
+#[cfg(feature = "std")]
+pub mod key_split;
+#[cfg(feature = "std")]
+pub mod latency;
+#[cfg(feature = "std")]
+pub mod layers;
+#[cfg(feature = "std")]
+pub mod lfo;
+#[cfg(feature = "std")]
+pub mod looper;
+#[cfg(feature = "std")]
+pub mod mailbox;
+#[cfg(feature = "std")]
+pub mod metronome;
 pub mod midi_input;
+#[cfg(feature = "midir")]
+pub mod midi_output;
+#[cfg(feature = "std")]
+pub mod mod_matrix;
+#[cfg(feature = "std")]
+pub mod mpe;
+#[cfg(feature = "std")]
+pub mod multitimbral;
+#[cfg(feature = "std")]
+pub mod note_echo;
+#[cfg(feature = "std")]
+pub mod nrpn_map;
+#[cfg(feature = "osc")]
+pub mod osc;
+#[cfg(feature = "std")]
+pub mod param_bridge;
+#[cfg(feature = "std")]
+pub mod poly_synth;
+#[cfg(feature = "std")]
+pub mod portamento;
+#[cfg(feature = "std")]
+pub mod preset;
+#[cfg(feature = "std")]
+pub mod processor;
+#[cfg(feature = "std")]
+pub mod program_map;
+#[cfg(feature = "std")]
+pub mod rpn;
+#[cfg(feature = "std")]
+pub mod scala;
+#[cfg(feature = "std")]
+pub mod scheduler;
+#[cfg(feature = "serde")]
+mod serde_array;
+mod slot_table;
+#[cfg(feature = "std")]
+pub mod smf;
 pub mod smoother;
+pub mod stream_parser;
+pub mod sustain;
+#[cfg(feature = "midir")]
+pub mod synth_controller;
+#[cfg(feature = "std")]
+pub mod sysex;
+#[cfg(feature = "std")]
+pub mod tempo;
+#[cfg(feature = "std")]
+pub mod timecode;
+#[cfg(feature = "std")]
+pub mod velocity;
 pub mod voice_allocator;
+#[cfg(feature = "std")]
+pub mod voice_control;
+#[cfg(feature = "std")]
+pub mod voice_graph;
+#[cfg(feature = "std")]
+pub mod voice_source;
+#[cfg(feature = "std")]
 pub mod voice_state;
 
+#[cfg(feature = "std")]
+pub use activity::*;
+#[cfg(feature = "std")]
+pub use automation::*;
 pub use cc_mapping::*;
+#[cfg(feature = "std")]
+pub use cc_profiles::*;
+pub use channel_mode::*;
+#[cfg(feature = "std")]
+pub use channel_router::*;
+#[cfg(feature = "std")]
+pub use clock::*;
+#[cfg(feature = "std")]
 pub use conversions::*;
+#[cfg(feature = "std")]
+pub use drift::*;
+#[cfg(feature = "std")]
+pub use event_consumer::*;
+#[cfg(feature = "std")]
+pub use expression::*;
+#[cfg(feature = "std")]
+pub use jitter::*;
+#[cfg(feature = "std")]
+pub use key_split::*;
+#[cfg(feature = "std")]
+pub use latency::*;
+#[cfg(feature = "std")]
+pub use layers::*;
+#[cfg(feature = "std")]
+pub use lfo::*;
+#[cfg(feature = "std")]
+pub use looper::*;
+#[cfg(feature = "std")]
+pub use mailbox::*;
+#[cfg(feature = "std")]
+pub use metronome::*;
 pub use midi_input::*;
+#[cfg(feature = "midir")]
+pub use midi_output::*;
+#[cfg(feature = "std")]
+pub use mod_matrix::*;
+#[cfg(feature = "std")]
+pub use mpe::*;
+#[cfg(feature = "std")]
+pub use multitimbral::*;
+#[cfg(feature = "std")]
+pub use note_echo::*;
+#[cfg(feature = "std")]
+pub use nrpn_map::*;
+#[cfg(feature = "osc")]
+pub use osc::*;
+#[cfg(feature = "std")]
+pub use param_bridge::*;
+#[cfg(feature = "std")]
+pub use poly_synth::*;
+#[cfg(feature = "std")]
+pub use portamento::*;
+#[cfg(feature = "std")]
+pub use preset::*;
+#[cfg(feature = "std")]
+pub use processor::*;
+#[cfg(feature = "std")]
+pub use program_map::*;
+#[cfg(feature = "std")]
+pub use rpn::*;
+#[cfg(feature = "std")]
+pub use scala::*;
+#[cfg(feature = "std")]
+pub use scheduler::*;
+#[cfg(feature = "std")]
+pub use smf::*;
 pub use smoother::*;
+pub use stream_parser::*;
+pub use sustain::*;
+#[cfg(feature = "midir")]
+pub use synth_controller::*;
+#[cfg(feature = "std")]
+pub use sysex::*;
+#[cfg(feature = "std")]
+pub use tempo::*;
+#[cfg(feature = "std")]
+pub use timecode::*;
+#[cfg(feature = "std")]
+pub use velocity::*;
 pub use voice_allocator::*;
+#[cfg(feature = "std")]
+pub use voice_control::*;
+#[cfg(feature = "std")]
+pub use voice_graph::*;
+#[cfg(feature = "std")]
+pub use voice_source::*;
+#[cfg(feature = "std")]
 pub use voice_state::*;
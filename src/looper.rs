@@ -0,0 +1,307 @@
+//! MIDI phrase looper: record a bar-quantized phrase, then loop it with
+//! overdub, merged with live input
+//!
+//! Times are in µs, like [`EventScheduler`](crate::scheduler::EventScheduler).
+//! The loop length is fixed when recording starts, from the bar count and
+//! tempo; later tempo changes apply to the next recording.
+
+use crate::midi_input::{ChannelEvent, MidiEvent};
+use crate::tempo::{beats_to_ms, MIDI_CLOCKS_PER_QUARTER};
+
+/// Most events a loop holds; further events are not recorded
+pub const MAX_LOOPER_EVENTS: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LooperState {
+    Empty,
+    /// Waiting for the next bar line to start recording
+    Armed,
+    Recording,
+    Playing,
+    Overdubbing,
+    Stopped,
+}
+
+/// Records incoming events for a whole number of bars and replays them
+///
+/// Route every live event through [`process`](Self::process) and call
+/// [`pop_due`](Self::pop_due) regularly; both emit loop playback merged
+/// with the live stream, in time order. Notes still held when a pass ends
+/// keep their note-offs, which land in the next pass.
+#[derive(Debug, Clone)]
+pub struct PhraseLooper {
+    bars: u32,
+    beats_per_bar: u32,
+    bpm: f64,
+    state: LooperState,
+    events: Vec<(u64, ChannelEvent)>, // Offset into the loop, sorted
+    length: u64,
+    cycle_start: u64,
+    cursor: usize,
+    recording: [u128; 16], // Recorded notes whose note-off is still to come
+    sounding: [u128; 16],  // Loop notes currently playing
+}
+
+impl PhraseLooper {
+    pub fn new(bars: u32, beats_per_bar: u32) -> Self {
+        Self {
+            bars: bars.max(1),
+            beats_per_bar: beats_per_bar.max(1),
+            bpm: 120.0,
+            state: LooperState::Empty,
+            events: Vec::with_capacity(MAX_LOOPER_EVENTS),
+            length: 0,
+            cycle_start: 0,
+            cursor: 0,
+            recording: [0; 16],
+            sounding: [0; 16],
+        }
+    }
+
+    pub fn state(&self) -> LooperState {
+        self.state
+    }
+
+    pub fn bars(&self) -> u32 {
+        self.bars
+    }
+
+    pub fn set_bars(&mut self, bars: u32) {
+        self.bars = bars.max(1);
+    }
+
+    /// Tempo used for the next recording (e.g. from `ClockFollower::bpm`)
+    pub fn set_tempo(&mut self, bpm: f64) {
+        if bpm > 0.0 {
+            self.bpm = bpm;
+        }
+    }
+
+    /// Loop length in µs, or 0 before anything is recorded
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    pub fn events(&self) -> &[(u64, ChannelEvent)] {
+        &self.events
+    }
+
+    /// Start recording at `time`, replacing any previous loop
+    pub fn record(&mut self, time: u64, emit: impl FnMut(ChannelEvent)) {
+        self.clear(emit);
+        let beats = (self.bars * self.beats_per_bar) as f64;
+        self.length = ((beats_to_ms(beats, self.bpm) * 1000.0) as u64).max(1);
+        self.cycle_start = time;
+        self.state = LooperState::Recording;
+    }
+
+    /// Start recording at the next bar line seen by [`clock_tick`](Self::clock_tick)
+    pub fn arm(&mut self) {
+        if self.state != LooperState::Recording {
+            self.state = LooperState::Armed;
+        }
+    }
+
+    /// Feed the MIDI clock position (24 per quarter note, e.g.
+    /// `ClockFollower::ticks`); starts an armed recording on a bar line
+    pub fn clock_tick(&mut self, ticks: u64, time: u64, emit: impl FnMut(ChannelEvent)) {
+        let ticks_per_bar = (MIDI_CLOCKS_PER_QUARTER * self.beats_per_bar) as u64;
+        if self.state == LooperState::Armed && ticks.is_multiple_of(ticks_per_bar) {
+            self.record(time, emit);
+        }
+    }
+
+    /// Toggle overdubbing while the loop plays
+    pub fn toggle_overdub(&mut self) {
+        self.state = match self.state {
+            LooperState::Playing => LooperState::Overdubbing,
+            LooperState::Overdubbing => LooperState::Playing,
+            state => state,
+        };
+    }
+
+    /// Restart playback of a stopped loop from its beginning at `time`
+    pub fn play(&mut self, time: u64) {
+        if self.state == LooperState::Stopped {
+            self.cycle_start = time;
+            self.cursor = 0;
+            self.state = LooperState::Playing;
+        }
+    }
+
+    /// Stop playback, releasing any notes the loop is holding
+    pub fn stop(&mut self, emit: impl FnMut(ChannelEvent)) {
+        if matches!(
+            self.state,
+            LooperState::Playing | LooperState::Overdubbing | LooperState::Recording
+        ) {
+            self.state = LooperState::Stopped;
+        }
+        self.release(emit);
+    }
+
+    /// Erase the loop, releasing any notes it is holding
+    pub fn clear(&mut self, emit: impl FnMut(ChannelEvent)) {
+        self.release(emit);
+        self.events.clear();
+        self.recording = [0; 16];
+        self.length = 0;
+        self.cursor = 0;
+        self.state = LooperState::Empty;
+    }
+
+    /// Emit loop playback up to `now`
+    pub fn pop_due(&mut self, now: u64, mut emit: impl FnMut(ChannelEvent)) {
+        if self.state == LooperState::Recording && now >= self.cycle_start + self.length {
+            self.cycle_start += self.length;
+            self.cursor = 0;
+            self.state = LooperState::Playing;
+        }
+        if !matches!(self.state, LooperState::Playing | LooperState::Overdubbing) {
+            return;
+        }
+        loop {
+            if self.cursor == self.events.len() {
+                if now < self.cycle_start + self.length {
+                    return;
+                }
+                self.cycle_start += self.length;
+                self.cursor = 0;
+                continue;
+            }
+            let (offset, event) = &self.events[self.cursor];
+            if self.cycle_start + offset > now {
+                return;
+            }
+            track(&mut self.sounding, event);
+            emit(event.clone());
+            self.cursor += 1;
+        }
+    }
+
+    /// Take a live event played at `time`: emits loop playback due first,
+    /// then the event itself, recording it when recording or overdubbing
+    pub fn process(&mut self, time: u64, event: ChannelEvent, mut emit: impl FnMut(ChannelEvent)) {
+        self.pop_due(time, &mut emit);
+        let recording = match (&self.state, &event.event) {
+            (LooperState::Recording | LooperState::Overdubbing, _) => true,
+            // Note-offs of recorded notes still belong to the loop
+            (LooperState::Playing, MidiEvent::NoteOff(note, _)) => {
+                is_set(&self.recording, event.channel, *note)
+            }
+            _ => false,
+        };
+        if recording && self.events.len() < MAX_LOOPER_EVENTS {
+            let offset = (time.saturating_sub(self.cycle_start)) % self.length;
+            let index = self.events.partition_point(|(t, _)| *t <= offset);
+            self.events.insert(index, (offset, event.clone()));
+            // Already passed in this cycle: first heard on the next one
+            if self.state != LooperState::Recording && index <= self.cursor {
+                self.cursor += 1;
+            }
+            track(&mut self.recording, &event);
+        }
+        emit(event);
+    }
+
+    fn release(&mut self, mut emit: impl FnMut(ChannelEvent)) {
+        for channel in 0..16u8 {
+            let notes = std::mem::take(&mut self.sounding[channel as usize]);
+            for note in (0..128u8).filter(|note| notes & (1 << note) != 0) {
+                emit(ChannelEvent {
+                    channel,
+                    event: MidiEvent::NoteOff(note, 0),
+                });
+            }
+        }
+    }
+}
+
+impl Default for PhraseLooper {
+    fn default() -> Self {
+        Self::new(1, 4)
+    }
+}
+
+/// Keep `notes` up to date with the note-ons and note-offs in `event`
+fn track(notes: &mut [u128; 16], event: &ChannelEvent) {
+    let notes = &mut notes[(event.channel & 0x0F) as usize];
+    match event.event {
+        MidiEvent::NoteOn(note, velocity) if velocity > 0 => *notes |= 1 << (note & 0x7F),
+        MidiEvent::NoteOn(note, _) | MidiEvent::NoteOff(note, _) => *notes &= !(1 << (note & 0x7F)),
+        _ => {}
+    }
+}
+
+fn is_set(notes: &[u128; 16], channel: u8, note: u8) -> bool {
+    notes[(channel & 0x0F) as usize] & (1 << (note & 0x7F)) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on(note: u8) -> ChannelEvent {
+        ChannelEvent {
+            channel: 0,
+            event: MidiEvent::NoteOn(note, 100),
+        }
+    }
+
+    fn off(note: u8) -> ChannelEvent {
+        ChannelEvent {
+            channel: 0,
+            event: MidiEvent::NoteOff(note, 0),
+        }
+    }
+
+    #[test]
+    fn records_one_bar_and_loops_it() {
+        // One bar of 4/4 at 120 bpm is 2 s
+        let mut looper = PhraseLooper::new(1, 4);
+        let mut out = Vec::new();
+        looper.record(1_000_000, |e| out.push(e));
+        looper.process(1_000_000, on(60), |e| out.push(e));
+        looper.process(1_500_000, off(60), |e| out.push(e));
+        looper.process(2_900_000, on(64), |e| out.push(e));
+        assert_eq!(looper.length(), 2_000_000);
+        assert_eq!(out, [on(60), off(60), on(64)]);
+
+        // Held across the loop point: its note-off is still recorded
+        out.clear();
+        looper.process(3_100_000, off(64), |e| out.push(e));
+        assert_eq!(looper.state(), LooperState::Playing);
+        assert_eq!(out, [on(60), off(64)]);
+
+        out.clear();
+        looper.pop_due(5_000_000, |e| out.push(e));
+        assert_eq!(out, [off(60), on(64), on(60)]);
+        assert_eq!(looper.events().len(), 4);
+    }
+
+    #[test]
+    fn overdub_adds_to_the_next_pass_and_stop_releases() {
+        let mut looper = PhraseLooper::new(1, 4);
+        let mut out = Vec::new();
+        looper.arm();
+        looper.clock_tick(12, 0, |e| out.push(e));
+        assert_eq!(looper.state(), LooperState::Armed);
+        looper.clock_tick(96, 0, |e| out.push(e));
+        assert_eq!(looper.state(), LooperState::Recording);
+        looper.process(0, on(48), |e| out.push(e));
+        looper.pop_due(2_000_000, |e| out.push(e));
+
+        looper.toggle_overdub();
+        looper.process(2_500_000, on(55), |e| out.push(e));
+        out.clear();
+        looper.pop_due(4_500_000, |e| out.push(e));
+        assert_eq!(out, [on(48), on(55)]);
+
+        out.clear();
+        looper.stop(|e| out.push(e));
+        assert_eq!(out, [off(48), off(55)]);
+        assert_eq!(looper.state(), LooperState::Stopped);
+        looper.clear(|e| out.push(e));
+        assert!(looper.events().is_empty());
+    }
+}
@@ -0,0 +1,225 @@
+//! Lock-free per-voice parameter mailboxes
+//!
+//! Each voice has a fixed set of atomic slots (frequency, gain, cutoff,
+//! gate) that the MIDI thread overwrites and the audio thread reads once per
+//! block. Only the latest value matters, so there is no queue to overflow
+//! and neither side ever blocks or allocates after construction.
+
+use crate::voice_allocator::VoiceId;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// An `f32` stored as its bit pattern in an `AtomicU32`
+#[derive(Debug, Default)]
+pub struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    pub fn new(value: f32) -> Self {
+        Self(AtomicU32::new(value.to_bits()))
+    }
+
+    pub fn load(&self, order: Ordering) -> f32 {
+        f32::from_bits(self.0.load(order))
+    }
+
+    pub fn store(&self, value: f32, order: Ordering) {
+        self.0.store(value.to_bits(), order)
+    }
+}
+
+/// One voice's mailbox
+#[derive(Debug, Default)]
+pub struct VoiceMailbox {
+    freq: AtomicF32, // Hz
+    gain: AtomicF32,
+    cutoff: AtomicF32, // Hz, 0.0 until first set
+    gate: AtomicBool,
+    generation: AtomicU32, // Generation of the VoiceId that owns the voice
+    triggers: AtomicU32,   // Bumped on every note-on so retriggers are seen
+}
+
+/// Snapshot of a voice's mailbox as seen by the audio thread
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct VoiceParams {
+    pub freq: f32,
+    pub gain: f32,
+    pub cutoff: f32,
+    pub gate: bool,
+    /// Note-on count; a change with the gate still open is a retrigger
+    pub triggers: u32,
+}
+
+impl VoiceMailbox {
+    /// Read every slot; the trigger count is read first so a note-on's
+    /// frequency and gain are never older than its trigger
+    pub fn read(&self) -> VoiceParams {
+        let triggers = self.triggers.load(Ordering::Acquire);
+        VoiceParams {
+            freq: self.freq.load(Ordering::Relaxed),
+            gain: self.gain.load(Ordering::Relaxed),
+            cutoff: self.cutoff.load(Ordering::Relaxed),
+            gate: self.gate.load(Ordering::Acquire),
+            triggers,
+        }
+    }
+
+    fn owned_by(&self, voice: VoiceId) -> bool {
+        self.generation.load(Ordering::Relaxed) == voice.generation()
+    }
+}
+
+/// MIDI-side writer for a set of voice mailboxes
+#[derive(Debug, Clone)]
+pub struct VoiceMailboxWriter {
+    mailboxes: Arc<[VoiceMailbox]>,
+}
+
+/// Audio-side reader for a set of voice mailboxes
+#[derive(Debug, Clone)]
+pub struct VoiceMailboxReader {
+    mailboxes: Arc<[VoiceMailbox]>,
+}
+
+/// Create `voice_count` mailboxes and their writer and reader
+pub fn voice_mailboxes(voice_count: usize) -> (VoiceMailboxWriter, VoiceMailboxReader) {
+    let mailboxes: Arc<[VoiceMailbox]> =
+        (0..voice_count).map(|_| VoiceMailbox::default()).collect();
+    (
+        VoiceMailboxWriter {
+            mailboxes: mailboxes.clone(),
+        },
+        VoiceMailboxReader { mailboxes },
+    )
+}
+
+impl VoiceMailboxWriter {
+    pub fn voice_count(&self) -> usize {
+        self.mailboxes.len()
+    }
+
+    /// Start `voice` at `freq` Hz and `gain`, retriggering its gate
+    /// Both are stored before the trigger so the reader never pairs the new
+    /// note with the previous one's gain.
+    pub fn note_on(&self, voice: VoiceId, freq: f32, gain: f32) {
+        let Some(mailbox) = self.mailboxes.get(voice.index()) else {
+            return;
+        };
+        mailbox.freq.store(freq, Ordering::Relaxed);
+        mailbox.gain.store(gain, Ordering::Relaxed);
+        mailbox
+            .generation
+            .store(voice.generation(), Ordering::Relaxed);
+        mailbox.gate.store(true, Ordering::Relaxed);
+        mailbox.triggers.fetch_add(1, Ordering::Release);
+    }
+
+    /// Close the gate of `voice`, unless its slot has since been retaken
+    pub fn note_off(&self, voice: VoiceId) {
+        if let Some(mailbox) = self.owned(voice) {
+            mailbox.gate.store(false, Ordering::Release);
+        }
+    }
+
+    /// Change the pitch of a sounding voice (pitch bend, glide) without retriggering
+    pub fn set_frequency(&self, voice: VoiceId, freq: f32) {
+        if let Some(mailbox) = self.owned(voice) {
+            mailbox.freq.store(freq, Ordering::Release);
+        }
+    }
+
+    pub fn set_gain(&self, voice: VoiceId, gain: f32) {
+        if let Some(mailbox) = self.owned(voice) {
+            mailbox.gain.store(gain, Ordering::Release);
+        }
+    }
+
+    pub fn set_cutoff(&self, voice: VoiceId, hz: f32) {
+        if let Some(mailbox) = self.owned(voice) {
+            mailbox.cutoff.store(hz, Ordering::Release);
+        }
+    }
+
+    /// Set the cutoff of every voice, sounding or not (a global filter control)
+    pub fn set_cutoff_all(&self, hz: f32) {
+        for mailbox in self.mailboxes.iter() {
+            mailbox.cutoff.store(hz, Ordering::Release);
+        }
+    }
+
+    /// Close every gate
+    pub fn all_notes_off(&self) {
+        for mailbox in self.mailboxes.iter() {
+            mailbox.gate.store(false, Ordering::Release);
+        }
+    }
+
+    fn owned(&self, voice: VoiceId) -> Option<&VoiceMailbox> {
+        self.mailboxes
+            .get(voice.index())
+            .filter(|mailbox| mailbox.owned_by(voice))
+    }
+}
+
+impl VoiceMailboxReader {
+    pub fn voice_count(&self) -> usize {
+        self.mailboxes.len()
+    }
+
+    /// Latest values for the voice in slot `index`
+    pub fn read(&self, index: usize) -> VoiceParams {
+        self.mailboxes
+            .get(index)
+            .map(VoiceMailbox::read)
+            .unwrap_or_default()
+    }
+
+    pub fn mailbox(&self, index: usize) -> Option<&VoiceMailbox> {
+        self.mailboxes.get(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_are_visible_to_the_reader() {
+        let (writer, reader) = voice_mailboxes(2);
+        writer.note_on(VoiceId(1, 3), 440.0, 0.5);
+        writer.set_cutoff_all(1200.0);
+
+        let params = reader.read(1);
+        assert_eq!(params.freq, 440.0);
+        assert_eq!(params.gain, 0.5);
+        assert_eq!(params.cutoff, 1200.0);
+        assert!(params.gate);
+        assert_eq!(params.triggers, 1);
+        assert_eq!(reader.read(0).cutoff, 1200.0);
+        assert_eq!(reader.read(5), VoiceParams::default());
+    }
+
+    #[test]
+    fn stale_handles_are_ignored() {
+        let (writer, reader) = voice_mailboxes(1);
+        writer.note_on(VoiceId(0, 1), 440.0, 1.0);
+        writer.note_on(VoiceId(0, 2), 220.0, 1.0); // Stolen
+        writer.set_frequency(VoiceId(0, 1), 880.0);
+        writer.note_off(VoiceId(0, 1));
+        let params = reader.read(0);
+        assert_eq!(params.freq, 220.0);
+        assert!(params.gate);
+
+        writer.note_off(VoiceId(0, 2));
+        assert!(!reader.read(0).gate);
+    }
+
+    #[test]
+    fn reader_sees_writes_from_another_thread() {
+        let (writer, reader) = voice_mailboxes(1);
+        std::thread::spawn(move || writer.note_on(VoiceId(0, 1), 330.0, 0.25))
+            .join()
+            .unwrap();
+        assert_eq!(reader.read(0).freq, 330.0);
+        assert_eq!(reader.read(0).gain, 0.25);
+    }
+}
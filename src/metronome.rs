@@ -0,0 +1,194 @@
+//! Metronome: accented bar and beat clicks from the MIDI clock
+//!
+//! Feed clock positions from a [`ClockMaster`](crate::clock::ClockMaster)
+//! or [`ClockFollower`] and turn the clicks into note events, e.g. into
+//! `MidiSynthController::schedule` to hear them through the synth's graph.
+
+use crate::clock::{ClockFollower, TransportEvent};
+use crate::midi_input::{ChannelEvent, MidiEvent};
+use crate::tempo::MIDI_CLOCKS_PER_QUARTER;
+
+/// GM percussion channel (channel 10)
+pub const GM_DRUM_CHANNEL: u8 = 9;
+/// GM Hi Wood Block, the default bar click
+pub const DEFAULT_ACCENT_NOTE: u8 = 76;
+/// GM Low Wood Block, the default beat click
+pub const DEFAULT_BEAT_NOTE: u8 = 77;
+/// Default click length, µs
+pub const DEFAULT_CLICK_LENGTH: u64 = 30_000;
+
+/// One metronome click
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetronomeClick {
+    /// Bar number, from 0
+    pub bar: u64,
+    /// Beat within the bar, from 0
+    pub beat: u32,
+    /// First beat of the bar
+    pub accent: bool,
+}
+
+/// Counts clock ticks into bars and beats and voices the clicks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metronome {
+    beats_per_bar: u32,
+    enabled: bool,
+    pub channel: u8,
+    pub accent_note: u8,
+    pub beat_note: u8,
+    pub accent_velocity: u8,
+    pub beat_velocity: u8,
+    /// Time from note-on to note-off, µs
+    pub click_length: u64,
+}
+
+impl Metronome {
+    pub fn new(beats_per_bar: u32) -> Self {
+        Self {
+            beats_per_bar: beats_per_bar.max(1),
+            enabled: true,
+            channel: GM_DRUM_CHANNEL,
+            accent_note: DEFAULT_ACCENT_NOTE,
+            beat_note: DEFAULT_BEAT_NOTE,
+            accent_velocity: 127,
+            beat_velocity: 90,
+            click_length: DEFAULT_CLICK_LENGTH,
+        }
+    }
+
+    pub fn beats_per_bar(&self) -> u32 {
+        self.beats_per_bar
+    }
+
+    pub fn set_beats_per_bar(&mut self, beats_per_bar: u32) {
+        self.beats_per_bar = beats_per_bar.max(1);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Mute or unmute; a muted metronome reports no clicks
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// The click at clock position `ticks` (24 per quarter note, counted
+    /// from 0 at the start of the song), if it falls on a beat
+    ///
+    /// `ClockMaster::process` passes this position to its tick callback.
+    pub fn clock_tick(&self, ticks: u64) -> Option<MetronomeClick> {
+        let ticks_per_beat = MIDI_CLOCKS_PER_QUARTER as u64;
+        if !self.enabled || !ticks.is_multiple_of(ticks_per_beat) {
+            return None;
+        }
+        let beats = ticks / ticks_per_beat;
+        let beat = (beats % self.beats_per_bar as u64) as u32;
+        Some(MetronomeClick {
+            bar: beats / self.beats_per_bar as u64,
+            beat,
+            accent: beat == 0,
+        })
+    }
+
+    /// The click for a transport event `clock` just handled, if any
+    pub fn follow(&self, clock: &ClockFollower, event: TransportEvent) -> Option<MetronomeClick> {
+        match event {
+            // The follower counts ticks after they happen
+            TransportEvent::Tick if clock.is_playing() => {
+                self.clock_tick(clock.ticks().checked_sub(1)?)
+            }
+            _ => None,
+        }
+    }
+
+    /// Voice `click` at `time` (µs): calls `emit` with a note-on and, one
+    /// click length later, its note-off
+    pub fn events(
+        &self,
+        click: &MetronomeClick,
+        time: u64,
+        mut emit: impl FnMut(u64, ChannelEvent),
+    ) {
+        let (note, velocity) = if click.accent {
+            (self.accent_note, self.accent_velocity)
+        } else {
+            (self.beat_note, self.beat_velocity)
+        };
+        let channel = self.channel & 0x0F;
+        emit(
+            time,
+            ChannelEvent {
+                channel,
+                event: MidiEvent::NoteOn(note, velocity.max(1)),
+            },
+        );
+        emit(
+            time + self.click_length,
+            ChannelEvent {
+                channel,
+                event: MidiEvent::NoteOff(note, 0),
+            },
+        );
+    }
+}
+
+impl Default for Metronome {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ClockMaster;
+
+    #[test]
+    fn counts_bars_and_accents_the_downbeat() {
+        let metronome = Metronome::new(3);
+        assert_eq!(metronome.clock_tick(12), None);
+        let clicks: Vec<_> = (0..24 * 4)
+            .filter_map(|tick| metronome.clock_tick(tick))
+            .map(|click| (click.bar, click.beat, click.accent))
+            .collect();
+        assert_eq!(
+            clicks,
+            [(0, 0, true), (0, 1, false), (0, 2, false), (1, 0, true)]
+        );
+
+        let mut events = Vec::new();
+        metronome.events(&metronome.clock_tick(24).unwrap(), 1000, |time, event| {
+            events.push((time, event.event))
+        });
+        assert_eq!(
+            events,
+            [
+                (1000, MidiEvent::NoteOn(DEFAULT_BEAT_NOTE, 90)),
+                (31_000, MidiEvent::NoteOff(DEFAULT_BEAT_NOTE, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_both_clock_sources() {
+        let metronome = Metronome::default();
+        let mut master = ClockMaster::new(120.0);
+        master.start();
+        let mut clicks = Vec::new();
+        // Two beats at 120 BPM and 48 kHz
+        master.process(48_000, 48_000.0, |offset, tick| {
+            if let Some(click) = metronome.clock_tick(tick) {
+                clicks.push((offset, click.beat));
+            }
+        });
+        assert_eq!(clicks, [(0, 0), (24_000, 1)]);
+
+        let mut follower = ClockFollower::new();
+        follower.start();
+        let downbeat = follower.handle_message(&[0xF8], 0).unwrap();
+        assert!(metronome.follow(&follower, downbeat).unwrap().accent);
+        let next = follower.handle_message(&[0xF8], 20_833).unwrap();
+        assert_eq!(metronome.follow(&follower, next), None);
+    }
+}
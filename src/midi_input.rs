@@ -1,9 +1,19 @@
-//! MIDI input handling with midir
+//! MIDI events and parsing, plus input handling with midir
+//!
+//! The event types and parser are always available; `MidiInputHandler`
+//! needs the `midir` feature (on by default).
 
+#[cfg(feature = "midir")]
+use crate::scheduler::TimedEvent;
+#[cfg(feature = "midir")]
 use anyhow::Result;
+#[cfg(feature = "midir")]
 use crossbeam_channel::{bounded, Receiver, Sender};
+#[cfg(feature = "midir")]
 use midir::{MidiInput, MidiInputConnection};
+#[cfg(feature = "midir")]
 use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "midir")]
 use std::sync::Arc;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -12,22 +22,176 @@ pub enum MidiEvent {
     NoteOff(u8, u8),       // note, velocity
     ControlChange(u8, u8), // cc_num, value
     PitchBend(i16),        // bend value
+    ChannelPressure(u8),   // pressure
+    ProgramChange(u8),     // program
 }
 
+/// CC 120: All Sound Off (cut voices immediately)
+pub const CC_ALL_SOUND_OFF: u8 = 120;
+/// CC 123: All Notes Off (release every sounding note)
+pub const CC_ALL_NOTES_OFF: u8 = 123;
+
+impl MidiEvent {
+    /// Check for All Notes Off (CC 123), All Sound Off (CC 120) or a
+    /// channel mode message (CC 124-127), which implies All Notes Off
+    pub fn is_all_notes_off(&self) -> bool {
+        matches!(
+            self,
+            MidiEvent::ControlChange(CC_ALL_NOTES_OFF | CC_ALL_SOUND_OFF | 124..=127, _)
+        )
+    }
+
+    /// Parse a channel voice message, dropping the channel
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        ChannelEvent::from_bytes(bytes).map(|e| e.event)
+    }
+}
+
+/// A channel voice message together with the MIDI channel it arrived on
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelEvent {
+    pub channel: u8, // 0-15
+    pub event: MidiEvent,
+}
+
+impl ChannelEvent {
+    /// Parse a channel voice message, keeping the channel from the status
+    /// byte; None for anything else
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.is_empty() {
+            return None;
+        }
+
+        let status = bytes[0];
+        let channel = status & 0x0F;
+
+        let event = match status & 0xF0 {
+            0x90 => {
+                // Note On
+                if bytes.len() >= 3 && bytes[2] > 0 {
+                    Some(MidiEvent::NoteOn(bytes[1], bytes[2]))
+                } else if bytes.len() >= 3 {
+                    // Note On with velocity 0 is Note Off
+                    Some(MidiEvent::NoteOff(bytes[1], bytes[2]))
+                } else {
+                    None
+                }
+            }
+            0x80 => {
+                // Note Off
+                if bytes.len() >= 3 {
+                    Some(MidiEvent::NoteOff(bytes[1], bytes[2]))
+                } else {
+                    None
+                }
+            }
+            0xB0 => {
+                // Control Change
+                if bytes.len() >= 3 {
+                    Some(MidiEvent::ControlChange(bytes[1], bytes[2]))
+                } else {
+                    None
+                }
+            }
+            0xE0 => {
+                // Pitch Bend
+                if bytes.len() >= 3 {
+                    let bend = ((bytes[2] as i16) << 7) | (bytes[1] as i16);
+                    Some(MidiEvent::PitchBend(bend))
+                } else {
+                    None
+                }
+            }
+            0xD0 => {
+                // Channel Pressure (aftertouch)
+                if bytes.len() >= 2 {
+                    Some(MidiEvent::ChannelPressure(bytes[1]))
+                } else {
+                    None
+                }
+            }
+            0xC0 => {
+                // Program Change
+                if bytes.len() >= 2 {
+                    Some(MidiEvent::ProgramChange(bytes[1]))
+                } else {
+                    None
+                }
+            }
+            _ => None, // Ignore other message types for now
+        }?;
+
+        Some(Self { channel, event })
+    }
+
+    /// Encode as a MIDI message; returns the bytes and how many are used
+    pub fn to_bytes(&self) -> ([u8; 3], usize) {
+        let channel = self.channel & 0x0F;
+        match self.event {
+            MidiEvent::NoteOn(note, velocity) => ([0x90 | channel, note, velocity], 3),
+            MidiEvent::NoteOff(note, velocity) => ([0x80 | channel, note, velocity], 3),
+            MidiEvent::ControlChange(cc_num, value) => ([0xB0 | channel, cc_num, value], 3),
+            MidiEvent::PitchBend(bend) => {
+                let bend = bend.clamp(0, 16383) as u16;
+                ([0xE0 | channel, (bend & 0x7F) as u8, (bend >> 7) as u8], 3)
+            }
+            MidiEvent::ChannelPressure(pressure) => ([0xD0 | channel, pressure, 0], 2),
+            MidiEvent::ProgramChange(program) => ([0xC0 | channel, program, 0], 2),
+        }
+    }
+}
+
+/// A System Common or Real-Time message (clock, transport, MTC quarter
+/// frame) with the driver's timestamp in µs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemMessage {
+    pub time: u64,
+    data: [u8; 3],
+    len: usize,
+}
+
+impl SystemMessage {
+    /// Keeps the first three bytes of `bytes`
+    pub(crate) fn new(bytes: &[u8], time: u64) -> Self {
+        let len = bytes.len().min(3);
+        let mut data = [0; 3];
+        data[..len].copy_from_slice(&bytes[..len]);
+        Self { time, data, len }
+    }
+
+    /// The message, for `ClockFollower::handle_message` or
+    /// `MtcFollower::handle_message`
+    pub fn bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+#[cfg(feature = "midir")]
 pub struct MidiInputHandler {
     connection: Option<MidiInputConnection<()>>,
-    event_sender: Sender<MidiEvent>,
-    event_receiver: Receiver<MidiEvent>,
+    event_sender: Sender<TimedEvent>,
+    event_receiver: Receiver<TimedEvent>,
+    sysex_sender: Sender<Vec<u8>>,
+    sysex_receiver: Receiver<Vec<u8>>,
+    system_sender: Sender<SystemMessage>,
+    system_receiver: Receiver<SystemMessage>,
     running: Arc<AtomicBool>,
 }
 
+#[cfg(feature = "midir")]
 impl MidiInputHandler {
     pub fn new() -> Self {
         let (sender, receiver) = bounded(256); // Bounded queue to prevent unbounded growth
+        let (sysex_sender, sysex_receiver) = bounded(64);
+        let (system_sender, system_receiver) = bounded(256);
         Self {
             connection: None,
             event_sender: sender,
             event_receiver: receiver,
+            sysex_sender,
+            sysex_receiver,
+            system_sender,
+            system_receiver,
             running: Arc::new(AtomicBool::new(true)),
         }
     }
@@ -52,19 +216,25 @@ impl MidiInputHandler {
         let port = &ports[index];
         let running = self.running.clone();
         let sender = self.event_sender.clone();
+        let sysex_sender = self.sysex_sender.clone();
+        let system_sender = self.system_sender.clone();
 
         let connection = midi_in
             .connect(
                 port,
                 "auxide-midi-input",
-                move |_, message, _| {
+                move |stamp, message, _| {
                     if !running.load(Ordering::Relaxed) {
                         return;
                     }
 
-                    if let Some(event) = Self::parse_message(message) {
+                    if let Some(event) = ChannelEvent::from_bytes(message) {
                         // Non-blocking send - drop message if queue is full
-                        let _ = sender.try_send(event);
+                        let _ = sender.try_send(TimedEvent { time: stamp, event });
+                    } else if message.first() == Some(&0xF0) {
+                        let _ = sysex_sender.try_send(message.to_vec());
+                    } else if let [0xF1..=0xFF, ..] = *message {
+                        let _ = system_sender.try_send(SystemMessage::new(message, stamp));
                     }
                 },
                 (),
@@ -76,9 +246,30 @@ impl MidiInputHandler {
     }
 
     pub fn try_recv(&self) -> Option<MidiEvent> {
+        self.try_recv_channel().map(|e| e.event)
+    }
+
+    /// Receive the next event along with its MIDI channel
+    pub fn try_recv_channel(&self) -> Option<ChannelEvent> {
+        self.try_recv_timed().map(|e| e.event)
+    }
+
+    /// Receive the next event with the driver's timestamp (µs, on the
+    /// driver's own clock)
+    pub fn try_recv_timed(&self) -> Option<TimedEvent> {
         self.event_receiver.try_recv().ok()
     }
 
+    /// Receive the next complete SysEx message, F0 to F7
+    pub fn try_recv_sysex(&self) -> Option<Vec<u8>> {
+        self.sysex_receiver.try_recv().ok()
+    }
+
+    /// Receive the next System Common or Real-Time message
+    pub fn try_recv_system(&self) -> Option<SystemMessage> {
+        self.system_receiver.try_recv().ok()
+    }
+
     pub fn disconnect(&mut self) {
         self.running.store(false, Ordering::Relaxed);
         if let Some(_connection) = self.connection.take() {
@@ -87,60 +278,23 @@ impl MidiInputHandler {
     }
 
     pub fn parse_message(bytes: &[u8]) -> Option<MidiEvent> {
-        if bytes.is_empty() {
-            return None;
-        }
-
-        let status = bytes[0];
+        MidiEvent::from_bytes(bytes)
+    }
 
-        match status & 0xF0 {
-            0x90 => {
-                // Note On
-                if bytes.len() >= 3 && bytes[2] > 0 {
-                    Some(MidiEvent::NoteOn(bytes[1], bytes[2]))
-                } else if bytes.len() >= 3 {
-                    // Note On with velocity 0 is Note Off
-                    Some(MidiEvent::NoteOff(bytes[1], bytes[2]))
-                } else {
-                    None
-                }
-            }
-            0x80 => {
-                // Note Off
-                if bytes.len() >= 3 {
-                    Some(MidiEvent::NoteOff(bytes[1], bytes[2]))
-                } else {
-                    None
-                }
-            }
-            0xB0 => {
-                // Control Change
-                if bytes.len() >= 3 {
-                    Some(MidiEvent::ControlChange(bytes[1], bytes[2]))
-                } else {
-                    None
-                }
-            }
-            0xE0 => {
-                // Pitch Bend
-                if bytes.len() >= 3 {
-                    let bend = ((bytes[2] as i16) << 7) | (bytes[1] as i16);
-                    Some(MidiEvent::PitchBend(bend))
-                } else {
-                    None
-                }
-            }
-            _ => None, // Ignore other message types for now
-        }
+    /// Parse a message, keeping the channel from the status byte
+    pub fn parse_channel_message(bytes: &[u8]) -> Option<ChannelEvent> {
+        ChannelEvent::from_bytes(bytes)
     }
 }
 
+#[cfg(feature = "midir")]
 impl Default for MidiInputHandler {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[cfg(feature = "midir")]
 impl Drop for MidiInputHandler {
     fn drop(&mut self) {
         self.disconnect();
@@ -154,42 +308,86 @@ mod tests {
     #[test]
     fn midi_bytes_to_note_on() {
         let bytes = [0x90, 60, 100]; // Note On, C4, velocity 100
-        let event = MidiInputHandler::parse_message(&bytes);
+        let event = MidiEvent::from_bytes(&bytes);
         assert_eq!(event, Some(MidiEvent::NoteOn(60, 100)));
     }
 
     #[test]
     fn midi_bytes_to_note_off() {
         let bytes = [0x80, 60, 64]; // Note Off, C4, velocity 64
-        let event = MidiInputHandler::parse_message(&bytes);
+        let event = MidiEvent::from_bytes(&bytes);
         assert_eq!(event, Some(MidiEvent::NoteOff(60, 64)));
     }
 
     #[test]
     fn midi_bytes_to_cc() {
         let bytes = [0xB0, 74, 127]; // CC, number 74, value 127
-        let event = MidiInputHandler::parse_message(&bytes);
+        let event = MidiEvent::from_bytes(&bytes);
         assert_eq!(event, Some(MidiEvent::ControlChange(74, 127)));
     }
 
     #[test]
     fn midi_bytes_pitch_bend() {
         let bytes = [0xE0, 0x00, 0x40]; // Pitch bend, center position
-        let event = MidiInputHandler::parse_message(&bytes);
+        let event = MidiEvent::from_bytes(&bytes);
         assert_eq!(event, Some(MidiEvent::PitchBend(8192)));
     }
 
+    #[test]
+    fn midi_bytes_channel_pressure() {
+        let bytes = [0xD0, 90]; // Channel pressure
+        let event = MidiEvent::from_bytes(&bytes);
+        assert_eq!(event, Some(MidiEvent::ChannelPressure(90)));
+    }
+
     #[test]
     fn garbage_bytes_none() {
         let bytes = [0xFF, 0xFF, 0xFF]; // Invalid MIDI
-        let event = MidiInputHandler::parse_message(&bytes);
+        let event = MidiEvent::from_bytes(&bytes);
         assert_eq!(event, None);
     }
 
+    #[test]
+    fn channel_taken_from_status_byte() {
+        let bytes = [0x93, 60, 100]; // Note On, channel 4
+        let event = ChannelEvent::from_bytes(&bytes);
+        assert_eq!(
+            event,
+            Some(ChannelEvent {
+                channel: 3,
+                event: MidiEvent::NoteOn(60, 100)
+            })
+        );
+    }
+
+    #[test]
+    fn all_notes_off_detected() {
+        assert!(MidiEvent::ControlChange(123, 0).is_all_notes_off());
+        assert!(MidiEvent::ControlChange(120, 0).is_all_notes_off());
+        assert!(MidiEvent::ControlChange(126, 1).is_all_notes_off()); // Mono On
+        assert!(!MidiEvent::ControlChange(64, 0).is_all_notes_off());
+        assert!(!MidiEvent::NoteOff(60, 0).is_all_notes_off());
+    }
+
+    #[test]
+    fn channel_events_encode_to_what_they_parse_from() {
+        for bytes in [
+            &[0x93, 60, 100][..],
+            &[0x80, 60, 64],
+            &[0xBF, 74, 127],
+            &[0xE1, 0x7F, 0x7F],
+            &[0xD2, 90],
+        ] {
+            let event = ChannelEvent::from_bytes(bytes).unwrap();
+            let (encoded, len) = event.to_bytes();
+            assert_eq!(&encoded[..len], bytes);
+        }
+    }
+
     #[test]
     fn note_on_velocity_zero_is_note_off() {
         let bytes = [0x90, 60, 0]; // Note On with velocity 0
-        let event = MidiInputHandler::parse_message(&bytes);
+        let event = MidiEvent::from_bytes(&bytes);
         assert_eq!(event, Some(MidiEvent::NoteOff(60, 0)));
     }
 }
@@ -0,0 +1,104 @@
+//! MIDI output with midir
+
+use crate::midi_input::{ChannelEvent, CC_ALL_NOTES_OFF, CC_ALL_SOUND_OFF};
+use crate::program_map::PatchSelect;
+use anyhow::Result;
+use midir::{MidiOutput, MidiOutputConnection};
+
+/// Sends raw MIDI messages to one output device
+#[derive(Default)]
+pub struct MidiOutputHandler {
+    connection: Option<MidiOutputConnection>,
+}
+
+impl MidiOutputHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn list_devices() -> Result<Vec<String>> {
+        let midi_out = MidiOutput::new("auxide-midi")?;
+        Ok(midi_out
+            .ports()
+            .into_iter()
+            .filter_map(|port| midi_out.port_name(&port).ok())
+            .collect())
+    }
+
+    pub fn connect_device(&mut self, index: usize) -> Result<()> {
+        let midi_out = MidiOutput::new("auxide-midi")?;
+        let ports = midi_out.ports();
+
+        if index >= ports.len() {
+            return Err(anyhow::anyhow!("Device index {} out of range", index));
+        }
+
+        let connection = midi_out
+            .connect(&ports[index], "auxide-midi-output")
+            .map_err(|e| anyhow::anyhow!("MIDI connect error: {:?}", e))?;
+        self.connection = Some(connection);
+        Ok(())
+    }
+
+    /// Open the first MIDI output whose name contains `name`, ignoring case
+    pub fn connect_by_name(&mut self, name: &str) -> Result<()> {
+        let name = name.to_lowercase();
+        let index = Self::list_devices()?
+            .iter()
+            .position(|device| device.to_lowercase().contains(&name))
+            .ok_or_else(|| anyhow::anyhow!("No MIDI output matching {:?}", name))?;
+        self.connect_device(index)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Send one complete message (channel, system or SysEx)
+    pub fn send(&mut self, message: &[u8]) -> Result<()> {
+        let connection = self
+            .connection
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("MIDI output not connected"))?;
+        connection
+            .send(message)
+            .map_err(|e| anyhow::anyhow!("MIDI send error: {:?}", e))
+    }
+
+    pub fn send_event(&mut self, event: &ChannelEvent) -> Result<()> {
+        let (bytes, len) = event.to_bytes();
+        self.send(&bytes[..len])
+    }
+
+    /// Select `patch` on `channel`: Bank Select MSB and LSB, then Program
+    /// Change
+    pub fn send_patch_select(&mut self, channel: u8, patch: PatchSelect) -> Result<()> {
+        let bytes = patch.to_bytes(channel);
+        for message in [&bytes[0..3], &bytes[3..6], &bytes[6..8]] {
+            self.send(message)?;
+        }
+        Ok(())
+    }
+
+    /// Send All Sound Off and All Notes Off on every channel
+    pub fn panic(&mut self) -> Result<()> {
+        for channel in 0..16u8 {
+            for cc in [CC_ALL_SOUND_OFF, CC_ALL_NOTES_OFF] {
+                self.send(&[0xB0 | channel, cc, 0])?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            connection.close();
+        }
+    }
+}
+
+impl Drop for MidiOutputHandler {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
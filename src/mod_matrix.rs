@@ -0,0 +1,296 @@
+//! Modulation matrix: MIDI sources routed to parameters with depth and curve
+
+use crate::cc_mapping::ParamTarget;
+use crate::midi_input::MidiEvent;
+
+/// A modulation source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ModSource {
+    /// Controller value, 0.0 to 1.0
+    ControlChange(u8),
+    /// Channel aftertouch, 0.0 to 1.0
+    ChannelPressure,
+    /// Pitch bend, -1.0 to 1.0
+    PitchBend,
+    /// Note-on velocity of the voice, 0.0 to 1.0
+    Velocity,
+    /// Key position of the voice in octaves from middle C (note 60)
+    KeyTrack,
+    /// Value of an LFO slot (e.g. a clock-synced `SyncedLfo`), -1.0 to 1.0
+    Lfo(u8),
+}
+
+impl ModSource {
+    /// Check whether the source depends on the voice being evaluated
+    pub fn is_per_voice(self) -> bool {
+        matches!(self, ModSource::Velocity | ModSource::KeyTrack)
+    }
+}
+
+/// Number of LFO slots a `ModMatrix` tracks
+pub const MOD_LFO_SLOTS: usize = 4;
+
+/// Response curve applied to a source before scaling by depth
+/// Curves shape the magnitude and keep the sign of bipolar sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ModCurve {
+    #[default]
+    Linear,
+    /// Slow start, fast finish (x²)
+    Exponential,
+    /// Fast start, slow finish (√x)
+    Logarithmic,
+    /// Slow at both ends (smoothstep)
+    SCurve,
+}
+
+impl ModCurve {
+    pub fn apply(self, value: f32) -> f32 {
+        let magnitude = value.abs();
+        let shaped = match self {
+            ModCurve::Linear => magnitude,
+            ModCurve::Exponential => magnitude * magnitude,
+            ModCurve::Logarithmic => magnitude.sqrt(),
+            ModCurve::SCurve => {
+                let x = magnitude.min(1.0);
+                x * x * (3.0 - 2.0 * x)
+            }
+        };
+        shaped.copysign(value)
+    }
+}
+
+/// One source-to-target connection
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModRoute {
+    pub source: ModSource,
+    pub target: ParamTarget,
+    pub depth: f32,
+    pub curve: ModCurve,
+}
+
+impl ModRoute {
+    pub fn new(source: ModSource, target: ParamTarget, depth: f32) -> Self {
+        Self {
+            source,
+            target,
+            depth,
+            curve: ModCurve::Linear,
+        }
+    }
+
+    pub fn with_curve(mut self, curve: ModCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+}
+
+/// Note and velocity of the voice a modulation is evaluated for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoiceContext {
+    pub note: u8,
+    pub velocity: u8,
+}
+
+/// Routes MIDI modulation sources to parameters
+///
+/// Channel-wide sources (CCs, pressure, bend) are tracked from incoming
+/// events; per-voice sources (velocity, key tracking) come from the
+/// `VoiceContext` passed to `evaluate`. Evaluation never allocates.
+#[derive(Debug, Clone)]
+pub struct ModMatrix {
+    routes: Vec<ModRoute>,
+    controllers: [f32; 128],
+    pressure: f32,
+    bend: f32,
+    lfos: [f32; MOD_LFO_SLOTS],
+}
+
+impl ModMatrix {
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            controllers: [0.0; 128],
+            pressure: 0.0,
+            bend: 0.0,
+            lfos: [0.0; MOD_LFO_SLOTS],
+        }
+    }
+
+    /// Add a route, returning its index
+    pub fn add_route(&mut self, route: ModRoute) -> usize {
+        self.routes.push(route);
+        self.routes.len() - 1
+    }
+
+    /// Remove a route by index
+    pub fn remove_route(&mut self, index: usize) -> Option<ModRoute> {
+        (index < self.routes.len()).then(|| self.routes.remove(index))
+    }
+
+    pub fn routes(&self) -> &[ModRoute] {
+        &self.routes
+    }
+
+    pub fn route_mut(&mut self, index: usize) -> Option<&mut ModRoute> {
+        self.routes.get_mut(index)
+    }
+
+    /// Update channel-wide sources from a MIDI event
+    pub fn handle_event(&mut self, event: &MidiEvent) {
+        match *event {
+            MidiEvent::ControlChange(cc_num, value) => {
+                if let Some(slot) = self.controllers.get_mut(cc_num as usize) {
+                    *slot = (value & 0x7F) as f32 / 127.0;
+                }
+            }
+            MidiEvent::ChannelPressure(value) => self.pressure = (value & 0x7F) as f32 / 127.0,
+            MidiEvent::PitchBend(bend) => {
+                self.bend = ((bend - 8192) as f32 / 8192.0).clamp(-1.0, 1.0);
+            }
+            _ => {}
+        }
+    }
+
+    /// Publish the current value of an LFO slot, typically once per block
+    pub fn set_lfo(&mut self, slot: u8, value: f32) {
+        if let Some(lfo) = self.lfos.get_mut(slot as usize) {
+            *lfo = value.clamp(-1.0, 1.0);
+        }
+    }
+
+    /// Get the current value of a source
+    /// Per-voice sources are 0.0 without a voice
+    pub fn source_value(&self, source: ModSource, voice: Option<&VoiceContext>) -> f32 {
+        match source {
+            ModSource::ControlChange(cc_num) => self
+                .controllers
+                .get(cc_num as usize)
+                .copied()
+                .unwrap_or(0.0),
+            ModSource::ChannelPressure => self.pressure,
+            ModSource::PitchBend => self.bend,
+            ModSource::Velocity => voice.map_or(0.0, |v| v.velocity as f32 / 127.0),
+            ModSource::KeyTrack => voice.map_or(0.0, |v| (v.note as f32 - 60.0) / 12.0),
+            ModSource::Lfo(slot) => self.lfos.get(slot as usize).copied().unwrap_or(0.0),
+        }
+    }
+
+    /// Sum of every route's contribution to a target
+    pub fn evaluate(&self, target: ParamTarget, voice: Option<&VoiceContext>) -> f32 {
+        self.routes
+            .iter()
+            .filter(|route| route.target == target)
+            .map(|route| route.depth * route.curve.apply(self.source_value(route.source, voice)))
+            .sum()
+    }
+
+    /// Reset channel-wide sources to rest (controllers 0, bend centred)
+    pub fn reset_sources(&mut self) {
+        self.controllers = [0.0; 128];
+        self.pressure = 0.0;
+        self.bend = 0.0;
+        self.lfos = [0.0; MOD_LFO_SLOTS];
+    }
+}
+
+impl Default for ModMatrix {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIBRATO_DEPTH: ParamTarget = ParamTarget::Custom(1);
+
+    #[test]
+    fn aftertouch_adds_vibrato() {
+        let mut matrix = ModMatrix::new();
+        matrix.add_route(ModRoute::new(
+            ModSource::ChannelPressure,
+            VIBRATO_DEPTH,
+            0.5,
+        ));
+
+        assert_eq!(matrix.evaluate(VIBRATO_DEPTH, None), 0.0);
+        matrix.handle_event(&MidiEvent::ChannelPressure(127));
+        assert_eq!(matrix.evaluate(VIBRATO_DEPTH, None), 0.5);
+    }
+
+    #[test]
+    fn routes_to_same_target_sum() {
+        let mut matrix = ModMatrix::new();
+        matrix.add_route(ModRoute::new(
+            ModSource::ControlChange(1),
+            ParamTarget::FilterCutoff,
+            1000.0,
+        ));
+        matrix.add_route(ModRoute::new(
+            ModSource::Velocity,
+            ParamTarget::FilterCutoff,
+            500.0,
+        ));
+        matrix.add_route(ModRoute::new(
+            ModSource::KeyTrack,
+            ParamTarget::FilterCutoff,
+            100.0,
+        ));
+
+        matrix.handle_event(&MidiEvent::ControlChange(1, 127));
+        let voice = VoiceContext {
+            note: 72,
+            velocity: 127,
+        };
+        assert_eq!(
+            matrix.evaluate(ParamTarget::FilterCutoff, Some(&voice)),
+            1600.0
+        );
+        assert_eq!(matrix.evaluate(ParamTarget::FilterCutoff, None), 1000.0);
+    }
+
+    #[test]
+    fn curves_keep_sign() {
+        assert_eq!(ModCurve::Exponential.apply(-0.5), -0.25);
+        assert_eq!(ModCurve::Logarithmic.apply(0.25), 0.5);
+        assert_eq!(ModCurve::SCurve.apply(1.0), 1.0);
+        assert_eq!(ModCurve::Linear.apply(-1.0), -1.0);
+    }
+
+    #[test]
+    fn synced_lfo_drives_a_route() {
+        use crate::lfo::{LfoShape, SyncedLfo};
+        use crate::tempo::{NoteDivision, MIDI_CLOCK};
+
+        let mut matrix = ModMatrix::new();
+        matrix.add_route(ModRoute::new(
+            ModSource::Lfo(0),
+            ParamTarget::FilterCutoff,
+            200.0,
+        ));
+        let mut lfo = SyncedLfo::new(LfoShape::Square, NoteDivision::Eighth.into());
+        lfo.handle_realtime(MIDI_CLOCK);
+        matrix.set_lfo(0, lfo.value());
+        assert_eq!(matrix.evaluate(ParamTarget::FilterCutoff, None), 200.0);
+        for _ in 0..6 {
+            lfo.handle_realtime(MIDI_CLOCK);
+        }
+        matrix.set_lfo(0, lfo.value());
+        assert_eq!(matrix.evaluate(ParamTarget::FilterCutoff, None), -200.0);
+        assert_eq!(matrix.source_value(ModSource::Lfo(9), None), 0.0);
+    }
+
+    #[test]
+    fn pitch_bend_is_bipolar() {
+        let mut matrix = ModMatrix::new();
+        matrix.handle_event(&MidiEvent::PitchBend(0));
+        assert_eq!(matrix.source_value(ModSource::PitchBend, None), -1.0);
+        matrix.handle_event(&MidiEvent::PitchBend(8192));
+        assert_eq!(matrix.source_value(ModSource::PitchBend, None), 0.0);
+    }
+}
@@ -0,0 +1,415 @@
+//! MIDI Polyphonic Expression (MPE) routing
+//!
+//! MPE controllers send every note on its own member channel, so pitch bend,
+//! channel pressure and CC74 (timbre) on that channel apply to a single note.
+
+use crate::midi_input::{ChannelEvent, MidiEvent};
+use crate::rpn::{
+    ParameterNumber, ParameterNumberDecoder, CC_NRPN_LSB, CC_RPN_MSB, RPN_MPE_CONFIGURATION,
+    RPN_PITCH_BEND_SENSITIVITY,
+};
+use crate::voice_allocator::{VoiceAllocator, VoiceId};
+
+/// CC number carrying per-note timbre (the MPE "Y" dimension)
+pub const MPE_TIMBRE_CC: u8 = 74;
+
+/// Default pitch bend range of member channels, in semitones
+pub const MPE_DEFAULT_NOTE_BEND_RANGE: f32 = 48.0;
+/// Default pitch bend range of the master channel, in semitones
+pub const MPE_DEFAULT_MASTER_BEND_RANGE: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpeZoneKind {
+    /// Master channel 1, members counting up from channel 2
+    Lower,
+    /// Master channel 16, members counting down from channel 15
+    Upper,
+}
+
+/// One MPE zone: a master channel plus a block of member channels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MpeZone {
+    pub kind: MpeZoneKind,
+    pub member_count: u8, // 0 = zone disabled
+    pub note_bend_range: f32,
+    pub master_bend_range: f32,
+}
+
+impl MpeZone {
+    pub fn new(kind: MpeZoneKind, member_count: u8) -> Self {
+        Self {
+            kind,
+            member_count: member_count.min(15),
+            note_bend_range: MPE_DEFAULT_NOTE_BEND_RANGE,
+            master_bend_range: MPE_DEFAULT_MASTER_BEND_RANGE,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.member_count > 0
+    }
+
+    /// Master channel (0-15)
+    pub fn master_channel(&self) -> u8 {
+        match self.kind {
+            MpeZoneKind::Lower => 0,
+            MpeZoneKind::Upper => 15,
+        }
+    }
+
+    /// Check whether a channel (0-15) is one of this zone's member channels
+    pub fn is_member(&self, channel: u8) -> bool {
+        let count = self.member_count;
+        match self.kind {
+            MpeZoneKind::Lower => (1..=count).contains(&channel),
+            MpeZoneKind::Upper => channel <= 14 && channel + count >= 15,
+        }
+    }
+}
+
+/// Lower/upper zone layout, kept up to date from MPE Configuration Messages
+#[derive(Debug, Clone)]
+pub struct MpeZoneConfig {
+    lower: MpeZone,
+    upper: MpeZone,
+    decoder: ParameterNumberDecoder,
+}
+
+impl MpeZoneConfig {
+    /// Create a configuration with both zones disabled
+    pub fn new() -> Self {
+        Self {
+            lower: MpeZone::new(MpeZoneKind::Lower, 0),
+            upper: MpeZone::new(MpeZoneKind::Upper, 0),
+            decoder: ParameterNumberDecoder::new(),
+        }
+    }
+
+    /// The common single-zone setup: lower zone using all 15 member channels
+    pub fn single_lower_zone() -> Self {
+        let mut config = Self::new();
+        config.set_zone(MpeZoneKind::Lower, 15);
+        config
+    }
+
+    pub fn lower(&self) -> &MpeZone {
+        &self.lower
+    }
+
+    pub fn upper(&self) -> &MpeZone {
+        &self.upper
+    }
+
+    /// Set a zone's member channel count (0 disables the zone)
+    /// Per the MPE spec the other zone shrinks if the two would overlap
+    pub fn set_zone(&mut self, kind: MpeZoneKind, member_count: u8) {
+        let member_count = member_count.min(15);
+        match kind {
+            MpeZoneKind::Lower => {
+                self.lower = MpeZone::new(kind, member_count);
+                self.upper.member_count = self.upper.member_count.min(14 - member_count.min(14));
+            }
+            MpeZoneKind::Upper => {
+                self.upper = MpeZone::new(kind, member_count);
+                self.lower.member_count = self.lower.member_count.min(14 - member_count.min(14));
+            }
+        }
+    }
+
+    /// Find the zone a channel belongs to, as master or member
+    pub fn zone_for_channel(&self, channel: u8) -> Option<&MpeZone> {
+        [&self.lower, &self.upper].into_iter().find(|zone| {
+            zone.is_enabled() && (zone.master_channel() == channel || zone.is_member(channel))
+        })
+    }
+
+    /// Check whether a channel is the master channel of an enabled zone
+    pub fn is_master_channel(&self, channel: u8) -> bool {
+        self.zone_for_channel(channel)
+            .is_some_and(|zone| zone.master_channel() == channel)
+    }
+
+    /// Pitch bend range in semitones for messages on a channel
+    pub fn bend_range_for_channel(&self, channel: u8) -> f32 {
+        match self.zone_for_channel(channel) {
+            Some(zone) if zone.master_channel() == channel => zone.master_bend_range,
+            Some(zone) => zone.note_bend_range,
+            None => MPE_DEFAULT_MASTER_BEND_RANGE,
+        }
+    }
+
+    /// Feed an event; MPE configuration and bend range RPNs update the zones
+    /// Returns true if the event was consumed as configuration data
+    pub fn handle_event(&mut self, event: &ChannelEvent) -> bool {
+        let MidiEvent::ControlChange(cc_num, value) = event.event else {
+            return false;
+        };
+        let channel = event.channel;
+
+        let Some(change) = self.decoder.handle_cc(channel, cc_num, value) else {
+            // Parameter selection CCs are consumed; data entry without a selection is not
+            return matches!(cc_num, CC_NRPN_LSB..=CC_RPN_MSB);
+        };
+
+        match change.number {
+            ParameterNumber::Registered(RPN_MPE_CONFIGURATION) => match channel {
+                0 => self.set_zone(MpeZoneKind::Lower, change.msb()),
+                15 => self.set_zone(MpeZoneKind::Upper, change.msb()),
+                _ => {}
+            },
+            ParameterNumber::Registered(RPN_PITCH_BEND_SENSITIVITY) => {
+                let range = change.msb() as f32 + change.lsb() as f32 / 100.0;
+                for zone in [&mut self.lower, &mut self.upper] {
+                    if !zone.is_enabled() {
+                        continue;
+                    }
+                    if zone.master_channel() == channel {
+                        zone.master_bend_range = range;
+                    } else if zone.is_member(channel) {
+                        zone.note_bend_range = range;
+                    }
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+impl Default for MpeZoneConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An MPE event resolved to the voice it belongs to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MpeMessage {
+    NoteOn {
+        voice: VoiceId,
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        voice: VoiceId,
+        note: u8,
+        velocity: u8,
+    },
+    PitchBend {
+        voice: VoiceId,
+        bend: i16,
+    },
+    Pressure {
+        voice: VoiceId,
+        value: u8,
+    },
+    Timbre {
+        voice: VoiceId,
+        value: u8,
+    },
+}
+
+impl MpeMessage {
+    /// The voice this message targets
+    pub fn voice(&self) -> VoiceId {
+        match *self {
+            MpeMessage::NoteOn { voice, .. }
+            | MpeMessage::NoteOff { voice, .. }
+            | MpeMessage::PitchBend { voice, .. }
+            | MpeMessage::Pressure { voice, .. }
+            | MpeMessage::Timbre { voice, .. } => voice,
+        }
+    }
+}
+
+/// Routes channel events to voices by tracking each note's member channel
+#[derive(Debug)]
+pub struct MpeRouter {
+    allocator: VoiceAllocator,
+    zones: MpeZoneConfig,
+}
+
+impl MpeRouter {
+    pub fn new() -> Self {
+        Self::with_allocator(VoiceAllocator::new())
+    }
+
+    /// Create a router that allocates voices from the given allocator
+    /// Zones start in the single lower zone layout until the controller
+    /// sends an MPE Configuration Message
+    pub fn with_allocator(allocator: VoiceAllocator) -> Self {
+        Self {
+            allocator,
+            zones: MpeZoneConfig::single_lower_zone(),
+        }
+    }
+
+    pub fn zones(&self) -> &MpeZoneConfig {
+        &self.zones
+    }
+
+    pub fn zones_mut(&mut self) -> &mut MpeZoneConfig {
+        &mut self.zones
+    }
+
+    pub fn allocator(&self) -> &VoiceAllocator {
+        &self.allocator
+    }
+
+    pub fn allocator_mut(&mut self) -> &mut VoiceAllocator {
+        &mut self.allocator
+    }
+
+    /// Handle an incoming event
+    /// Returns None for events that don't belong to a sounding note
+    pub fn handle_event(&mut self, event: &ChannelEvent) -> Option<MpeMessage> {
+        if self.zones.handle_event(event) {
+            return None;
+        }
+
+        let channel = event.channel;
+        match event.event {
+            MidiEvent::NoteOn(note, velocity) => {
+                let voice = self.allocator.allocate_voice_on_channel(note, channel)?;
+                Some(MpeMessage::NoteOn {
+                    voice,
+                    channel,
+                    note,
+                    velocity,
+                })
+            }
+            MidiEvent::NoteOff(note, velocity) => {
+                let voice = self.allocator.release_voice_on_channel(note, channel)?;
+                Some(MpeMessage::NoteOff {
+                    voice,
+                    note,
+                    velocity,
+                })
+            }
+            MidiEvent::PitchBend(bend) => {
+                let voice = self.allocator.voice_for_channel(channel)?;
+                Some(MpeMessage::PitchBend { voice, bend })
+            }
+            MidiEvent::ChannelPressure(value) => {
+                let voice = self.allocator.voice_for_channel(channel)?;
+                Some(MpeMessage::Pressure { voice, value })
+            }
+            MidiEvent::ControlChange(MPE_TIMBRE_CC, value) => {
+                let voice = self.allocator.voice_for_channel(channel)?;
+                Some(MpeMessage::Timbre { voice, value })
+            }
+            MidiEvent::ControlChange(..) | MidiEvent::ProgramChange(_) => None,
+        }
+    }
+}
+
+impl Default for MpeRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on_channel(channel: u8, event: MidiEvent) -> ChannelEvent {
+        ChannelEvent { channel, event }
+    }
+
+    #[test]
+    fn expression_follows_member_channel() {
+        let mut router = MpeRouter::new();
+
+        let first = router
+            .handle_event(&on_channel(1, MidiEvent::NoteOn(60, 100)))
+            .unwrap()
+            .voice();
+        let second = router
+            .handle_event(&on_channel(2, MidiEvent::NoteOn(64, 100)))
+            .unwrap()
+            .voice();
+
+        assert_eq!(
+            router.handle_event(&on_channel(2, MidiEvent::PitchBend(9000))),
+            Some(MpeMessage::PitchBend {
+                voice: second,
+                bend: 9000
+            })
+        );
+        assert_eq!(
+            router.handle_event(&on_channel(1, MidiEvent::ChannelPressure(50))),
+            Some(MpeMessage::Pressure {
+                voice: first,
+                value: 50
+            })
+        );
+        assert_eq!(
+            router.handle_event(&on_channel(1, MidiEvent::ControlChange(74, 20))),
+            Some(MpeMessage::Timbre {
+                voice: first,
+                value: 20
+            })
+        );
+    }
+
+    #[test]
+    fn mpe_configuration_message_sets_zones() {
+        let mut config = MpeZoneConfig::new();
+        assert!(config.zone_for_channel(1).is_none());
+
+        // Lower zone with 7 members, then upper zone with 10 (lower shrinks to 4)
+        for (channel, members) in [(0, 7), (15, 10)] {
+            for (cc, value) in [(101, 0), (100, 6), (6, members)] {
+                let cc_event = on_channel(channel, MidiEvent::ControlChange(cc, value));
+                assert!(config.handle_event(&cc_event));
+            }
+        }
+
+        assert_eq!(config.upper().member_count, 10);
+        assert_eq!(config.lower().member_count, 4);
+        assert!(config.lower().is_member(4));
+        assert!(!config.lower().is_member(5));
+        assert!(config.upper().is_member(5));
+        assert!(config.is_master_channel(15));
+        assert_eq!(
+            config.bend_range_for_channel(5),
+            MPE_DEFAULT_NOTE_BEND_RANGE
+        );
+    }
+
+    #[test]
+    fn bend_sensitivity_rpn_per_zone() {
+        let mut config = MpeZoneConfig::single_lower_zone();
+
+        // Member channel 3 sets per-note range to 24 semitones
+        for (cc, value) in [(101, 0), (100, 0), (6, 24)] {
+            config.handle_event(&on_channel(3, MidiEvent::ControlChange(cc, value)));
+        }
+        assert_eq!(config.bend_range_for_channel(3), 24.0);
+        assert_eq!(
+            config.bend_range_for_channel(0),
+            MPE_DEFAULT_MASTER_BEND_RANGE
+        );
+
+        // Unrelated CCs are not consumed
+        assert!(!config.handle_event(&on_channel(3, MidiEvent::ControlChange(74, 10))));
+    }
+
+    #[test]
+    fn expression_without_note_ignored() {
+        let mut router = MpeRouter::new();
+        router.handle_event(&on_channel(1, MidiEvent::NoteOn(60, 100)));
+        router.handle_event(&on_channel(1, MidiEvent::NoteOff(60, 0)));
+
+        assert_eq!(
+            router.handle_event(&on_channel(1, MidiEvent::PitchBend(0))),
+            None
+        );
+        assert_eq!(
+            router.handle_event(&on_channel(3, MidiEvent::ChannelPressure(10))),
+            None
+        );
+    }
+}
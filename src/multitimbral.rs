@@ -0,0 +1,232 @@
+//! Multitimbral voice allocation: one independent part per MIDI channel
+
+use crate::channel_mode::ChannelModes;
+use crate::midi_input::{ChannelEvent, MidiEvent};
+use crate::voice_allocator::{VoiceAllocator, VoiceId};
+
+pub const MIDI_CHANNELS: usize = 16;
+
+/// Voice allocation across up to 16 parts, one per MIDI channel
+///
+/// Each part owns its own allocator and voice budget, so a busy lead part
+/// can never steal voices from the drum or bass part.
+#[derive(Debug)]
+pub struct MultiTimbralAllocator {
+    parts: Vec<VoiceAllocator>,
+    modes: ChannelModes,
+    apply_modes: bool,
+}
+
+impl MultiTimbralAllocator {
+    /// Create an allocator giving every part the same voice budget
+    pub fn new(voices_per_part: usize) -> Self {
+        Self {
+            parts: (0..MIDI_CHANNELS)
+                .map(|_| VoiceAllocator::with_voices(voices_per_part))
+                .collect(),
+            modes: ChannelModes::new(),
+            apply_modes: false,
+        }
+    }
+
+    /// Create an allocator with an individual voice budget per part
+    /// A budget of 0 disables the part
+    pub fn with_budgets(budgets: [usize; MIDI_CHANNELS]) -> Self {
+        Self {
+            parts: budgets
+                .iter()
+                .map(|&count| VoiceAllocator::with_voices(count))
+                .collect(),
+            modes: ChannelModes::new(),
+            apply_modes: false,
+        }
+    }
+
+    /// Change the voice budget of a part (not RT-safe, reallocates the part)
+    pub fn set_part_voices(&mut self, channel: u8, voice_count: usize) {
+        if let Some(part) = self.parts.get_mut(channel as usize) {
+            *part = VoiceAllocator::with_voices(voice_count);
+        }
+        self.sync_modes();
+    }
+
+    /// Modes set by the channel mode messages received so far
+    pub fn channel_modes(&self) -> &ChannelModes {
+        &self.modes
+    }
+
+    /// Let Mono On / Poly On switch parts between mono and poly allocation
+    /// (off by default; modes are tracked either way)
+    pub fn set_apply_channel_modes(&mut self, apply: bool) {
+        self.apply_modes = apply;
+        self.sync_modes();
+    }
+
+    fn sync_modes(&mut self) {
+        for (channel, part) in self.parts.iter_mut().enumerate() {
+            let mono = self.apply_modes && self.modes.mode(channel as u8).mono;
+            part.set_polyphony(mono.then_some(1));
+        }
+    }
+
+    /// Get the allocator for a part
+    pub fn part(&self, channel: u8) -> Option<&VoiceAllocator> {
+        self.parts.get(channel as usize)
+    }
+
+    /// Get the mutable allocator for a part
+    pub fn part_mut(&mut self, channel: u8) -> Option<&mut VoiceAllocator> {
+        self.parts.get_mut(channel as usize)
+    }
+
+    /// Allocate a voice for a note in the given part
+    pub fn allocate_voice(&mut self, channel: u8, note: u8) -> Option<VoiceId> {
+        self.part_mut(channel)?.allocate_voice(note)
+    }
+
+    /// Release the voice playing a note in the given part
+    pub fn release_voice(&mut self, channel: u8, note: u8) -> Option<VoiceId> {
+        self.part_mut(channel)?.release_voice(note)
+    }
+
+    /// Release a specific voice in the given part
+    pub fn release_voice_id(&mut self, channel: u8, voice_id: VoiceId) -> bool {
+        self.part_mut(channel)
+            .is_some_and(|part| part.release_voice_id(voice_id))
+    }
+
+    /// Route a note event to its part
+    /// Returns the affected part and voice for NoteOn/NoteOff, None otherwise.
+    /// All Notes Off and channel mode messages release every voice of the
+    /// part.
+    pub fn handle_event(&mut self, event: &ChannelEvent) -> Option<(u8, VoiceId)> {
+        if self.modes.handle_event(event).is_some() && self.apply_modes {
+            self.sync_modes();
+        }
+        if event.event.is_all_notes_off() {
+            self.part_mut(event.channel)?.release_all();
+            return None;
+        }
+
+        let voice_id = match event.event {
+            MidiEvent::NoteOn(note, _) => self.allocate_voice(event.channel, note),
+            MidiEvent::NoteOff(note, _) => self.release_voice(event.channel, note),
+            _ => None,
+        }?;
+        Some((event.channel, voice_id))
+    }
+
+    /// Get the number of active voices across all parts
+    pub fn active_voice_count(&self) -> usize {
+        self.parts.iter().map(|p| p.active_voice_count()).sum()
+    }
+}
+
+impl Default for MultiTimbralAllocator {
+    fn default() -> Self {
+        Self::new(crate::voice_allocator::MAX_VOICES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel_mode::{CC_MONO_ON, CC_POLY_ON};
+
+    #[test]
+    fn parts_are_independent() {
+        let mut allocator = MultiTimbralAllocator::new(2);
+
+        // Fill part 0; part 9 (drums) must be unaffected
+        allocator.allocate_voice(0, 60).unwrap();
+        allocator.allocate_voice(0, 62).unwrap();
+        allocator.allocate_voice(0, 64).unwrap(); // steals within part 0
+        allocator.allocate_voice(9, 36).unwrap();
+
+        assert_eq!(allocator.part(0).unwrap().active_voice_count(), 2);
+        assert_eq!(allocator.part(9).unwrap().active_voice_count(), 1);
+        assert_eq!(allocator.active_voice_count(), 3);
+    }
+
+    #[test]
+    fn disabled_part_allocates_nothing() {
+        let mut budgets = [4; MIDI_CHANNELS];
+        budgets[5] = 0;
+        let mut allocator = MultiTimbralAllocator::with_budgets(budgets);
+
+        assert_eq!(allocator.allocate_voice(5, 60), None);
+        assert!(allocator.allocate_voice(4, 60).is_some());
+        assert_eq!(allocator.allocate_voice(16, 60), None);
+    }
+
+    #[test]
+    fn events_routed_by_channel() {
+        let mut allocator = MultiTimbralAllocator::default();
+
+        let on = ChannelEvent {
+            channel: 2,
+            event: MidiEvent::NoteOn(48, 100),
+        };
+        let (channel, voice_id) = allocator.handle_event(&on).unwrap();
+        assert_eq!(channel, 2);
+
+        // Same note on another channel is not released
+        let off_other = ChannelEvent {
+            channel: 3,
+            event: MidiEvent::NoteOff(48, 0),
+        };
+        assert_eq!(allocator.handle_event(&off_other), None);
+
+        let off = ChannelEvent {
+            channel: 2,
+            event: MidiEvent::NoteOff(48, 0),
+        };
+        assert_eq!(allocator.handle_event(&off), Some((2, voice_id)));
+        assert_eq!(allocator.active_voice_count(), 0);
+    }
+
+    #[test]
+    fn mode_messages_switch_parts_to_mono() {
+        let mut allocator = MultiTimbralAllocator::new(4);
+        let mono_on = |channel| ChannelEvent {
+            channel,
+            event: MidiEvent::ControlChange(CC_MONO_ON, 1),
+        };
+        allocator.handle_event(&mono_on(1));
+        assert!(allocator.channel_modes().mode(1).mono);
+        assert!(!allocator.part(1).unwrap().is_mono()); // Not applied
+
+        allocator.set_apply_channel_modes(true);
+        assert!(allocator.part(1).unwrap().is_mono());
+        allocator.allocate_voice(1, 60).unwrap();
+        allocator.allocate_voice(1, 64).unwrap();
+        allocator.allocate_voice(2, 60).unwrap();
+        allocator.allocate_voice(2, 64).unwrap();
+        assert_eq!(allocator.part(1).unwrap().active_voice_count(), 1);
+        assert_eq!(allocator.part(2).unwrap().active_voice_count(), 2);
+
+        let poly_on = ChannelEvent {
+            channel: 1,
+            event: MidiEvent::ControlChange(CC_POLY_ON, 0),
+        };
+        allocator.handle_event(&poly_on);
+        assert!(!allocator.part(1).unwrap().is_mono());
+        assert_eq!(allocator.part(1).unwrap().active_voice_count(), 0);
+    }
+
+    #[test]
+    fn all_notes_off_clears_part() {
+        let mut allocator = MultiTimbralAllocator::default();
+        allocator.allocate_voice(0, 60).unwrap();
+        allocator.allocate_voice(0, 64).unwrap();
+        allocator.allocate_voice(1, 60).unwrap();
+
+        let panic = ChannelEvent {
+            channel: 0,
+            event: MidiEvent::ControlChange(123, 0),
+        };
+        allocator.handle_event(&panic);
+        assert_eq!(allocator.part(0).unwrap().active_voice_count(), 0);
+        assert_eq!(allocator.part(1).unwrap().active_voice_count(), 1);
+    }
+}
@@ -0,0 +1,190 @@
+//! MIDI note echo: repeats of each played note, scheduled as ordinary
+//! events so they go through voice allocation like any other note
+
+use crate::midi_input::{ChannelEvent, MidiEvent};
+use crate::scheduler::EventScheduler;
+use crate::tempo::{NoteDivision, NoteLength};
+
+/// Spacing between repeats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EchoTime {
+    Millis(f64),
+    /// A note length at the echo's tempo
+    Synced(NoteLength),
+}
+
+impl EchoTime {
+    pub fn to_micros(&self, bpm: f64) -> u64 {
+        let ms = match self {
+            EchoTime::Millis(ms) => *ms,
+            EchoTime::Synced(length) => length.to_ms(bpm),
+        };
+        (ms.max(0.0) * 1000.0) as u64
+    }
+}
+
+/// Repeats incoming notes with decaying velocity and optional transposition
+///
+/// Each note-on schedules `repeats` further note-ons `delay` apart, and the
+/// matching note-off schedules the same number of note-offs, so every
+/// repeat is as long as the played note. Repeats stop early once the
+/// velocity decays below 1 or the transposition leaves the MIDI range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteEcho {
+    repeats: u8,
+    delay: EchoTime,
+    bpm: f64,
+    decay: f32,
+    transpose: i8,
+}
+
+impl NoteEcho {
+    pub fn new(repeats: u8, delay: EchoTime) -> Self {
+        Self {
+            repeats,
+            delay,
+            bpm: 120.0,
+            decay: 0.7,
+            transpose: 0,
+        }
+    }
+
+    pub fn repeats(&self) -> u8 {
+        self.repeats
+    }
+
+    pub fn set_repeats(&mut self, repeats: u8) {
+        self.repeats = repeats;
+    }
+
+    pub fn set_delay(&mut self, delay: EchoTime) {
+        self.delay = delay;
+    }
+
+    /// Tempo for synced delays (e.g. from `ClockFollower::bpm`)
+    pub fn set_tempo(&mut self, bpm: f64) {
+        if bpm > 0.0 {
+            self.bpm = bpm;
+        }
+    }
+
+    /// Velocity multiplier applied per repeat (1.0 keeps it constant)
+    pub fn set_decay(&mut self, decay: f32) {
+        self.decay = decay.clamp(0.0, 1.0);
+    }
+
+    /// Semitones added per repeat
+    pub fn set_transpose(&mut self, semitones: i8) {
+        self.transpose = semitones;
+    }
+
+    /// Note and velocity of repeat `index` (1-based), if it sounds
+    fn repeat(&self, note: u8, velocity: u8, index: u8) -> Option<(u8, u8)> {
+        let note = note as i32 + self.transpose as i32 * index as i32;
+        let velocity = (velocity as f32 * self.decay.powi(index as i32)).round();
+        ((0..=127).contains(&note) && velocity >= 1.0).then_some((note as u8, velocity as u8))
+    }
+
+    /// Schedule the echoes of `event`, played at `time` (µs), into
+    /// `scheduler`; returns the number of events scheduled
+    pub fn process(
+        &self,
+        time: u64,
+        event: &ChannelEvent,
+        scheduler: &mut EventScheduler,
+    ) -> usize {
+        let mut scheduled = 0;
+        self.echoes(time, event, |time, echo| {
+            if scheduler.schedule(time, echo) {
+                scheduled += 1;
+            }
+        });
+        scheduled
+    }
+
+    /// Call `emit` with each echo of `event`, played at `time` (µs), and
+    /// the time it is due
+    pub fn echoes(&self, time: u64, event: &ChannelEvent, mut emit: impl FnMut(u64, ChannelEvent)) {
+        let delay = self.delay.to_micros(self.bpm);
+        for index in 1..=self.repeats {
+            let echo = match event.event {
+                MidiEvent::NoteOn(note, velocity) => match self.repeat(note, velocity, index) {
+                    Some((note, velocity)) => MidiEvent::NoteOn(note, velocity),
+                    None => break,
+                },
+                MidiEvent::NoteOff(note, velocity) => match self.repeat(note, 127, index) {
+                    Some((note, _)) => MidiEvent::NoteOff(note, velocity),
+                    None => break,
+                },
+                _ => break,
+            };
+            let echo = ChannelEvent {
+                channel: event.channel,
+                event: echo,
+            };
+            emit(time + delay * index as u64, echo);
+        }
+    }
+}
+
+impl Default for NoteEcho {
+    fn default() -> Self {
+        Self::new(
+            3,
+            EchoTime::Synced(NoteLength::dotted(NoteDivision::Eighth)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on(note: u8, velocity: u8) -> ChannelEvent {
+        ChannelEvent {
+            channel: 1,
+            event: MidiEvent::NoteOn(note, velocity),
+        }
+    }
+
+    #[test]
+    fn repeats_decay_and_transpose() {
+        let mut echo = NoteEcho::new(4, EchoTime::Millis(250.0));
+        echo.set_decay(0.5);
+        echo.set_transpose(12);
+        let mut scheduler = EventScheduler::new();
+        // The fourth repeat would be note 132, out of range
+        assert_eq!(echo.process(1000, &on(84, 100), &mut scheduler), 3);
+
+        let mut repeats = Vec::new();
+        while let Some(due) = scheduler.pop_due(u64::MAX) {
+            repeats.push((due.time, due.event));
+        }
+        assert_eq!(
+            repeats,
+            [
+                (251_000, on(96, 50)),
+                (501_000, on(108, 25)),
+                (751_000, on(120, 13)),
+            ]
+        );
+    }
+
+    #[test]
+    fn synced_note_offs_follow_the_note_ons() {
+        let mut echo = NoteEcho::new(2, EchoTime::Synced(NoteDivision::Quarter.into()));
+        echo.set_tempo(60.0);
+        let mut scheduler = EventScheduler::new();
+        let off = ChannelEvent {
+            channel: 0,
+            event: MidiEvent::NoteOff(60, 0),
+        };
+        assert_eq!(echo.process(500_000, &off, &mut scheduler), 2);
+        assert_eq!(scheduler.next_time(), Some(1_500_000));
+        let pitch_bend = ChannelEvent {
+            channel: 0,
+            event: MidiEvent::PitchBend(0),
+        };
+        assert_eq!(echo.process(0, &pitch_bend, &mut scheduler), 0);
+    }
+}
@@ -0,0 +1,139 @@
+//! NRPN parameter mapping
+
+use crate::cc_mapping::{ParamTarget, DEFAULT_RANGE};
+use crate::rpn::{ParameterChange, ParameterNumber, ParameterNumberDecoder};
+
+const MAX_14BIT: f32 = 16383.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NrpnMapping {
+    number: u16,
+    target: ParamTarget,
+    range: (f32, f32),
+}
+
+/// Maps NRPN parameter numbers to synth parameters
+///
+/// Feed raw CCs through `handle_cc`; NRPN selection and data entry are
+/// decoded per channel and the 14-bit data value is scaled into the
+/// mapping's output range (0.0-1.0 by default).
+#[derive(Debug)]
+pub struct NrpnMap {
+    mappings: Vec<NrpnMapping>,
+    decoder: ParameterNumberDecoder,
+}
+
+impl NrpnMap {
+    pub fn new() -> Self {
+        Self {
+            mappings: Vec::new(),
+            decoder: ParameterNumberDecoder::new(),
+        }
+    }
+
+    /// Map an NRPN number (0-16383) to a target with the default range
+    pub fn set_mapping(&mut self, number: u16, target: ParamTarget) {
+        self.set_mapping_with_range(number, target, DEFAULT_RANGE.0, DEFAULT_RANGE.1);
+    }
+
+    /// Map an NRPN number to a target that outputs values in `min..=max`
+    /// Allocates when adding a new number; not for the audio thread
+    pub fn set_mapping_with_range(&mut self, number: u16, target: ParamTarget, min: f32, max: f32) {
+        let mapping = NrpnMapping {
+            number: number & 0x3FFF,
+            target,
+            range: (min, max),
+        };
+        match self
+            .mappings
+            .iter_mut()
+            .find(|m| m.number == mapping.number)
+        {
+            Some(existing) => *existing = mapping,
+            None => self.mappings.push(mapping),
+        }
+    }
+
+    /// Remove the mapping for an NRPN number, returning its previous target
+    pub fn remove_mapping(&mut self, number: u16) -> Option<ParamTarget> {
+        let index = self.mappings.iter().position(|m| m.number == number)?;
+        Some(self.mappings.remove(index).target)
+    }
+
+    /// Get the target mapped to an NRPN number
+    pub fn target(&self, number: u16) -> Option<ParamTarget> {
+        self.mappings
+            .iter()
+            .find(|m| m.number == number)
+            .map(|m| m.target)
+    }
+
+    /// Map an already decoded parameter change
+    /// RPNs and unmapped NRPNs return None
+    pub fn map_change(&self, change: &ParameterChange) -> Option<(ParamTarget, f32)> {
+        let ParameterNumber::NonRegistered(number) = change.number else {
+            return None;
+        };
+        let mapping = self.mappings.iter().find(|m| m.number == number)?;
+        let (min, max) = mapping.range;
+        let normalized = change.value as f32 / MAX_14BIT;
+        Some((mapping.target, min + normalized * (max - min)))
+    }
+
+    /// Feed a raw control change from a channel
+    /// Returns the target and value when a mapped NRPN receives data entry
+    pub fn handle_cc(&mut self, channel: u8, cc_num: u8, value: u8) -> Option<(ParamTarget, f32)> {
+        let change = self.decoder.handle_cc(channel, cc_num, value)?;
+        self.map_change(&change)
+    }
+}
+
+impl Default for NrpnMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpn::{CC_DATA_ENTRY_LSB, CC_DATA_ENTRY_MSB, CC_NRPN_LSB, CC_NRPN_MSB};
+
+    #[test]
+    fn nrpn_data_entry_mapped() {
+        let mut map = NrpnMap::new();
+        map.set_mapping_with_range(136, ParamTarget::FilterCutoff, 0.0, 16383.0);
+
+        assert_eq!(map.handle_cc(0, CC_NRPN_MSB, 1), None);
+        assert_eq!(map.handle_cc(0, CC_NRPN_LSB, 8), None);
+        assert_eq!(
+            map.handle_cc(0, CC_DATA_ENTRY_MSB, 2),
+            Some((ParamTarget::FilterCutoff, 256.0))
+        );
+        assert_eq!(
+            map.handle_cc(0, CC_DATA_ENTRY_LSB, 3),
+            Some((ParamTarget::FilterCutoff, 259.0))
+        );
+    }
+
+    #[test]
+    fn rpn_and_unmapped_ignored() {
+        let mut map = NrpnMap::new();
+        map.set_mapping(0, ParamTarget::AttackTime);
+
+        let rpn = ParameterChange {
+            number: ParameterNumber::Registered(0),
+            value: 100,
+        };
+        assert_eq!(map.map_change(&rpn), None);
+
+        let unmapped = ParameterChange {
+            number: ParameterNumber::NonRegistered(5),
+            value: 100,
+        };
+        assert_eq!(map.map_change(&unmapped), None);
+
+        assert_eq!(map.remove_mapping(0), Some(ParamTarget::AttackTime));
+        assert_eq!(map.target(0), None);
+    }
+}
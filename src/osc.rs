@@ -0,0 +1,457 @@
+//! OSC bridge: drive the synth from OSC controllers (TouchOSC and similar)
+//! and send state back to them
+//!
+//! Fixed addresses carry MIDI events, with channels 0-15 and values in
+//! their MIDI ranges (ints or floats):
+//!
+//! | Address             | Arguments                |
+//! |---------------------|--------------------------|
+//! | `/midi/note_on`     | channel, note, velocity  |
+//! | `/midi/note_off`    | channel, note, velocity  |
+//! | `/midi/cc`          | channel, controller, value |
+//! | `/midi/pitch_bend`  | channel, bend (0-16383)  |
+//! | `/midi/pressure`    | channel, pressure        |
+//! | `/midi/program`     | channel, program         |
+//!
+//! Any other address can be mapped to a [`ParamTarget`] and carries one
+//! value, like a TouchOSC fader's `/1/fader1 0.5`.
+
+use crate::cc_mapping::ParamTarget;
+use crate::midi_input::{ChannelEvent, MidiEvent};
+use anyhow::{bail, Result};
+use std::collections::VecDeque;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+/// Largest OSC packet received
+pub const MAX_OSC_PACKET: usize = 4096;
+
+/// One OSC argument
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Blob(Vec<u8>),
+    Bool(bool),
+}
+
+impl OscArg {
+    /// Numeric value, with true/false as 1/0
+    pub fn as_f32(&self) -> Option<f32> {
+        match *self {
+            OscArg::Int(value) => Some(value as f32),
+            OscArg::Float(value) => Some(value),
+            OscArg::Bool(value) => Some(value as u8 as f32),
+            _ => None,
+        }
+    }
+}
+
+/// An OSC message: address pattern and arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    pub fn new(address: &str, args: Vec<OscArg>) -> Self {
+        Self {
+            address: address.to_string(),
+            args,
+        }
+    }
+
+    /// Encode as an OSC 1.0 packet
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_string(&mut bytes, &self.address);
+        let tags: String = std::iter::once(',')
+            .chain(self.args.iter().map(|arg| match arg {
+                OscArg::Int(_) => 'i',
+                OscArg::Float(_) => 'f',
+                OscArg::String(_) => 's',
+                OscArg::Blob(_) => 'b',
+                OscArg::Bool(true) => 'T',
+                OscArg::Bool(false) => 'F',
+            }))
+            .collect();
+        write_string(&mut bytes, &tags);
+        for arg in &self.args {
+            match arg {
+                OscArg::Int(value) => bytes.extend(value.to_be_bytes()),
+                OscArg::Float(value) => bytes.extend(value.to_be_bytes()),
+                OscArg::String(value) => write_string(&mut bytes, value),
+                OscArg::Blob(data) => {
+                    bytes.extend((data.len() as u32).to_be_bytes());
+                    bytes.extend(data);
+                    pad(&mut bytes);
+                }
+                OscArg::Bool(_) => {}
+            }
+        }
+        bytes
+    }
+
+    /// Decode a packet; bundles are flattened into their messages
+    pub fn parse_packet(bytes: &[u8]) -> Result<Vec<OscMessage>> {
+        let mut messages = Vec::new();
+        parse_packet_into(bytes, &mut messages)?;
+        Ok(messages)
+    }
+}
+
+fn parse_packet_into(bytes: &[u8], messages: &mut Vec<OscMessage>) -> Result<()> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if bytes.starts_with(b"#bundle\0") {
+        reader.take(16)?; // "#bundle" and the time tag
+        while reader.pos < bytes.len() {
+            let len = reader.size()?;
+            parse_packet_into(reader.take(len)?, messages)?;
+        }
+        return Ok(());
+    }
+
+    let address = reader.string()?;
+    if !address.starts_with('/') {
+        bail!("OSC address {:?} does not start with '/'", address);
+    }
+    let tags = if reader.pos < bytes.len() {
+        reader.string()?
+    } else {
+        ",".to_string() // Very old senders omit the type tags
+    };
+    let Some(tags) = tags.strip_prefix(',') else {
+        bail!("OSC type tags {:?} do not start with ','", tags);
+    };
+    let mut args = Vec::with_capacity(tags.len());
+    for tag in tags.chars() {
+        args.push(match tag {
+            'i' => OscArg::Int(reader.i32()?),
+            'f' => OscArg::Float(f32::from_bits(reader.i32()? as u32)),
+            's' => OscArg::String(reader.string()?),
+            'b' => {
+                let len = reader.size()?;
+                let data = reader.take(len)?.to_vec();
+                reader.align()?;
+                OscArg::Blob(data)
+            }
+            'T' => OscArg::Bool(true),
+            'F' => OscArg::Bool(false),
+            _ => bail!("Unsupported OSC type tag {:?}", tag),
+        });
+    }
+    messages.push(OscMessage {
+        address: address.to_string(),
+        args,
+    });
+    Ok(())
+}
+
+/// Write a null-terminated string padded to 4 bytes
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    out.extend(value.as_bytes());
+    out.push(0);
+    pad(out);
+}
+
+fn pad(out: &mut Vec<u8>) {
+    while !out.len().is_multiple_of(4) {
+        out.push(0);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len);
+        let Some(data) = end.and_then(|end| self.bytes.get(self.pos..end)) else {
+            bail!("OSC packet truncated");
+        };
+        self.pos += len;
+        Ok(data)
+    }
+
+    fn align(&mut self) -> Result<()> {
+        let padding = (4 - self.pos % 4) % 4;
+        self.take(padding).map(|_| ())
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        let data = self.take(4)?;
+        Ok(i32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+    }
+
+    /// A blob or bundle element size, which must not be negative
+    fn size(&mut self) -> Result<usize> {
+        let size = self.i32()?;
+        usize::try_from(size).map_err(|_| anyhow::anyhow!("Negative OSC size {}", size))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let rest = &self.bytes[self.pos.min(self.bytes.len())..];
+        let Some(len) = rest.iter().position(|&b| b == 0) else {
+            bail!("OSC string is not terminated");
+        };
+        let value = std::str::from_utf8(&rest[..len])?.to_string();
+        self.take(len + 1)?;
+        self.align()?;
+        Ok(value)
+    }
+}
+
+/// What an incoming OSC message means to the synth
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscInput {
+    Event(ChannelEvent),
+    Param(ParamTarget, f32),
+}
+
+/// Translates between OSC messages and MIDI events / parameter updates
+#[derive(Debug, Clone, Default)]
+pub struct OscBridge {
+    params: Vec<(String, ParamTarget)>,
+}
+
+impl OscBridge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Route `address` to `target`, replacing any earlier route for it
+    pub fn map_param(&mut self, address: &str, target: ParamTarget) {
+        self.params.retain(|(a, _)| a != address);
+        self.params.push((address.to_string(), target));
+    }
+
+    pub fn remove_param(&mut self, address: &str) -> Option<ParamTarget> {
+        let index = self.params.iter().position(|(a, _)| a == address)?;
+        Some(self.params.remove(index).1)
+    }
+
+    pub fn params(&self) -> &[(String, ParamTarget)] {
+        &self.params
+    }
+
+    /// Interpret an incoming message; None for unknown addresses or
+    /// missing arguments
+    pub fn translate(&self, message: &OscMessage) -> Option<OscInput> {
+        let arg = |i: usize| message.args.get(i)?.as_f32();
+        let byte = |i: usize| Some(arg(i)?.round().clamp(0.0, 127.0) as u8);
+        let event = match message.address.as_str() {
+            "/midi/note_on" => MidiEvent::NoteOn(byte(1)?, byte(2)?),
+            "/midi/note_off" => MidiEvent::NoteOff(byte(1)?, byte(2).unwrap_or(0)),
+            "/midi/cc" => MidiEvent::ControlChange(byte(1)?, byte(2)?),
+            "/midi/pitch_bend" => MidiEvent::PitchBend(arg(1)?.round().clamp(0.0, 16383.0) as i16),
+            "/midi/pressure" => MidiEvent::ChannelPressure(byte(1)?),
+            "/midi/program" => MidiEvent::ProgramChange(byte(1)?),
+            address => {
+                let (_, target) = self.params.iter().find(|(a, _)| a == address)?;
+                return Some(OscInput::Param(*target, arg(0)?));
+            }
+        };
+        let channel = arg(0)?.round().clamp(0.0, 15.0) as u8;
+        Some(OscInput::Event(ChannelEvent { channel, event }))
+    }
+
+    /// The message `translate` reads back as `event`
+    pub fn event_message(&self, event: &ChannelEvent) -> OscMessage {
+        let channel = OscArg::Int(event.channel as i32 & 0x0F);
+        let (address, values) = match event.event {
+            MidiEvent::NoteOn(note, velocity) => {
+                ("/midi/note_on", vec![note as i32, velocity as i32])
+            }
+            MidiEvent::NoteOff(note, velocity) => {
+                ("/midi/note_off", vec![note as i32, velocity as i32])
+            }
+            MidiEvent::ControlChange(cc, value) => ("/midi/cc", vec![cc as i32, value as i32]),
+            MidiEvent::PitchBend(bend) => ("/midi/pitch_bend", vec![bend as i32]),
+            MidiEvent::ChannelPressure(pressure) => ("/midi/pressure", vec![pressure as i32]),
+            MidiEvent::ProgramChange(program) => ("/midi/program", vec![program as i32]),
+        };
+        let args = std::iter::once(channel)
+            .chain(values.into_iter().map(OscArg::Int))
+            .collect();
+        OscMessage::new(address, args)
+    }
+
+    /// Feedback for the control mapped to `target` (e.g. to move a
+    /// TouchOSC fader after a preset change)
+    pub fn param_message(&self, target: ParamTarget, value: f32) -> Option<OscMessage> {
+        let (address, _) = self.params.iter().find(|(_, t)| *t == target)?;
+        Some(OscMessage::new(address, vec![OscArg::Float(value)]))
+    }
+}
+
+/// Receives OSC packets on a UDP port without blocking
+#[derive(Debug)]
+pub struct OscServer {
+    socket: UdpSocket,
+    pending: VecDeque<(OscMessage, SocketAddr)>,
+    buffer: Vec<u8>,
+    errors: u64,
+}
+
+impl OscServer {
+    /// Listen on `addr` (e.g. "0.0.0.0:8000")
+    pub fn bind(addr: impl ToSocketAddrs) -> Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            pending: VecDeque::new(),
+            buffer: vec![0; MAX_OSC_PACKET],
+            errors: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Packets that failed to decode and were dropped
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Next received message and its sender, if any has arrived
+    pub fn try_recv(&mut self) -> Option<(OscMessage, SocketAddr)> {
+        while self.pending.is_empty() {
+            let (len, from) = self.socket.recv_from(&mut self.buffer).ok()?;
+            match OscMessage::parse_packet(&self.buffer[..len]) {
+                Ok(messages) => self
+                    .pending
+                    .extend(messages.into_iter().map(|message| (message, from))),
+                Err(_) => self.errors += 1,
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+/// Sends OSC messages to one UDP destination
+#[derive(Debug)]
+pub struct OscClient {
+    socket: UdpSocket,
+}
+
+impl OscClient {
+    /// Send to `target` (e.g. a tablet at "192.168.1.20:9000")
+    pub fn connect(target: impl ToSocketAddrs) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(target)?;
+        Ok(Self { socket })
+    }
+
+    pub fn send(&self, message: &OscMessage) -> Result<()> {
+        self.socket.send(&message.to_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip_and_bundles_flatten() {
+        let message = OscMessage::new(
+            "/synth/patch",
+            vec![
+                OscArg::Int(-3),
+                OscArg::Float(0.25),
+                OscArg::String("pad".to_string()),
+                OscArg::Blob(vec![1, 2, 3, 4, 5]),
+                OscArg::Bool(true),
+            ],
+        );
+        let bytes = message.to_bytes();
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(&bytes[..16], b"/synth/patch\0\0\0\0");
+        assert_eq!(OscMessage::parse_packet(&bytes).unwrap(), [message]);
+
+        let fader = OscMessage::new("/1/fader1", vec![OscArg::Float(0.5)]).to_bytes();
+        let mut bundle = b"#bundle\0".to_vec();
+        bundle.extend([0, 0, 0, 0, 0, 0, 0, 1]); // Immediately
+        for packet in [&bytes, &fader] {
+            bundle.extend((packet.len() as u32).to_be_bytes());
+            bundle.extend(packet);
+        }
+        let messages = OscMessage::parse_packet(&bundle).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].address, "/1/fader1");
+
+        assert!(OscMessage::parse_packet(&bytes[..bytes.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn negative_sizes_are_rejected() {
+        let mut blob = b"/a\0\0,b\0\0".to_vec();
+        blob.extend([0xFF; 4]);
+        assert!(OscMessage::parse_packet(&blob).is_err());
+
+        let mut bundle = b"#bundle\0".to_vec();
+        bundle.extend([0; 8]);
+        bundle.extend([0xFF; 4]);
+        assert!(OscMessage::parse_packet(&bundle).is_err());
+    }
+
+    #[test]
+    fn bridge_translates_both_ways() {
+        let mut bridge = OscBridge::new();
+        bridge.map_param("/1/fader1", ParamTarget::FilterCutoff);
+
+        let note = OscMessage::new(
+            "/midi/note_on",
+            vec![OscArg::Int(2), OscArg::Float(60.0), OscArg::Int(100)],
+        );
+        let expected = ChannelEvent {
+            channel: 2,
+            event: MidiEvent::NoteOn(60, 100),
+        };
+        assert_eq!(
+            bridge.translate(&note),
+            Some(OscInput::Event(expected.clone()))
+        );
+        assert_eq!(
+            bridge.translate(&bridge.event_message(&expected)),
+            Some(OscInput::Event(expected))
+        );
+
+        let fader = OscMessage::new("/1/fader1", vec![OscArg::Float(0.75)]);
+        assert_eq!(
+            bridge.translate(&fader),
+            Some(OscInput::Param(ParamTarget::FilterCutoff, 0.75))
+        );
+        assert_eq!(
+            bridge.param_message(ParamTarget::FilterCutoff, 0.75),
+            Some(fader)
+        );
+        assert_eq!(bridge.translate(&OscMessage::new("/unknown", vec![])), None);
+    }
+
+    #[test]
+    fn server_receives_from_client() {
+        let mut server = OscServer::bind("127.0.0.1:0").unwrap();
+        let client = OscClient::connect(server.local_addr().unwrap()).unwrap();
+        let message = OscMessage::new("/midi/program", vec![OscArg::Int(0), OscArg::Int(5)]);
+        client.send(&message).unwrap();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(2);
+        let received = loop {
+            if let Some((received, _)) = server.try_recv() {
+                break received;
+            }
+            assert!(
+                std::time::Instant::now() < deadline,
+                "no OSC packet received"
+            );
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        };
+        assert_eq!(received, message);
+    }
+}
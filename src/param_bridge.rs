@@ -0,0 +1,245 @@
+//! Graph parameter updates: MIDI-side parameter changes delivered to auxide
+//! graph nodes between audio blocks
+//!
+//! The MIDI side binds [`ParamTarget`]s to `(node, param)` pairs and pushes
+//! values into a [`ParamUpdateQueue`]. The audio side owns the matching
+//! [`ParamUpdateReceiver`] and drains it into a [`ParamSink`] before each
+//! block, so nodes see new values without rebuilding the graph. Messages use
+//! auxide's own [`ControlMsg`] and lock-free control queue.
+
+use crate::cc_mapping::ParamTarget;
+use crate::conversions::normalized_to_freq;
+use auxide::control::{new_control_queue, ControlMsg};
+use auxide::graph::NodeId;
+use rtrb::{Consumer, Producer};
+
+/// Which parameter of a node a value is written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeParam {
+    Gain,
+    /// Oscillator frequency, Hz
+    Frequency,
+    /// Filter cutoff, Hz
+    FilterCutoff,
+    FilterResonance,
+    /// Detune, cents
+    Detune,
+    /// Pan, -1.0 (left) to 1.0 (right)
+    Pan,
+    /// Node-specific parameter index
+    Index(u8),
+}
+
+impl NodeParam {
+    /// The auxide control message that sets this parameter on `node`
+    pub fn message(self, node: NodeId, value: f32) -> ControlMsg {
+        match self {
+            NodeParam::Gain => ControlMsg::SetGain { node, gain: value },
+            NodeParam::Frequency => ControlMsg::SetFrequency { node, hz: value },
+            NodeParam::FilterCutoff => ControlMsg::SetFilterCutoff { node, hz: value },
+            NodeParam::FilterResonance => ControlMsg::SetFilterResonance { node, q: value },
+            NodeParam::Detune => ControlMsg::SetDetune { node, cents: value },
+            NodeParam::Pan => ControlMsg::SetPan { node, pan: value },
+            NodeParam::Index(param_idx) => ControlMsg::SetParam {
+                node,
+                param_idx,
+                value,
+            },
+        }
+    }
+}
+
+/// How a normalized (0.0 to 1.0) value is scaled before reaching the node
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ParamScale {
+    /// Pass the value through unchanged
+    #[default]
+    Raw,
+    /// Straight line from `min` to `max`
+    Linear(f32, f32),
+    /// Equal ratios per step from `min` to `max` (cutoffs, frequencies)
+    Logarithmic(f32, f32),
+}
+
+impl ParamScale {
+    pub fn apply(self, value: f32) -> f32 {
+        match self {
+            ParamScale::Raw => value,
+            ParamScale::Linear(min, max) => min + value.clamp(0.0, 1.0) * (max - min),
+            ParamScale::Logarithmic(min, max) => normalized_to_freq(value, min, max),
+        }
+    }
+}
+
+/// One destination of a bound [`ParamTarget`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamBinding {
+    pub node: NodeId,
+    pub param: NodeParam,
+    pub scale: ParamScale,
+}
+
+/// MIDI-side end of the bridge: resolves parameter targets to node updates
+/// and queues them for the audio thread
+pub struct ParamUpdateQueue {
+    bindings: Vec<(ParamTarget, ParamBinding)>,
+    producer: Producer<ControlMsg>,
+    dropped: usize,
+}
+
+/// Audio-side end of the bridge
+pub struct ParamUpdateReceiver {
+    consumer: Consumer<ControlMsg>,
+}
+
+/// Anything that can apply control messages to graph nodes between blocks
+///
+/// auxide 0.3's `Runtime` does not consume control messages itself, so the
+/// audio callback supplies a sink that forwards them to the nodes it owns.
+pub trait ParamSink {
+    fn apply(&mut self, msg: ControlMsg);
+}
+
+impl<F: FnMut(ControlMsg)> ParamSink for F {
+    fn apply(&mut self, msg: ControlMsg) {
+        self(msg)
+    }
+}
+
+impl ParamUpdateQueue {
+    /// Create a connected queue/receiver pair sized by auxide's
+    /// `CONTROL_QUEUE_CAPACITY`
+    pub fn new() -> (Self, ParamUpdateReceiver) {
+        let (producer, consumer) = new_control_queue();
+        (
+            Self {
+                bindings: Vec::new(),
+                producer,
+                dropped: 0,
+            },
+            ParamUpdateReceiver { consumer },
+        )
+    }
+
+    /// Route `target` to `param` of `node`; a target may drive several nodes
+    pub fn bind(&mut self, target: ParamTarget, node: NodeId, param: NodeParam, scale: ParamScale) {
+        self.bindings
+            .push((target, ParamBinding { node, param, scale }));
+    }
+
+    /// Remove every binding of `target`
+    pub fn unbind(&mut self, target: ParamTarget) {
+        self.bindings.retain(|(bound, _)| *bound != target);
+    }
+
+    /// Destinations currently bound to `target`
+    pub fn bindings(&self, target: ParamTarget) -> impl Iterator<Item = &ParamBinding> {
+        self.bindings
+            .iter()
+            .filter(move |(bound, _)| *bound == target)
+            .map(|(_, binding)| binding)
+    }
+
+    /// Queue `value` for every node bound to `target`, returning how many
+    /// updates were queued
+    pub fn send(&mut self, target: ParamTarget, value: f32) -> usize {
+        let mut sent = 0;
+        for i in 0..self.bindings.len() {
+            let binding = self.bindings[i].1;
+            if self.bindings[i].0 == target
+                && self.send_to(binding.node, binding.param, binding.scale.apply(value))
+            {
+                sent += 1;
+            }
+        }
+        sent
+    }
+
+    /// Queue a value for one node parameter directly (e.g. a voice's pitch)
+    pub fn send_to(&mut self, node: NodeId, param: NodeParam, value: f32) -> bool {
+        self.send_message(param.message(node, value))
+    }
+
+    /// Queue a raw control message; returns false if the queue is full
+    pub fn send_message(&mut self, msg: ControlMsg) -> bool {
+        let pushed = self.producer.push(msg).is_ok();
+        if !pushed {
+            self.dropped += 1;
+        }
+        pushed
+    }
+
+    /// Number of updates lost because the audio side fell behind
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+impl ParamUpdateReceiver {
+    /// Apply every pending update to `sink`; call between blocks
+    pub fn drain(&mut self, sink: &mut impl ParamSink) -> usize {
+        let mut applied = 0;
+        while let Ok(msg) = self.consumer.pop() {
+            sink.apply(msg);
+            applied += 1;
+        }
+        applied
+    }
+
+    /// Number of updates waiting
+    pub fn pending(&self) -> usize {
+        self.consumer.slots()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bound_target_reaches_every_node() {
+        let (mut queue, mut receiver) = ParamUpdateQueue::new();
+        queue.bind(
+            ParamTarget::FilterCutoff,
+            NodeId(3),
+            NodeParam::FilterCutoff,
+            ParamScale::Logarithmic(100.0, 6400.0),
+        );
+        queue.bind(
+            ParamTarget::FilterCutoff,
+            NodeId(7),
+            NodeParam::Index(2),
+            ParamScale::Raw,
+        );
+
+        assert_eq!(queue.send(ParamTarget::FilterCutoff, 0.5), 2);
+        assert_eq!(queue.send(ParamTarget::Volume, 0.5), 0);
+        assert_eq!(receiver.pending(), 2);
+
+        let mut applied = Vec::new();
+        assert_eq!(receiver.drain(&mut |msg| applied.push(msg)), 2);
+        assert!(matches!(
+            applied[0],
+            ControlMsg::SetFilterCutoff { node: NodeId(3), hz } if (hz - 800.0).abs() < 0.1
+        ));
+        assert!(matches!(
+            applied[1],
+            ControlMsg::SetParam { node: NodeId(7), param_idx: 2, value } if value == 0.5
+        ));
+
+        queue.unbind(ParamTarget::FilterCutoff);
+        assert_eq!(queue.bindings(ParamTarget::FilterCutoff).count(), 0);
+    }
+
+    #[test]
+    fn full_queue_counts_dropped_updates() {
+        let (mut queue, mut receiver) = ParamUpdateQueue::new();
+        let capacity = auxide::control::CONTROL_QUEUE_CAPACITY;
+        for i in 0..capacity + 3 {
+            queue.send_to(NodeId(0), NodeParam::Frequency, i as f32);
+        }
+        assert_eq!(queue.dropped(), 3);
+        assert_eq!(receiver.drain(&mut |_| {}), capacity);
+        assert!(queue.send_to(NodeId(0), NodeParam::Gain, 1.0));
+    }
+}
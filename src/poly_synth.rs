@@ -0,0 +1,453 @@
+//! `PolySynth`: voice allocation, per-voice rendering and CC smoothing in one
+//! engine with a MIDI-in, audio-out interface
+
+use crate::cc_mapping::{CCMap, ParamTarget, MAX_CC_TARGETS};
+use crate::conversions::{PitchBendState, PITCH_BEND_CENTER};
+use crate::expression::ChannelGain;
+use crate::midi_input::{MidiEvent, CC_ALL_SOUND_OFF};
+use crate::portamento::{Portamento, CC_PORTAMENTO};
+use crate::smoother::ParamSmoother;
+use crate::sustain::{SustainPedal, CC_SUSTAIN};
+use crate::voice_allocator::VoiceAllocator;
+use crate::voice_state::{VoicePool, VoiceRenderer};
+
+/// A mapped parameter and its smoother
+#[derive(Debug, Clone)]
+struct SynthParam {
+    target: ParamTarget,
+    smoother: ParamSmoother,
+    dirty: bool, // Changed since the renderers last saw it
+}
+
+/// Polyphonic synthesizer engine
+///
+/// Owns the voice allocator, a voice pool whose user data `R` is each
+/// voice's sound generator (its subgraph), the CC map and one smoother per
+/// mapped parameter. Feed it MIDI with [`handle_event`](Self::handle_event)
+/// and pull audio with [`process`](Self::process), both from the audio
+/// thread; neither allocates.
+#[derive(Debug)]
+pub struct PolySynth<R> {
+    allocator: VoiceAllocator,
+    pool: VoicePool<R>,
+    cc_map: CCMap,
+    params: Vec<SynthParam>,
+    bend: PitchBendState,
+    sustain: SustainPedal,
+    gain: ChannelGain,
+    portamento: Portamento,
+    output_gain: f32, // Gain reached at the end of the last block
+    sample_rate: f32,
+}
+
+impl<R: VoiceRenderer + Default> PolySynth<R> {
+    /// Create an engine with `voice_count` voices and the default CC map
+    pub fn new(voice_count: usize, sample_rate: f32) -> Self {
+        let allocator = VoiceAllocator::with_voices(voice_count);
+        let mut synth = Self {
+            pool: VoicePool::with_user_data_voices(allocator.voice_count()),
+            allocator,
+            cc_map: CCMap::new(),
+            params: Vec::new(),
+            bend: PitchBendState::default(),
+            sustain: SustainPedal::new(),
+            gain: ChannelGain::new(),
+            portamento: Portamento::new(),
+            output_gain: 1.0,
+            sample_rate,
+        };
+        synth.rebuild_params();
+        synth
+    }
+}
+
+impl<R: VoiceRenderer> PolySynth<R> {
+    /// Replace the CC map, rebuilding the parameter smoothers from its
+    /// smoothing times; allocates, so call before streaming starts
+    pub fn set_cc_map(&mut self, cc_map: CCMap) {
+        self.cc_map = cc_map;
+        self.rebuild_params();
+    }
+
+    pub fn cc_map(&self) -> &CCMap {
+        &self.cc_map
+    }
+
+    pub fn allocator(&self) -> &VoiceAllocator {
+        &self.allocator
+    }
+
+    pub fn pool(&self) -> &VoicePool<R> {
+        &self.pool
+    }
+
+    pub fn pool_mut(&mut self) -> &mut VoicePool<R> {
+        &mut self.pool
+    }
+
+    pub fn pitch_bend_mut(&mut self) -> &mut PitchBendState {
+        &mut self.bend
+    }
+
+    pub fn sustain(&self) -> &SustainPedal {
+        &self.sustain
+    }
+
+    /// Expression and breath levels and the output gain they give
+    pub fn gain(&self) -> &ChannelGain {
+        &self.gain
+    }
+
+    pub fn portamento(&self) -> &Portamento {
+        &self.portamento
+    }
+
+    /// Portamento settings, e.g. to change the longest glide time
+    pub fn portamento_mut(&mut self) -> &mut Portamento {
+        &mut self.portamento
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for param in &mut self.params {
+            param.smoother.set_sample_rate(sample_rate);
+        }
+    }
+
+    /// Current smoothed value of a mapped parameter
+    pub fn param(&self, target: ParamTarget) -> Option<f32> {
+        self.params
+            .iter()
+            .find(|param| param.target == target)
+            .map(|param| param.smoother.current_value())
+    }
+
+    /// Apply a MIDI event, returning true if it changed the synth's state
+    pub fn handle_event(&mut self, event: MidiEvent) -> bool {
+        match event {
+            MidiEvent::ControlChange(CC_ALL_SOUND_OFF, _) => {
+                self.allocator.release_all();
+                self.pool.kill_all();
+                true
+            }
+            event if event.is_all_notes_off() => {
+                for (voice, _) in self.allocator.active_voices() {
+                    self.pool.get_voice_mut(voice.index()).release();
+                }
+                self.allocator.release_all() > 0
+            }
+            MidiEvent::NoteOn(note, velocity) => {
+                let Some(voice) = self.allocator.allocate_voice(note) else {
+                    return false;
+                };
+                self.pool.trigger_voice(voice, note, velocity);
+                let (bend, range) = (self.bend.bend(), self.bend.range());
+                let state = self.pool.get_voice_mut(voice.index());
+                state.set_pitch_bend(bend, range);
+                if let Some((from, samples)) = self.portamento.note_on(note, self.sample_rate) {
+                    state.pitch = from as f32;
+                    state.glide_to(note, samples);
+                }
+                // A new voice starts from the current parameter values
+                if let Some(renderer) = self.pool.user_data_mut(voice) {
+                    for param in &self.params {
+                        renderer.set_param(param.target, param.smoother.current_value());
+                    }
+                    renderer.set_sustain(self.sustain.depth());
+                }
+                true
+            }
+            MidiEvent::NoteOff(note, velocity) => match self.allocator.release_voice(note) {
+                Some(voice) => self.pool.release_voice(voice, velocity),
+                None => false,
+            },
+            MidiEvent::ControlChange(CC_SUSTAIN, value) => {
+                self.sustain.handle_event(&event);
+                let depth = self.sustain.depth();
+                for (_, renderer) in self.pool.iter_mut() {
+                    renderer.set_sustain(depth);
+                }
+                // The pedal may also be mapped to a parameter
+                self.handle_mapped_cc(CC_SUSTAIN, value);
+                true
+            }
+            MidiEvent::ControlChange(CC_PORTAMENTO, value) => {
+                self.portamento.handle_event(&event);
+                self.handle_mapped_cc(CC_PORTAMENTO, value);
+                true
+            }
+            MidiEvent::ControlChange(cc_num, value) => self.handle_mapped_cc(cc_num, value),
+            MidiEvent::PitchBend(_) => {
+                self.bend.handle_event(&event);
+                let (bend, range) = (self.bend.bend(), self.bend.range());
+                for voice in self.pool.voices_mut().iter_mut().filter(|v| v.active) {
+                    voice.set_pitch_bend(bend, range);
+                }
+                true
+            }
+            MidiEvent::ChannelPressure(pressure) => {
+                for voice in self.pool.voices_mut().iter_mut().filter(|v| v.active) {
+                    voice.set_pressure(pressure);
+                }
+                true
+            }
+            // Preset switching is up to a `ProgramMap`
+            MidiEvent::ProgramChange(_) => false,
+        }
+    }
+
+    /// Recover from stuck notes: free every voice, cut release tails,
+    /// centre the pitch wheel, lift the sustain pedal and forget the last
+    /// note for portamento
+    pub fn panic(&mut self) {
+        self.allocator.release_all();
+        self.pool.kill_all();
+        self.bend.set_bend(PITCH_BEND_CENTER);
+        self.sustain.reset();
+        self.portamento.reset();
+    }
+
+    /// Render the next block into `out`, replacing its contents
+    ///
+    /// Smoothed parameters advance by the block length and reach every
+    /// voice's renderer before it runs; finished voices are freed afterwards.
+    /// The mix is scaled by the expression and breath gain, ramped across
+    /// the block.
+    pub fn process(&mut self, out: &mut [f32]) {
+        for param in &mut self.params {
+            if !param.dirty && !param.smoother.is_smoothing() {
+                continue;
+            }
+            let mut value = param.smoother.current_value();
+            for _ in 0..out.len() {
+                value = param.smoother.next_sample();
+            }
+            param.dirty = false;
+            self.gain.handle_param(param.target, value);
+            for (_, renderer) in self.pool.iter_mut() {
+                renderer.set_param(param.target, value);
+            }
+        }
+        self.pool.render(out, self.sample_rate);
+        let gain = self.gain.gain();
+        if gain != 1.0 || self.output_gain != 1.0 {
+            let step = (gain - self.output_gain) / out.len().max(1) as f32;
+            for (i, sample) in out.iter_mut().enumerate() {
+                *sample *= self.output_gain + step * (i + 1) as f32;
+            }
+        }
+        self.output_gain = gain;
+        self.pool.collect_finished(&mut self.allocator);
+    }
+
+    /// Send a CC through the map to the smoothers of every parameter it drives
+    fn handle_mapped_cc(&mut self, cc_num: u8, value: u8) -> bool {
+        let mut changes = [(ParamTarget::Unused, 0.0); MAX_CC_TARGETS];
+        let count = self.cc_map.handle_cc_into(cc_num, value, &mut changes);
+        let mut handled = false;
+        for &(target, value) in &changes[..count] {
+            // Glides use the time as it is when a note starts, unsmoothed
+            self.portamento.handle_param(target, value);
+            if let Some(param) = self.params.iter_mut().find(|param| param.target == target) {
+                param.smoother.set_target(value);
+                param.dirty = true;
+                handled = true;
+            }
+        }
+        handled
+    }
+
+    fn rebuild_params(&mut self) {
+        self.params.clear();
+        self.gain.reset();
+        for (cc_num, target) in self.cc_map.iter_active() {
+            if self.params.iter().any(|param| param.target == target) {
+                continue;
+            }
+            let mut smoother = self
+                .cc_map
+                .smoother_for(cc_num, self.sample_rate)
+                .unwrap_or_default();
+            smoother.set_sample_rate(self.sample_rate);
+            if let Some((_, value)) = self.cc_map.current_value(cc_num) {
+                smoother.reset(value);
+                self.portamento.handle_param(target, value);
+            }
+            self.params.push(SynthParam {
+                target,
+                smoother,
+                dirty: true,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::CC_BREATH;
+    use crate::portamento::{CC_PORTAMENTO_TIME, DEFAULT_MAX_PORTAMENTO_SECONDS};
+    use crate::voice_allocator::VoiceId;
+    use crate::voice_state::{EnvStage, VoiceState};
+
+    /// Constant-level voice that records the last cutoff and pedal depth
+    /// it was sent
+    #[derive(Debug, Default)]
+    struct TestVoice {
+        cutoff: f32,
+        sustain: f32,
+    }
+
+    impl VoiceRenderer for TestVoice {
+        fn render(&mut self, voice: &mut VoiceState, out: &mut [f32], _sample_rate: f32) {
+            if voice.env_stage == EnvStage::Release {
+                voice.active = false;
+                return;
+            }
+            out.iter_mut().for_each(|sample| *sample += 0.25);
+        }
+
+        fn set_param(&mut self, target: ParamTarget, value: f32) {
+            if target == ParamTarget::FilterCutoff {
+                self.cutoff = value;
+            }
+        }
+
+        fn set_sustain(&mut self, depth: f32) {
+            self.sustain = depth;
+        }
+    }
+
+    #[test]
+    fn notes_in_audio_out() {
+        let mut synth = PolySynth::<TestVoice>::new(4, 48000.0);
+        let mut out = [0.0; 64];
+        assert!(synth.handle_event(MidiEvent::NoteOn(60, 100)));
+        assert!(synth.handle_event(MidiEvent::NoteOn(64, 100)));
+        synth.process(&mut out);
+        assert_eq!(out[0], 0.5);
+
+        assert!(synth.handle_event(MidiEvent::NoteOff(60, 0)));
+        synth.process(&mut out);
+        assert_eq!(out[0], 0.25);
+        assert_eq!(synth.allocator().active_voice_count(), 1);
+    }
+
+    #[test]
+    fn panic_silences_everything() {
+        let mut synth = PolySynth::<TestVoice>::new(4, 48000.0);
+        let mut out = [0.0; 64];
+        synth.handle_event(MidiEvent::NoteOn(60, 100));
+        synth.handle_event(MidiEvent::NoteOn(64, 100));
+        synth.handle_event(MidiEvent::PitchBend(0));
+        synth.handle_event(MidiEvent::ControlChange(CC_SUSTAIN, 127));
+        synth.panic();
+        assert_eq!(synth.allocator().active_voice_count(), 0);
+        assert_eq!(synth.sustain().value(), 0);
+        assert_eq!(synth.pitch_bend_mut().bend(), PITCH_BEND_CENTER);
+        synth.process(&mut out);
+        assert_eq!(out[0], 0.0);
+    }
+
+    #[test]
+    fn sustain_depth_reaches_renderers() {
+        let mut synth = PolySynth::<TestVoice>::new(2, 48000.0);
+        synth.handle_event(MidiEvent::NoteOn(60, 100));
+        assert!(synth.handle_event(MidiEvent::ControlChange(CC_SUSTAIN, 127)));
+        let voice = |synth: &PolySynth<TestVoice>, index| {
+            synth.pool().user_data(VoiceId(index, 1)).unwrap().sustain
+        };
+        assert_eq!(voice(&synth, 0), 1.0);
+
+        // Half pedal, then a new note picks up the current depth
+        synth.handle_event(MidiEvent::ControlChange(CC_SUSTAIN, 50));
+        synth.handle_event(MidiEvent::NoteOn(64, 100));
+        let depth = synth.sustain().depth();
+        assert!(depth > 0.0 && depth < 0.5);
+        assert_eq!(voice(&synth, 0), depth);
+        assert_eq!(voice(&synth, 1), depth);
+    }
+
+    #[test]
+    fn breath_controls_output_level() {
+        let mut synth = PolySynth::<TestVoice>::new(2, 48000.0);
+        let mut out = [0.0; 64];
+        synth.handle_event(MidiEvent::NoteOn(60, 100));
+        synth.process(&mut out);
+        assert_eq!(out[63], 0.25);
+
+        synth.handle_event(MidiEvent::ControlChange(CC_BREATH, 0));
+        for _ in 0..100 {
+            synth.process(&mut out);
+        }
+        assert!(synth.gain().breath() < 1e-3);
+        assert!(out[63].abs() < 1e-3);
+
+        // Remapped, breath no longer touches the gain
+        let mut cc_map = CCMap::new();
+        cc_map.set_mapping(CC_BREATH, ParamTarget::FilterCutoff);
+        synth.set_cc_map(cc_map);
+        synth.handle_event(MidiEvent::ControlChange(CC_BREATH, 0));
+        synth.process(&mut out);
+        synth.process(&mut out);
+        assert_eq!(synth.gain().gain(), 1.0);
+        assert_eq!(out[63], 0.25);
+    }
+
+    #[test]
+    fn portamento_controls_configure_glides() {
+        let mut synth = PolySynth::<TestVoice>::new(2, 1000.0);
+        synth.handle_event(MidiEvent::ControlChange(CC_PORTAMENTO_TIME, 127));
+        assert_eq!(synth.portamento().time(), DEFAULT_MAX_PORTAMENTO_SECONDS);
+        assert!(synth.handle_event(MidiEvent::ControlChange(CC_PORTAMENTO, 127)));
+
+        synth.handle_event(MidiEvent::NoteOn(60, 100));
+        synth.handle_event(MidiEvent::NoteOn(72, 100));
+        let voice = synth.pool().get_voice(1);
+        assert_eq!(voice.pitch, 60.0);
+        assert_eq!(voice.target_pitch, 72.0);
+        assert_eq!(voice.glide_remaining, 2000);
+
+        synth.handle_event(MidiEvent::ControlChange(CC_PORTAMENTO, 0));
+        synth.handle_event(MidiEvent::NoteOn(48, 100));
+        assert!(!synth.pool().get_voice(0).is_gliding());
+    }
+
+    #[test]
+    fn cc_changes_are_smoothed_into_renderers() {
+        let mut synth = PolySynth::<TestVoice>::new(2, 48000.0);
+        let mut out = [0.0; 64];
+        synth.handle_event(MidiEvent::NoteOn(60, 100));
+        assert!(synth.handle_event(MidiEvent::ControlChange(1, 127)));
+        synth.process(&mut out);
+
+        let cutoff = synth.param(ParamTarget::FilterCutoff).unwrap();
+        assert!(cutoff > 0.0 && cutoff < 1.0);
+        assert_eq!(
+            synth.pool().user_data(VoiceId(0, 1)).unwrap().cutoff,
+            cutoff
+        );
+        for _ in 0..100 {
+            synth.process(&mut out);
+        }
+        let target = synth.cc_map().current_value(1).unwrap().1;
+        assert!((synth.param(ParamTarget::FilterCutoff).unwrap() - target).abs() < 1e-4);
+    }
+
+    #[test]
+    fn fanned_out_cc_moves_every_target() {
+        let mut map = CCMap::new();
+        map.add_mapping(1, ParamTarget::Custom(1), 0.0, 1.0);
+        let mut synth = PolySynth::<TestVoice>::new(2, 48000.0);
+        synth.set_cc_map(map);
+        assert!(synth.handle_event(MidiEvent::ControlChange(1, 127)));
+        let mut out = [0.0; 64];
+        synth.process(&mut out);
+        assert!(synth.param(ParamTarget::FilterCutoff).unwrap() > 0.0);
+        assert!(synth.param(ParamTarget::Custom(1)).unwrap() > 0.0);
+    }
+}
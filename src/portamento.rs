@@ -0,0 +1,130 @@
+//! Portamento switch (CC65) and time (CC5) driving voice glides
+//!
+//! [`Portamento`] remembers the last note played; while the switch is on,
+//! each new note starts at that pitch and glides to its own with
+//! [`VoiceState::glide_to`](crate::voice_state::VoiceState::glide_to).
+//! The time comes from whatever controller the `CCMap` sends to
+//! [`ParamTarget::PortamentoTime`] (CC5 by default).
+
+use crate::cc_mapping::ParamTarget;
+use crate::midi_input::MidiEvent;
+
+/// Portamento time controller number
+pub const CC_PORTAMENTO_TIME: u8 = 5;
+/// Portamento on/off switch controller number
+pub const CC_PORTAMENTO: u8 = 65;
+
+/// Glide time at full portamento time, seconds
+pub const DEFAULT_MAX_PORTAMENTO_SECONDS: f32 = 2.0;
+
+/// Portamento settings for one channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Portamento {
+    enabled: bool,
+    time: f32, // Seconds
+    max_time: f32,
+    last_note: Option<u8>,
+}
+
+impl Portamento {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            time: 0.0,
+            max_time: DEFAULT_MAX_PORTAMENTO_SECONDS,
+            last_note: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Glide time, seconds
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn set_time(&mut self, seconds: f32) {
+        self.time = seconds.max(0.0);
+    }
+
+    /// Glide time the controller reaches at full travel, seconds
+    pub fn max_time(&self) -> f32 {
+        self.max_time
+    }
+
+    pub fn set_max_time(&mut self, seconds: f32) {
+        self.max_time = seconds.max(0.0);
+    }
+
+    /// Feed an event; CC65 switches portamento on (>= 64) or off
+    /// Returns true if the event was a portamento switch message
+    pub fn handle_event(&mut self, event: &MidiEvent) -> bool {
+        match *event {
+            MidiEvent::ControlChange(CC_PORTAMENTO, value) => {
+                self.enabled = value >= 64;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Feed a mapped parameter value (0.0-1.0); the time follows a square
+    /// law up to `max_time` so short glides get most of the travel
+    /// Returns true if `target` is the portamento time.
+    pub fn handle_param(&mut self, target: ParamTarget, value: f32) -> bool {
+        if target != ParamTarget::PortamentoTime {
+            return false;
+        }
+        let value = value.clamp(0.0, 1.0);
+        self.time = value * value * self.max_time;
+        true
+    }
+
+    /// Record `note` as played; returns the note it should glide from and
+    /// the glide length in samples, if portamento is on and there is one
+    pub fn note_on(&mut self, note: u8, sample_rate: f32) -> Option<(u8, u32)> {
+        let from = self.last_note.replace(note)?;
+        let samples = (self.time * sample_rate) as u32;
+        (self.enabled && samples > 0 && from != note).then_some((from, samples))
+    }
+
+    /// Forget the last note, so the next one starts at its own pitch
+    pub fn reset(&mut self) {
+        self.last_note = None;
+    }
+}
+
+impl Default for Portamento {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glides_only_while_switched_on() {
+        let mut portamento = Portamento::new();
+        assert!(portamento.handle_param(ParamTarget::PortamentoTime, 0.5));
+        assert_eq!(portamento.time(), 0.5);
+        assert_eq!(portamento.note_on(60, 1000.0), None);
+        assert_eq!(portamento.note_on(64, 1000.0), None); // Off
+
+        assert!(portamento.handle_event(&MidiEvent::ControlChange(CC_PORTAMENTO, 127)));
+        assert_eq!(portamento.note_on(67, 1000.0), Some((64, 500)));
+        assert_eq!(portamento.note_on(67, 1000.0), None); // Same note
+
+        portamento.reset();
+        assert_eq!(portamento.note_on(72, 1000.0), None);
+        portamento.handle_event(&MidiEvent::ControlChange(CC_PORTAMENTO, 0));
+        assert!(!portamento.is_enabled());
+    }
+}
@@ -0,0 +1,265 @@
+//! Presets: complete synth sounds that can be stored and recalled
+//!
+//! A [`Preset`] captures parameter values, CC mappings, the velocity curve,
+//! pitch bend range and tuning. A [`PresetBank`] holds presets in numbered
+//! slots, one per program number by default, so a [`ProgramMap`] with
+//! identity mapping can select them directly.
+//!
+//! [`ProgramMap`]: crate::program_map::ProgramMap
+
+use crate::cc_mapping::{CCMap, CCMapping, ParamTarget};
+use crate::conversions::{PitchBendState, Tuning, VelocityCurve, DEFAULT_BEND_RANGE};
+
+/// Slots in a default `PresetBank`, one per program number
+pub const DEFAULT_PRESET_SLOTS: usize = 128;
+
+/// A complete synth sound
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Preset {
+    pub name: String,
+    /// Parameter values, sent to their targets when the preset loads
+    pub params: Vec<(ParamTarget, f32)>,
+    pub cc_mappings: Vec<CCMapping>,
+    /// Applied to every channel's velocity transform
+    pub velocity_curve: VelocityCurve,
+    /// Semitones
+    pub bend_range: f32,
+    pub tuning: Tuning,
+}
+
+impl Preset {
+    /// An init sound: default CC map, no parameter values, concert tuning
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            params: Vec::new(),
+            cc_mappings: CCMap::new().to_mappings(),
+            velocity_curve: VelocityCurve::Linear,
+            bend_range: DEFAULT_BEND_RANGE,
+            tuning: Tuning::concert(),
+        }
+    }
+
+    pub fn param(&self, target: ParamTarget) -> Option<f32> {
+        self.params
+            .iter()
+            .find(|(t, _)| *t == target)
+            .map(|&(_, value)| value)
+    }
+
+    /// Set a parameter value, replacing any earlier one for `target`
+    pub fn set_param(&mut self, target: ParamTarget, value: f32) {
+        match self.params.iter_mut().find(|(t, _)| *t == target) {
+            Some(param) => param.1 = value,
+            None => self.params.push((target, value)),
+        }
+    }
+
+    /// Build a CC map from the preset's mappings
+    pub fn cc_map(&self) -> CCMap {
+        CCMap::from_mappings(&self.cc_mappings)
+    }
+
+    /// Store the mappings of `cc_map`
+    pub fn set_cc_map(&mut self, cc_map: &CCMap) {
+        self.cc_mappings = cc_map.to_mappings();
+    }
+
+    /// A centred pitch wheel with the preset's range
+    pub fn pitch_bend(&self) -> PitchBendState {
+        PitchBendState::new(self.bend_range)
+    }
+
+    /// Save the preset to a JSON file
+    #[cfg(feature = "serde")]
+    pub fn save_to(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Load a preset from a JSON file
+    #[cfg(feature = "serde")]
+    pub fn load_from(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Self::new("Init")
+    }
+}
+
+/// Numbered preset slots with a current selection
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PresetBank {
+    slots: Vec<Option<Preset>>,
+    current: Option<usize>,
+}
+
+impl PresetBank {
+    pub fn new() -> Self {
+        Self::with_slots(DEFAULT_PRESET_SLOTS)
+    }
+
+    pub fn with_slots(slots: usize) -> Self {
+        Self {
+            slots: vec![None; slots],
+            current: None,
+        }
+    }
+
+    /// Number of slots, empty or not
+    pub fn slots(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&Preset> {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, slot: usize) -> Option<&mut Preset> {
+        self.slots.get_mut(slot)?.as_mut()
+    }
+
+    /// Put `preset` in `slot`; returns false if the slot is out of range
+    pub fn store(&mut self, slot: usize, preset: Preset) -> bool {
+        match self.slots.get_mut(slot) {
+            Some(entry) => {
+                *entry = Some(preset);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Empty `slot`, returning its preset
+    pub fn remove(&mut self, slot: usize) -> Option<Preset> {
+        let preset = self.slots.get_mut(slot)?.take();
+        if self.current == Some(slot) {
+            self.current = None;
+        }
+        preset
+    }
+
+    /// Make `slot` current if it holds a preset, returning it
+    pub fn select(&mut self, slot: usize) -> Option<&Preset> {
+        self.get(slot)?;
+        self.current = Some(slot);
+        self.get(slot)
+    }
+
+    pub fn current_slot(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn current(&self) -> Option<&Preset> {
+        self.get(self.current?)
+    }
+
+    /// Slot of the first preset called `name`
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.iter()
+            .find(|(_, preset)| preset.name == name)
+            .map(|(slot, _)| slot)
+    }
+
+    /// Occupied slots and their presets
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Preset)> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, preset)| Some((slot, preset.as_ref()?)))
+    }
+
+    /// Save the bank to a JSON file
+    #[cfg(feature = "serde")]
+    pub fn save_to(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Load a bank from a JSON file
+    #[cfg(feature = "serde")]
+    pub fn load_from(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+impl Default for PresetBank {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bright_lead() -> Preset {
+        let mut preset = Preset::new("Bright Lead");
+        preset.set_param(ParamTarget::FilterCutoff, 0.8);
+        preset.set_param(ParamTarget::FilterCutoff, 0.9);
+        preset.velocity_curve = VelocityCurve::Hard;
+        preset.bend_range = 12.0;
+        preset.tuning = Tuning::new(432.0);
+        preset
+    }
+
+    #[test]
+    fn preset_captures_a_sound() {
+        let preset = bright_lead();
+        assert_eq!(preset.params.len(), 1);
+        assert_eq!(preset.param(ParamTarget::FilterCutoff), Some(0.9));
+        assert_eq!(preset.param(ParamTarget::Volume), None);
+        assert_eq!(preset.pitch_bend().range(), 12.0);
+        assert_eq!(preset.cc_map().to_mappings(), CCMap::new().to_mappings());
+    }
+
+    #[test]
+    fn bank_stores_and_selects_slots() {
+        let mut bank = PresetBank::with_slots(8);
+        assert!(bank.store(3, bright_lead()));
+        assert!(!bank.store(8, Preset::default()));
+        assert!(bank.select(2).is_none());
+        assert_eq!(bank.select(3).map(|p| p.name.as_str()), Some("Bright Lead"));
+        assert_eq!(bank.current_slot(), Some(3));
+        assert_eq!(bank.find("Bright Lead"), Some(3));
+        assert_eq!(bank.iter().count(), 1);
+
+        bank.remove(3);
+        assert!(bank.current().is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn bank_file_round_trip() {
+        use crate::scala::{KeyboardMapping, Scale};
+
+        let mut bank = PresetBank::new();
+        bank.store(0, bright_lead());
+        let mut microtonal = Preset::new("Pelog");
+        let scale = Scale::parse("! pelog.scl\nPelog\n 2\n!\n 240.0\n 2/1\n").unwrap();
+        microtonal.tuning = Tuning::from_scale(&scale, &KeyboardMapping::default());
+        bank.store(1, microtonal);
+        bank.select(1);
+
+        let path = std::env::temp_dir().join("auxide_midi_preset_bank_test.json");
+        bank.save_to(&path).unwrap();
+        let loaded = PresetBank::load_from(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded, bank);
+        assert!(loaded.current().unwrap().tuning.is_microtonal());
+    }
+}
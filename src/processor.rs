@@ -0,0 +1,962 @@
+//! Composable MIDI event processors
+//!
+//! A [`MidiProcessor`] takes one timed event and pushes zero or more events
+//! out; a [`ProcessorChain`] runs stages in order, feeding each stage's
+//! output to the next. Stages may emit events later than their input
+//! (echoes, delays); those pass through the rest of the chain straight
+//! away, so put time-shifting stages last and schedule the chain's output,
+//! e.g. with an [`EventScheduler`](crate::scheduler::EventScheduler).
+//!
+//! Stages that generate notes on their own ([`Arpeggiator`]) do it in
+//! [`MidiProcessor::advance`], which the owner calls regularly with the
+//! current time; [`Quantize`] and [`Humanize`] only move events later or
+//! earlier in time. [`EventHook`] turns a closure into a stage for one-off
+//! rewriting.
+
+use crate::channel_router::ChannelRouter;
+use crate::midi_input::{ChannelEvent, MidiEvent};
+use crate::note_echo::NoteEcho;
+use crate::scheduler::TimedEvent;
+use crate::tempo::{NoteDivision, NoteLength};
+use crate::velocity::VelocityProcessor;
+
+/// Events a `ProcessorChain` buffers between stages before allocating
+pub const DEFAULT_CHAIN_BUFFER: usize = 64;
+
+/// Default number of events an `EventHook` may emit per input event
+pub const DEFAULT_HOOK_OUTPUT: usize = 8;
+
+/// One stage of an event pipeline
+pub trait MidiProcessor: Send {
+    /// Handle `event`, pushing the resulting events (if any) to `out`
+    fn process(&mut self, event: TimedEvent, out: &mut Vec<TimedEvent>);
+
+    /// Emit anything due by `now` (µs) that no input event caused, e.g.
+    /// arpeggio steps
+    fn advance(&mut self, _now: u64, _out: &mut Vec<TimedEvent>) {}
+
+    /// Forget held notes and other state, e.g. after All Notes Off, pushing
+    /// note-offs for any notes the stage started itself to `out`
+    fn reset(&mut self, _now: u64, _out: &mut Vec<TimedEvent>) {}
+}
+
+/// Runs events through a sequence of processors
+pub struct ProcessorChain {
+    stages: Vec<Box<dyn MidiProcessor>>,
+    current: Vec<TimedEvent>,
+    next: Vec<TimedEvent>,
+}
+
+impl ProcessorChain {
+    pub fn new() -> Self {
+        Self {
+            stages: Vec::new(),
+            current: Vec::with_capacity(DEFAULT_CHAIN_BUFFER),
+            next: Vec::with_capacity(DEFAULT_CHAIN_BUFFER),
+        }
+    }
+
+    /// Append a stage
+    pub fn push(&mut self, stage: impl MidiProcessor + 'static) {
+        self.stages.push(Box::new(stage));
+    }
+
+    /// Append a stage, builder style
+    pub fn with(mut self, stage: impl MidiProcessor + 'static) -> Self {
+        self.push(stage);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Remove every stage; events then pass through unchanged
+    pub fn clear(&mut self) {
+        self.stages.clear();
+    }
+}
+
+impl ProcessorChain {
+    /// Run `current` through the stages from `first` on
+    fn run_from(&mut self, first: usize) {
+        for stage in &mut self.stages[first..] {
+            self.next.clear();
+            for event in self.current.drain(..) {
+                stage.process(event, &mut self.next);
+            }
+            std::mem::swap(&mut self.current, &mut self.next);
+        }
+    }
+}
+
+impl MidiProcessor for ProcessorChain {
+    fn process(&mut self, event: TimedEvent, out: &mut Vec<TimedEvent>) {
+        self.current.clear();
+        self.current.push(event);
+        self.run_from(0);
+        out.append(&mut self.current);
+    }
+
+    /// Advance every stage; what one emits goes through the stages after it
+    fn advance(&mut self, now: u64, out: &mut Vec<TimedEvent>) {
+        for index in 0..self.stages.len() {
+            self.current.clear();
+            self.stages[index].advance(now, &mut self.current);
+            self.run_from(index + 1);
+            out.append(&mut self.current);
+        }
+    }
+
+    /// Reset every stage; what one emits goes through the stages after it
+    /// before they are reset in turn
+    fn reset(&mut self, now: u64, out: &mut Vec<TimedEvent>) {
+        for index in 0..self.stages.len() {
+            self.current.clear();
+            self.stages[index].reset(now, &mut self.current);
+            self.run_from(index + 1);
+            out.append(&mut self.current);
+        }
+    }
+}
+
+impl Default for ProcessorChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for ProcessorChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProcessorChain")
+            .field("stages", &self.stages.len())
+            .finish()
+    }
+}
+
+/// Shifts notes by a number of semitones
+///
+/// Note-offs use the transposition their note-on had, so changing the
+/// amount while notes are held never leaves them stuck. Notes pushed out
+/// of the MIDI range are dropped.
+#[derive(Debug, Clone)]
+pub struct Transpose {
+    semitones: i8,
+    channel: Option<u8>,
+    held: Vec<Option<u8>>, // Sounding note for each channel and played note
+}
+
+impl Transpose {
+    pub fn new(semitones: i8) -> Self {
+        Self {
+            semitones,
+            channel: None,
+            held: vec![None; 16 * 128],
+        }
+    }
+
+    pub fn semitones(&self) -> i8 {
+        self.semitones
+    }
+
+    pub fn set_semitones(&mut self, semitones: i8) {
+        self.semitones = semitones;
+    }
+
+    /// Only transpose `channel` (None for every channel, the default)
+    pub fn set_channel(&mut self, channel: Option<u8>) {
+        self.channel = channel.map(|channel| channel & 0x0F);
+    }
+}
+
+impl MidiProcessor for Transpose {
+    fn process(&mut self, mut event: TimedEvent, out: &mut Vec<TimedEvent>) {
+        let channel = event.event.channel & 0x0F;
+        if self.channel.is_some_and(|only| only != channel) {
+            out.push(event);
+            return;
+        }
+        let slot = |note: u8| channel as usize * 128 + (note & 0x7F) as usize;
+        match &mut event.event.event {
+            MidiEvent::NoteOn(note, _) => {
+                let shifted = *note as i16 + self.semitones as i16;
+                let shifted = (0..=127).contains(&shifted).then_some(shifted as u8);
+                self.held[slot(*note)] = shifted;
+                match shifted {
+                    Some(shifted) => *note = shifted,
+                    None => return,
+                }
+            }
+            MidiEvent::NoteOff(note, _) => match self.held[slot(*note)].take() {
+                Some(shifted) => *note = shifted,
+                None => return,
+            },
+            _ => {}
+        }
+        out.push(event);
+    }
+
+    fn reset(&mut self, _now: u64, _out: &mut Vec<TimedEvent>) {
+        self.held.fill(None);
+    }
+}
+
+/// Index of a channel and note in the per-note tables below
+fn note_slot(channel: u8, note: u8) -> usize {
+    (channel & 0x0F) as usize * 128 + (note & 0x7F) as usize
+}
+
+/// Moves note-ons towards a tempo grid
+///
+/// Each note-off moves by the same amount as its note-on, so note lengths
+/// are kept. Snapping to the nearest grid line can move a note earlier
+/// than it was played, which only works for events scheduled ahead (a
+/// sequence, a recorded take); for live input use `set_delay_only` so
+/// notes wait for the next grid line instead.
+#[derive(Debug, Clone)]
+pub struct Quantize {
+    grid: NoteLength,
+    bpm: f64,
+    origin: u64,
+    strength: f32,
+    delay_only: bool,
+    shifts: Vec<i64>, // Shift given to each channel and note's note-on, µs
+}
+
+impl Quantize {
+    pub fn new(grid: NoteLength) -> Self {
+        Self {
+            grid,
+            bpm: 120.0,
+            origin: 0,
+            strength: 1.0,
+            delay_only: false,
+            shifts: vec![0; 16 * 128],
+        }
+    }
+
+    pub fn grid(&self) -> NoteLength {
+        self.grid
+    }
+
+    pub fn set_grid(&mut self, grid: NoteLength) {
+        self.grid = grid;
+    }
+
+    /// Tempo the grid is measured at (e.g. from `ClockFollower::bpm`)
+    pub fn set_tempo(&mut self, bpm: f64) {
+        if bpm > 0.0 {
+            self.bpm = bpm;
+        }
+    }
+
+    /// Time (µs) of a grid line, e.g. the downbeat when the transport started
+    pub fn set_origin(&mut self, time: u64) {
+        self.origin = time;
+    }
+
+    /// How far notes move towards the grid, 0.0 (not at all) to 1.0 (onto it)
+    pub fn set_strength(&mut self, strength: f32) {
+        self.strength = strength.clamp(0.0, 1.0);
+    }
+
+    /// Only ever move notes later, to the next grid line
+    pub fn set_delay_only(&mut self, delay_only: bool) {
+        self.delay_only = delay_only;
+    }
+
+    /// Where a note-on at `time` lands
+    fn quantize(&self, time: u64) -> u64 {
+        let grid = self.grid.to_ms(self.bpm) * 1000.0;
+        if time < self.origin || grid <= 0.0 {
+            return time;
+        }
+        let lines = (time - self.origin) as f64 / grid;
+        let lines = if self.delay_only {
+            lines.ceil()
+        } else {
+            lines.round()
+        };
+        let target = self.origin as f64 + lines * grid;
+        let moved = time as f64 + (target - time as f64) * self.strength as f64;
+        moved.round().max(0.0) as u64
+    }
+}
+
+impl Default for Quantize {
+    /// Sixteenth notes at 120 BPM
+    fn default() -> Self {
+        Self::new(NoteLength::straight(NoteDivision::Sixteenth))
+    }
+}
+
+impl MidiProcessor for Quantize {
+    fn process(&mut self, mut event: TimedEvent, out: &mut Vec<TimedEvent>) {
+        let channel = event.event.channel;
+        match event.event.event {
+            MidiEvent::NoteOn(note, _) => {
+                let time = self.quantize(event.time);
+                self.shifts[note_slot(channel, note)] = time as i64 - event.time as i64;
+                event.time = time;
+            }
+            MidiEvent::NoteOff(note, _) => {
+                let shift = std::mem::take(&mut self.shifts[note_slot(channel, note)]);
+                event.time = (event.time as i64 + shift).max(0) as u64;
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+
+    fn reset(&mut self, _now: u64, _out: &mut Vec<TimedEvent>) {
+        self.shifts.fill(0);
+    }
+}
+
+/// Adds small random delays and velocity changes to notes
+///
+/// Delays are never negative, so live input isn't moved into the past;
+/// each note-off gets its note-on's delay so note lengths are kept. The
+/// randomness comes from a seeded PRNG, so a seed always humanizes a
+/// performance the same way.
+#[derive(Debug, Clone)]
+pub struct Humanize {
+    max_delay: u64, // µs
+    velocity: u8,
+    rng: u32,
+    delays: Vec<u64>, // Delay given to each channel and note's note-on
+}
+
+impl Humanize {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            max_delay: 10_000,
+            velocity: 8,
+            rng: seed | 1, // xorshift state must be non-zero
+            delays: vec![0; 16 * 128],
+        }
+    }
+
+    /// Longest delay added to a note, µs
+    pub fn set_max_delay(&mut self, micros: u64) {
+        self.max_delay = micros;
+    }
+
+    /// Largest velocity change either way
+    pub fn set_velocity(&mut self, amount: u8) {
+        self.velocity = amount.min(127);
+    }
+
+    /// Uniform value in 0..=max (xorshift32)
+    fn random(&mut self, max: u64) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as u64 % (max + 1)
+    }
+}
+
+impl Default for Humanize {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl MidiProcessor for Humanize {
+    fn process(&mut self, mut event: TimedEvent, out: &mut Vec<TimedEvent>) {
+        let channel = event.event.channel;
+        match &mut event.event.event {
+            MidiEvent::NoteOn(note, velocity) => {
+                let delay = self.random(self.max_delay);
+                self.delays[note_slot(channel, *note)] = delay;
+                event.time += delay;
+                let change = self.random(2 * self.velocity as u64) as i16 - self.velocity as i16;
+                *velocity = (*velocity as i16 + change).clamp(1, 127) as u8;
+            }
+            MidiEvent::NoteOff(note, _) => {
+                event.time += std::mem::take(&mut self.delays[note_slot(channel, *note)]);
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+
+    fn reset(&mut self, _now: u64, _out: &mut Vec<TimedEvent>) {
+        self.delays.fill(0);
+    }
+}
+
+/// Order an [`Arpeggiator`] steps through the held notes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArpMode {
+    #[default]
+    Up,
+    Down,
+    /// Up then down, without repeating the top and bottom notes
+    UpDown,
+    /// In the order the keys were pressed
+    AsPlayed,
+}
+
+/// Plays held notes one at a time at a tempo-synced rate
+///
+/// Note-ons and note-offs are taken in as the chord to arpeggiate; the
+/// steps come out of `advance`, which must be called at least once per
+/// step (e.g. from every `MidiSynthController::poll`). Steps play on the
+/// channel of the latest note-on, at its velocity.
+#[derive(Debug, Clone)]
+pub struct Arpeggiator {
+    mode: ArpMode,
+    rate: NoteLength,
+    bpm: f64,
+    gate: f64,
+    octaves: u8,
+    played: Vec<u8>, // Held notes in the order pressed
+    sorted: Vec<u8>, // Held notes, lowest first
+    channel: u8,
+    velocity: u8,
+    position: usize,
+    next_step: Option<u64>,
+    sounding: Option<(u8, u8, u64)>, // Channel, note and note-off time
+}
+
+impl Arpeggiator {
+    pub fn new(mode: ArpMode, rate: NoteLength) -> Self {
+        Self {
+            mode,
+            rate,
+            bpm: 120.0,
+            gate: 0.5,
+            octaves: 1,
+            played: Vec::with_capacity(128),
+            sorted: Vec::with_capacity(128),
+            channel: 0,
+            velocity: 100,
+            position: 0,
+            next_step: None,
+            sounding: None,
+        }
+    }
+
+    pub fn mode(&self) -> ArpMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: ArpMode) {
+        self.mode = mode;
+    }
+
+    /// Time between steps
+    pub fn set_rate(&mut self, rate: NoteLength) {
+        self.rate = rate;
+    }
+
+    /// Tempo the rate is measured at (e.g. from `ClockFollower::bpm`)
+    pub fn set_tempo(&mut self, bpm: f64) {
+        if bpm > 0.0 {
+            self.bpm = bpm;
+        }
+    }
+
+    /// Length of each note as a fraction of the step
+    pub fn set_gate(&mut self, gate: f64) {
+        self.gate = gate.clamp(0.01, 1.0);
+    }
+
+    /// Octaves the pattern spans, 1-4
+    pub fn set_octaves(&mut self, octaves: u8) {
+        self.octaves = octaves.clamp(1, 4);
+    }
+
+    /// Notes currently held, in the order pressed
+    pub fn held(&self) -> &[u8] {
+        &self.played
+    }
+
+    /// Note for step `index` of the pattern, if it's in the MIDI range
+    fn step_note(&self, index: usize) -> Option<u8> {
+        let count = self.played.len();
+        let length = count * self.octaves as usize;
+        let up = |index: usize| (self.sorted[index % count], index / count);
+        let (note, octave) = match self.mode {
+            ArpMode::Up => up(index % length),
+            ArpMode::Down => up(length - 1 - index % length),
+            ArpMode::UpDown if length > 1 => {
+                let index = index % (2 * length - 2);
+                up(if index < length {
+                    index
+                } else {
+                    2 * length - 2 - index
+                })
+            }
+            ArpMode::UpDown => up(0),
+            ArpMode::AsPlayed => {
+                let index = index % length;
+                (self.played[index % count], index / count)
+            }
+        };
+        let note = note as usize + 12 * octave;
+        (note <= 127).then_some(note as u8)
+    }
+
+    fn note_off(&mut self, time: u64, out: &mut Vec<TimedEvent>) {
+        if let Some((channel, note, _)) = self.sounding.take() {
+            out.push(TimedEvent {
+                time,
+                event: ChannelEvent {
+                    channel,
+                    event: MidiEvent::NoteOff(note, 0),
+                },
+            });
+        }
+    }
+}
+
+impl Default for Arpeggiator {
+    /// Up in sixteenth notes
+    fn default() -> Self {
+        Self::new(ArpMode::Up, NoteLength::straight(NoteDivision::Sixteenth))
+    }
+}
+
+impl MidiProcessor for Arpeggiator {
+    fn process(&mut self, event: TimedEvent, out: &mut Vec<TimedEvent>) {
+        match event.event.event {
+            MidiEvent::NoteOn(note, velocity) => {
+                if !self.played.contains(&note) {
+                    self.played.push(note);
+                    let index = self.sorted.partition_point(|&held| held < note);
+                    self.sorted.insert(index, note);
+                }
+                self.channel = event.event.channel;
+                self.velocity = velocity;
+                if self.next_step.is_none() {
+                    self.position = 0;
+                    self.next_step = Some(event.time);
+                }
+            }
+            MidiEvent::NoteOff(note, _) => {
+                self.played.retain(|&held| held != note);
+                self.sorted.retain(|&held| held != note);
+            }
+            _ if event.event.event.is_all_notes_off() => {
+                self.reset(event.time, out);
+                out.push(event);
+                return;
+            }
+            _ => {
+                out.push(event);
+                return;
+            }
+        }
+        self.advance(event.time, out);
+    }
+
+    fn advance(&mut self, now: u64, out: &mut Vec<TimedEvent>) {
+        let step = (self.rate.to_ms(self.bpm) * 1000.0).max(1.0) as u64;
+        // After a long gap between calls, play only the latest due step
+        // rather than a burst of notes in the past
+        if let Some(time) = self.next_step.filter(|&time| time <= now) {
+            let missed = (now - time) / step;
+            self.next_step = Some(time + missed * step);
+            self.position += missed as usize;
+        }
+        loop {
+            let due = self
+                .next_step
+                .filter(|&time| time <= now && !self.played.is_empty());
+            if let Some((_, _, off)) = self.sounding {
+                let off = due.map_or(off, |time| off.min(time));
+                if off <= now {
+                    self.note_off(off, out);
+                }
+            }
+            let Some(time) = due else {
+                break;
+            };
+            if let Some(note) = self.step_note(self.position) {
+                out.push(TimedEvent {
+                    time,
+                    event: ChannelEvent {
+                        channel: self.channel,
+                        event: MidiEvent::NoteOn(note, self.velocity),
+                    },
+                });
+                let length = (step as f64 * self.gate).max(1.0) as u64;
+                self.sounding = Some((self.channel, note, time + length));
+            }
+            self.position += 1;
+            self.next_step = Some(time + step);
+        }
+        if self.played.is_empty() {
+            self.next_step = None;
+        }
+    }
+
+    fn reset(&mut self, now: u64, out: &mut Vec<TimedEvent>) {
+        if let Some((_, _, off)) = self.sounding {
+            self.note_off(off.min(now), out);
+        }
+        self.played.clear();
+        self.sorted.clear();
+        self.next_step = None;
+    }
+}
+
+impl MidiProcessor for VelocityProcessor {
+    fn process(&mut self, mut event: TimedEvent, out: &mut Vec<TimedEvent>) {
+        VelocityProcessor::process(self, &mut event.event);
+        out.push(event);
+    }
+}
+
+impl MidiProcessor for ChannelRouter {
+    fn process(&mut self, event: TimedEvent, out: &mut Vec<TimedEvent>) {
+        let time = event.time;
+        self.route(event.event, |event: ChannelEvent| {
+            out.push(TimedEvent { time, event })
+        });
+    }
+}
+
+/// Where an [`EventHook`] sends its events; holds a bounded number
+pub struct HookOutput<'a> {
+    out: &'a mut Vec<TimedEvent>,
+    remaining: usize,
+    dropped: &'a mut usize,
+}
+
+impl HookOutput<'_> {
+    /// Emit `event`; returns false (and counts it dropped) once the hook's
+    /// limit for this input is used up
+    pub fn push(&mut self, event: TimedEvent) -> bool {
+        if self.remaining == 0 {
+            *self.dropped += 1;
+            return false;
+        }
+        self.remaining -= 1;
+        self.out.push(event);
+        true
+    }
+
+    /// Events that can still be emitted for this input
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+}
+
+/// A pipeline stage running user code, e.g. to swap two CCs or block a
+/// note range without writing a `MidiProcessor`
+///
+/// The hook gets each event and emits whatever should replace it, up to a
+/// fixed number of events per input, so a misbehaving hook can't flood the
+/// stages after it. Emit nothing to drop the event.
+pub struct EventHook<F> {
+    hook: F,
+    limit: usize,
+    dropped: usize,
+}
+
+impl<F> EventHook<F>
+where
+    F: FnMut(TimedEvent, &mut HookOutput) + Send,
+{
+    pub fn new(hook: F) -> Self {
+        Self::with_limit(hook, DEFAULT_HOOK_OUTPUT)
+    }
+
+    /// Allow at most `limit` output events per input event
+    pub fn with_limit(hook: F, limit: usize) -> Self {
+        Self {
+            hook,
+            limit,
+            dropped: 0,
+        }
+    }
+
+    /// Events the hook tried to emit past its limit
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+impl<F> MidiProcessor for EventHook<F>
+where
+    F: FnMut(TimedEvent, &mut HookOutput) + Send,
+{
+    fn process(&mut self, event: TimedEvent, out: &mut Vec<TimedEvent>) {
+        let mut output = HookOutput {
+            out,
+            remaining: self.limit,
+            dropped: &mut self.dropped,
+        };
+        (self.hook)(event, &mut output);
+    }
+}
+
+impl<F> std::fmt::Debug for EventHook<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventHook")
+            .field("limit", &self.limit)
+            .field("dropped", &self.dropped)
+            .finish()
+    }
+}
+
+/// Passes each event through, followed by its echoes
+impl MidiProcessor for NoteEcho {
+    fn process(&mut self, event: TimedEvent, out: &mut Vec<TimedEvent>) {
+        let (time, source) = (event.time, event.event.clone());
+        out.push(event);
+        self.echoes(time, &source, |time, event| {
+            out.push(TimedEvent { time, event })
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note_echo::EchoTime;
+    use crate::velocity::VelocityTransform;
+
+    fn timed(time: u64, channel: u8, event: MidiEvent) -> TimedEvent {
+        TimedEvent {
+            time,
+            event: ChannelEvent { channel, event },
+        }
+    }
+
+    #[test]
+    fn transpose_keeps_note_offs_paired() {
+        let mut transpose = Transpose::new(12);
+        let mut out = Vec::new();
+        transpose.process(timed(0, 0, MidiEvent::NoteOn(60, 100)), &mut out);
+        transpose.set_semitones(-12);
+        transpose.process(timed(1, 0, MidiEvent::NoteOff(60, 0)), &mut out);
+        transpose.process(timed(2, 0, MidiEvent::NoteOn(5, 100)), &mut out);
+        transpose.process(timed(3, 0, MidiEvent::NoteOff(5, 0)), &mut out);
+        assert_eq!(
+            out,
+            [
+                timed(0, 0, MidiEvent::NoteOn(72, 100)),
+                timed(1, 0, MidiEvent::NoteOff(72, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn hooks_rewrite_and_are_bounded() {
+        let swap_and_block = EventHook::new(|mut event: TimedEvent, out: &mut HookOutput| {
+            match &mut event.event.event {
+                MidiEvent::ControlChange(cc @ (1 | 2), _) => *cc = 3 - *cc,
+                MidiEvent::NoteOn(note, _) | MidiEvent::NoteOff(note, _) if *note < 36 => return,
+                _ => {}
+            }
+            out.push(event);
+        });
+        let mut chain = ProcessorChain::new().with(swap_and_block);
+        let mut out = Vec::new();
+        chain.process(timed(0, 0, MidiEvent::ControlChange(1, 64)), &mut out);
+        chain.process(timed(0, 0, MidiEvent::NoteOn(30, 100)), &mut out);
+        chain.process(timed(0, 0, MidiEvent::NoteOn(60, 100)), &mut out);
+        assert_eq!(
+            out,
+            [
+                timed(0, 0, MidiEvent::ControlChange(2, 64)),
+                timed(0, 0, MidiEvent::NoteOn(60, 100)),
+            ]
+        );
+
+        let mut flood = EventHook::with_limit(
+            |event: TimedEvent, out: &mut HookOutput| while out.push(event.clone()) {},
+            3,
+        );
+        out.clear();
+        flood.process(timed(0, 0, MidiEvent::PitchBend(0)), &mut out);
+        assert_eq!(out.len(), 3);
+        assert_eq!(flood.dropped(), 1);
+    }
+
+    #[test]
+    fn chain_feeds_each_stage_the_previous_output() {
+        let mut velocity = VelocityProcessor::new();
+        velocity.set_all(VelocityTransform::fixed(80));
+        let mut router = ChannelRouter::new();
+        router.duplicate(0, &[1, 2]);
+        let mut echo = NoteEcho::new(1, EchoTime::Millis(100.0));
+        echo.set_decay(0.5);
+
+        let mut chain = ProcessorChain::new()
+            .with(Transpose::new(7))
+            .with(velocity)
+            .with(router)
+            .with(echo);
+        assert_eq!(chain.len(), 4);
+
+        let mut out = Vec::new();
+        chain.process(timed(1000, 0, MidiEvent::NoteOn(60, 20)), &mut out);
+        assert_eq!(
+            out,
+            [
+                timed(1000, 1, MidiEvent::NoteOn(67, 80)),
+                timed(101_000, 1, MidiEvent::NoteOn(67, 40)),
+                timed(1000, 2, MidiEvent::NoteOn(67, 80)),
+                timed(101_000, 2, MidiEvent::NoteOn(67, 40)),
+            ]
+        );
+    }
+    #[test]
+    fn quantize_snaps_notes_and_keeps_lengths() {
+        // Sixteenths at 120 BPM are 125 ms apart
+        let mut quantize = Quantize::default();
+        let mut out = Vec::new();
+        quantize.process(timed(130_000, 0, MidiEvent::NoteOn(60, 100)), &mut out);
+        quantize.process(timed(180_000, 0, MidiEvent::NoteOff(60, 0)), &mut out);
+        quantize.process(timed(180_000, 0, MidiEvent::ControlChange(1, 5)), &mut out);
+        assert_eq!(
+            out,
+            [
+                timed(125_000, 0, MidiEvent::NoteOn(60, 100)),
+                timed(175_000, 0, MidiEvent::NoteOff(60, 0)),
+                timed(180_000, 0, MidiEvent::ControlChange(1, 5)),
+            ]
+        );
+
+        out.clear();
+        quantize.set_delay_only(true);
+        quantize.set_strength(0.5);
+        quantize.process(timed(130_000, 0, MidiEvent::NoteOn(60, 100)), &mut out);
+        assert_eq!(out[0].time, 190_000);
+    }
+
+    #[test]
+    fn humanize_delays_within_range_and_pairs_note_offs() {
+        let mut humanize = Humanize::new(7);
+        humanize.set_max_delay(5_000);
+        humanize.set_velocity(10);
+        let mut out = Vec::new();
+        for note in 0..50 {
+            humanize.process(timed(100_000, 0, MidiEvent::NoteOn(note, 120)), &mut out);
+            humanize.process(timed(200_000, 0, MidiEvent::NoteOff(note, 0)), &mut out);
+        }
+        for pair in out.chunks(2) {
+            let MidiEvent::NoteOn(_, velocity) = pair[0].event.event else {
+                panic!("expected a note-on");
+            };
+            assert!((110..=127).contains(&velocity));
+            assert!((100_000..=105_000).contains(&pair[0].time));
+            assert_eq!(pair[1].time - pair[0].time, 100_000);
+        }
+        // Not every note lands on the same delay
+        assert!(out.chunks(2).any(|pair| pair[0].time != out[0].time));
+
+        // The same seed humanizes the same way
+        let mut again = Humanize::new(7);
+        again.set_max_delay(5_000);
+        again.set_velocity(10);
+        let mut repeat = Vec::new();
+        again.process(timed(100_000, 0, MidiEvent::NoteOn(0, 120)), &mut repeat);
+        assert_eq!(repeat[0], out[0]);
+    }
+
+    #[test]
+    fn arpeggiator_steps_through_held_notes() {
+        let mut arp = Arpeggiator::default();
+        arp.set_mode(ArpMode::UpDown);
+        let mut out = Vec::new();
+        arp.process(timed(0, 3, MidiEvent::NoteOn(64, 90)), &mut out);
+        arp.process(timed(0, 3, MidiEvent::NoteOn(60, 90)), &mut out);
+        arp.process(timed(0, 3, MidiEvent::NoteOn(67, 90)), &mut out);
+        for now in (0..=500_000).step_by(10_000) {
+            arp.advance(now, &mut out);
+        }
+        let ons: Vec<_> = out
+            .iter()
+            .filter_map(|event| match event.event.event {
+                MidiEvent::NoteOn(note, _) => Some((event.time, note)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            ons,
+            [
+                (0, 64),
+                (125_000, 64),
+                (250_000, 67),
+                (375_000, 64),
+                (500_000, 60)
+            ]
+        );
+        // The first step came before the chord was complete; each is gated
+        // to half a step and released before the next
+        assert_eq!(out[1], timed(62_500, 3, MidiEvent::NoteOff(64, 0)));
+        assert!(out.iter().all(|event| event.event.channel == 3));
+
+        // Releasing every key stops the pattern after the sounding note ends
+        out.clear();
+        for note in [60, 64, 67] {
+            arp.process(timed(510_000, 3, MidiEvent::NoteOff(note, 0)), &mut out);
+        }
+        arp.advance(1_000_000, &mut out);
+        assert_eq!(out, [timed(562_500, 3, MidiEvent::NoteOff(60, 0))]);
+    }
+
+    #[test]
+    fn arpeggiator_spans_octaves_in_a_chain() {
+        let mut arp = Arpeggiator::new(ArpMode::Down, NoteLength::straight(NoteDivision::Quarter));
+        arp.set_octaves(2);
+        arp.set_gate(1.0);
+        let mut chain = ProcessorChain::new().with(arp).with(Transpose::new(1));
+        let mut out = Vec::new();
+        chain.process(timed(0, 0, MidiEvent::NoteOn(60, 100)), &mut out);
+        chain.process(timed(0, 0, MidiEvent::NoteOn(62, 100)), &mut out);
+        for now in (0..=1_500_000).step_by(100_000) {
+            chain.advance(now, &mut out);
+        }
+        let ons: Vec<_> = out
+            .iter()
+            .filter_map(|event| match event.event.event {
+                MidiEvent::NoteOn(note, _) => Some(note),
+                _ => None,
+            })
+            .collect();
+        // 500 ms steps down from the top octave; the first played before 62
+        // arrived, and every step is transposed by the later stage
+        assert_eq!(ons, [73, 73, 63, 61]);
+        // Gated to the full step, each note ends as the next starts
+        assert_eq!(out.len(), 7);
+
+        // Resetting the chain ends the sounding note, transposed like its
+        // note-on by the stage after the arpeggiator
+        out.clear();
+        chain.reset(1_600_000, &mut out);
+        assert_eq!(out, [timed(1_600_000, 0, MidiEvent::NoteOff(61, 0))]);
+        chain.advance(3_000_000, &mut out);
+        assert_eq!(out.len(), 1);
+    }
+
+    #[test]
+    fn arpeggiator_skips_steps_missed_between_calls() {
+        let mut arp = Arpeggiator::default();
+        let mut out = Vec::new();
+        arp.process(timed(0, 0, MidiEvent::NoteOn(60, 100)), &mut out);
+        arp.process(timed(0, 0, MidiEvent::NoteOn(64, 100)), &mut out);
+        out.clear();
+
+        // A second late: only the step due at 1 s plays, the ninth of the
+        // pattern (60, 64, 60, ...)
+        arp.advance(1_010_000, &mut out);
+        assert_eq!(
+            out,
+            [
+                timed(62_500, 0, MidiEvent::NoteOff(60, 0)),
+                timed(1_000_000, 0, MidiEvent::NoteOn(60, 100)),
+            ]
+        );
+    }
+}
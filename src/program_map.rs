@@ -0,0 +1,347 @@
+//! Program Change dispatch: map hardware patch buttons to presets
+//!
+//! Bank Select (CC 0 MSB, CC 32 LSB) is remembered per channel and applies
+//! to the Program Changes that follow it, as on most hardware synths.
+//! [`BankSelect`] does the tracking on its own, turning the three messages
+//! into one [`PatchSelect`].
+
+use crate::midi_input::{ChannelEvent, MidiEvent};
+use std::collections::HashMap;
+
+/// CC 0: Bank Select MSB
+pub const CC_BANK_SELECT_MSB: u8 = 0;
+/// CC 32: Bank Select LSB
+pub const CC_BANK_SELECT_LSB: u8 = 32;
+
+/// A patch: a program number within a bank
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PatchSelect {
+    /// 14-bit bank number (MSB << 7 | LSB)
+    pub bank: u16,
+    pub program: u8,
+}
+
+impl PatchSelect {
+    pub fn new(bank: u16, program: u8) -> Self {
+        Self {
+            bank: bank & 0x3FFF,
+            program: program & 0x7F,
+        }
+    }
+
+    pub fn bank_msb(&self) -> u8 {
+        (self.bank >> 7) as u8 & 0x7F
+    }
+
+    pub fn bank_lsb(&self) -> u8 {
+        self.bank as u8 & 0x7F
+    }
+
+    /// Bank Select MSB, Bank Select LSB and Program Change on `channel`,
+    /// in the order devices expect them
+    pub fn to_bytes(&self, channel: u8) -> [u8; 8] {
+        let status = 0xB0 | (channel & 0x0F);
+        [
+            status,
+            CC_BANK_SELECT_MSB,
+            self.bank_msb(),
+            status,
+            CC_BANK_SELECT_LSB,
+            self.bank_lsb(),
+            0xC0 | (channel & 0x0F),
+            self.program & 0x7F,
+        ]
+    }
+}
+
+/// Remembers Bank Select per channel and combines it with the Program
+/// Change that follows into a [`PatchSelect`]
+#[derive(Debug, Clone, Default)]
+pub struct BankSelect {
+    banks: [u16; 16],
+}
+
+impl BankSelect {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bank currently selected on `channel`
+    pub fn bank(&self, channel: u8) -> u16 {
+        self.banks[(channel & 0x0F) as usize]
+    }
+
+    pub fn set_bank(&mut self, channel: u8, bank: u16) {
+        self.banks[(channel & 0x0F) as usize] = bank & 0x3FFF;
+    }
+
+    /// Track Bank Select; a Program Change returns the patch it selects
+    pub fn handle_event(&mut self, event: &ChannelEvent) -> Option<PatchSelect> {
+        let bank = &mut self.banks[(event.channel & 0x0F) as usize];
+        match event.event {
+            MidiEvent::ControlChange(CC_BANK_SELECT_MSB, value) => {
+                *bank = (*bank & 0x7F) | ((value as u16 & 0x7F) << 7);
+                None
+            }
+            MidiEvent::ControlChange(CC_BANK_SELECT_LSB, value) => {
+                *bank = (*bank & !0x7F) | (value as u16 & 0x7F);
+                None
+            }
+            MidiEvent::ProgramChange(program) => Some(PatchSelect::new(*bank, program)),
+            _ => None,
+        }
+    }
+
+    /// Back to bank 0 on every channel
+    pub fn reset(&mut self) {
+        self.banks = [0; 16];
+    }
+}
+
+/// A Program Change together with the bank selected when it arrived
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramSelection {
+    pub channel: u8,
+    /// 14-bit bank number (MSB << 7 | LSB)
+    pub bank: u16,
+    pub program: u8,
+}
+
+impl ProgramSelection {
+    pub fn patch(&self) -> PatchSelect {
+        PatchSelect::new(self.bank, self.program)
+    }
+}
+
+/// Called when its program is selected
+pub type PresetCallback = Box<dyn FnMut(ProgramSelection) + Send>;
+
+/// What a program number selects
+pub enum ProgramTarget {
+    /// A preset ID, returned from [`ProgramMap::handle_event`]
+    Preset(u32),
+    Callback(PresetCallback),
+}
+
+impl std::fmt::Debug for ProgramTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProgramTarget::Preset(id) => f.debug_tuple("Preset").field(id).finish(),
+            ProgramTarget::Callback(_) => f.write_str("Callback"),
+        }
+    }
+}
+
+/// Maps Program Change (and Bank Select) numbers to presets
+///
+/// Mappings for a specific bank take precedence over ones registered for
+/// every bank. With identity mapping on, unmapped programs select preset
+/// `bank * 128 + program`.
+#[derive(Debug, Default)]
+pub struct ProgramMap {
+    targets: HashMap<(Option<u16>, u8), ProgramTarget>,
+    banks: BankSelect,
+    channel: Option<u8>,
+    identity: bool,
+    last: Option<ProgramSelection>,
+}
+
+impl ProgramMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Select `preset` with `program` in any bank
+    pub fn map(&mut self, program: u8, preset: u32) {
+        self.set_target(None, program, ProgramTarget::Preset(preset));
+    }
+
+    /// Select `preset` with `program` in `bank` only
+    pub fn map_bank(&mut self, bank: u16, program: u8, preset: u32) {
+        self.set_target(Some(bank), program, ProgramTarget::Preset(preset));
+    }
+
+    /// Run `callback` when `program` is selected, in `bank` or any bank
+    pub fn on_program(
+        &mut self,
+        bank: Option<u16>,
+        program: u8,
+        callback: impl FnMut(ProgramSelection) + Send + 'static,
+    ) {
+        self.set_target(bank, program, ProgramTarget::Callback(Box::new(callback)));
+    }
+
+    pub fn set_target(&mut self, bank: Option<u16>, program: u8, target: ProgramTarget) {
+        self.targets
+            .insert((bank.map(|bank| bank & 0x3FFF), program & 0x7F), target);
+    }
+
+    pub fn remove(&mut self, bank: Option<u16>, program: u8) -> Option<ProgramTarget> {
+        self.targets
+            .remove(&(bank.map(|bank| bank & 0x3FFF), program & 0x7F))
+    }
+
+    pub fn clear(&mut self) {
+        self.targets.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Only respond on `channel` (None for every channel, the default)
+    pub fn set_channel(&mut self, channel: Option<u8>) {
+        self.channel = channel.map(|channel| channel & 0x0F);
+    }
+
+    /// Let unmapped programs select preset `bank * 128 + program`
+    pub fn set_identity(&mut self, identity: bool) {
+        self.identity = identity;
+    }
+
+    /// Bank currently selected on `channel`
+    pub fn bank(&self, channel: u8) -> u16 {
+        self.banks.bank(channel)
+    }
+
+    /// The most recent Program Change received
+    pub fn last_selection(&self) -> Option<ProgramSelection> {
+        self.last
+    }
+
+    /// Track Bank Select and dispatch Program Change
+    ///
+    /// Returns the preset ID a Program Change selects; callbacks run here
+    /// and return None, as do all other events.
+    pub fn handle_event(&mut self, event: &ChannelEvent) -> Option<u32> {
+        let channel = event.channel & 0x0F;
+        if self.channel.is_some_and(|only| only != channel) {
+            return None;
+        }
+        let patch = self.banks.handle_event(event)?;
+        let selection = ProgramSelection {
+            channel,
+            bank: patch.bank,
+            program: patch.program,
+        };
+        self.last = Some(selection);
+        self.select(selection)
+    }
+
+    fn select(&mut self, selection: ProgramSelection) -> Option<u32> {
+        let program = selection.program;
+        let bank =
+            Some(selection.bank).filter(|&bank| self.targets.contains_key(&(Some(bank), program)));
+        match self.targets.get_mut(&(bank, program)) {
+            Some(ProgramTarget::Preset(id)) => Some(*id),
+            Some(ProgramTarget::Callback(callback)) => {
+                callback(selection);
+                None
+            }
+            None if self.identity => Some(selection.bank as u32 * 128 + program as u32),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    fn event(channel: u8, event: MidiEvent) -> ChannelEvent {
+        ChannelEvent { channel, event }
+    }
+
+    #[test]
+    fn bank_specific_mappings_win() {
+        let mut map = ProgramMap::new();
+        map.map(3, 10);
+        map.map_bank(2, 3, 20);
+
+        assert_eq!(
+            map.handle_event(&event(0, MidiEvent::ProgramChange(3))),
+            Some(10)
+        );
+        map.handle_event(&event(0, MidiEvent::ControlChange(CC_BANK_SELECT_MSB, 0)));
+        map.handle_event(&event(0, MidiEvent::ControlChange(CC_BANK_SELECT_LSB, 2)));
+        assert_eq!(map.bank(0), 2);
+        assert_eq!(
+            map.handle_event(&event(0, MidiEvent::ProgramChange(3))),
+            Some(20)
+        );
+        // Banks are per channel
+        assert_eq!(
+            map.handle_event(&event(1, MidiEvent::ProgramChange(3))),
+            Some(10)
+        );
+
+        assert_eq!(
+            map.handle_event(&event(0, MidiEvent::ProgramChange(4))),
+            None
+        );
+        map.set_identity(true);
+        assert_eq!(
+            map.handle_event(&event(0, MidiEvent::ProgramChange(4))),
+            Some(2 * 128 + 4)
+        );
+    }
+
+    #[test]
+    fn bank_select_coalesces_into_patches() {
+        let mut banks = BankSelect::new();
+        for cc in [
+            MidiEvent::ControlChange(CC_BANK_SELECT_MSB, 1),
+            MidiEvent::ControlChange(CC_BANK_SELECT_LSB, 5),
+            MidiEvent::ControlChange(7, 100),
+        ] {
+            assert_eq!(banks.handle_event(&event(3, cc)), None);
+        }
+        let patch = banks
+            .handle_event(&event(3, MidiEvent::ProgramChange(12)))
+            .unwrap();
+        assert_eq!(patch, PatchSelect::new(133, 12));
+        assert_eq!((patch.bank_msb(), patch.bank_lsb()), (1, 5));
+        assert_eq!(patch.to_bytes(3), [0xB3, 0, 1, 0xB3, 32, 5, 0xC3, 12]);
+        // Another channel is still on bank 0
+        assert_eq!(
+            banks.handle_event(&event(4, MidiEvent::ProgramChange(12))),
+            Some(PatchSelect::new(0, 12))
+        );
+    }
+
+    #[test]
+    fn callbacks_and_channel_filter() {
+        let selected = Arc::new(AtomicU32::new(0));
+        let mut map = ProgramMap::new();
+        let seen = selected.clone();
+        map.on_program(None, 7, move |selection| {
+            seen.store(selection.program as u32 + 100, Ordering::Relaxed);
+        });
+        map.set_channel(Some(5));
+
+        assert_eq!(
+            map.handle_event(&event(4, MidiEvent::ProgramChange(7))),
+            None
+        );
+        assert_eq!(selected.load(Ordering::Relaxed), 0);
+        assert_eq!(
+            map.handle_event(&event(5, MidiEvent::ProgramChange(7))),
+            None
+        );
+        assert_eq!(selected.load(Ordering::Relaxed), 107);
+        assert_eq!(
+            map.last_selection(),
+            Some(ProgramSelection {
+                channel: 5,
+                bank: 0,
+                program: 7
+            })
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Registered / non-registered parameter number (RPN/NRPN) decoding
+
+pub const CC_DATA_ENTRY_MSB: u8 = 6;
+pub const CC_DATA_ENTRY_LSB: u8 = 38;
+pub const CC_NRPN_LSB: u8 = 98;
+pub const CC_NRPN_MSB: u8 = 99;
+pub const CC_RPN_LSB: u8 = 100;
+pub const CC_RPN_MSB: u8 = 101;
+
+/// RPN 0: pitch bend sensitivity (MSB semitones, LSB cents)
+pub const RPN_PITCH_BEND_SENSITIVITY: u16 = 0x0000;
+/// RPN 6: MPE configuration message (MSB = member channel count)
+pub const RPN_MPE_CONFIGURATION: u16 = 0x0006;
+/// RPN 127/127: deselects the current parameter
+pub const RPN_NULL: u16 = 0x3FFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterNumber {
+    Registered(u16),
+    NonRegistered(u16),
+}
+
+/// A data entry resolved to the parameter it targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParameterChange {
+    pub number: ParameterNumber,
+    pub value: u16, // 14-bit: MSB << 7 | LSB
+}
+
+impl ParameterChange {
+    /// The data entry MSB (coarse value)
+    pub fn msb(&self) -> u8 {
+        (self.value >> 7) as u8
+    }
+
+    /// The data entry LSB (fine value)
+    pub fn lsb(&self) -> u8 {
+        (self.value & 0x7F) as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ChannelState {
+    number_msb: u8,
+    number_lsb: u8,
+    registered: bool,
+    selected: bool,
+    data_msb: u8,
+}
+
+/// Decodes RPN/NRPN selection and data entry CCs on all 16 channels
+#[derive(Debug, Clone)]
+pub struct ParameterNumberDecoder {
+    channels: [ChannelState; 16],
+}
+
+impl ParameterNumberDecoder {
+    pub fn new() -> Self {
+        Self {
+            channels: [ChannelState::default(); 16],
+        }
+    }
+
+    /// Feed a control change
+    /// Returns a parameter change when the CC is a data entry for a selected parameter
+    pub fn handle_cc(&mut self, channel: u8, cc_num: u8, value: u8) -> Option<ParameterChange> {
+        let state = self.channels.get_mut(channel as usize)?;
+        let value = value & 0x7F;
+
+        match cc_num {
+            CC_RPN_MSB | CC_NRPN_MSB => {
+                state.registered = cc_num == CC_RPN_MSB;
+                state.number_msb = value;
+                state.selected = !(state.registered && Self::is_null(state));
+                state.data_msb = 0;
+                None
+            }
+            CC_RPN_LSB | CC_NRPN_LSB => {
+                state.registered = cc_num == CC_RPN_LSB;
+                state.number_lsb = value;
+                state.selected = !(state.registered && Self::is_null(state));
+                state.data_msb = 0;
+                None
+            }
+            CC_DATA_ENTRY_MSB if state.selected => {
+                state.data_msb = value;
+                Some(Self::change(state, (value as u16) << 7))
+            }
+            CC_DATA_ENTRY_LSB if state.selected => Some(Self::change(
+                state,
+                ((state.data_msb as u16) << 7) | value as u16,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Get the parameter currently selected on a channel
+    pub fn selected(&self, channel: u8) -> Option<ParameterNumber> {
+        let state = self.channels.get(channel as usize)?;
+        state.selected.then(|| Self::number(state))
+    }
+
+    /// Forget all selections
+    pub fn reset(&mut self) {
+        self.channels = [ChannelState::default(); 16];
+    }
+
+    fn is_null(state: &ChannelState) -> bool {
+        ((state.number_msb as u16) << 7 | state.number_lsb as u16) == RPN_NULL
+    }
+
+    fn number(state: &ChannelState) -> ParameterNumber {
+        let number = (state.number_msb as u16) << 7 | state.number_lsb as u16;
+        if state.registered {
+            ParameterNumber::Registered(number)
+        } else {
+            ParameterNumber::NonRegistered(number)
+        }
+    }
+
+    fn change(state: &ChannelState, value: u16) -> ParameterChange {
+        ParameterChange {
+            number: Self::number(state),
+            value,
+        }
+    }
+}
+
+impl Default for ParameterNumberDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rpn_data_entry_decoded() {
+        let mut decoder = ParameterNumberDecoder::new();
+        assert_eq!(decoder.handle_cc(0, CC_RPN_MSB, 0), None);
+        assert_eq!(decoder.handle_cc(0, CC_RPN_LSB, 0), None);
+
+        let change = decoder.handle_cc(0, CC_DATA_ENTRY_MSB, 12).unwrap();
+        assert_eq!(
+            change.number,
+            ParameterNumber::Registered(RPN_PITCH_BEND_SENSITIVITY)
+        );
+        assert_eq!(change.msb(), 12);
+
+        let fine = decoder.handle_cc(0, CC_DATA_ENTRY_LSB, 50).unwrap();
+        assert_eq!((fine.msb(), fine.lsb()), (12, 50));
+    }
+
+    #[test]
+    fn nrpn_and_null_selection() {
+        let mut decoder = ParameterNumberDecoder::new();
+        decoder.handle_cc(2, CC_NRPN_MSB, 1);
+        decoder.handle_cc(2, CC_NRPN_LSB, 8);
+        assert_eq!(
+            decoder.selected(2),
+            Some(ParameterNumber::NonRegistered(136))
+        );
+        assert_eq!(decoder.selected(0), None);
+
+        decoder.handle_cc(2, CC_RPN_MSB, 127);
+        decoder.handle_cc(2, CC_RPN_LSB, 127);
+        assert_eq!(decoder.handle_cc(2, CC_DATA_ENTRY_MSB, 64), None);
+    }
+}
@@ -0,0 +1,338 @@
+//! Scala scale (.scl) and keyboard mapping (.kbm) files for microtonal tuning
+//!
+//! See <https://www.huygens-fokker.org/scala/scl_format.html> for the formats.
+
+use anyhow::{anyhow, bail, Result};
+use std::path::Path;
+
+/// A scale read from a Scala `.scl` file
+///
+/// Degrees are stored in cents above the root; the last degree is the period
+/// (usually the octave, 1200 cents).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    description: String,
+    degrees: Vec<f64>,
+}
+
+impl Scale {
+    /// Parse the contents of a `.scl` file
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines().filter(|line| !line.starts_with('!'));
+        let description = lines
+            .next()
+            .ok_or_else(|| anyhow!("Scala file is missing its description line"))?
+            .trim()
+            .to_string();
+        let count_line = lines
+            .next()
+            .ok_or_else(|| anyhow!("Scala file is missing its note count"))?;
+        let count: usize = first_token(count_line)
+            .parse()
+            .map_err(|_| anyhow!("Invalid Scala note count: {:?}", count_line.trim()))?;
+
+        let degrees = lines
+            .take(count)
+            .map(parse_pitch)
+            .collect::<Result<Vec<_>>>()?;
+        if degrees.len() != count {
+            bail!(
+                "Scala file declares {} notes but lists {}",
+                count,
+                degrees.len()
+            );
+        }
+        if count == 0 {
+            bail!("Scala file has no notes");
+        }
+        Ok(Self {
+            description,
+            degrees,
+        })
+    }
+
+    /// Read and parse a `.scl` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Equal division of the octave into `divisions` steps
+    pub fn equal_temperament(divisions: u16) -> Self {
+        let divisions = divisions.max(1);
+        let step = 1200.0 / divisions as f64;
+        Self {
+            description: format!("{}-tone equal temperament", divisions),
+            degrees: (1..=divisions).map(|i| i as f64 * step).collect(),
+        }
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Number of notes per period
+    pub fn len(&self) -> usize {
+        self.degrees.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.degrees.is_empty()
+    }
+
+    /// Size of the repeating interval in cents
+    pub fn period_cents(&self) -> f64 {
+        self.degrees[self.degrees.len() - 1]
+    }
+
+    /// Cents above the root for any degree, extending past the period
+    pub fn degree_cents(&self, degree: i32) -> f64 {
+        let len = self.len() as i32;
+        let periods = degree.div_euclid(len);
+        let index = degree.rem_euclid(len);
+        let within = if index == 0 {
+            0.0
+        } else {
+            self.degrees[index as usize - 1]
+        };
+        periods as f64 * self.period_cents() + within
+    }
+}
+
+/// How MIDI keys map onto scale degrees, read from a Scala `.kbm` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardMapping {
+    first_note: u8,
+    last_note: u8,
+    middle_note: u8,
+    reference_note: u8,
+    reference_freq: f64,
+    octave_degree: usize,
+    keys: Vec<Option<usize>>, // Empty = linear mapping
+}
+
+impl KeyboardMapping {
+    /// Map every key linearly onto consecutive scale degrees, with the
+    /// scale's root on `middle_note` and `reference_note` at `reference_freq` Hz
+    pub fn linear(middle_note: u8, reference_note: u8, reference_freq: f64) -> Self {
+        Self {
+            first_note: 0,
+            last_note: 127,
+            middle_note: middle_note.min(127),
+            reference_note: reference_note.min(127),
+            reference_freq,
+            octave_degree: 0,
+            keys: Vec::new(),
+        }
+    }
+
+    /// Parse the contents of a `.kbm` file
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text
+            .lines()
+            .filter(|line| !line.starts_with('!') && !line.trim().is_empty());
+        let mut header = |name: &str| {
+            lines
+                .next()
+                .map(first_token)
+                .ok_or_else(|| anyhow!("Keyboard mapping is missing its {}", name))
+        };
+
+        let size = parse_number::<usize>(header("map size")?, "map size")?;
+        let first_note = parse_note(header("first note")?)?;
+        let last_note = parse_note(header("last note")?)?;
+        let middle_note = parse_note(header("middle note")?)?;
+        let reference_note = parse_note(header("reference note")?)?;
+        let reference_freq = parse_number::<f64>(header("reference frequency")?, "frequency")?;
+        let octave_degree = parse_number::<usize>(header("octave degree")?, "octave degree")?;
+
+        let keys = lines
+            .take(size)
+            .map(|line| match first_token(line) {
+                "x" | "X" => Ok(None),
+                token => parse_number::<usize>(token, "key mapping").map(Some),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // Scala pads short mappings with unmapped keys
+        let mut keys = keys;
+        keys.resize(size, None);
+
+        Ok(Self {
+            first_note,
+            last_note,
+            middle_note,
+            reference_note,
+            reference_freq,
+            octave_degree,
+            keys,
+        })
+    }
+
+    /// Read and parse a `.kbm` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn reference_note(&self) -> u8 {
+        self.reference_note
+    }
+
+    pub fn reference_freq(&self) -> f64 {
+        self.reference_freq
+    }
+
+    /// Cents of `note` relative to the scale root, or `None` if the key is
+    /// unmapped or outside the retuned range
+    pub fn note_cents(&self, scale: &Scale, note: u8) -> Option<f64> {
+        if note < self.first_note || note > self.last_note {
+            return None;
+        }
+        self.cents_unbounded(scale, note)
+    }
+
+    /// Frequency of every MIDI note (unmapped notes are `None`)
+    pub fn frequencies(&self, scale: &Scale) -> [Option<f32>; 128] {
+        let reference = self
+            .cents_unbounded(scale, self.reference_note)
+            .unwrap_or(0.0);
+        let mut table = [None; 128];
+        for (note, freq) in table.iter_mut().enumerate() {
+            *freq = self.note_cents(scale, note as u8).map(|cents| {
+                (self.reference_freq * 2.0_f64.powf((cents - reference) / 1200.0)) as f32
+            });
+        }
+        table
+    }
+
+    fn cents_unbounded(&self, scale: &Scale, note: u8) -> Option<f64> {
+        let offset = note as i32 - self.middle_note as i32;
+        if self.keys.is_empty() {
+            return Some(scale.degree_cents(offset));
+        }
+        let size = self.keys.len() as i32;
+        let repeats = offset.div_euclid(size);
+        let degree = self.keys[offset.rem_euclid(size) as usize]?;
+        let formal_octave = if self.octave_degree == 0 {
+            scale.period_cents()
+        } else {
+            scale.degree_cents(self.octave_degree as i32)
+        };
+        Some(repeats as f64 * formal_octave + scale.degree_cents(degree as i32))
+    }
+}
+
+impl Default for KeyboardMapping {
+    fn default() -> Self {
+        Self::linear(60, 69, 440.0)
+    }
+}
+
+fn first_token(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+fn parse_number<T: std::str::FromStr>(token: &str, name: &str) -> Result<T> {
+    token
+        .parse()
+        .map_err(|_| anyhow!("Invalid {} in keyboard mapping: {:?}", name, token))
+}
+
+fn parse_note(token: &str) -> Result<u8> {
+    let note = parse_number::<u8>(token, "note")?;
+    if note > 127 {
+        bail!("Note {} out of MIDI range", note);
+    }
+    Ok(note)
+}
+
+/// A pitch line is cents if it contains a '.', otherwise a ratio or integer
+fn parse_pitch(line: &str) -> Result<f64> {
+    let token = first_token(line);
+    let invalid = || anyhow!("Invalid Scala pitch: {:?}", line.trim());
+    if token.contains('.') {
+        return token.parse().map_err(|_| invalid());
+    }
+    let (num, den) = match token.split_once('/') {
+        Some((num, den)) => (num, den),
+        None => (token, "1"),
+    };
+    let num: f64 = num.parse().map_err(|_| invalid())?;
+    let den: f64 = den.parse().map_err(|_| invalid())?;
+    if num <= 0.0 || den <= 0.0 {
+        return Err(invalid());
+    }
+    Ok(1200.0 * (num / den).log2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JUST_MAJOR: &str = "! just.scl
+!
+Just major
+ 7
+!
+ 9/8
+ 5/4
+ 4/3
+ 3/2
+ 5/3
+ 15/8
+ 2/1
+";
+
+    #[test]
+    fn parses_ratios_and_cents() {
+        let scale = Scale::parse(JUST_MAJOR).unwrap();
+        assert_eq!(scale.description(), "Just major");
+        assert_eq!(scale.len(), 7);
+        assert!((scale.degree_cents(4) - 701.955).abs() < 1e-3);
+        assert!((scale.period_cents() - 1200.0).abs() < 1e-9);
+
+        let cents = Scale::parse("cents\n2\n 350.0 neutral third\n1200.\n").unwrap();
+        assert_eq!(cents.degree_cents(1), 350.0);
+        assert_eq!(cents.degree_cents(-1), -850.0);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert!(Scale::parse("").is_err());
+        assert!(Scale::parse("x\n3\n9/8\n").is_err());
+        assert!(Scale::parse("x\n1\nabc\n").is_err());
+        assert!(KeyboardMapping::parse("12\n0\n127\n").is_err());
+    }
+
+    #[test]
+    fn keyboard_mapping_skips_unmapped_keys() {
+        // Map the white keys of each octave onto a 7-note scale
+        let kbm = "! white keys
+12
+0
+127
+60
+69
+440.0
+7
+0
+x
+1
+x
+2
+3
+x
+4
+x
+5
+x
+6
+";
+        let mapping = KeyboardMapping::parse(kbm).unwrap();
+        let scale = Scale::parse(JUST_MAJOR).unwrap();
+        let table = mapping.frequencies(&scale);
+        assert_eq!(table[61], None);
+        assert!((table[69].unwrap() - 440.0).abs() < 1e-3);
+        // A4 is the major sixth (5/3) above C4
+        assert!((table[60].unwrap() - 264.0).abs() < 1e-2);
+        assert!((table[72].unwrap() - 528.0).abs() < 1e-2);
+    }
+}
@@ -0,0 +1,131 @@
+//! Timestamped MIDI event scheduling
+
+use crate::midi_input::ChannelEvent;
+use std::collections::VecDeque;
+
+/// Default number of events an `EventScheduler` can hold
+pub const DEFAULT_SCHEDULER_CAPACITY: usize = 1024;
+
+/// A channel event due at `time` (microseconds on the scheduler's clock)
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedEvent {
+    pub time: u64,
+    pub event: ChannelEvent,
+}
+
+/// Holds events in time order until they fall due
+///
+/// Storage is reserved up front and never grows: `schedule` refuses events
+/// once the scheduler is full. Events with equal times come out in the
+/// order they were scheduled.
+#[derive(Debug, Clone)]
+pub struct EventScheduler {
+    events: VecDeque<TimedEvent>,
+    capacity: usize,
+    dropped: usize,
+}
+
+impl EventScheduler {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_SCHEDULER_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+            dropped: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Events refused because the scheduler was full
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Queue `event` for `time`; returns false (and counts a drop) when full
+    pub fn schedule(&mut self, time: u64, event: ChannelEvent) -> bool {
+        if self.events.len() >= self.capacity {
+            self.dropped += 1;
+            return false;
+        }
+        // Live input arrives in order, so this is almost always the back
+        let index = self.events.partition_point(|pending| pending.time <= time);
+        self.events.insert(index, TimedEvent { time, event });
+        true
+    }
+
+    /// Time of the earliest pending event
+    pub fn next_time(&self) -> Option<u64> {
+        self.events.front().map(|pending| pending.time)
+    }
+
+    /// Remove and return the earliest event due at or before `now`
+    pub fn pop_due(&mut self, now: u64) -> Option<TimedEvent> {
+        if self.next_time()? <= now {
+            self.events.pop_front()
+        } else {
+            None
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+impl Default for EventScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_input::MidiEvent;
+
+    fn note(note: u8) -> ChannelEvent {
+        ChannelEvent {
+            channel: 0,
+            event: MidiEvent::NoteOn(note, 100),
+        }
+    }
+
+    #[test]
+    fn events_come_out_in_time_order() {
+        let mut scheduler = EventScheduler::with_capacity(4);
+        scheduler.schedule(300, note(62));
+        scheduler.schedule(100, note(60));
+        scheduler.schedule(300, note(64)); // Same time, keeps arrival order
+        assert_eq!(scheduler.next_time(), Some(100));
+
+        assert_eq!(scheduler.pop_due(99), None);
+        assert_eq!(scheduler.pop_due(100).unwrap().event, note(60));
+        assert_eq!(scheduler.pop_due(200), None);
+        assert_eq!(scheduler.pop_due(500).unwrap().event, note(62));
+        assert_eq!(scheduler.pop_due(500).unwrap().event, note(64));
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn full_scheduler_drops_events() {
+        let mut scheduler = EventScheduler::with_capacity(1);
+        assert!(scheduler.schedule(0, note(60)));
+        assert!(!scheduler.schedule(0, note(61)));
+        assert_eq!(scheduler.dropped(), 1);
+        assert_eq!(scheduler.len(), 1);
+    }
+}
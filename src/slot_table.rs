@@ -0,0 +1,84 @@
+//! Per-slot storage for `VoiceAllocator` and `CCMap`
+//!
+//! With `std` the slots live in a `Vec` sized at construction, which
+//! `push` may grow; without it they live in a fixed array of `N`, and
+//! `push` fails once it is full.
+
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub(crate) struct SlotTable<T, const N: usize>(Vec<T>);
+
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone)]
+pub(crate) struct SlotTable<T, const N: usize> {
+    items: [T; N],
+    len: usize,
+}
+
+#[cfg(feature = "std")]
+impl<T: Copy, const N: usize> SlotTable<T, N> {
+    /// A table of `len` copies of `value`
+    pub(crate) fn filled(value: T, len: usize) -> Option<Self> {
+        Some(Self(vec![value; len]))
+    }
+
+    /// Append a slot; returns false if the table is full
+    pub(crate) fn push(&mut self, value: T) -> bool {
+        self.0.push(value);
+        true
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<T: Copy, const N: usize> SlotTable<T, N> {
+    /// A table of `len` copies of `value`, or None if `len` exceeds `N`
+    pub(crate) fn filled(value: T, len: usize) -> Option<Self> {
+        (len <= N).then_some(Self {
+            items: [value; N],
+            len,
+        })
+    }
+
+    /// Append a slot; returns false if the table is full
+    pub(crate) fn push(&mut self, value: T) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.items[self.len] = value;
+        self.len += 1;
+        true
+    }
+}
+
+impl<T, const N: usize> Deref for SlotTable<T, N> {
+    type Target = [T];
+
+    #[cfg(feature = "std")]
+    fn deref(&self) -> &[T] {
+        &self.0
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn deref(&self) -> &[T] {
+        &self.items[..self.len]
+    }
+}
+
+impl<T, const N: usize> DerefMut for SlotTable<T, N> {
+    #[cfg(feature = "std")]
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.0
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.items[..self.len]
+    }
+}
@@ -0,0 +1,657 @@
+//! Standard MIDI File (format 0 and 1) reading, writing, playback and
+//! recording
+//!
+//! See the MIDI Association's "Standard MIDI Files 1.0" for the format.
+
+use crate::midi_input::ChannelEvent;
+use crate::scheduler::TimedEvent;
+use anyhow::{anyhow, bail, Result};
+use std::path::Path;
+
+/// Tempo assumed until the first Set Tempo meta event (120 BPM)
+pub const DEFAULT_MICROS_PER_QUARTER: u32 = 500_000;
+
+/// A parsed Standard MIDI File
+///
+/// Channel messages the crate has a [`MidiEvent`](crate::MidiEvent) for,
+/// program changes included, are kept per track with their tick positions;
+/// tempo changes from every track are merged into one tempo map. Other
+/// events (SysEx, polyphonic aftertouch, text meta events) are skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiFile {
+    format: u16,
+    ticks_per_quarter: u16,
+    tracks: Vec<Vec<(u64, ChannelEvent)>>,
+    tempo_map: Vec<(u64, u32)>, // (tick, microseconds per quarter), sorted by tick
+}
+
+impl MidiFile {
+    /// Parse the bytes of a `.mid` file
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(4)? != b"MThd" {
+            bail!("Not a Standard MIDI File (missing MThd header)");
+        }
+        let header_len = reader.u32()? as usize;
+        if header_len < 6 {
+            bail!("MIDI file header is too short ({} bytes)", header_len);
+        }
+        let header = reader.take(header_len)?;
+        let format = u16::from_be_bytes([header[0], header[1]]);
+        let track_count = u16::from_be_bytes([header[2], header[3]]);
+        let division = u16::from_be_bytes([header[4], header[5]]);
+        if format > 1 {
+            bail!("Unsupported MIDI file format {}", format);
+        }
+        if division & 0x8000 != 0 || division == 0 {
+            bail!("SMPTE time division is not supported");
+        }
+
+        let mut file = Self {
+            format,
+            ticks_per_quarter: division,
+            tracks: Vec::with_capacity(track_count as usize),
+            tempo_map: Vec::new(),
+        };
+        while file.tracks.len() < track_count as usize && !reader.is_empty() {
+            let id = reader.take(4)?;
+            let len = reader.u32()? as usize;
+            let chunk = reader.take(len)?;
+            if id == b"MTrk" {
+                let track = file.parse_track(chunk)?;
+                file.tracks.push(track);
+            } // Unknown chunks are skipped, as the spec requires
+        }
+        if file.tracks.len() < track_count as usize {
+            bail!(
+                "MIDI file declares {} tracks but contains {}",
+                track_count,
+                file.tracks.len()
+            );
+        }
+        file.tempo_map.sort_by_key(|&(tick, _)| tick);
+        Ok(file)
+    }
+
+    /// Read and parse a `.mid` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    /// 0 (one multi-channel track) or 1 (simultaneous tracks)
+    pub fn format(&self) -> u16 {
+        self.format
+    }
+
+    pub fn ticks_per_quarter(&self) -> u16 {
+        self.ticks_per_quarter
+    }
+
+    /// Events of each track as (tick, event)
+    pub fn tracks(&self) -> &[Vec<(u64, ChannelEvent)>] {
+        &self.tracks
+    }
+
+    /// Tempo changes as (tick, microseconds per quarter note)
+    pub fn tempo_map(&self) -> &[(u64, u32)] {
+        &self.tempo_map
+    }
+
+    /// Time of `tick` in microseconds from the start, following the tempo map
+    pub fn tick_to_micros(&self, tick: u64) -> u64 {
+        let ppq = self.ticks_per_quarter as u64;
+        let mut micros = 0;
+        let mut from = 0;
+        let mut tempo = DEFAULT_MICROS_PER_QUARTER as u64;
+        for &(change, next_tempo) in &self.tempo_map {
+            if change >= tick {
+                break;
+            }
+            micros += (change - from) * tempo / ppq;
+            from = change;
+            tempo = next_tempo as u64;
+        }
+        micros + (tick - from) * tempo / ppq
+    }
+
+    /// Every track merged into one list of events timed in microseconds
+    /// Events at the same time keep their track order.
+    pub fn timed_events(&self) -> Vec<TimedEvent> {
+        let mut events: Vec<(u64, TimedEvent)> = self
+            .tracks
+            .iter()
+            .flatten()
+            .map(|(tick, event)| {
+                let time = self.tick_to_micros(*tick);
+                (
+                    *tick,
+                    TimedEvent {
+                        time,
+                        event: event.clone(),
+                    },
+                )
+            })
+            .collect();
+        events.sort_by_key(|(tick, _)| *tick);
+        events.into_iter().map(|(_, event)| event).collect()
+    }
+
+    /// Length of the file in microseconds (the last event's time)
+    pub fn duration_micros(&self) -> u64 {
+        let last_tick = self
+            .tracks
+            .iter()
+            .filter_map(|track| track.last().map(|(tick, _)| *tick))
+            .max()
+            .unwrap_or(0);
+        self.tick_to_micros(last_tick)
+    }
+
+    /// Encode as a Standard MIDI File; the tempo map is written into the
+    /// first track
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = b"MThd".to_vec();
+        bytes.extend(6u32.to_be_bytes());
+        bytes.extend(self.format.to_be_bytes());
+        bytes.extend((self.tracks.len().max(1) as u16).to_be_bytes());
+        bytes.extend(self.ticks_per_quarter.to_be_bytes());
+
+        let empty = Vec::new();
+        let first = self.tracks.first().unwrap_or(&empty);
+        let rest = self.tracks.iter().skip(1);
+        for (index, track) in std::iter::once(first).chain(rest).enumerate() {
+            // (tick, message bytes, length); tempo changes sort before
+            // notes on the same tick
+            let tempos = self
+                .tempo_map
+                .iter()
+                .filter(|_| index == 0)
+                .map(|&(tick, tempo)| {
+                    let [_, a, b, c] = tempo.to_be_bytes();
+                    (tick, [0xFF, 0x51, 0x03, a, b, c], 6)
+                });
+            let messages = track.iter().map(|(tick, event)| {
+                let (message, len) = event.to_bytes();
+                (*tick, [message[0], message[1], message[2], 0, 0, 0], len)
+            });
+            let mut items: Vec<_> = tempos.chain(messages).collect();
+            items.sort_by_key(|&(tick, _, _)| tick);
+
+            let mut body = Vec::new();
+            let mut last_tick = 0;
+            for (tick, message, len) in items {
+                write_vlq(&mut body, (tick - last_tick) as u32);
+                body.extend(&message[..len]);
+                last_tick = tick;
+            }
+            body.extend([0x00, 0xFF, 0x2F, 0x00]); // End of track
+
+            bytes.extend(b"MTrk");
+            bytes.extend((body.len() as u32).to_be_bytes());
+            bytes.extend(body);
+        }
+        bytes
+    }
+
+    /// Write the file to `path`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    fn parse_track(&mut self, chunk: &[u8]) -> Result<Vec<(u64, ChannelEvent)>> {
+        let mut reader = Reader {
+            bytes: chunk,
+            pos: 0,
+        };
+        let mut events = Vec::new();
+        let mut tick = 0u64;
+        let mut running_status = None;
+        while !reader.is_empty() {
+            tick += reader.vlq()? as u64;
+            let status = match reader.peek()? {
+                byte if byte & 0x80 != 0 => {
+                    reader.pos += 1;
+                    byte
+                }
+                _ => running_status.ok_or_else(|| anyhow!("Data byte without a status"))?,
+            };
+            match status {
+                0xFF => {
+                    running_status = None;
+                    let kind = reader.byte()?;
+                    let len = reader.vlq()? as usize;
+                    let data = reader.take(len)?;
+                    match (kind, data) {
+                        (0x2F, _) => break, // End of track
+                        (0x51, &[a, b, c]) => {
+                            self.tempo_map
+                                .push((tick, u32::from_be_bytes([0, a, b, c])));
+                        }
+                        _ => {}
+                    }
+                }
+                0xF0 | 0xF7 => {
+                    running_status = None;
+                    let len = reader.vlq()? as usize;
+                    reader.take(len)?;
+                }
+                0x80..=0xEF => {
+                    running_status = Some(status);
+                    let data_len = if matches!(status & 0xF0, 0xC0 | 0xD0) {
+                        1
+                    } else {
+                        2
+                    };
+                    let data = reader.take(data_len)?;
+                    let message = [status, data[0], data.get(1).copied().unwrap_or(0)];
+                    if let Some(event) = ChannelEvent::from_bytes(&message) {
+                        events.push((tick, event));
+                    }
+                }
+                _ => bail!("Unexpected status byte {:#04x} in track", status),
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// Append `value` as a variable-length quantity
+fn write_vlq(out: &mut Vec<u8>, value: u32) {
+    let mut groups = [0u8; 5];
+    let mut count = 0;
+    let mut rest = value;
+    loop {
+        groups[count] = (rest & 0x7F) as u8;
+        count += 1;
+        rest >>= 7;
+        if rest == 0 {
+            break;
+        }
+    }
+    for i in (0..count).rev() {
+        let more = if i > 0 { 0x80 } else { 0 };
+        out.push(groups[i] | more);
+    }
+}
+
+/// Byte cursor over a chunk
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.bytes
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| anyhow!("Unexpected end of MIDI data"))
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| anyhow!("Unexpected end of MIDI data"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Variable-length quantity: 7 bits per byte, high bit set on all but the last
+    fn vlq(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("Variable-length quantity longer than four bytes")
+    }
+}
+
+/// Plays a [`MidiFile`] back as timestamped events
+///
+/// Call [`pop_due`](Self::pop_due) with the playback position (in
+/// microseconds from the start) to take each event as it falls due, for
+/// example from a [`MidiSynthController`](crate::MidiSynthController) loop.
+#[derive(Debug, Clone)]
+pub struct SmfPlayer {
+    events: Vec<TimedEvent>,
+    next: usize,
+    looping: bool,
+    offset: u64, // Start of the current pass when looping
+    duration: u64,
+}
+
+impl SmfPlayer {
+    pub fn new(file: &MidiFile) -> Self {
+        Self {
+            events: file.timed_events(),
+            next: 0,
+            looping: false,
+            offset: 0,
+            duration: file.duration_micros(),
+        }
+    }
+
+    pub fn events(&self) -> &[TimedEvent] {
+        &self.events
+    }
+
+    /// Length of one pass in microseconds
+    pub fn duration_micros(&self) -> u64 {
+        self.duration
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.next >= self.events.len()
+    }
+
+    /// Go back to the start
+    pub fn rewind(&mut self) {
+        self.next = 0;
+        self.offset = 0;
+    }
+
+    /// Jump to `micros`; events before it will not be played
+    pub fn seek(&mut self, micros: u64) {
+        self.offset = 0;
+        self.next = self.events.partition_point(|event| event.time < micros);
+    }
+
+    /// Next event due at or before `now`, with its time shifted to the
+    /// current pass when looping
+    pub fn pop_due(&mut self, now: u64) -> Option<TimedEvent> {
+        if self.next >= self.events.len() {
+            if !self.looping || self.events.is_empty() {
+                return None;
+            }
+            self.next = 0;
+            self.offset += self.duration.max(1);
+        }
+        let event = &self.events[self.next];
+        let time = event.time + self.offset;
+        if time > now {
+            return None;
+        }
+        self.next += 1;
+        Some(TimedEvent {
+            time,
+            event: event.event.clone(),
+        })
+    }
+}
+
+/// Default resolution of recorded files
+pub const DEFAULT_RECORD_PPQ: u16 = 480;
+
+/// Captures a live performance for saving as a Standard MIDI File
+///
+/// Events are recorded with their timestamps in microseconds (for example
+/// [`MidiSynthController::now`](crate::MidiSynthController::now)) and
+/// converted to ticks at the recording tempo; time zero is the first
+/// recorded event unless [`start`](Self::start) set it earlier.
+#[derive(Debug, Clone)]
+pub struct SmfRecorder {
+    events: Vec<TimedEvent>,
+    origin: Option<u64>,
+    micros_per_quarter: u32,
+    ticks_per_quarter: u16,
+}
+
+impl SmfRecorder {
+    /// Record at `bpm`, which sets the tempo written to the file
+    pub fn new(bpm: f64) -> Self {
+        let mut recorder = Self {
+            events: Vec::new(),
+            origin: None,
+            micros_per_quarter: DEFAULT_MICROS_PER_QUARTER,
+            ticks_per_quarter: DEFAULT_RECORD_PPQ,
+        };
+        recorder.set_tempo(bpm);
+        recorder
+    }
+
+    pub fn bpm(&self) -> f64 {
+        60_000_000.0 / self.micros_per_quarter as f64
+    }
+
+    pub fn set_tempo(&mut self, bpm: f64) {
+        if bpm > 0.0 {
+            self.micros_per_quarter = (60_000_000.0 / bpm).round() as u32;
+        }
+    }
+
+    pub fn set_ticks_per_quarter(&mut self, ticks: u16) {
+        self.ticks_per_quarter = ticks.clamp(1, 0x7FFF);
+    }
+
+    /// Mark time zero (e.g. when the user presses record)
+    pub fn start(&mut self, time: u64) {
+        self.origin = Some(time);
+    }
+
+    pub fn record(&mut self, time: u64, event: ChannelEvent) {
+        let origin = *self.origin.get_or_insert(time);
+        self.events.push(TimedEvent {
+            time: time.saturating_sub(origin),
+            event,
+        });
+    }
+
+    pub fn events(&self) -> &[TimedEvent] {
+        &self.events
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.origin = None;
+    }
+
+    /// The take as a file: format 0 puts everything in one track, format 1
+    /// writes a tempo track followed by one track per MIDI channel used
+    pub fn to_midi_file(&self, format: u16) -> MidiFile {
+        let ppq = self.ticks_per_quarter as u64;
+        let mpq = self.micros_per_quarter as u64;
+        let mut events: Vec<(u64, &TimedEvent)> = self
+            .events
+            .iter()
+            .map(|event| ((event.time * ppq + mpq / 2) / mpq, event))
+            .collect();
+        events.sort_by_key(|(tick, _)| *tick);
+
+        let tracks = if format == 0 {
+            vec![events
+                .iter()
+                .map(|(tick, timed)| (*tick, timed.event.clone()))
+                .collect()]
+        } else {
+            let mut tracks = vec![Vec::new()]; // Tempo track
+            for channel in 0..16 {
+                let track: Vec<_> = events
+                    .iter()
+                    .filter(|(_, timed)| timed.event.channel == channel)
+                    .map(|(tick, timed)| (*tick, timed.event.clone()))
+                    .collect();
+                if !track.is_empty() {
+                    tracks.push(track);
+                }
+            }
+            tracks
+        };
+        MidiFile {
+            format: format.min(1),
+            ticks_per_quarter: self.ticks_per_quarter,
+            tracks,
+            tempo_map: vec![(0, self.micros_per_quarter)],
+        }
+    }
+
+    /// Write the take to `path` as a format 1 file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.to_midi_file(1).save(path)
+    }
+}
+
+impl Default for SmfRecorder {
+    fn default() -> Self {
+        Self::new(120.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_input::MidiEvent;
+
+    /// Build a file from raw track bodies
+    fn smf(format: u16, ppq: u16, tracks: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = b"MThd".to_vec();
+        bytes.extend(6u32.to_be_bytes());
+        bytes.extend(format.to_be_bytes());
+        bytes.extend((tracks.len() as u16).to_be_bytes());
+        bytes.extend(ppq.to_be_bytes());
+        for track in tracks {
+            bytes.extend(b"MTrk");
+            bytes.extend((track.len() as u32).to_be_bytes());
+            bytes.extend(*track);
+        }
+        bytes
+    }
+
+    fn note(event: MidiEvent) -> ChannelEvent {
+        ChannelEvent { channel: 0, event }
+    }
+
+    #[test]
+    fn parses_format_0_with_running_status() {
+        let track: &[u8] = &[
+            0x00, 0x90, 60, 100, // Note on
+            0x60, 64, 100, // Running status, 96 ticks later
+            0x81, 0x40, 0x80, 60, 0, // 192 ticks later (two-byte delta)
+            0x00, 0xC0, 5, // Program change, one data byte
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let file = MidiFile::parse(&smf(0, 96, &[track])).unwrap();
+        assert_eq!(file.format(), 0);
+        assert_eq!(
+            file.tracks()[0],
+            [
+                (0, note(MidiEvent::NoteOn(60, 100))),
+                (96, note(MidiEvent::NoteOn(64, 100))),
+                (288, note(MidiEvent::NoteOff(60, 0))),
+                (288, note(MidiEvent::ProgramChange(5))),
+            ]
+        );
+        // 120 BPM default: a quarter note is half a second
+        assert_eq!(file.tick_to_micros(96), 500_000);
+    }
+
+    #[test]
+    fn format_1_tempo_map_times_every_track() {
+        let conductor: &[u8] = &[
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 120 BPM
+            0x60, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, // 60 BPM after one beat
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let melody: &[u8] = &[
+            0x60, 0x91, 62, 90, 0x60, 0x81, 62, 0, 0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let file = MidiFile::parse(&smf(1, 96, &[conductor, melody])).unwrap();
+        assert_eq!(file.tempo_map(), &[(0, 500_000), (96, 1_000_000)]);
+
+        let events = file.timed_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].time, 500_000);
+        assert_eq!(events[0].event.channel, 1);
+        assert_eq!(events[1].time, 1_500_000);
+
+        let mut player = SmfPlayer::new(&file);
+        assert!(player.pop_due(499_999).is_none());
+        assert!(player.pop_due(500_000).is_some());
+        assert!(player.pop_due(2_000_000).is_some());
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn recorded_take_round_trips_through_a_file() {
+        let mut recorder = SmfRecorder::new(60.0); // One quarter per second
+        recorder.record(5_000_000, note(MidiEvent::NoteOn(60, 100)));
+        recorder.record(5_250_000, note(MidiEvent::ProgramChange(42)));
+        recorder.record(
+            5_500_000,
+            ChannelEvent {
+                channel: 2,
+                event: MidiEvent::PitchBend(9000),
+            },
+        );
+        recorder.record(6_000_000, note(MidiEvent::NoteOff(60, 0)));
+
+        for format in [0, 1] {
+            let file = recorder.to_midi_file(format);
+            let parsed = MidiFile::parse(&file.to_bytes()).unwrap();
+            assert_eq!(parsed, file);
+            assert_eq!(parsed.tempo_map(), &[(0, 1_000_000)]);
+            let times: Vec<u64> = parsed.timed_events().iter().map(|e| e.time).collect();
+            assert_eq!(times, [0, 250_000, 500_000, 1_000_000]);
+            assert!(parsed
+                .timed_events()
+                .iter()
+                .any(|e| e.event.event == MidiEvent::ProgramChange(42)));
+        }
+        assert_eq!(recorder.to_midi_file(1).tracks().len(), 3);
+    }
+
+    #[test]
+    fn long_deltas_use_multi_byte_quantities() {
+        for value in [0, 0x7F, 0x80, 0x3FFF, 0x4000, 0x0FFF_FFFF] {
+            let mut bytes = Vec::new();
+            write_vlq(&mut bytes, value);
+            let mut reader = Reader {
+                bytes: &bytes,
+                pos: 0,
+            };
+            assert_eq!(reader.vlq().unwrap(), value);
+            assert!(reader.is_empty());
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert!(MidiFile::parse(b"RIFF").is_err());
+        let truncated = smf(0, 96, &[&[0x00, 0x90, 60]]);
+        assert!(MidiFile::parse(&truncated).is_err());
+        let smpte = smf(0, 0xE728, &[&[0x00, 0xFF, 0x2F, 0x00]]);
+        assert!(MidiFile::parse(&smpte).is_err());
+    }
+}
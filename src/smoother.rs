@@ -1,52 +1,371 @@
 //! Parameter smoothing to prevent zipper noise
 
+use core::fmt::Debug;
+use core::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
+
+/// How a smoother moves towards its target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SmoothingMode {
+    /// One-pole lowpass; approaches the target asymptotically
+    #[default]
+    Exponential,
+    /// Constant-rate ramp that arrives exactly after the ramp time
+    Linear,
+    /// One-pole lowpass in the log domain: equal time per octave, for
+    /// frequencies and other strictly positive values
+    Logarithmic,
+    /// Moves at most a fixed amount per second, with separate rise and fall rates
+    SlewLimited,
+}
+
+/// Maximum number of targets that can be scheduled ahead with `set_target_at`
+pub const MAX_SCHEDULED_TARGETS: usize = 16;
+
+/// Values at or below zero are clamped to this in logarithmic mode
+pub const MIN_LOG_VALUE: f32 = 1e-6;
+
+/// Floating-point sample type a `Smoother` can run on
+pub trait SmootherFloat:
+    Copy
+    + Debug
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+{
+    const INFINITY: Self;
+
+    fn from_f32(value: f32) -> Self;
+    fn from_u32(value: u32) -> Self;
+    /// Round to the nearest non-negative whole number of samples
+    fn to_samples(self) -> u32;
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn abs(self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn clamp(self, min: Self, max: Self) -> Self;
+}
+
+// Without std, the float math comes from libm
+macro_rules! impl_smoother_float {
+    ($t:ty, $exp:path, $ln:path, $round:path) => {
+        impl SmootherFloat for $t {
+            const INFINITY: Self = <$t>::INFINITY;
+
+            fn from_f32(value: f32) -> Self {
+                value as $t
+            }
+            fn from_u32(value: u32) -> Self {
+                value as $t
+            }
+            #[cfg(feature = "std")]
+            fn to_samples(self) -> u32 {
+                self.round().max(0.0) as u32
+            }
+            #[cfg(not(feature = "std"))]
+            fn to_samples(self) -> u32 {
+                $round(self).max(0.0) as u32
+            }
+            #[cfg(feature = "std")]
+            fn exp(self) -> Self {
+                <$t>::exp(self)
+            }
+            #[cfg(not(feature = "std"))]
+            fn exp(self) -> Self {
+                $exp(self)
+            }
+            #[cfg(feature = "std")]
+            fn ln(self) -> Self {
+                <$t>::ln(self)
+            }
+            #[cfg(not(feature = "std"))]
+            fn ln(self) -> Self {
+                $ln(self)
+            }
+            fn abs(self) -> Self {
+                <$t>::abs(self)
+            }
+            fn max(self, other: Self) -> Self {
+                <$t>::max(self, other)
+            }
+            fn clamp(self, min: Self, max: Self) -> Self {
+                <$t>::clamp(self, min, max)
+            }
+        }
+    };
+}
+
+impl_smoother_float!(f32, libm::expf, libm::logf, libm::roundf);
+impl_smoother_float!(f64, libm::exp, libm::log, libm::round);
+
+/// Single-precision smoother for ordinary control paths
+pub type ParamSmoother = Smoother<f32>;
+
+/// Double-precision smoother for long ramps and high-precision control paths,
+/// where repeated f32 multiplies would accumulate error
+pub type ParamSmoother64 = Smoother<f64>;
+
 #[derive(Debug, Clone)]
-pub struct ParamSmoother {
-    current: f32,
-    target: f32,
-    coeff: f32,
+pub struct Smoother<T: SmootherFloat> {
+    current: T,
+    target: T,
+    coeff: T,
+    time_constant: T, // Seconds (ramp time in linear mode)
+    sample_rate: T,
+    mode: SmoothingMode,
+    ramp_samples: u32,
+    step: T,        // Linear: increment per sample
+    remaining: u32, // Linear: samples left in the current ramp
+    snap_threshold: T,
+    rise_rate: T, // Slew limited: max increase per second
+    fall_rate: T, // Slew limited: max decrease per second
+    scheduled: [(u32, T); MAX_SCHEDULED_TARGETS], // (samples until due, target); fixed for RT-safety
+    scheduled_len: usize,
 }
 
-impl ParamSmoother {
+/// Default distance from the target at which a smoother snaps and settles,
+/// relative to the target's size (absolute for targets within ±1)
+pub const DEFAULT_SNAP_THRESHOLD: f32 = 1e-5;
+
+impl<T: SmootherFloat> Smoother<T> {
     /// Create a new smoother with default 10ms time constant at 44.1kHz
+    /// Call `set_sample_rate` once the stream's actual rate is known
     pub fn new() -> Self {
-        Self::with_time_constant(0.01, 44100.0) // 10ms at 44.1kHz
+        Self::with_time_constant(T::from_f32(0.01), T::from_f32(44100.0)) // 10ms at 44.1kHz
     }
 
     /// Create a smoother with specific time constant and sample rate
-    pub fn with_time_constant(time_constant_seconds: f32, sample_rate: f32) -> Self {
-        let coeff = (-1.0 / (time_constant_seconds * sample_rate)).exp();
+    pub fn with_time_constant(time_constant_seconds: T, sample_rate: T) -> Self {
+        let coeff = (-T::from_f32(1.0) / (time_constant_seconds * sample_rate)).exp();
         Self {
-            current: 0.0,
-            target: 0.0,
+            current: T::from_f32(0.0),
+            target: T::from_f32(0.0),
             coeff,
+            time_constant: time_constant_seconds,
+            sample_rate,
+            mode: SmoothingMode::Exponential,
+            ramp_samples: 0,
+            step: T::from_f32(0.0),
+            remaining: 0,
+            snap_threshold: T::from_f32(DEFAULT_SNAP_THRESHOLD),
+            rise_rate: T::INFINITY,
+            fall_rate: T::INFINITY,
+            scheduled: [(0, T::from_f32(0.0)); MAX_SCHEDULED_TARGETS],
+            scheduled_len: 0,
         }
     }
 
+    /// Create a linear-ramp smoother that reaches each new target in exactly
+    /// `ramp_seconds`
+    pub fn linear(ramp_seconds: T, sample_rate: T) -> Self {
+        let mut smoother = Self::with_time_constant(ramp_seconds, sample_rate);
+        smoother.mode = SmoothingMode::Linear;
+        smoother.ramp_samples = Self::samples(ramp_seconds, sample_rate);
+        smoother
+    }
+
+    /// Create a smoother that glides in log-frequency space
+    pub fn logarithmic(time_constant_seconds: T, sample_rate: T) -> Self {
+        let mut smoother = Self::with_time_constant(time_constant_seconds, sample_rate);
+        smoother.mode = SmoothingMode::Logarithmic;
+        smoother
+    }
+
+    /// Create a slew limiter moving at most `rise_per_second` up and
+    /// `fall_per_second` down (in the parameter's units)
+    pub fn slew_limited(rise_per_second: T, fall_per_second: T, sample_rate: T) -> Self {
+        let mut smoother = Self::with_time_constant(T::from_f32(0.01), sample_rate);
+        smoother.mode = SmoothingMode::SlewLimited;
+        smoother.set_slew_rates(rise_per_second, fall_per_second);
+        smoother
+    }
+
+    /// Change the slew limiter's maximum rise and fall rates per second
+    pub fn set_slew_rates(&mut self, rise_per_second: T, fall_per_second: T) {
+        self.rise_rate = rise_per_second.abs();
+        self.fall_rate = fall_per_second.abs();
+    }
+
+    pub fn mode(&self) -> SmoothingMode {
+        self.mode
+    }
+
+    /// Change the time constant (ramp time in linear mode), keeping the
+    /// current and target values
+    pub fn set_time_constant(&mut self, time_constant_seconds: T) {
+        self.time_constant = time_constant_seconds;
+        self.update_coefficients();
+    }
+
+    /// Change the sample rate, keeping the current and target values
+    pub fn set_sample_rate(&mut self, sample_rate: T) {
+        self.sample_rate = sample_rate;
+        self.update_coefficients();
+    }
+
+    pub fn time_constant(&self) -> T {
+        self.time_constant
+    }
+
+    pub fn sample_rate(&self) -> T {
+        self.sample_rate
+    }
+
     /// Set the target value (instantaneous)
-    pub fn set_target(&mut self, new_target: f32) {
+    /// In linear mode this starts a new ramp from the current value
+    pub fn set_target(&mut self, new_target: T) {
         self.target = new_target;
+        if self.mode == SmoothingMode::Linear {
+            if self.ramp_samples == 0 {
+                self.current = new_target;
+                self.remaining = 0;
+            } else {
+                self.step = (new_target - self.current) / T::from_u32(self.ramp_samples);
+                self.remaining = self.ramp_samples;
+            }
+        }
+    }
+
+    /// Set how close to the target the smoother must get before it snaps
+    /// to the exact target and reports settled (in the parameter's units,
+    /// scaled by the target's magnitude when that is above 1)
+    pub fn set_snap_threshold(&mut self, threshold: T) {
+        self.snap_threshold = threshold.abs();
+    }
+
+    /// Check whether the smoother is still moving towards its target
+    /// Callers can skip per-sample work while this is false
+    pub fn is_smoothing(&self) -> bool {
+        self.current != self.target
+    }
+
+    /// Set a target that takes effect `offset` samples from now
+    /// (offset 0 applies before the next sample is computed). Use the sample
+    /// offset of a timestamped event within the block for sample-accurate
+    /// automation. When the schedule is full, the earliest entry is applied early.
+    pub fn set_target_at(&mut self, offset: u32, new_target: T) {
+        if self.scheduled_len == MAX_SCHEDULED_TARGETS {
+            let earliest = (0..self.scheduled_len)
+                .min_by_key(|&i| self.scheduled[i].0)
+                .unwrap_or(0);
+            let (_, value) = self.scheduled[earliest];
+            self.set_target(value);
+            self.remove_scheduled(earliest);
+        }
+        self.scheduled[self.scheduled_len] = (offset, new_target);
+        self.scheduled_len += 1;
+    }
+
+    /// Number of targets waiting to take effect
+    pub fn scheduled_count(&self) -> usize {
+        self.scheduled_len
     }
 
     /// Get the next smoothed sample
-    pub fn next_sample(&mut self) -> f32 {
-        self.current = self.current * self.coeff + self.target * (1.0 - self.coeff);
+    pub fn next_sample(&mut self) -> T {
+        if self.scheduled_len > 0 {
+            self.apply_scheduled();
+        }
+        if !self.is_smoothing() {
+            return self.current;
+        }
+        match self.mode {
+            SmoothingMode::Exponential => {
+                let previous = self.current;
+                self.current =
+                    self.current * self.coeff + self.target * (T::from_f32(1.0) - self.coeff);
+                self.snap(previous);
+            }
+            SmoothingMode::Linear => {
+                if self.remaining > 0 {
+                    self.remaining -= 1;
+                    self.current = if self.remaining == 0 {
+                        self.target // Exact arrival, no accumulated rounding error
+                    } else {
+                        self.current + self.step
+                    };
+                }
+            }
+            SmoothingMode::SlewLimited => {
+                let max_rise = self.rise_rate / self.sample_rate;
+                let max_fall = self.fall_rate / self.sample_rate;
+                let delta = (self.target - self.current).clamp(-max_fall, max_rise);
+                let previous = self.current;
+                self.current += delta;
+                if delta != T::from_f32(0.0) {
+                    self.snap(previous);
+                }
+            }
+            SmoothingMode::Logarithmic => {
+                let floor = T::from_f32(MIN_LOG_VALUE);
+                let current = self.current.max(floor).ln();
+                let target = self.target.max(floor).ln();
+                let previous = self.current;
+                self.current =
+                    (current * self.coeff + target * (T::from_f32(1.0) - self.coeff)).exp();
+                self.snap(previous);
+            }
+        }
         self.current
     }
 
+    /// Jump to the target once close enough, or once rounding stops the
+    /// step from `previous` moving at all (f32 stalls short of large targets)
+    fn snap(&mut self, previous: T) {
+        let threshold = self.snap_threshold * self.target.abs().max(T::from_f32(1.0));
+        if (self.target - self.current).abs() <= threshold || self.current == previous {
+            self.current = self.target;
+        }
+    }
+
     /// Get current value without advancing
-    pub fn current_value(&self) -> f32 {
+    pub fn current_value(&self) -> T {
         self.current
     }
 
     /// Reset to a specific value
-    pub fn reset(&mut self, value: f32) {
+    pub fn reset(&mut self, value: T) {
         self.current = value;
         self.target = value;
+        self.remaining = 0;
+        self.scheduled_len = 0;
+    }
+
+    /// Apply due targets in the order they were scheduled, then count down the rest
+    fn apply_scheduled(&mut self) {
+        let mut i = 0;
+        while i < self.scheduled_len {
+            let (due, value) = self.scheduled[i];
+            if due == 0 {
+                self.set_target(value);
+                self.remove_scheduled(i);
+            } else {
+                self.scheduled[i].0 = due - 1;
+                i += 1;
+            }
+        }
+    }
+
+    fn remove_scheduled(&mut self, index: usize) {
+        self.scheduled
+            .copy_within(index + 1..self.scheduled_len, index);
+        self.scheduled_len -= 1;
+    }
+
+    fn update_coefficients(&mut self) {
+        self.coeff = (-T::from_f32(1.0) / (self.time_constant * self.sample_rate)).exp();
+        self.ramp_samples = Self::samples(self.time_constant, self.sample_rate);
+    }
+
+    fn samples(seconds: T, sample_rate: T) -> u32 {
+        (seconds * sample_rate).to_samples()
     }
 }
 
-impl Default for ParamSmoother {
+impl<T: SmootherFloat> Default for Smoother<T> {
     fn default() -> Self {
         Self::new()
     }
@@ -86,6 +405,186 @@ mod tests {
         assert!(sample2 > sample1 && sample2 < 1.0);
     }
 
+    #[test]
+    fn slower_time_constant_lags_more() {
+        let mut fast = ParamSmoother::with_time_constant(0.001, 44100.0);
+        let mut slow = ParamSmoother::with_time_constant(0.001, 44100.0);
+        slow.set_time_constant(0.1);
+        fast.set_target(1.0);
+        slow.set_target(1.0);
+
+        for _ in 0..100 {
+            fast.next_sample();
+            slow.next_sample();
+        }
+        assert!(slow.current_value() < fast.current_value());
+    }
+
+    #[test]
+    fn linear_ramp_arrives_exactly() {
+        let mut smoother = ParamSmoother::linear(0.001, 10000.0); // 10 samples
+        smoother.reset(0.0);
+        smoother.set_target(1.0);
+
+        let first = smoother.next_sample();
+        assert!((first - 0.1).abs() < 1e-6);
+        for _ in 0..8 {
+            smoother.next_sample();
+        }
+        assert_eq!(smoother.next_sample(), 1.0);
+        assert_eq!(smoother.next_sample(), 1.0);
+    }
+
+    #[test]
+    fn linear_retarget_ramps_from_current() {
+        let mut smoother = ParamSmoother::linear(0.001, 10000.0);
+        smoother.set_target(1.0);
+        for _ in 0..5 {
+            smoother.next_sample();
+        }
+
+        smoother.set_target(0.0);
+        let next = smoother.next_sample();
+        assert!((next - 0.45).abs() < 1e-6);
+        assert_eq!(smoother.mode(), SmoothingMode::Linear);
+    }
+
+    #[test]
+    fn settles_exactly_on_target() {
+        let mut smoother = ParamSmoother::with_time_constant(0.001, 44100.0);
+        smoother.set_snap_threshold(1e-3);
+        assert!(!smoother.is_smoothing());
+
+        smoother.set_target(1.0);
+        assert!(smoother.is_smoothing());
+        for _ in 0..1000 {
+            smoother.next_sample();
+        }
+        assert!(!smoother.is_smoothing());
+        assert_eq!(smoother.current_value(), 1.0);
+    }
+
+    #[test]
+    fn default_smoother_settles_at_any_scale() {
+        for target in [1.0, 200.0, 8000.0, 20000.0] {
+            let mut smoother = ParamSmoother::new();
+            smoother.set_target(target);
+            let samples = (0..44100)
+                .take_while(|_| {
+                    smoother.next_sample();
+                    smoother.is_smoothing()
+                })
+                .count();
+            assert!(samples < 44100, "never settled on {target}");
+            assert_eq!(smoother.current_value(), target);
+        }
+    }
+
+    #[test]
+    fn linear_ramp_settles() {
+        let mut smoother = ParamSmoother::linear(0.001, 10000.0);
+        smoother.set_target(2.0);
+        for _ in 0..9 {
+            smoother.next_sample();
+            assert!(smoother.is_smoothing());
+        }
+        smoother.next_sample();
+        assert!(!smoother.is_smoothing());
+    }
+
+    #[test]
+    fn sample_rate_change_keeps_state() {
+        let mut smoother = ParamSmoother::linear(0.001, 10000.0);
+        smoother.set_target(1.0);
+        smoother.next_sample();
+
+        smoother.set_sample_rate(20000.0);
+        assert_eq!(smoother.sample_rate(), 20000.0);
+        assert_eq!(smoother.time_constant(), 0.001);
+        assert!((smoother.current_value() - 0.1).abs() < 1e-6);
+
+        // New ramps use the new rate: 20 samples
+        smoother.set_target(0.1);
+        smoother.set_target(1.1);
+        let next = smoother.next_sample();
+        assert!((next - 0.15).abs() < 1e-6);
+    }
+
+    #[test]
+    fn logarithmic_glide_equal_time_per_octave() {
+        // Gliding up one octave and gliding up two octaves cover the same
+        // fraction of the interval (in octaves) after the same time
+        let mut one = ParamSmoother::logarithmic(0.01, 44100.0);
+        let mut two = ParamSmoother::logarithmic(0.01, 44100.0);
+        one.reset(100.0);
+        two.reset(100.0);
+        one.set_target(200.0);
+        two.set_target(400.0);
+
+        for _ in 0..441 {
+            one.next_sample();
+            two.next_sample();
+        }
+        let one_octaves = (one.current_value() / 100.0).log2();
+        let two_octaves = (two.current_value() / 100.0).log2();
+        assert!((two_octaves - 2.0 * one_octaves).abs() < 1e-3);
+        assert_eq!(one.mode(), SmoothingMode::Logarithmic);
+    }
+
+    #[test]
+    fn slew_limits_rise_and_fall_independently() {
+        let mut smoother = ParamSmoother::slew_limited(1000.0, 100.0, 1000.0);
+        smoother.set_target(5.0);
+        assert_eq!(smoother.next_sample(), 1.0);
+        for _ in 0..3 {
+            smoother.next_sample();
+        }
+        assert_eq!(smoother.next_sample(), 5.0);
+        assert_eq!(smoother.next_sample(), 5.0);
+
+        smoother.set_target(0.0);
+        assert!((smoother.next_sample() - 4.9).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scheduled_target_applies_at_offset() {
+        let mut smoother = ParamSmoother::linear(0.0, 44100.0); // Instant
+        smoother.set_target_at(3, 1.0);
+        smoother.set_target_at(5, 2.0);
+        assert_eq!(smoother.scheduled_count(), 2);
+
+        let block: Vec<f32> = (0..6).map(|_| smoother.next_sample()).collect();
+        assert_eq!(block, vec![0.0, 0.0, 0.0, 1.0, 1.0, 2.0]);
+        assert_eq!(smoother.scheduled_count(), 0);
+    }
+
+    #[test]
+    fn full_schedule_applies_earliest() {
+        let mut smoother = ParamSmoother::linear(0.0, 44100.0);
+        for i in 0..MAX_SCHEDULED_TARGETS as u32 {
+            smoother.set_target_at(10 + i, (i + 1) as f32);
+        }
+        smoother.set_target_at(100, 99.0);
+
+        assert_eq!(smoother.scheduled_count(), MAX_SCHEDULED_TARGETS);
+        assert_eq!(smoother.next_sample(), 1.0); // first entry applied early
+        smoother.reset(5.0);
+        assert_eq!(smoother.scheduled_count(), 0);
+    }
+
+    #[test]
+    fn f64_smoother_tracks_analytic_curve() {
+        let mut smoother = ParamSmoother64::with_time_constant(1.0, 48000.0);
+        smoother.set_snap_threshold(1e-12);
+        smoother.set_target(1.0);
+        for _ in 0..48000 {
+            smoother.next_sample();
+        }
+        let expected = 1.0 - (-1.0f64).exp();
+        assert!((smoother.current_value() - expected).abs() < 1e-9);
+        assert!(smoother.is_smoothing());
+    }
+
     #[test]
     fn reset_works() {
         let mut smoother = ParamSmoother::new();
@@ -0,0 +1,316 @@
+//! Incremental parser for raw MIDI byte streams (DIN/UART, USB bulk,
+//! network tunnels)
+//!
+//! [`StreamParser`] takes one byte at a time and is built to stay in sync
+//! on a noisy line:
+//!
+//! - Real-Time bytes (F8-FF) pass straight through wherever they land, even
+//!   inside another message or a SysEx, without disturbing it
+//! - A status byte arriving before a message is complete drops the partial
+//!   message and starts the new one
+//! - Data bytes with no status to belong to are dropped
+//! - Running status is followed for channel messages and cancelled by
+//!   System Common messages
+//!
+//! It needs neither `std` nor an allocator; SysEx is collected into a fixed
+//! buffer of [`MAX_STREAM_SYSEX`] bytes.
+
+use crate::midi_input::{ChannelEvent, SystemMessage};
+
+/// Longest SysEx message, F0 to F7, a `StreamParser` collects; longer ones
+/// are dropped
+pub const MAX_STREAM_SYSEX: usize = 512;
+
+const SYSEX_START: u8 = 0xF0;
+const SYSEX_END: u8 = 0xF7;
+
+/// A complete message from a [`StreamParser`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamMessage<'a> {
+    Channel(ChannelEvent),
+    /// System Common (other than SysEx) or Real-Time message
+    System(SystemMessage),
+    /// SysEx message, F0 to F7
+    SysEx(&'a [u8]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Between messages
+    Idle,
+    /// Collecting the data bytes of a channel or System Common message
+    Message { needed: usize },
+    /// Inside a SysEx message
+    SysEx,
+    /// Skipping the rest of a SysEx too long for the buffer
+    SysExOverflow,
+}
+
+/// Number of data bytes following a channel or System Common status
+fn data_length(status: u8) -> usize {
+    match status {
+        0xC0..=0xDF | 0xF1 | 0xF3 => 1,
+        0x80..=0xEF | 0xF2 => 2,
+        _ => 0,
+    }
+}
+
+/// Byte-at-a-time MIDI parser with running status
+#[derive(Debug, Clone)]
+pub struct StreamParser {
+    state: State,
+    running_status: Option<u8>,
+    message: [u8; 3],
+    len: usize,
+    sysex: [u8; MAX_STREAM_SYSEX],
+    sysex_len: usize,
+    discarded: u32,
+}
+
+impl StreamParser {
+    pub fn new() -> Self {
+        Self {
+            state: State::Idle,
+            running_status: None,
+            message: [0; 3],
+            len: 0,
+            sysex: [0; MAX_STREAM_SYSEX],
+            sysex_len: 0,
+            discarded: 0,
+        }
+    }
+
+    /// Partial messages, oversized SysEx and stray data bytes dropped so far
+    pub fn discarded(&self) -> u32 {
+        self.discarded
+    }
+
+    /// Feed one byte received at `time` (µs, stamped on System messages)
+    /// Returns a message if the byte completed one.
+    pub fn push(&mut self, byte: u8, time: u64) -> Option<StreamMessage<'_>> {
+        match byte {
+            0xF8..=0xFF => Some(StreamMessage::System(SystemMessage::new(&[byte], time))),
+            0x80..=0xF7 => self.status(byte, time),
+            _ => self.data(byte, time),
+        }
+    }
+
+    /// Feed a buffer of bytes received at `time`, handing each complete
+    /// message to `handle`
+    pub fn feed(&mut self, bytes: &[u8], time: u64, mut handle: impl FnMut(StreamMessage<'_>)) {
+        for &byte in bytes {
+            if let Some(message) = self.push(byte, time) {
+                handle(message);
+            }
+        }
+    }
+
+    /// Drop any partial message and forget the running status
+    pub fn reset(&mut self) {
+        self.state = State::Idle;
+        self.running_status = None;
+        self.len = 0;
+        self.sysex_len = 0;
+    }
+
+    fn status(&mut self, status: u8, time: u64) -> Option<StreamMessage<'_>> {
+        let previous = core::mem::replace(&mut self.state, State::Idle);
+        match previous {
+            State::SysEx if status == SYSEX_END => {
+                self.sysex[self.sysex_len] = SYSEX_END;
+                self.sysex_len += 1;
+                return Some(StreamMessage::SysEx(&self.sysex[..self.sysex_len]));
+            }
+            State::SysExOverflow if status == SYSEX_END => return None,
+            State::Message { .. } | State::SysEx => self.discarded += 1, // Cut short
+            State::Idle | State::SysExOverflow => {}
+        }
+
+        match status {
+            0x80..=0xEF => {
+                self.running_status = Some(status);
+                self.begin(status);
+                None
+            }
+            SYSEX_START => {
+                self.running_status = None;
+                self.sysex[0] = SYSEX_START;
+                self.sysex_len = 1;
+                self.state = State::SysEx;
+                None
+            }
+            SYSEX_END => {
+                // End of a SysEx we never saw start; a message it cut short
+                // is already counted
+                self.running_status = None;
+                if previous == State::Idle {
+                    self.discarded += 1;
+                }
+                None
+            }
+            _ => {
+                // System Common cancels running status
+                self.running_status = None;
+                self.begin(status);
+                if self.state == State::Idle {
+                    self.complete(time)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn data(&mut self, byte: u8, time: u64) -> Option<StreamMessage<'_>> {
+        match self.state {
+            State::Idle => match self.running_status {
+                Some(status) => {
+                    self.begin(status);
+                    self.data(byte, time)
+                }
+                None => {
+                    self.discarded += 1;
+                    None
+                }
+            },
+            State::Message { needed } => {
+                self.message[self.len] = byte;
+                self.len += 1;
+                if self.len > needed {
+                    self.state = State::Idle;
+                    self.complete(time)
+                } else {
+                    None
+                }
+            }
+            State::SysEx => {
+                // Keep room for the F7
+                if self.sysex_len + 1 < MAX_STREAM_SYSEX {
+                    self.sysex[self.sysex_len] = byte;
+                    self.sysex_len += 1;
+                } else {
+                    self.state = State::SysExOverflow;
+                    self.discarded += 1;
+                }
+                None
+            }
+            State::SysExOverflow => None,
+        }
+    }
+
+    /// Start a message; leaves the state Idle if it has no data bytes
+    fn begin(&mut self, status: u8) {
+        self.message[0] = status;
+        self.len = 1;
+        let needed = data_length(status);
+        if needed > 0 {
+            self.state = State::Message { needed };
+        }
+    }
+
+    fn complete(&self, time: u64) -> Option<StreamMessage<'_>> {
+        let bytes = &self.message[..self.len];
+        if bytes[0] < 0xF0 {
+            // Channel messages we don't model (poly aftertouch) are consumed
+            ChannelEvent::from_bytes(bytes).map(StreamMessage::Channel)
+        } else {
+            Some(StreamMessage::System(SystemMessage::new(bytes, time)))
+        }
+    }
+}
+
+impl Default for StreamParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_input::MidiEvent;
+
+    /// Channel and System messages parsed from `bytes`, and the discard count
+    fn parse(bytes: &[u8]) -> (Vec<StreamMessage<'static>>, u32) {
+        let mut parser = StreamParser::new();
+        let mut out = Vec::new();
+        parser.feed(bytes, 0, |message| match message {
+            StreamMessage::Channel(event) => out.push(StreamMessage::Channel(event)),
+            StreamMessage::System(message) => out.push(StreamMessage::System(message)),
+            StreamMessage::SysEx(_) => {}
+        });
+        (out, parser.discarded())
+    }
+
+    fn channel(channel: u8, event: MidiEvent) -> StreamMessage<'static> {
+        StreamMessage::Channel(ChannelEvent { channel, event })
+    }
+
+    fn system(byte: u8) -> StreamMessage<'static> {
+        StreamMessage::System(SystemMessage::new(&[byte], 0))
+    }
+
+    #[test]
+    fn running_status_and_interleaved_clock() {
+        let (out, discarded) = parse(&[0x91, 60, 0xF8, 100, 62, 0, 0xB1, 7, 0xFE, 90]);
+        assert_eq!(
+            out,
+            [
+                system(0xF8),
+                channel(1, MidiEvent::NoteOn(60, 100)),
+                channel(1, MidiEvent::NoteOff(62, 0)),
+                system(0xFE),
+                channel(1, MidiEvent::ControlChange(7, 90)),
+            ]
+        );
+        assert_eq!(discarded, 0);
+    }
+
+    #[test]
+    fn recovers_from_truncation_and_garbage() {
+        // Stray data, a Note On cut short, then a complete Program Change
+        let (out, discarded) = parse(&[5, 6, 0x90, 60, 0xC2, 9]);
+        assert_eq!(out, [channel(2, MidiEvent::ProgramChange(9))]);
+        assert_eq!(discarded, 3);
+
+        // System Common cancels running status
+        let (out, discarded) = parse(&[0x90, 60, 100, 0xF3, 4, 61, 100]);
+        assert_eq!(out.len(), 2);
+        assert_eq!(discarded, 2);
+
+        // An F7 cutting a message short counts once
+        let (out, discarded) = parse(&[0x90, 60, 0xF7]);
+        assert!(out.is_empty());
+        assert_eq!(discarded, 1);
+
+        // A lone F7 is stray
+        let (out, discarded) = parse(&[0xF7]);
+        assert!(out.is_empty());
+        assert_eq!(discarded, 1);
+    }
+
+    #[test]
+    fn collects_sysex_around_real_time() {
+        let mut parser = StreamParser::new();
+        let mut sysex = Vec::new();
+        parser.feed(&[0xF0, 0x7E, 0xF8, 0x7F, 0xF7], 0, |message| {
+            if let StreamMessage::SysEx(bytes) = message {
+                sysex.push(bytes.to_vec());
+            }
+        });
+        assert_eq!(sysex, [vec![0xF0, 0x7E, 0x7F, 0xF7]]);
+
+        // Unterminated SysEx is dropped when the next status arrives
+        let (out, discarded) = parse(&[0xF0, 1, 2, 0x80, 60, 0]);
+        assert_eq!(out, [channel(0, MidiEvent::NoteOff(60, 0))]);
+        assert_eq!(discarded, 1);
+
+        // Oversized SysEx is skipped through its F7
+        let mut long = vec![0xF0];
+        long.resize(MAX_STREAM_SYSEX + 10, 0x11);
+        long.extend([0xF7, 0xC0, 1]);
+        let (out, discarded) = parse(&long);
+        assert_eq!(out, [channel(0, MidiEvent::ProgramChange(1))]);
+        assert_eq!(discarded, 1);
+    }
+}
@@ -0,0 +1,421 @@
+//! `MidiSynthController`: MIDI input, event scheduling and the parameter
+//! bridge wired to an auxide-io output stream
+//!
+//! ```rust,no_run
+//! use auxide::graph::NodeType;
+//! use auxide::plan::Plan;
+//! use auxide::rt::Runtime;
+//! use auxide_midi::{MidiSynthController, VoiceGraphBuilder, VoiceRole};
+//! use std::sync::atomic::AtomicBool;
+//! use std::time::Duration;
+//!
+//! fn main() -> anyhow::Result<()> {
+//!     let voices = VoiceGraphBuilder::new(8)
+//!         .stage(VoiceRole::Oscillator, |graph| {
+//!             graph.add_node(NodeType::SineOsc { freq: 440.0 })
+//!         })
+//!         .stage(VoiceRole::Amp, |graph| graph.add_node(NodeType::Gain { gain: 0.0 }))
+//!         .build()
+//!         .unwrap();
+//!     let plan = Plan::compile(voices.graph(), 64).unwrap();
+//!     let runtime = Runtime::new(plan, voices.graph(), 44100.0);
+//!
+//!     let (mut synth, _updates) = MidiSynthController::new(&voices);
+//!     synth.connect(0)?;
+//!     synth.start(runtime)?;
+//!     synth.run(&AtomicBool::new(true), Duration::from_millis(1));
+//!     Ok(())
+//! }
+//! ```
+
+use crate::activity::{ActivityMeter, ActivitySnapshot};
+use crate::cc_mapping::{CCMap, ParamTarget, MAX_CC_TARGETS};
+use crate::conversions::PITCH_BEND_CENTER;
+use crate::expression::ChannelGain;
+use crate::jitter::JitterFilter;
+use crate::midi_input::{ChannelEvent, MidiEvent, MidiInputHandler, CC_ALL_NOTES_OFF};
+use crate::midi_output::MidiOutputHandler;
+use crate::note_echo::NoteEcho;
+use crate::param_bridge::{ParamUpdateQueue, ParamUpdateReceiver};
+use crate::preset::Preset;
+use crate::processor::{MidiProcessor, ProcessorChain};
+use crate::scheduler::{EventScheduler, TimedEvent};
+use crate::velocity::VelocityProcessor;
+use crate::voice_allocator::VoiceAllocator;
+use crate::voice_control::{GraphVoiceControl, VoiceDriver};
+use crate::voice_graph::VoiceGraph;
+use anyhow::Result;
+use auxide::rt::Runtime;
+use auxide_io::stream_controller::StreamController;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// A complete live synth: MIDI device in, voice graph out
+///
+/// Incoming events are stamped on arrival (or re-timed by an optional
+/// [`JitterFilter`]) and pass through an [`EventScheduler`], so events
+/// queued ahead of time with [`schedule`](Self::schedule) interleave with
+/// live input. Due events drive a [`VoiceDriver`] and the CC map, whose
+/// updates go out through the [`ParamUpdateQueue`] returned by
+/// [`new`](Self::new). The graph itself runs in an auxide-io
+/// [`StreamController`] started by [`start`](Self::start).
+pub struct MidiSynthController {
+    input: MidiInputHandler,
+    scheduler: EventScheduler,
+    driver: VoiceDriver,
+    voices: GraphVoiceControl,
+    cc_map: CCMap,
+    stream: Option<StreamController>,
+    clock: Instant,
+    jitter: Option<JitterFilter>,
+    echo: Option<NoteEcho>,
+    velocity: VelocityProcessor,
+    processors: ProcessorChain,
+    processed: Vec<TimedEvent>, // Output of `processors`, reused between events
+    output: Option<MidiOutputHandler>,
+    activity: ActivityMeter,
+    gain: ChannelGain,
+}
+
+impl MidiSynthController {
+    /// Control every voice of `voices`, with its parameter bindings
+    /// registered; the receiver side of the parameter queue is returned for
+    /// whatever owns the graph's nodes to drain between blocks
+    pub fn new(voices: &VoiceGraph) -> (Self, ParamUpdateReceiver) {
+        let (mut updates, receiver) = ParamUpdateQueue::new();
+        voices.bind_params(&mut updates);
+        let controller = Self {
+            input: MidiInputHandler::new(),
+            scheduler: EventScheduler::new(),
+            driver: VoiceDriver::new(VoiceAllocator::with_voices(voices.voice_count())),
+            voices: GraphVoiceControl::new(updates, voices.voice_nodes()),
+            cc_map: CCMap::new(),
+            stream: None,
+            clock: Instant::now(),
+            jitter: None,
+            echo: None,
+            velocity: VelocityProcessor::new(),
+            processors: ProcessorChain::new(),
+            processed: Vec::with_capacity(crate::processor::DEFAULT_CHAIN_BUFFER),
+            output: None,
+            activity: ActivityMeter::new(),
+            gain: ChannelGain::new(),
+        };
+        (controller, receiver)
+    }
+
+    /// Open the MIDI input device at `index` (see `MidiInputHandler::list_devices`)
+    pub fn connect(&mut self, index: usize) -> Result<()> {
+        self.input.connect_device(index)
+    }
+
+    /// Open the first MIDI input whose name contains `name`, ignoring case
+    pub fn connect_by_name(&mut self, name: &str) -> Result<()> {
+        let name = name.to_lowercase();
+        let index = MidiInputHandler::list_devices()?
+            .iter()
+            .position(|device| device.to_lowercase().contains(&name))
+            .ok_or_else(|| anyhow::anyhow!("No MIDI input matching {:?}", name))?;
+        self.connect(index)
+    }
+
+    pub fn input_mut(&mut self) -> &mut MidiInputHandler {
+        &mut self.input
+    }
+
+    pub fn scheduler(&self) -> &EventScheduler {
+        &self.scheduler
+    }
+
+    pub fn driver(&self) -> &VoiceDriver {
+        &self.driver
+    }
+
+    pub fn driver_mut(&mut self) -> &mut VoiceDriver {
+        &mut self.driver
+    }
+
+    pub fn voice_control_mut(&mut self) -> &mut GraphVoiceControl {
+        &mut self.voices
+    }
+
+    pub fn cc_map(&self) -> &CCMap {
+        &self.cc_map
+    }
+
+    pub fn cc_map_mut(&mut self) -> &mut CCMap {
+        &mut self.cc_map
+    }
+
+    /// Re-time live input from the driver's timestamps with `filter`
+    /// instead of applying it on arrival (None turns smoothing off)
+    pub fn set_jitter_filter(&mut self, filter: Option<JitterFilter>) {
+        self.jitter = filter;
+    }
+
+    pub fn jitter_filter(&self) -> Option<&JitterFilter> {
+        self.jitter.as_ref()
+    }
+
+    /// Echo live notes with `echo` (None turns it off)
+    pub fn set_note_echo(&mut self, echo: Option<NoteEcho>) {
+        self.echo = echo;
+    }
+
+    pub fn note_echo_mut(&mut self) -> Option<&mut NoteEcho> {
+        self.echo.as_mut()
+    }
+
+    /// Velocity transforms applied to live input before echo and allocation
+    pub fn velocity_mut(&mut self) -> &mut VelocityProcessor {
+        &mut self.velocity
+    }
+
+    /// MIDI output to external gear, silenced along with the synth by
+    /// [`panic`](Self::panic)
+    pub fn set_output(&mut self, output: Option<MidiOutputHandler>) {
+        self.output = output;
+    }
+
+    pub fn output_mut(&mut self) -> Option<&mut MidiOutputHandler> {
+        self.output.as_mut()
+    }
+
+    /// User stages run on live input after velocity processing and before
+    /// note echo; empty by default
+    pub fn processors_mut(&mut self) -> &mut ProcessorChain {
+        &mut self.processors
+    }
+
+    /// Switch to `preset`: its CC map, tuning, bend range and velocity
+    /// curve replace the current ones and its parameter values are sent to
+    /// the graph. Sounding voices pick up the new tuning on their next bend.
+    pub fn load_preset(&mut self, preset: &Preset) {
+        self.cc_map = preset.cc_map();
+        self.driver.set_tuning(preset.tuning.clone());
+        self.driver.pitch_bend_mut().set_range(preset.bend_range);
+        for channel in 0..16 {
+            self.velocity.channel_mut(channel).curve = preset.velocity_curve.clone();
+        }
+        self.gain.reset();
+        let updates = self.voices.updates_mut();
+        for &(target, value) in &preset.params {
+            self.gain.handle_param(target, value);
+            updates.send(target, value);
+        }
+        self.voices.set_gain_scale(self.gain.gain());
+    }
+
+    /// Microseconds since the controller was created; the scheduler's clock
+    pub fn now(&self) -> u64 {
+        self.clock.elapsed().as_micros() as u64
+    }
+
+    /// Per-channel activity of the MIDI input so far, counted as events
+    /// are polled
+    pub fn activity(&self) -> ActivitySnapshot {
+        self.activity.snapshot(self.now())
+    }
+
+    /// Expression and breath levels; their product scales every voice's
+    /// amp gain
+    pub fn gain(&self) -> &ChannelGain {
+        &self.gain
+    }
+
+    /// Queue an event for `time` on the controller's clock
+    pub fn schedule(&mut self, time: u64, event: ChannelEvent) -> bool {
+        self.scheduler.schedule(time, event)
+    }
+
+    /// Take pending MIDI input and apply every event that is due
+    /// Returns the number of events that changed the synth.
+    pub fn poll(&mut self) -> usize {
+        self.poll_at(self.now())
+    }
+
+    /// `poll` with an explicit time, for driving the controller from
+    /// another clock
+    pub fn poll_at(&mut self, now: u64) -> usize {
+        while let Some(mut input) = self.input.try_recv_timed() {
+            self.activity.record(now, &input.event);
+            self.velocity.process(&mut input.event);
+            let time = match &mut self.jitter {
+                Some(filter) => filter.retime(input.time, now),
+                None => now,
+            };
+            let input = TimedEvent {
+                time,
+                event: input.event,
+            };
+            self.processors.process(input, &mut self.processed);
+            self.schedule_processed();
+        }
+        // Stages such as an arpeggiator also play without new input
+        self.processors.advance(now, &mut self.processed);
+        self.schedule_processed();
+        let mut applied = 0;
+        while let Some(due) = self.scheduler.pop_due(now) {
+            if self.apply(due.event.event) {
+                applied += 1;
+            }
+        }
+        applied
+    }
+
+    /// Echo and schedule what the processors emitted
+    fn schedule_processed(&mut self) {
+        for event in self.processed.drain(..) {
+            if let Some(echo) = &self.echo {
+                echo.process(event.time, &event.event, &mut self.scheduler);
+            }
+            self.scheduler.schedule(event.time, event.event);
+        }
+    }
+
+    /// Apply one event immediately, bypassing the scheduler
+    pub fn apply(&mut self, event: MidiEvent) -> bool {
+        match event {
+            MidiEvent::ControlChange(cc_num, value) if !event.is_all_notes_off() => {
+                let mut changes = [(ParamTarget::Unused, 0.0); MAX_CC_TARGETS];
+                let count = self.cc_map.handle_cc_into(cc_num, value, &mut changes);
+                let mut applied = false;
+                for &(target, value) in &changes[..count] {
+                    let scaled = self.gain.handle_param(target, value)
+                        && self.voices.set_gain_scale(self.gain.gain());
+                    applied |= self.voices.updates_mut().send(target, value) > 0 || scaled;
+                }
+                applied
+            }
+            event => self.driver.handle_event(event, &mut self.voices),
+        }
+    }
+
+    /// Recover from stuck notes in one call: drop scheduled events (echoes
+    /// included), reset the processors, release every voice, centre the
+    /// pitch wheel and send All Sound Off / All Notes Off on every channel
+    /// of the MIDI output, if there is one
+    pub fn panic(&mut self) -> Result<()> {
+        self.scheduler.clear();
+        // The All Notes Off below also releases whatever the stages started
+        self.processors.reset(self.now(), &mut self.processed);
+        self.processed.clear();
+        self.apply(MidiEvent::ControlChange(CC_ALL_NOTES_OFF, 0));
+        self.driver.pitch_bend_mut().set_bend(PITCH_BEND_CENTER);
+        match &mut self.output {
+            Some(output) => output.panic(),
+            None => Ok(()),
+        }
+    }
+
+    /// Hand the graph's runtime to an output stream and start playing
+    /// Any stream already running is stopped first.
+    pub fn start(&mut self, runtime: Runtime) -> Result<()> {
+        self.stop();
+        let stream = StreamController::play(runtime)?;
+        stream.start()?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    /// Stop the output stream and release every voice
+    pub fn stop(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            stream.stop();
+            self.apply(MidiEvent::ControlChange(CC_ALL_NOTES_OFF, 0));
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// True if the output stream has reported an error
+    pub fn has_error(&self) -> bool {
+        self.stream
+            .as_ref()
+            .is_some_and(StreamController::has_error)
+    }
+
+    /// Poll every `interval` until `running` is cleared (e.g. by a Ctrl+C
+    /// handler), then stop the stream
+    pub fn run(&mut self, running: &AtomicBool, interval: Duration) {
+        while running.load(Ordering::Relaxed) {
+            self.poll();
+            std::thread::sleep(interval);
+        }
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cc_mapping::ParamTarget;
+    use crate::param_bridge::{NodeParam, ParamScale};
+    use crate::voice_graph::{VoiceGraphBuilder, VoiceRole};
+    use auxide::control::ControlMsg;
+    use auxide::graph::NodeType;
+
+    fn channel(event: MidiEvent) -> ChannelEvent {
+        ChannelEvent { channel: 0, event }
+    }
+
+    #[test]
+    fn scheduled_events_reach_the_parameter_bridge() {
+        let voices = VoiceGraphBuilder::new(2)
+            .stage(VoiceRole::Oscillator, |graph| {
+                graph.add_node(NodeType::SineOsc { freq: 440.0 })
+            })
+            .bind(
+                ParamTarget::FilterCutoff,
+                NodeParam::Index(1),
+                ParamScale::Raw,
+            )
+            .build()
+            .unwrap();
+        let (mut synth, mut receiver) = MidiSynthController::new(&voices);
+        synth.schedule(0, channel(MidiEvent::NoteOn(69, 100)));
+        synth.schedule(1000, channel(MidiEvent::ControlChange(1, 127)));
+
+        assert_eq!(synth.poll_at(500), 1);
+        assert_eq!(synth.driver().allocator().active_voice_count(), 1);
+        assert_eq!(synth.scheduler().len(), 1);
+        assert_eq!(synth.poll_at(1000), 1);
+        assert!(!synth.is_running());
+
+        let mut messages = Vec::new();
+        receiver.drain(&mut |msg: ControlMsg| messages.push(msg));
+        assert!(matches!(
+            messages[0],
+            ControlMsg::SetFrequency { hz, .. } if hz == 440.0
+        ));
+        // One cutoff update per voice
+        let cutoffs = messages
+            .iter()
+            .filter(|msg| matches!(msg, ControlMsg::SetParam { .. }))
+            .count();
+        assert_eq!(cutoffs, 2);
+    }
+
+    #[test]
+    fn panic_clears_voices_and_pending_events() {
+        let voices = VoiceGraphBuilder::new(2)
+            .stage(VoiceRole::Oscillator, |graph| {
+                graph.add_node(NodeType::SineOsc { freq: 440.0 })
+            })
+            .build()
+            .unwrap();
+        let (mut synth, _receiver) = MidiSynthController::new(&voices);
+        synth.schedule(0, channel(MidiEvent::NoteOn(60, 100)));
+        synth.schedule(0, channel(MidiEvent::PitchBend(0)));
+        synth.schedule(5000, channel(MidiEvent::NoteOn(64, 100)));
+        synth.poll_at(0);
+        assert_eq!(synth.driver().allocator().active_voice_count(), 1);
+
+        synth.panic().unwrap();
+        assert_eq!(synth.driver().allocator().active_voice_count(), 0);
+        assert!(synth.scheduler().is_empty());
+        assert_eq!(synth.driver().pitch_bend().bend(), PITCH_BEND_CENTER);
+    }
+}
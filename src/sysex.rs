@@ -0,0 +1,272 @@
+//! SysEx librarian helpers: identify devices, request dumps and collect
+//! the replies, for backing up hardware configurations
+//!
+//! Send the built messages with a [`MidiOutputHandler`] and feed replies
+//! from [`MidiInputHandler::try_recv_sysex`] to a [`DumpCollector`].
+//!
+//! [`MidiOutputHandler`]: crate::midi_output::MidiOutputHandler
+//! [`MidiInputHandler::try_recv_sysex`]: crate::midi_input::MidiInputHandler::try_recv_sysex
+
+use anyhow::{bail, Result};
+
+pub const SYSEX_START: u8 = 0xF0;
+pub const SYSEX_END: u8 = 0xF7;
+/// Universal Non-Real Time SysEx ID
+pub const SYSEX_NON_REALTIME: u8 = 0x7E;
+/// Device ID that every device answers to
+pub const SYSEX_ALL_DEVICES: u8 = 0x7F;
+
+/// Wrap `body` in F0 ... F7 after `manufacturer` (1 or 3 bytes)
+/// Fails if any byte is not 7-bit.
+pub fn build_sysex(manufacturer: &[u8], body: &[u8]) -> Result<Vec<u8>> {
+    if !matches!(manufacturer.len(), 1 | 3) {
+        bail!("Manufacturer ID must be 1 or 3 bytes");
+    }
+    if let Some(byte) = manufacturer.iter().chain(body).find(|&&b| b > 0x7F) {
+        bail!("SysEx data byte {:#04x} is not 7-bit", byte);
+    }
+    let mut message = Vec::with_capacity(manufacturer.len() + body.len() + 2);
+    message.push(SYSEX_START);
+    message.extend(manufacturer);
+    message.extend(body);
+    message.push(SYSEX_END);
+    Ok(message)
+}
+
+/// Identity Request (F0 7E id 06 01 F7); use `SYSEX_ALL_DEVICES` to ask
+/// everything on the port
+pub fn device_inquiry(device_id: u8) -> [u8; 6] {
+    [
+        SYSEX_START,
+        SYSEX_NON_REALTIME,
+        device_id & 0x7F,
+        0x06,
+        0x01,
+        SYSEX_END,
+    ]
+}
+
+/// A device-specific dump request: `manufacturer`, `request` bytes, then a
+/// checksum over `request[checksum_from..]` when `checksum` is set
+pub fn dump_request(
+    manufacturer: &[u8],
+    request: &[u8],
+    checksum: Checksum,
+    checksum_from: usize,
+) -> Result<Vec<u8>> {
+    let mut body = request.to_vec();
+    if let Some(sum) = checksum.compute(request.get(checksum_from..).unwrap_or(&[])) {
+        body.push(sum);
+    }
+    build_sysex(manufacturer, &body)
+}
+
+/// Identity Reply to a [`device_inquiry`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub device_id: u8,
+    /// 1 byte, or 3 for extended IDs starting with 00
+    pub manufacturer: Vec<u8>,
+    pub family: u16,
+    pub member: u16,
+    pub version: [u8; 4],
+}
+
+impl DeviceIdentity {
+    /// Parse an Identity Reply (F0 7E id 06 02 ...), or None for anything else
+    pub fn parse(message: &[u8]) -> Option<Self> {
+        let body = message
+            .strip_prefix(&[SYSEX_START, SYSEX_NON_REALTIME])?
+            .strip_suffix(&[SYSEX_END])?;
+        let (&device_id, rest) = body.split_first()?;
+        let rest = rest.strip_prefix(&[0x06, 0x02])?;
+        let id_len = if rest.first() == Some(&0x00) { 3 } else { 1 };
+        if rest.len() < id_len + 8 {
+            return None;
+        }
+        let (manufacturer, rest) = rest.split_at(id_len);
+        let word = |i: usize| rest[i] as u16 | (rest[i + 1] as u16) << 7;
+        Some(Self {
+            device_id,
+            manufacturer: manufacturer.to_vec(),
+            family: word(0),
+            member: word(2),
+            version: [rest[4], rest[5], rest[6], rest[7]],
+        })
+    }
+}
+
+/// Checksum scheme used by a device's dump packets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Checksum {
+    #[default]
+    None,
+    /// Two's complement of the 7-bit sum (Roland, Yamaha and others)
+    Roland,
+    /// XOR of the bytes
+    Xor,
+}
+
+impl Checksum {
+    /// Checksum byte for `data`, or None for `Checksum::None`
+    pub fn compute(self, data: &[u8]) -> Option<u8> {
+        match self {
+            Checksum::None => None,
+            Checksum::Roland => {
+                let sum = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b) & 0x7F);
+                Some((0x80 - sum) & 0x7F)
+            }
+            Checksum::Xor => Some(data.iter().fold(0, |x, &b| x ^ b) & 0x7F),
+        }
+    }
+}
+
+/// Collects a multi-packet dump into one blob
+///
+/// Packets are the messages starting with `header` (e.g. `F0 41 10 42 12`
+/// for a Roland data set). The bytes after the header, less the checksum
+/// byte when there is one, are appended to the blob once the checksum
+/// over them matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpCollector {
+    header: Vec<u8>,
+    checksum: Checksum,
+    expected: Option<usize>,
+    packets: usize,
+    data: Vec<u8>,
+}
+
+impl DumpCollector {
+    pub fn new(header: &[u8], checksum: Checksum) -> Self {
+        Self {
+            header: header.to_vec(),
+            checksum,
+            expected: None,
+            packets: 0,
+            data: Vec::new(),
+        }
+    }
+
+    /// Treat the dump as complete after `packets` packets
+    pub fn expect_packets(&mut self, packets: usize) {
+        self.expected = Some(packets);
+    }
+
+    pub fn packets(&self) -> usize {
+        self.packets
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.expected
+            .is_some_and(|expected| self.packets >= expected)
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Take the collected blob, ready for the next dump
+    pub fn finish(&mut self) -> Vec<u8> {
+        self.packets = 0;
+        std::mem::take(&mut self.data)
+    }
+
+    /// Add one received message
+    ///
+    /// Returns false for messages that are not packets of this dump, and
+    /// an error for a packet that is truncated or fails its checksum.
+    pub fn handle_message(&mut self, message: &[u8]) -> Result<bool> {
+        let Some(rest) = message.strip_prefix(self.header.as_slice()) else {
+            return Ok(false);
+        };
+        let Some(mut payload) = rest.strip_suffix(&[SYSEX_END]) else {
+            bail!("SysEx packet is missing its F7 terminator");
+        };
+        if self.checksum != Checksum::None {
+            let Some((&received, data)) = payload.split_last() else {
+                bail!("SysEx packet has no checksum byte");
+            };
+            let expected = self.checksum.compute(data).unwrap_or(0);
+            if received != expected {
+                bail!(
+                    "SysEx checksum mismatch in packet {}: got {:#04x}, expected {:#04x}",
+                    self.packets + 1,
+                    received,
+                    expected
+                );
+            }
+            payload = data;
+        }
+        self.data.extend(payload);
+        self.packets += 1;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_requests_and_parses_identity() {
+        assert_eq!(
+            device_inquiry(SYSEX_ALL_DEVICES),
+            [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]
+        );
+        assert!(build_sysex(&[0x41], &[0x80]).is_err());
+
+        // Roland RQ1 for 4 bytes at address 40 00 00: checksum 3C
+        let request = dump_request(
+            &[0x41],
+            &[0x10, 0x42, 0x11, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04],
+            Checksum::Roland,
+            3,
+        )
+        .unwrap();
+        assert_eq!(request[request.len() - 2], 0x3C);
+
+        let reply = [
+            0xF0, 0x7E, 0x10, 0x06, 0x02, 0x00, 0x20, 0x32, 0x01, 0x02, 0x03, 0x00, 1, 2, 3, 4,
+            0xF7,
+        ];
+        let identity = DeviceIdentity::parse(&reply).unwrap();
+        assert_eq!(identity.manufacturer, [0x00, 0x20, 0x32]);
+        assert_eq!(identity.family, 0x101);
+        assert_eq!(identity.member, 3);
+        assert_eq!(identity.version, [1, 2, 3, 4]);
+        assert!(DeviceIdentity::parse(&device_inquiry(0x10)).is_none());
+    }
+
+    #[test]
+    fn collects_packets_and_checks_checksums() {
+        let header = [0xF0, 0x41, 0x10, 0x42, 0x12];
+        let packet = |data: &[u8]| {
+            let mut message = header.to_vec();
+            message.extend(data);
+            message.push(Checksum::Roland.compute(data).unwrap());
+            message.push(SYSEX_END);
+            message
+        };
+        let mut dump = DumpCollector::new(&header, Checksum::Roland);
+        dump.expect_packets(2);
+
+        assert!(dump
+            .handle_message(&packet(&[0x40, 0x00, 0x00, 1, 2]))
+            .unwrap());
+        assert!(!dump.handle_message(&device_inquiry(0x10)).unwrap());
+        let mut corrupt = packet(&[0x40, 0x00, 0x02, 3, 4]);
+        corrupt[7] ^= 1;
+        assert!(dump.handle_message(&corrupt).is_err());
+        assert!(!dump.is_complete());
+        assert!(dump
+            .handle_message(&packet(&[0x40, 0x00, 0x02, 3, 4]))
+            .unwrap());
+
+        assert!(dump.is_complete());
+        assert_eq!(
+            dump.finish(),
+            [0x40, 0x00, 0x00, 1, 2, 0x40, 0x00, 0x02, 3, 4]
+        );
+        assert_eq!(dump.packets(), 0);
+    }
+}
@@ -0,0 +1,365 @@
+//! Tempo, MIDI clock and note-length conversions, and tap tempo
+
+use std::time::Duration;
+
+/// MIDI clock messages per quarter note
+pub const MIDI_CLOCKS_PER_QUARTER: u32 = 24;
+
+/// Real-time status byte: Timing Clock
+pub const MIDI_CLOCK: u8 = 0xF8;
+/// Real-time status byte: Start
+pub const MIDI_START: u8 = 0xFA;
+/// Real-time status byte: Continue
+pub const MIDI_CONTINUE: u8 = 0xFB;
+/// Real-time status byte: Stop
+pub const MIDI_STOP: u8 = 0xFC;
+/// System common status byte: Song Position Pointer (14-bit sixteenth count)
+pub const MIDI_SONG_POSITION: u8 = 0xF2;
+/// System common status byte: MIDI Time Code quarter frame
+pub const MIDI_TIME_CODE: u8 = 0xF1;
+
+/// Base note value, measured against a quarter-note beat
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NoteDivision {
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+}
+
+impl NoteDivision {
+    /// Length in quarter-note beats
+    pub fn beats(&self) -> f64 {
+        match self {
+            NoteDivision::Whole => 4.0,
+            NoteDivision::Half => 2.0,
+            NoteDivision::Quarter => 1.0,
+            NoteDivision::Eighth => 0.5,
+            NoteDivision::Sixteenth => 0.25,
+            NoteDivision::ThirtySecond => 0.125,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NoteModifier {
+    #[default]
+    Straight,
+    /// One and a half times the base length
+    Dotted,
+    /// Two thirds of the base length
+    Triplet,
+}
+
+/// A tempo-relative length such as a dotted eighth or a sixteenth triplet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NoteLength {
+    pub division: NoteDivision,
+    pub modifier: NoteModifier,
+}
+
+impl NoteLength {
+    pub fn straight(division: NoteDivision) -> Self {
+        Self {
+            division,
+            modifier: NoteModifier::Straight,
+        }
+    }
+
+    pub fn dotted(division: NoteDivision) -> Self {
+        Self {
+            division,
+            modifier: NoteModifier::Dotted,
+        }
+    }
+
+    pub fn triplet(division: NoteDivision) -> Self {
+        Self {
+            division,
+            modifier: NoteModifier::Triplet,
+        }
+    }
+
+    /// Length in quarter-note beats
+    pub fn beats(&self) -> f64 {
+        let base = self.division.beats();
+        match self.modifier {
+            NoteModifier::Straight => base,
+            NoteModifier::Dotted => base * 1.5,
+            NoteModifier::Triplet => base * 2.0 / 3.0,
+        }
+    }
+
+    /// Duration in milliseconds at `bpm`
+    pub fn to_ms(&self, bpm: f64) -> f64 {
+        beats_to_ms(self.beats(), bpm)
+    }
+
+    /// Duration in samples at `bpm` and `sample_rate`
+    pub fn to_samples(&self, bpm: f64, sample_rate: f64) -> f64 {
+        beats_to_samples(self.beats(), bpm, sample_rate)
+    }
+
+    /// Rate in Hz of something that repeats once per this length (e.g. a synced LFO)
+    pub fn to_hz(&self, bpm: f64) -> f64 {
+        1000.0 / self.to_ms(bpm)
+    }
+
+    /// Number of MIDI clock ticks spanned (24 per quarter note)
+    pub fn to_clock_ticks(&self) -> f64 {
+        beats_to_clock_ticks(self.beats())
+    }
+}
+
+impl From<NoteDivision> for NoteLength {
+    fn from(division: NoteDivision) -> Self {
+        Self::straight(division)
+    }
+}
+
+/// Milliseconds per quarter-note beat
+pub fn bpm_to_ms(bpm: f64) -> f64 {
+    60_000.0 / bpm
+}
+
+/// Tempo whose quarter-note beat lasts `ms` milliseconds
+pub fn ms_to_bpm(ms: f64) -> f64 {
+    60_000.0 / ms
+}
+
+pub fn beats_to_ms(beats: f64, bpm: f64) -> f64 {
+    beats * bpm_to_ms(bpm)
+}
+
+pub fn ms_to_samples(ms: f64, sample_rate: f64) -> f64 {
+    ms * sample_rate / 1000.0
+}
+
+pub fn samples_to_ms(samples: f64, sample_rate: f64) -> f64 {
+    samples * 1000.0 / sample_rate
+}
+
+pub fn beats_to_samples(beats: f64, bpm: f64, sample_rate: f64) -> f64 {
+    ms_to_samples(beats_to_ms(beats, bpm), sample_rate)
+}
+
+pub fn clock_ticks_to_beats(ticks: f64) -> f64 {
+    ticks / MIDI_CLOCKS_PER_QUARTER as f64
+}
+
+pub fn beats_to_clock_ticks(beats: f64) -> f64 {
+    beats * MIDI_CLOCKS_PER_QUARTER as f64
+}
+
+/// Samples between MIDI clock ticks at `bpm`
+pub fn clock_tick_samples(bpm: f64, sample_rate: f64) -> f64 {
+    beats_to_samples(clock_ticks_to_beats(1.0), bpm, sample_rate)
+}
+
+/// Tempo implied by the interval between consecutive MIDI clock ticks
+pub fn clock_interval_to_bpm(seconds_per_tick: f64) -> f64 {
+    60.0 / (seconds_per_tick * MIDI_CLOCKS_PER_QUARTER as f64)
+}
+
+/// Most tap intervals a `TapTempo` can average
+pub const MAX_TAP_WINDOW: usize = 16;
+
+/// Turns taps (a button, a footswitch CC) into a smoothed tempo
+///
+/// The tempo is the average of the last `window` tap intervals. An interval
+/// more than `tolerance` away from that average is an outlier and ignored,
+/// unless the next one agrees with it, in which case the player has changed
+/// tempo and averaging starts over. A pause longer than the timeout starts a
+/// new tap sequence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TapTempo {
+    intervals: [u64; MAX_TAP_WINDOW], // µs, ring buffer
+    count: usize,
+    next: usize,
+    window: usize,
+    tolerance: f64,
+    timeout: u64,
+    last_tap: Option<u64>,
+    outlier: Option<u64>, // Rejected interval awaiting confirmation
+    restart: bool,        // Next interval replaces the average
+    pressed: bool,        // Footswitch state for `handle_cc`
+}
+
+impl TapTempo {
+    pub fn new() -> Self {
+        Self {
+            intervals: [0; MAX_TAP_WINDOW],
+            count: 0,
+            next: 0,
+            window: 4,
+            tolerance: 0.25,
+            timeout: 2_000_000,
+            last_tap: None,
+            outlier: None,
+            restart: false,
+            pressed: false,
+        }
+    }
+
+    /// Average over the last `taps` intervals (1 to `MAX_TAP_WINDOW`)
+    pub fn set_window(&mut self, taps: usize) {
+        self.window = taps.clamp(1, MAX_TAP_WINDOW);
+        self.reset();
+    }
+
+    /// Largest accepted deviation from the average, as a fraction of it
+    pub fn set_outlier_tolerance(&mut self, tolerance: f64) {
+        self.tolerance = tolerance.max(0.0);
+    }
+
+    /// Pause after which the next tap starts a new sequence
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout.as_micros() as u64;
+    }
+
+    /// Register a tap at `time` (µs) and return the current tempo
+    /// The first tap after a pause keeps the previous tempo until the
+    /// second one measures a new interval.
+    pub fn tap(&mut self, time: u64) -> Option<f64> {
+        let previous = self.last_tap.replace(time);
+        let interval = match previous {
+            Some(previous) if time > previous && time - previous <= self.timeout => time - previous,
+            _ => {
+                self.restart = true;
+                self.outlier = None;
+                return self.bpm();
+            }
+        };
+        if std::mem::take(&mut self.restart) {
+            self.count = 0;
+        }
+        match self.average() {
+            Some(average) if (interval as f64 - average).abs() > average * self.tolerance => {
+                let confirmed = self.outlier.take().is_some_and(|outlier| {
+                    (interval as f64 - outlier as f64).abs() <= outlier as f64 * self.tolerance
+                });
+                if confirmed {
+                    self.count = 0;
+                    self.push(interval);
+                } else {
+                    self.outlier = Some(interval);
+                }
+            }
+            _ => {
+                self.outlier = None;
+                self.push(interval);
+            }
+        }
+        self.bpm()
+    }
+
+    /// Treat a CC as a footswitch: each press (value crossing 64) is a tap
+    pub fn handle_cc(&mut self, value: u8, time: u64) -> Option<f64> {
+        let pressed = value >= 64;
+        let was_pressed = std::mem::replace(&mut self.pressed, pressed);
+        if pressed && !was_pressed {
+            self.tap(time)
+        } else {
+            None
+        }
+    }
+
+    /// Tempo from the intervals so far, once there is one
+    pub fn bpm(&self) -> Option<f64> {
+        self.average().map(|interval| 60_000_000.0 / interval)
+    }
+
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.last_tap = None;
+        self.outlier = None;
+        self.restart = false;
+    }
+
+    fn push(&mut self, interval: u64) {
+        if self.count == 0 {
+            self.next = 0;
+        }
+        self.intervals[self.next] = interval;
+        self.next = (self.next + 1) % self.window;
+        self.count = (self.count + 1).min(self.window);
+    }
+
+    fn average(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let sum: u64 = self.intervals[..self.count].iter().sum();
+        Some(sum as f64 / self.count as f64)
+    }
+}
+
+impl Default for TapTempo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn note_lengths_at_120_bpm() {
+        let quarter = NoteLength::from(NoteDivision::Quarter);
+        assert_eq!(quarter.to_ms(120.0), 500.0);
+        assert_eq!(NoteLength::dotted(NoteDivision::Eighth).to_ms(120.0), 375.0);
+        let triplet = NoteLength::triplet(NoteDivision::Eighth);
+        assert!((triplet.to_ms(120.0) - 500.0 / 3.0).abs() < 1e-9);
+        assert_eq!(triplet.to_clock_ticks(), 8.0);
+        assert_eq!(quarter.to_samples(120.0, 48000.0), 24000.0);
+        assert_eq!(NoteLength::straight(NoteDivision::Whole).to_hz(120.0), 0.5);
+    }
+
+    #[test]
+    fn clock_tick_timing() {
+        assert_eq!(clock_tick_samples(125.0, 48000.0), 960.0);
+        assert!((clock_interval_to_bpm(0.02) - 125.0).abs() < 1e-9);
+        assert_eq!(clock_ticks_to_beats(96.0), 4.0);
+        assert_eq!(ms_to_bpm(bpm_to_ms(93.0)), 93.0);
+        assert_eq!(samples_to_ms(ms_to_samples(12.5, 44100.0), 44100.0), 12.5);
+    }
+
+    #[test]
+    fn tap_tempo_averages_and_rejects_outliers() {
+        let mut taps = TapTempo::new();
+        assert_eq!(taps.tap(0), None);
+        let mut time = 0;
+        for interval in [500_000, 510_000, 490_000, 800_000, 500_000] {
+            time += interval;
+            taps.tap(time);
+        }
+        // The 800 ms hiccup was ignored
+        assert!((taps.bpm().unwrap() - 120.0).abs() < 0.5);
+
+        // Two agreeing slower taps mean a new tempo
+        for _ in 0..2 {
+            time += 1_000_000;
+            taps.tap(time);
+        }
+        assert_eq!(taps.bpm(), Some(60.0));
+
+        // A long pause starts over
+        assert_eq!(taps.tap(time + 5_000_000), Some(60.0));
+        assert_eq!(taps.tap(time + 5_400_000), Some(150.0));
+    }
+
+    #[test]
+    fn footswitch_presses_are_taps() {
+        let mut taps = TapTempo::new();
+        taps.handle_cc(127, 0);
+        assert_eq!(taps.handle_cc(127, 100_000), None); // Still held
+        taps.handle_cc(0, 200_000);
+        assert_eq!(taps.handle_cc(127, 600_000), Some(100.0));
+    }
+}
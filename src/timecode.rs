@@ -0,0 +1,400 @@
+//! MIDI Time Code: SMPTE positions from quarter-frame and full-frame
+//! messages, for syncing to video or an external recorder
+//!
+//! A running source sends eight quarter-frame messages (F1 0nnn dddd) per
+//! two frames, each carrying a nibble of the time; [`MtcFollower`]
+//! assembles them into a [`Timecode`]. Full-frame SysEx (F0 7F id 01 01
+//! hh mm ss ff F7) locates without running.
+
+use crate::sysex::{SYSEX_END, SYSEX_START};
+use crate::tempo::MIDI_TIME_CODE;
+use std::fmt;
+
+/// Universal Real Time SysEx ID
+pub const SYSEX_REALTIME: u8 = 0x7F;
+
+/// Quarter frames missing for this many frame lengths mean MTC stopped
+const MTC_TIMEOUT_FRAMES: f64 = 4.0;
+
+/// SMPTE frame rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameRate {
+    /// Film
+    #[default]
+    Fps24,
+    /// PAL video
+    Fps25,
+    /// NTSC color video, drop-frame
+    Fps29_97Drop,
+    /// NTSC black and white / audio
+    Fps30,
+}
+
+impl FrameRate {
+    /// The rate encoded in bits 5-6 of an MTC hours byte
+    pub fn from_code(code: u8) -> Self {
+        match code & 0x03 {
+            0 => Self::Fps24,
+            1 => Self::Fps25,
+            2 => Self::Fps29_97Drop,
+            _ => Self::Fps30,
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Fps24 => 0,
+            Self::Fps25 => 1,
+            Self::Fps29_97Drop => 2,
+            Self::Fps30 => 3,
+        }
+    }
+
+    /// Frames per second of real time
+    pub fn fps(&self) -> f64 {
+        match self {
+            Self::Fps24 => 24.0,
+            Self::Fps25 => 25.0,
+            Self::Fps29_97Drop => 30_000.0 / 1001.0,
+            Self::Fps30 => 30.0,
+        }
+    }
+
+    /// Frame numbers per second (30 for drop-frame)
+    pub fn nominal_fps(&self) -> u8 {
+        match self {
+            Self::Fps24 => 24,
+            Self::Fps25 => 25,
+            Self::Fps29_97Drop | Self::Fps30 => 30,
+        }
+    }
+
+    pub fn is_drop_frame(&self) -> bool {
+        *self == Self::Fps29_97Drop
+    }
+}
+
+/// An SMPTE time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub rate: FrameRate,
+}
+
+impl Timecode {
+    pub fn new(hours: u8, minutes: u8, seconds: u8, frames: u8, rate: FrameRate) -> Self {
+        Self {
+            hours,
+            minutes,
+            seconds,
+            frames,
+            rate,
+        }
+    }
+
+    /// Frames since 00:00:00:00, skipping the numbers drop-frame leaves out
+    pub fn frame_count(&self) -> u64 {
+        let fps = self.rate.nominal_fps() as u64;
+        let minutes = self.hours as u64 * 60 + self.minutes as u64;
+        let count = (minutes * 60 + self.seconds as u64) * fps + self.frames as u64;
+        if self.rate.is_drop_frame() {
+            // Frames 0 and 1 are skipped every minute except each tenth
+            count - 2 * (minutes - minutes / 10)
+        } else {
+            count
+        }
+    }
+
+    /// The time `count` frames after 00:00:00:00, wrapping at 24 hours
+    pub fn from_frame_count(count: u64, rate: FrameRate) -> Self {
+        let fps = rate.nominal_fps() as u64;
+        let mut count = count;
+        if rate.is_drop_frame() {
+            const PER_TEN_MINUTES: u64 = 17_982;
+            const PER_MINUTE: u64 = 1_798;
+            let (tens, rest) = (count / PER_TEN_MINUTES, count % PER_TEN_MINUTES);
+            count += 18 * tens + 2 * (rest.saturating_sub(2) / PER_MINUTE);
+        }
+        let count = count % (24 * 3600 * fps);
+        let seconds = count / fps;
+        Self {
+            hours: (seconds / 3600) as u8,
+            minutes: (seconds / 60 % 60) as u8,
+            seconds: (seconds % 60) as u8,
+            frames: (count % fps) as u8,
+            rate,
+        }
+    }
+
+    /// Move by `frames` (negative for earlier), wrapping at 24 hours
+    pub fn add_frames(&self, frames: i64) -> Self {
+        let day = Self::new(24, 0, 0, 0, self.rate).frame_count() as i64;
+        let count = (self.frame_count() as i64 + frames).rem_euclid(day);
+        Self::from_frame_count(count as u64, self.rate)
+    }
+
+    /// Position in seconds of real time
+    pub fn to_seconds(&self) -> f64 {
+        self.frame_count() as f64 / self.rate.fps()
+    }
+
+    /// Full-frame SysEx locating a device to this time
+    pub fn full_frame(&self, device_id: u8) -> [u8; 10] {
+        [
+            SYSEX_START,
+            SYSEX_REALTIME,
+            device_id & 0x7F,
+            0x01,
+            0x01,
+            (self.rate.code() << 5) | (self.hours & 0x1F),
+            self.minutes & 0x3F,
+            self.seconds & 0x3F,
+            self.frames & 0x1F,
+            SYSEX_END,
+        ]
+    }
+
+    /// Parse a full-frame SysEx message
+    pub fn parse_full_frame(message: &[u8]) -> Option<Self> {
+        match *message {
+            [SYSEX_START, SYSEX_REALTIME, _, 0x01, 0x01, hours, minutes, seconds, frames, SYSEX_END] => {
+                Some(Self::new(
+                    hours & 0x1F,
+                    minutes & 0x3F,
+                    seconds & 0x3F,
+                    frames & 0x1F,
+                    FrameRate::from_code(hours >> 5),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// The eight quarter-frame messages (data bytes after F1) that send
+    /// this time, piece 0 first
+    pub fn quarter_frames(&self) -> [u8; 8] {
+        let hours = (self.rate.code() << 5) | (self.hours & 0x1F);
+        let fields = [self.frames, self.seconds, self.minutes, hours];
+        std::array::from_fn(|piece| {
+            let field = fields[piece / 2];
+            let nibble = if piece % 2 == 0 {
+                field & 0x0F
+            } else {
+                field >> 4
+            };
+            ((piece as u8) << 4) | nibble
+        })
+    }
+}
+
+impl fmt::Display for Timecode {
+    /// HH:MM:SS:FF, with `;` before the frames for drop-frame
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if self.rate.is_drop_frame() { ';' } else { ':' };
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours, self.minutes, self.seconds, separator, self.frames
+        )
+    }
+}
+
+/// What a message did to the timecode position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimecodeEvent {
+    /// A quarter frame that didn't complete a time
+    QuarterFrame,
+    /// Eight quarter frames completed a time; the position is now this
+    Position(Timecode),
+    /// A full-frame message located to this time
+    Located(Timecode),
+}
+
+/// Follows incoming MIDI Time Code
+///
+/// Feed it quarter-frame and full-frame messages with their arrival time
+/// in microseconds. A completed set of quarter frames describes the time
+/// at which its first piece was sent, two frames ago, so the position
+/// reported is that time plus two frames (minus two when the source runs
+/// backwards).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MtcFollower {
+    pieces: [u8; 8],
+    received: u8, // Bit per piece of the set in progress
+    last_piece: Option<u8>,
+    reverse: bool,
+    position: Option<Timecode>,
+    quarters: u32, // Quarter frames since the position was set
+    last_time: Option<u64>,
+}
+
+impl MtcFollower {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Last complete position, if one has been received
+    pub fn timecode(&self) -> Option<Timecode> {
+        self.position
+    }
+
+    /// Position in seconds, advanced by the quarter frames received since
+    /// the last complete time
+    pub fn seconds(&self) -> Option<f64> {
+        let position = self.position?;
+        let quarters = self.quarters as f64 / (4.0 * position.rate.fps());
+        Some(if self.reverse {
+            position.to_seconds() - quarters
+        } else {
+            position.to_seconds() + quarters
+        })
+    }
+
+    /// True while the source runs backwards
+    pub fn is_reverse(&self) -> bool {
+        self.reverse
+    }
+
+    /// True if a quarter frame arrived recently enough at `now` (µs)
+    pub fn is_running(&self, now: u64) -> bool {
+        let fps = self.position.map_or(FrameRate::Fps24, |t| t.rate).fps();
+        self.last_time.is_some_and(|last| {
+            (now.saturating_sub(last) as f64) < MTC_TIMEOUT_FRAMES * 1_000_000.0 / fps
+        })
+    }
+
+    /// Handle a MIDI message received at `time` (µs)
+    /// Returns what it did, or None for messages that aren't timecode.
+    pub fn handle_message(&mut self, bytes: &[u8], time: u64) -> Option<TimecodeEvent> {
+        match *bytes {
+            [MIDI_TIME_CODE, data] => Some(self.quarter_frame(data, time)),
+            [SYSEX_START, SYSEX_REALTIME, ..] => {
+                let timecode = Timecode::parse_full_frame(bytes)?;
+                self.locate(timecode);
+                Some(TimecodeEvent::Located(timecode))
+            }
+            _ => None,
+        }
+    }
+
+    /// Handle the data byte of a quarter-frame message received at `time`
+    pub fn quarter_frame(&mut self, data: u8, time: u64) -> TimecodeEvent {
+        let piece = (data >> 4) & 0x07;
+        self.last_time = Some(time);
+        self.quarters += 1;
+        match self.last_piece {
+            Some(last) if piece == (last + 1) % 8 => self.reverse = false,
+            Some(last) if piece == (last + 7) % 8 => self.reverse = true,
+            _ => self.received = 0, // Out of sequence; start a new set
+        }
+        self.last_piece = Some(piece);
+        self.pieces[piece as usize] = data & 0x0F;
+        self.received |= 1 << piece;
+
+        let last_of_set = if self.reverse { 0 } else { 7 };
+        if self.received != 0xFF || piece != last_of_set {
+            return TimecodeEvent::QuarterFrame;
+        }
+        self.received = 0;
+        let field = |index: usize| self.pieces[index * 2] | (self.pieces[index * 2 + 1] << 4);
+        let hours = field(3);
+        let timecode = Timecode::new(
+            hours & 0x1F,
+            field(2) & 0x3F,
+            field(1) & 0x3F,
+            field(0) & 0x1F,
+            FrameRate::from_code(hours >> 5),
+        );
+        let timecode = timecode.add_frames(if self.reverse { -2 } else { 2 });
+        self.position = Some(timecode);
+        self.quarters = 0;
+        TimecodeEvent::Position(timecode)
+    }
+
+    /// Jump to `timecode`, e.g. from a full-frame message
+    pub fn locate(&mut self, timecode: Timecode) {
+        self.position = Some(timecode);
+        self.quarters = 0;
+        self.received = 0;
+        self.last_piece = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sysex::SYSEX_ALL_DEVICES;
+
+    #[test]
+    fn drop_frame_counts_skip_numbers() {
+        let rate = FrameRate::Fps29_97Drop;
+        let minute = Timecode::new(0, 1, 0, 2, rate);
+        assert_eq!(minute.frame_count(), 1800);
+        assert_eq!(minute.add_frames(-1), Timecode::new(0, 0, 59, 29, rate));
+        assert_eq!(minute.to_string(), "00:01:00;02");
+        // Every tenth minute keeps frames 0 and 1
+        let ten = Timecode::new(0, 10, 0, 0, rate);
+        assert_eq!(Timecode::from_frame_count(ten.frame_count(), rate), ten);
+        // An hour of drop-frame is an hour of real time, to the frame
+        let hour = Timecode::new(1, 0, 0, 0, rate);
+        assert!((hour.to_seconds() - 3600.0).abs() < 1.0 / 30.0);
+
+        let day_end = Timecode::new(23, 59, 59, 24, FrameRate::Fps25);
+        assert_eq!(
+            day_end.add_frames(1),
+            Timecode::new(0, 0, 0, 0, FrameRate::Fps25)
+        );
+    }
+
+    #[test]
+    fn assembles_quarter_frames() {
+        let sent = Timecode::new(1, 2, 3, 4, FrameRate::Fps25);
+        let mut follower = MtcFollower::new();
+        // Start mid-set: the first complete set begins at piece 0
+        follower.quarter_frame(sent.quarter_frames()[6], 0);
+        follower.quarter_frame(sent.quarter_frames()[7], 0);
+        let mut events = Vec::new();
+        for (i, data) in sent.quarter_frames().into_iter().enumerate() {
+            let time = i as u64 * 10_000;
+            events.push(follower.handle_message(&[MIDI_TIME_CODE, data], time));
+        }
+        assert_eq!(
+            events[7],
+            Some(TimecodeEvent::Position(Timecode::new(
+                1,
+                2,
+                3,
+                6,
+                FrameRate::Fps25
+            )))
+        );
+        assert!(events[..7]
+            .iter()
+            .all(|event| *event == Some(TimecodeEvent::QuarterFrame)));
+        assert!(follower.is_running(80_000));
+        assert!(!follower.is_running(1_000_000));
+
+        follower.quarter_frame(0x00, 80_000);
+        let seconds = follower.seconds().unwrap();
+        assert!((seconds - (3723.0 + 6.0 / 25.0 + 0.01)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn full_frame_locates() {
+        let target = Timecode::new(10, 0, 0, 0, FrameRate::Fps29_97Drop);
+        let message = target.full_frame(SYSEX_ALL_DEVICES);
+        let mut follower = MtcFollower::new();
+        assert_eq!(
+            follower.handle_message(&message, 0),
+            Some(TimecodeEvent::Located(target))
+        );
+        assert_eq!(follower.timecode(), Some(target));
+        assert_eq!(
+            follower.handle_message(&[0xF0, 0x7E, 0, 6, 1, 0xF7], 0),
+            None
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Velocity processing: reshape note-on velocities per channel before they
+//! reach voice allocation and `velocity_to_gain`
+
+use crate::conversions::VelocityCurve;
+use crate::midi_input::{ChannelEvent, MidiEvent};
+
+/// Velocity around which `dynamics` compresses or expands
+const DYNAMICS_CENTER: f32 = 64.0;
+
+/// One channel's velocity transform
+///
+/// Stages run in order: fixed velocity, curve, dynamics, scale, offset,
+/// then the min/max clamp. The result is always a valid note-on velocity
+/// (1-127); velocity 0 is a note-off and passes through untouched.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VelocityTransform {
+    /// Replace every velocity with this one (organ-style)
+    pub fixed: Option<u8>,
+    /// Reshape through a response curve (`Linear` leaves it unchanged)
+    pub curve: VelocityCurve,
+    /// Distance from velocity 64 is multiplied by this: below 1.0
+    /// compresses, above 1.0 expands
+    pub dynamics: f32,
+    pub scale: f32,
+    pub offset: i16,
+    pub min: u8,
+    pub max: u8,
+}
+
+impl VelocityTransform {
+    /// Leaves velocities unchanged
+    pub fn identity() -> Self {
+        Self {
+            fixed: None,
+            curve: VelocityCurve::Linear,
+            dynamics: 1.0,
+            scale: 1.0,
+            offset: 0,
+            min: 1,
+            max: 127,
+        }
+    }
+
+    /// Every note at `velocity`
+    pub fn fixed(velocity: u8) -> Self {
+        Self {
+            fixed: Some(velocity),
+            ..Self::identity()
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::identity()
+    }
+
+    pub fn apply(&self, velocity: u8) -> u8 {
+        if velocity == 0 {
+            return 0;
+        }
+        let mut v = self.fixed.unwrap_or(velocity) as f32;
+        v = self.curve.gain(v as u8) * 127.0;
+        v = DYNAMICS_CENTER + (v - DYNAMICS_CENTER) * self.dynamics;
+        v = v * self.scale + self.offset as f32;
+        let (min, max) = (self.min.clamp(1, 127), self.max.clamp(1, 127));
+        v.round().clamp(min.min(max) as f32, max as f32) as u8
+    }
+}
+
+impl Default for VelocityTransform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+/// Per-channel velocity transforms applied to note-on events
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VelocityProcessor {
+    channels: [VelocityTransform; 16],
+}
+
+impl VelocityProcessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn channel(&self, channel: u8) -> &VelocityTransform {
+        &self.channels[(channel & 0x0F) as usize]
+    }
+
+    pub fn channel_mut(&mut self, channel: u8) -> &mut VelocityTransform {
+        &mut self.channels[(channel & 0x0F) as usize]
+    }
+
+    /// Use `transform` on every channel
+    pub fn set_all(&mut self, transform: VelocityTransform) {
+        self.channels = std::array::from_fn(|_| transform.clone());
+    }
+
+    /// Rewrite the velocity of a note-on; other events are left alone
+    pub fn process(&self, event: &mut ChannelEvent) {
+        if let MidiEvent::NoteOn(_, velocity) = &mut event.event {
+            *velocity = self.channel(event.channel).apply(*velocity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stages_shape_velocity() {
+        assert_eq!(VelocityTransform::identity().apply(37), 37);
+        assert_eq!(VelocityTransform::fixed(100).apply(12), 100);
+
+        let compress = VelocityTransform {
+            dynamics: 0.5,
+            ..VelocityTransform::identity()
+        };
+        assert_eq!(compress.apply(127), 96);
+        assert_eq!(compress.apply(1), 33);
+
+        let boost = VelocityTransform {
+            scale: 2.0,
+            offset: -10,
+            max: 110,
+            ..VelocityTransform::identity()
+        };
+        assert_eq!(boost.apply(30), 50);
+        assert_eq!(boost.apply(100), 110);
+        assert_eq!(boost.apply(3), 1); // Never turns a note-on into a note-off
+        assert_eq!(boost.apply(0), 0);
+
+        let soft = VelocityTransform {
+            curve: VelocityCurve::Soft,
+            ..VelocityTransform::identity()
+        };
+        assert_eq!(soft.apply(32), 64);
+    }
+
+    #[test]
+    fn processor_works_per_channel() {
+        let mut processor = VelocityProcessor::new();
+        *processor.channel_mut(9) = VelocityTransform::fixed(127);
+        let mut drums = ChannelEvent {
+            channel: 9,
+            event: MidiEvent::NoteOn(36, 40),
+        };
+        let mut keys = ChannelEvent {
+            channel: 0,
+            event: MidiEvent::NoteOn(60, 40),
+        };
+        processor.process(&mut drums);
+        processor.process(&mut keys);
+        assert_eq!(drums.event, MidiEvent::NoteOn(36, 127));
+        assert_eq!(keys.event, MidiEvent::NoteOn(60, 40));
+    }
+}
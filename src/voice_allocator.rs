@@ -1,67 +1,440 @@
 //! Voice allocation for polyphonic synthesis
+//!
+//! With `std` the voices are allocated on the heap once, at construction.
+//! Without it [`VoiceAllocator`] needs no allocator: its voices live in a
+//! fixed array whose size is a const parameter (`VoiceAllocator::<8>::fixed()`
+//! on a microcontroller), defaulting to [`DEFAULT_VOICE_CAPACITY`].
 
+use crate::slot_table::SlotTable;
+
+/// Voices used by `VoiceAllocator::new`
 pub const MAX_VOICES: usize = 8;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub struct VoiceId(pub usize);
+/// Most voices a `VoiceAllocator` without an explicit capacity holds
+/// when built without `std`
+pub const DEFAULT_VOICE_CAPACITY: usize = 64;
+
+/// Most undrained events the allocator's event log holds
+pub const MAX_VOICE_EVENTS: usize = 64;
+
+/// Handle to an allocated voice: slot index plus the slot's generation
+///
+/// The generation increments every time the slot is (re)allocated, so a handle
+/// kept across a steal no longer matches and can be detected as stale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VoiceId(pub usize, pub u32);
+
+impl VoiceId {
+    /// Slot index of this voice
+    pub fn index(&self) -> usize {
+        self.0
+    }
+
+    /// Generation of the slot when this handle was issued
+    pub fn generation(&self) -> u32 {
+        self.1
+    }
+}
 
 #[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VoiceSlot {
     pub active: bool,
     pub note: u8,
+    pub channel: u8,
     pub age: u32,
+    pub generation: u32,
+}
+
+/// Allocation activity reported through the allocator's event log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VoiceEvent {
+    /// A voice started playing a note
+    Allocated { voice: VoiceId, note: u8 },
+    /// A sounding voice was taken over (followed by `Allocated` for the new note)
+    Stolen { voice: VoiceId, note: u8 },
+    /// A voice was released by its note, channel or ID
+    Released { voice: VoiceId, note: u8 },
+}
+
+impl VoiceEvent {
+    /// Placeholder filling unused log entries
+    const EMPTY: Self = VoiceEvent::Released {
+        voice: VoiceId(0, 0),
+        note: 0,
+    };
+}
+
+/// Fixed-capacity record of voice events, oldest first
+#[derive(Debug, Clone)]
+struct EventLog {
+    events: [VoiceEvent; MAX_VOICE_EVENTS],
+    len: usize,
+    capacity: usize, // 0 = logging disabled
+}
+
+impl EventLog {
+    fn record(&mut self, event: VoiceEvent) {
+        if self.len < self.capacity {
+            self.events[self.len] = event;
+            self.len += 1;
+        }
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = VoiceEvent> + '_ {
+        let len = core::mem::take(&mut self.len);
+        self.events[..len].iter().copied()
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self {
+            events: [VoiceEvent::EMPTY; MAX_VOICE_EVENTS],
+            len: 0,
+            capacity: 0,
+        }
+    }
 }
 
+/// FIFO of free voice indices in a ring with one entry per voice
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct FreeList<const N: usize> {
+    ring: SlotTable<usize, N>,
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> FreeList<N> {
+    /// A list holding every voice of `ring`, which must be filled
+    fn all(mut ring: SlotTable<usize, N>) -> Self {
+        for (idx, slot) in ring.iter_mut().enumerate() {
+            *slot = idx;
+        }
+        Self {
+            len: ring.len(),
+            ring,
+            head: 0,
+        }
+    }
+
+    fn pop_front(&mut self) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        let idx = self.ring[self.head];
+        self.head = (self.head + 1) % self.ring.len();
+        self.len -= 1;
+        Some(idx)
+    }
+
+    /// Append a voice; there is always room, as each voice is listed once
+    fn push_back(&mut self, idx: usize) {
+        let end = (self.head + self.len) % self.ring.len();
+        self.ring[end] = idx;
+        self.len += 1;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Per-voice storage for `voice_count` voices
+fn voice_table<T: Copy, const N: usize>(value: T, voice_count: usize) -> SlotTable<T, N> {
+    SlotTable::filled(value, voice_count)
+        .unwrap_or_else(|| panic!("{voice_count} voices exceed the capacity of {N}"))
+}
+
+/// Sentinel for "no voice" in the intrusive lists below
+const NONE: usize = usize::MAX;
+
+/// Intrusive list links kept alongside each slot
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct SlotLinks {
+    // Voices playing the same note, oldest first
+    note_prev: usize,
+    note_next: usize,
+    // Active voices in allocation order, oldest first
+    age_prev: usize,
+    age_next: usize,
+}
+
+impl Default for SlotLinks {
+    fn default() -> Self {
+        Self {
+            note_prev: NONE,
+            note_next: NONE,
+            age_prev: NONE,
+            age_next: NONE,
+        }
+    }
+}
+
+/// Voice allocator with O(1) allocate, release and steal
+///
+/// A 128-entry note table indexes the voices playing each note, active voices
+/// are kept in allocation order for stealing, and free voices sit in a FIFO
+/// so a just-released voice isn't reused while its release tail still sounds.
+/// Without `std` at most `N` voices fit.
 #[derive(Debug)]
-pub struct VoiceAllocator {
-    voices: [VoiceSlot; MAX_VOICES],
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VoiceAllocator<const N: usize = DEFAULT_VOICE_CAPACITY> {
+    voices: SlotTable<VoiceSlot, N>, // Allocated at construction for RT-safety
+    links: SlotTable<SlotLinks, N>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array"))]
+    note_head: [usize; 128],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array"))]
+    note_tail: [usize; 128],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array"))]
+    note_count: [usize; 128],
+    max_voices_per_note: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    polyphony: Option<usize>,
+    oldest: usize,
+    newest: usize,
+    free: FreeList<N>,
+    #[cfg_attr(feature = "serde", serde(skip))] // Snapshots restore with the log disabled
+    events: EventLog,
     next_age: u32,
 }
 
 impl VoiceAllocator {
     pub fn new() -> Self {
+        Self::with_voices(MAX_VOICES)
+    }
+
+    /// Create an allocator managing the given number of voices
+    ///
+    /// # Panics
+    ///
+    /// Without `std`, if `voice_count` exceeds `DEFAULT_VOICE_CAPACITY`
+    pub fn with_voices(voice_count: usize) -> Self {
+        Self::with_voice_count(voice_count)
+    }
+}
+
+impl<const N: usize> VoiceAllocator<N> {
+    /// Create an allocator managing `N` voices
+    pub fn fixed() -> Self {
+        Self::with_voice_count(N)
+    }
+
+    fn with_voice_count(voice_count: usize) -> Self {
         Self {
-            voices: [VoiceSlot::default(); MAX_VOICES],
+            voices: voice_table(VoiceSlot::default(), voice_count),
+            links: voice_table(SlotLinks::default(), voice_count),
+            note_head: [NONE; 128],
+            note_tail: [NONE; 128],
+            note_count: [0; 128],
+            max_voices_per_note: None,
+            polyphony: None,
+            oldest: NONE,
+            newest: NONE,
+            free: FreeList::all(voice_table(0, voice_count)),
+            events: EventLog::default(),
             next_age: 0,
         }
     }
 
+    /// Get the number of voices managed by this allocator
+    pub fn voice_count(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// Start recording voice events, keeping up to `capacity` undrained events
+    /// (at most `MAX_VOICE_EVENTS`); events beyond the capacity are dropped
+    pub fn enable_event_log(&mut self, capacity: usize) {
+        self.events = EventLog {
+            capacity: capacity.min(MAX_VOICE_EVENTS),
+            ..EventLog::default()
+        };
+    }
+
+    /// Stop recording voice events and discard pending ones
+    pub fn disable_event_log(&mut self) {
+        self.events = EventLog::default();
+    }
+
+    /// Take all events recorded since the last drain, oldest first
+    pub fn drain_events(&mut self) -> impl Iterator<Item = VoiceEvent> + '_ {
+        self.events.drain()
+    }
+
+    /// Limit how many voices a single note number may occupy at once
+    /// (None = unlimited). When a note exceeds the cap its oldest instance is
+    /// stolen, so fast trills and repeated notes can't hog the pool.
+    pub fn set_max_voices_per_note(&mut self, max: Option<usize>) {
+        self.max_voices_per_note = max.map(|m| m.max(1));
+    }
+
+    pub fn max_voices_per_note(&self) -> Option<usize> {
+        self.max_voices_per_note
+    }
+
+    /// Limit how many voices may sound at once (None = every voice); past
+    /// the limit a new note steals the oldest. `Some(1)` plays
+    /// monophonically, as in MIDI Mono mode.
+    pub fn set_polyphony(&mut self, limit: Option<usize>) {
+        self.polyphony = limit.map(|limit| limit.max(1));
+    }
+
+    pub fn polyphony(&self) -> Option<usize> {
+        self.polyphony
+    }
+
+    pub fn is_mono(&self) -> bool {
+        self.polyphony == Some(1)
+    }
+
+    /// Get the number of voices currently playing a note
+    pub fn note_voice_count(&self, note: u8) -> usize {
+        self.note_count[(note & 0x7F) as usize]
+    }
+
     /// Allocate a voice for the given note
-    /// Returns Some(VoiceId) if successful, None if all voices busy
+    /// Returns Some(VoiceId) if successful, None if the allocator has no voices
     pub fn allocate_voice(&mut self, note: u8) -> Option<VoiceId> {
-        // First try to find an inactive voice
-        for (i, voice) in self.voices.iter_mut().enumerate() {
-            if !voice.active {
-                voice.active = true;
-                voice.note = note;
-                voice.age = self.next_age;
-                self.next_age = self.next_age.wrapping_add(1);
-                return Some(VoiceId(i));
-            }
-        }
+        self.allocate_voice_on_channel(note, 0)
+    }
+
+    /// Allocate a voice for a note, remembering the channel it arrived on
+    /// (the member channel for MPE controllers)
+    pub fn allocate_voice_on_channel(&mut self, note: u8, channel: u8) -> Option<VoiceId> {
+        let note = note & 0x7F;
+        let note_capped = self
+            .max_voices_per_note
+            .is_some_and(|max| self.note_count[note as usize] >= max);
 
-        // All voices active, steal the oldest one
-        let oldest_idx = self.find_oldest_voice();
-        self.voices[oldest_idx].active = true;
-        self.voices[oldest_idx].note = note;
-        self.voices[oldest_idx].age = self.next_age;
+        let voices_capped = self
+            .polyphony
+            .is_some_and(|limit| self.active_voice_count() >= limit);
+
+        // Steal the note's oldest instance if it hit its cap, otherwise take
+        // a free voice (within the polyphony limit), otherwise steal the
+        // oldest voice overall
+        let idx = if note_capped {
+            let idx = self.note_head[note as usize];
+            self.steal(idx);
+            idx
+        } else if let Some(idx) = (!voices_capped).then(|| self.free.pop_front()).flatten() {
+            idx
+        } else if self.oldest != NONE {
+            let idx = self.oldest;
+            self.steal(idx);
+            idx
+        } else {
+            return None;
+        };
+
+        let voice = &mut self.voices[idx];
+        voice.active = true;
+        voice.note = note;
+        voice.channel = channel;
+        voice.age = self.next_age;
+        voice.generation = voice.generation.wrapping_add(1);
+        let voice_id = VoiceId(idx, voice.generation);
         self.next_age = self.next_age.wrapping_add(1);
-        Some(VoiceId(oldest_idx))
+        self.link(idx);
+        self.record(VoiceEvent::Allocated {
+            voice: voice_id,
+            note,
+        });
+        Some(voice_id)
     }
 
     /// Release the voice playing the given note
-    pub fn release_voice(&mut self, note: u8) {
-        for voice in &mut self.voices {
-            if voice.active && voice.note == note {
-                voice.active = false;
-                break;
+    /// Returns the released VoiceId, or None if no voice was playing it
+    pub fn release_voice(&mut self, note: u8) -> Option<VoiceId> {
+        let voice_id = self.voice_for_note(note)?;
+        self.free_voice(voice_id.0);
+        Some(voice_id)
+    }
+
+    /// Release the voice playing the given note on the given channel
+    pub fn release_voice_on_channel(&mut self, note: u8, channel: u8) -> Option<VoiceId> {
+        let voice_id = self
+            .voices_for_note(note)
+            .find(|id| self.voices[id.0].channel == channel)?;
+        self.free_voice(voice_id.0);
+        Some(voice_id)
+    }
+
+    /// Find the most recently allocated active voice on a channel
+    /// With MPE each member channel carries one note, so this is the voice
+    /// that per-channel bend, pressure and timbre messages belong to
+    pub fn voice_for_channel(&self, channel: u8) -> Option<VoiceId> {
+        let mut idx = self.newest;
+        while idx != NONE {
+            let voice = &self.voices[idx];
+            if voice.channel == channel {
+                return Some(VoiceId(idx, voice.generation));
             }
+            idx = self.links[idx].age_prev;
         }
+        None
+    }
+
+    /// Release a specific voice by ID
+    /// Use this when the same note may occupy several voices (retrigger).
+    /// Stale IDs (voice since stolen or reused) are ignored.
+    pub fn release_voice_id(&mut self, voice_id: VoiceId) -> bool {
+        if !self.is_current(voice_id) {
+            return false;
+        }
+        self.free_voice(voice_id.0);
+        true
+    }
+
+    /// Release every active voice (e.g. on All Notes Off / panic)
+    /// Returns the number of voices released
+    pub fn release_all(&mut self) -> usize {
+        let mut released = 0;
+        while self.oldest != NONE {
+            self.free_voice(self.oldest);
+            released += 1;
+        }
+        released
+    }
+
+    /// Check whether a handle still refers to the slot's current allocation
+    pub fn is_valid(&self, voice_id: VoiceId) -> bool {
+        self.voices
+            .get(voice_id.0)
+            .is_some_and(|v| v.generation == voice_id.1)
+    }
+
+    /// Check whether a handle is valid and its voice is still sounding
+    pub fn is_current(&self, voice_id: VoiceId) -> bool {
+        self.is_valid(voice_id) && self.voices[voice_id.0].active
+    }
+
+    /// Find the voice playing a note (the one `release_voice` would release)
+    pub fn voice_for_note(&self, note: u8) -> Option<VoiceId> {
+        self.voices_for_note(note).next()
+    }
+
+    /// Get all voices playing a note (several when the note was retriggered),
+    /// oldest first
+    pub fn voices_for_note(&self, note: u8) -> impl Iterator<Item = VoiceId> + '_ {
+        let mut idx = self.note_head[(note & 0x7F) as usize];
+        core::iter::from_fn(move || {
+            if idx == NONE {
+                return None;
+            }
+            let voice_id = VoiceId(idx, self.voices[idx].generation);
+            idx = self.links[idx].note_next;
+            Some(voice_id)
+        })
     }
 
     /// Get the number of active voices
     pub fn active_voice_count(&self) -> usize {
-        self.voices.iter().filter(|v| v.active).count()
+        self.voices.len() - self.free.len()
     }
 
     /// Get all active voices
@@ -70,21 +443,88 @@ impl VoiceAllocator {
             .iter()
             .enumerate()
             .filter(|(_, v)| v.active)
-            .map(|(i, v)| (VoiceId(i), v.note))
+            .map(|(i, v)| (VoiceId(i, v.generation), v.note))
     }
 
-    fn find_oldest_voice(&self) -> usize {
-        let mut oldest_idx = 0;
-        let mut oldest_age = self.voices[0].age;
+    fn free_voice(&mut self, idx: usize) {
+        self.unlink(idx);
+        let voice = &mut self.voices[idx];
+        voice.active = false;
+        let event = VoiceEvent::Released {
+            voice: VoiceId(idx, voice.generation),
+            note: voice.note,
+        };
+        self.free.push_back(idx);
+        self.record(event);
+    }
 
-        for (i, voice) in self.voices.iter().enumerate() {
-            if voice.age < oldest_age {
-                oldest_age = voice.age;
-                oldest_idx = i;
-            }
+    fn steal(&mut self, idx: usize) {
+        self.unlink(idx);
+        let voice = &self.voices[idx];
+        self.record(VoiceEvent::Stolen {
+            voice: VoiceId(idx, voice.generation),
+            note: voice.note,
+        });
+    }
+
+    fn record(&mut self, event: VoiceEvent) {
+        self.events.record(event);
+    }
+
+    /// Append a voice to its note list and the allocation-order list
+    fn link(&mut self, idx: usize) {
+        let note = self.voices[idx].note as usize;
+
+        let tail = self.note_tail[note];
+        self.links[idx].note_prev = tail;
+        self.links[idx].note_next = NONE;
+        match tail {
+            NONE => self.note_head[note] = idx,
+            tail => self.links[tail].note_next = idx,
+        }
+        self.note_tail[note] = idx;
+        self.note_count[note] += 1;
+
+        let newest = self.newest;
+        self.links[idx].age_prev = newest;
+        self.links[idx].age_next = NONE;
+        match newest {
+            NONE => self.oldest = idx,
+            newest => self.links[newest].age_next = idx,
         }
+        self.newest = idx;
+    }
+
+    /// Remove a voice from its note list and the allocation-order list
+    fn unlink(&mut self, idx: usize) {
+        let note = self.voices[idx].note as usize;
+        let SlotLinks {
+            note_prev,
+            note_next,
+            age_prev,
+            age_next,
+        } = self.links[idx];
 
-        oldest_idx
+        match note_prev {
+            NONE => self.note_head[note] = note_next,
+            prev => self.links[prev].note_next = note_next,
+        }
+        match note_next {
+            NONE => self.note_tail[note] = note_prev,
+            next => self.links[next].note_prev = note_prev,
+        }
+        self.note_count[note] -= 1;
+
+        match age_prev {
+            NONE => self.oldest = age_next,
+            prev => self.links[prev].age_next = age_next,
+        }
+        match age_next {
+            NONE => self.newest = age_prev,
+            next => self.links[next].age_prev = age_prev,
+        }
+
+        self.links[idx] = SlotLinks::default();
     }
 }
 
@@ -141,6 +581,216 @@ mod tests {
         assert_eq!(allocator.active_voice_count(), 2);
     }
 
+    #[test]
+    fn release_by_id_frees_exact_voice() {
+        let mut allocator = VoiceAllocator::new();
+
+        let first = allocator.allocate_voice(60).unwrap();
+        let second = allocator.allocate_voice(60).unwrap();
+
+        assert!(allocator.release_voice_id(second));
+        let remaining: Vec<_> = allocator.active_voices().map(|(id, _)| id).collect();
+        assert_eq!(remaining, vec![first]);
+
+        // Releasing an already free voice is a no-op
+        assert!(!allocator.release_voice_id(second));
+    }
+
+    #[test]
+    fn stolen_voice_invalidates_old_handle() {
+        let mut allocator = VoiceAllocator::new();
+
+        let handles: Vec<_> = (0..MAX_VOICES)
+            .map(|i| allocator.allocate_voice(60 + i as u8).unwrap())
+            .collect();
+        let stolen = allocator.allocate_voice(100).unwrap();
+
+        assert_eq!(stolen.index(), handles[0].index());
+        assert_ne!(stolen.generation(), handles[0].generation());
+        assert!(!allocator.is_valid(handles[0]));
+        assert!(allocator.is_current(stolen));
+
+        // A stale release must not free the voice that took over the slot
+        assert!(!allocator.release_voice_id(handles[0]));
+        assert!(allocator.is_current(stolen));
+    }
+
+    #[test]
+    fn custom_voice_count() {
+        let mut allocator = VoiceAllocator::with_voices(3);
+        assert_eq!(allocator.voice_count(), 3);
+
+        for note in 60..63 {
+            allocator.allocate_voice(note).unwrap();
+        }
+        assert_eq!(allocator.allocate_voice(70).unwrap().index(), 0);
+        assert_eq!(allocator.active_voice_count(), 3);
+
+        let mut empty = VoiceAllocator::with_voices(0);
+        assert_eq!(empty.allocate_voice(60), None);
+    }
+
+    #[test]
+    fn voices_tracked_per_channel() {
+        let mut allocator = VoiceAllocator::new();
+
+        let a = allocator.allocate_voice_on_channel(60, 1).unwrap();
+        let b = allocator.allocate_voice_on_channel(60, 2).unwrap();
+
+        assert_eq!(allocator.voice_for_channel(1), Some(a));
+        assert_eq!(allocator.voice_for_channel(2), Some(b));
+        assert_eq!(allocator.voice_for_channel(3), None);
+
+        // Same note on another channel is left alone
+        assert_eq!(allocator.release_voice_on_channel(60, 2), Some(b));
+        assert_eq!(allocator.voice_for_channel(1), Some(a));
+        assert_eq!(allocator.voice_for_channel(2), None);
+    }
+
+    #[test]
+    fn released_voice_reused_last() {
+        let mut allocator = VoiceAllocator::with_voices(3);
+
+        let first = allocator.allocate_voice(60).unwrap();
+        allocator.release_voice(60);
+
+        // Other free voices are used before the one still in its release tail
+        assert_ne!(allocator.allocate_voice(62).unwrap().index(), first.index());
+        assert_ne!(allocator.allocate_voice(64).unwrap().index(), first.index());
+        assert_eq!(allocator.allocate_voice(65).unwrap().index(), first.index());
+    }
+
+    #[test]
+    fn steal_order_follows_allocation_age() {
+        let mut allocator = VoiceAllocator::with_voices(3);
+        let a = allocator.allocate_voice(60).unwrap();
+        let b = allocator.allocate_voice(62).unwrap();
+        let c = allocator.allocate_voice(64).unwrap();
+
+        // Freeing the middle voice and refilling makes it the newest
+        allocator.release_voice_id(b);
+        let d = allocator.allocate_voice(66).unwrap();
+        assert_eq!(d.index(), b.index());
+
+        assert_eq!(allocator.allocate_voice(67).unwrap().index(), a.index());
+        assert_eq!(allocator.allocate_voice(68).unwrap().index(), c.index());
+        assert_eq!(allocator.allocate_voice(69).unwrap().index(), d.index());
+        assert!(allocator.voice_for_note(60).is_none());
+        assert!(allocator.voice_for_note(66).is_none());
+    }
+
+    #[test]
+    fn per_note_cap_steals_oldest_instance() {
+        let mut allocator = VoiceAllocator::new();
+        allocator.set_max_voices_per_note(Some(2));
+
+        let first = allocator.allocate_voice(60).unwrap();
+        let second = allocator.allocate_voice(60).unwrap();
+        allocator.allocate_voice(64).unwrap();
+        let third = allocator.allocate_voice(60).unwrap();
+
+        // The oldest C4 is reused rather than taking another free voice
+        assert_eq!(third.index(), first.index());
+        assert_eq!(allocator.note_voice_count(60), 2);
+        assert_eq!(allocator.active_voice_count(), 3);
+        let voices: Vec<_> = allocator.voices_for_note(60).collect();
+        assert_eq!(voices, vec![second, third]);
+    }
+
+    #[test]
+    fn event_log_reports_activity() {
+        let mut allocator = VoiceAllocator::with_voices(1);
+        allocator.enable_event_log(16);
+
+        let first = allocator.allocate_voice(60).unwrap();
+        let second = allocator.allocate_voice(62).unwrap();
+        allocator.release_voice(62);
+
+        let events: Vec<_> = allocator.drain_events().collect();
+        assert_eq!(
+            events,
+            vec![
+                VoiceEvent::Allocated {
+                    voice: first,
+                    note: 60
+                },
+                VoiceEvent::Stolen {
+                    voice: first,
+                    note: 60
+                },
+                VoiceEvent::Allocated {
+                    voice: second,
+                    note: 62
+                },
+                VoiceEvent::Released {
+                    voice: second,
+                    note: 62
+                },
+            ]
+        );
+        assert_eq!(allocator.drain_events().count(), 0);
+    }
+
+    #[test]
+    fn event_log_bounded_and_optional() {
+        let mut allocator = VoiceAllocator::new();
+        allocator.allocate_voice(60).unwrap();
+        assert_eq!(allocator.drain_events().count(), 0);
+
+        allocator.enable_event_log(2);
+        for note in 0..5 {
+            allocator.allocate_voice(note).unwrap();
+        }
+        assert_eq!(allocator.drain_events().count(), 2);
+    }
+
+    #[test]
+    fn fixed_allocator_uses_n_voices() {
+        let mut allocator = VoiceAllocator::<2>::fixed();
+        assert_eq!(allocator.voice_count(), 2);
+        let first = allocator.allocate_voice(60).unwrap();
+        let second = allocator.allocate_voice(62).unwrap();
+        assert_eq!(allocator.allocate_voice(64), Some(VoiceId(0, 2))); // Steals
+        assert!(!allocator.is_current(first));
+
+        // Freed voices are reused oldest-released first, around the ring
+        allocator.release_voice_id(second);
+        allocator.release_voice(64);
+        assert_eq!(allocator.allocate_voice(65).unwrap().index(), 1);
+        assert_eq!(allocator.allocate_voice(67).unwrap().index(), 0);
+
+        // With std the voices are on the heap and any count fits
+        #[cfg(feature = "std")]
+        assert_eq!(VoiceAllocator::with_voices(1000).voice_count(), 1000);
+
+        let mut logged = VoiceAllocator::new();
+        logged.enable_event_log(1000);
+        for _ in 0..MAX_VOICE_EVENTS {
+            logged.allocate_voice(60).unwrap();
+        }
+        assert_eq!(logged.drain_events().count(), MAX_VOICE_EVENTS);
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[test]
+    #[should_panic(expected = "65 voices exceed the capacity of 64")]
+    fn too_many_voices_panics_without_std() {
+        VoiceAllocator::with_voices(DEFAULT_VOICE_CAPACITY + 1);
+    }
+
+    #[test]
+    fn release_all_frees_everything() {
+        let mut allocator = VoiceAllocator::new();
+        for note in 60..70 {
+            allocator.allocate_voice(note).unwrap();
+        }
+
+        assert_eq!(allocator.release_all(), MAX_VOICES);
+        assert_eq!(allocator.active_voice_count(), 0);
+        assert_eq!(allocator.voice_for_note(69), None);
+        assert_eq!(allocator.release_all(), 0);
+    }
+
     #[test]
     fn active_voices_iteration() {
         let mut allocator = VoiceAllocator::new();
@@ -155,4 +805,23 @@ mod tests {
         assert!(active.contains(&64));
         assert!(active.contains(&67));
     }
+
+    #[test]
+    fn polyphony_limit_steals_the_oldest() {
+        let mut allocator = VoiceAllocator::with_voices(4);
+        allocator.set_polyphony(Some(1));
+        assert!(allocator.is_mono());
+        let first = allocator.allocate_voice(60).unwrap();
+        let second = allocator.allocate_voice(64).unwrap();
+        assert_eq!(allocator.active_voice_count(), 1);
+        assert!(!allocator.is_current(first));
+        assert_eq!(allocator.voice_for_note(64), Some(second));
+
+        // Free voices stay free while capped, and are used again after
+        allocator.set_polyphony(None);
+        allocator.allocate_voice(67).unwrap();
+        allocator.allocate_voice(69).unwrap();
+        allocator.allocate_voice(71).unwrap();
+        assert_eq!(allocator.active_voice_count(), 4);
+    }
 }
@@ -0,0 +1,353 @@
+//! Driving each voice's oscillator frequency and envelope gate from
+//! `VoiceAllocator` decisions
+//!
+//! [`VoiceDriver`] turns MIDI note and pitch bend events into per-voice
+//! commands for any [`VoiceControl`] backend: a [`VoiceMailboxWriter`] for
+//! graphs built around [`MidiVoiceSource`](crate::voice_source::MidiVoiceSource)
+//! nodes, or [`GraphVoiceControl`] to retune existing oscillator nodes
+//! through a [`ParamUpdateQueue`].
+
+use crate::conversions::{velocity_to_gain, PitchBendState, Tuning};
+use crate::mailbox::VoiceMailboxWriter;
+use crate::midi_input::MidiEvent;
+use crate::param_bridge::{NodeParam, ParamUpdateQueue};
+use crate::voice_allocator::{VoiceAllocator, VoiceId};
+use auxide::control::ControlMsg;
+use auxide::graph::NodeId;
+
+/// Receives per-voice pitch and gate commands
+pub trait VoiceControl {
+    /// Start `voice` at `freq` Hz
+    fn note_on(&mut self, voice: VoiceId, freq: f32, velocity: u8);
+
+    /// Release `voice`
+    fn note_off(&mut self, voice: VoiceId);
+
+    /// Retune a sounding voice without retriggering it
+    fn set_frequency(&mut self, voice: VoiceId, freq: f32);
+
+    /// Release every voice
+    fn all_notes_off(&mut self);
+}
+
+impl VoiceControl for VoiceMailboxWriter {
+    fn note_on(&mut self, voice: VoiceId, freq: f32, velocity: u8) {
+        VoiceMailboxWriter::note_on(self, voice, freq, velocity_to_gain(velocity));
+    }
+
+    fn note_off(&mut self, voice: VoiceId) {
+        VoiceMailboxWriter::note_off(self, voice);
+    }
+
+    fn set_frequency(&mut self, voice: VoiceId, freq: f32) {
+        VoiceMailboxWriter::set_frequency(self, voice, freq);
+    }
+
+    fn all_notes_off(&mut self) {
+        VoiceMailboxWriter::all_notes_off(self);
+    }
+}
+
+/// The graph nodes making up one voice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoiceNodes {
+    /// Receives `SetFrequency`
+    pub oscillator: NodeId,
+    /// Receives `TriggerGate` (usually the envelope)
+    pub gate: Option<NodeId>,
+    /// Receives `SetGain` from the note-on velocity times the gain scale
+    pub amp: Option<NodeId>,
+}
+
+/// Retunes and gates existing graph nodes through a [`ParamUpdateQueue`]
+pub struct GraphVoiceControl {
+    updates: ParamUpdateQueue,
+    voices: Vec<VoiceNodes>,
+    generations: Vec<Option<u32>>, // Generation currently holding each voice
+    velocity_gains: Vec<f32>,      // Note-on gain of each voice before scaling
+    gain_scale: f32,
+}
+
+impl GraphVoiceControl {
+    /// Control `voices[i]` for allocator slot `i`
+    pub fn new(updates: ParamUpdateQueue, voices: Vec<VoiceNodes>) -> Self {
+        let generations = vec![None; voices.len()];
+        let velocity_gains = vec![0.0; voices.len()];
+        Self {
+            updates,
+            voices,
+            generations,
+            velocity_gains,
+            gain_scale: 1.0,
+        }
+    }
+
+    pub fn voice_nodes(&self) -> &[VoiceNodes] {
+        &self.voices
+    }
+
+    /// The underlying queue, for other parameter updates (e.g. filter cutoff)
+    pub fn updates_mut(&mut self) -> &mut ParamUpdateQueue {
+        &mut self.updates
+    }
+
+    pub fn gain_scale(&self) -> f32 {
+        self.gain_scale
+    }
+
+    /// Scale every voice's amp gain (e.g. by expression and breath),
+    /// resending it to the voices that are sounding
+    /// Returns true if any voice was updated.
+    pub fn set_gain_scale(&mut self, scale: f32) -> bool {
+        self.gain_scale = scale;
+        let mut any = false;
+        for (index, nodes) in self.voices.iter().enumerate() {
+            if let (Some(amp), Some(_)) = (nodes.amp, self.generations[index]) {
+                let gain = self.velocity_gains[index] * scale;
+                self.updates.send_to(amp, NodeParam::Gain, gain);
+                any = true;
+            }
+        }
+        any
+    }
+
+    /// Nodes of `voice` if the handle still owns its slot
+    fn current(&self, voice: VoiceId) -> Option<VoiceNodes> {
+        let generation = *self.generations.get(voice.index())?;
+        (generation == Some(voice.generation())).then(|| self.voices[voice.index()])
+    }
+
+    fn gate(&mut self, node: Option<NodeId>, on: bool) {
+        if let Some(node) = node {
+            self.updates
+                .send_message(ControlMsg::TriggerGate { node, on });
+        }
+    }
+}
+
+impl VoiceControl for GraphVoiceControl {
+    fn note_on(&mut self, voice: VoiceId, freq: f32, velocity: u8) {
+        let Some(&nodes) = self.voices.get(voice.index()) else {
+            return;
+        };
+        self.generations[voice.index()] = Some(voice.generation());
+        self.velocity_gains[voice.index()] = velocity_to_gain(velocity);
+        self.updates
+            .send_to(nodes.oscillator, NodeParam::Frequency, freq);
+        if let Some(amp) = nodes.amp {
+            let gain = velocity_to_gain(velocity) * self.gain_scale;
+            self.updates.send_to(amp, NodeParam::Gain, gain);
+        }
+        self.gate(nodes.gate, true);
+    }
+
+    fn note_off(&mut self, voice: VoiceId) {
+        if let Some(nodes) = self.current(voice) {
+            self.generations[voice.index()] = None;
+            self.gate(nodes.gate, false);
+        }
+    }
+
+    fn set_frequency(&mut self, voice: VoiceId, freq: f32) {
+        if let Some(nodes) = self.current(voice) {
+            self.updates
+                .send_to(nodes.oscillator, NodeParam::Frequency, freq);
+        }
+    }
+
+    fn all_notes_off(&mut self) {
+        for index in 0..self.voices.len() {
+            self.generations[index] = None;
+            self.gate(self.voices[index].gate, false);
+        }
+    }
+}
+
+/// Allocates voices for incoming notes and tells a [`VoiceControl`] what
+/// each voice should play, including pitch bend and tuning
+#[derive(Debug)]
+pub struct VoiceDriver {
+    allocator: VoiceAllocator,
+    tuning: Tuning,
+    bend: PitchBendState,
+}
+
+impl VoiceDriver {
+    pub fn new(allocator: VoiceAllocator) -> Self {
+        Self {
+            allocator,
+            tuning: Tuning::concert(),
+            bend: PitchBendState::default(),
+        }
+    }
+
+    pub fn allocator(&self) -> &VoiceAllocator {
+        &self.allocator
+    }
+
+    pub fn allocator_mut(&mut self) -> &mut VoiceAllocator {
+        &mut self.allocator
+    }
+
+    pub fn tuning(&self) -> &Tuning {
+        &self.tuning
+    }
+
+    /// Change the tuning; sounding voices pick it up on the next bend or note
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.tuning = tuning;
+    }
+
+    pub fn pitch_bend(&self) -> &PitchBendState {
+        &self.bend
+    }
+
+    pub fn pitch_bend_mut(&mut self) -> &mut PitchBendState {
+        &mut self.bend
+    }
+
+    /// Frequency a voice playing `note` should sound at right now
+    pub fn note_freq(&self, note: u8) -> f32 {
+        self.tuning.note_to_freq(note) * self.bend.ratio()
+    }
+
+    /// Apply a MIDI event, returning true if it affected any voice
+    pub fn handle_event(&mut self, event: MidiEvent, control: &mut impl VoiceControl) -> bool {
+        match event {
+            event if event.is_all_notes_off() => {
+                self.allocator.release_all();
+                control.all_notes_off();
+                true
+            }
+            MidiEvent::NoteOn(note, velocity) => match self.allocator.allocate_voice(note) {
+                Some(voice) => {
+                    control.note_on(voice, self.note_freq(note), velocity);
+                    true
+                }
+                None => false,
+            },
+            MidiEvent::NoteOff(note, _) => match self.allocator.release_voice(note) {
+                Some(voice) => {
+                    control.note_off(voice);
+                    true
+                }
+                None => false,
+            },
+            MidiEvent::PitchBend(_) => {
+                self.bend.handle_event(&event);
+                self.retune(control)
+            }
+            _ => false,
+        }
+    }
+
+    /// Resend the frequency of every sounding voice
+    pub fn retune(&mut self, control: &mut impl VoiceControl) -> bool {
+        let mut any = false;
+        for (voice, note) in self.allocator.active_voices() {
+            control.set_frequency(voice, self.note_freq(note));
+            any = true;
+        }
+        any
+    }
+}
+
+impl Default for VoiceDriver {
+    fn default() -> Self {
+        Self::new(VoiceAllocator::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversions::{note_to_freq, pitch_bend_to_ratio};
+
+    #[derive(Default)]
+    struct Recorder(Vec<(&'static str, usize, f32)>);
+
+    impl VoiceControl for Recorder {
+        fn note_on(&mut self, voice: VoiceId, freq: f32, _velocity: u8) {
+            self.0.push(("on", voice.index(), freq));
+        }
+        fn note_off(&mut self, voice: VoiceId) {
+            self.0.push(("off", voice.index(), 0.0));
+        }
+        fn set_frequency(&mut self, voice: VoiceId, freq: f32) {
+            self.0.push(("freq", voice.index(), freq));
+        }
+        fn all_notes_off(&mut self) {
+            self.0.push(("all", 0, 0.0));
+        }
+    }
+
+    #[test]
+    fn driver_sends_each_voice_its_own_pitch() {
+        let mut driver = VoiceDriver::default();
+        let mut control = Recorder::default();
+        assert!(driver.handle_event(MidiEvent::NoteOn(60, 100), &mut control));
+        assert!(driver.handle_event(MidiEvent::NoteOn(64, 100), &mut control));
+        assert!(driver.handle_event(MidiEvent::PitchBend(16383), &mut control));
+        assert!(driver.handle_event(MidiEvent::NoteOff(60, 0), &mut control));
+        assert!(!driver.handle_event(MidiEvent::NoteOff(60, 0), &mut control));
+
+        let whole_tone = pitch_bend_to_ratio(16383);
+        let events = &control.0;
+        assert_eq!(events[0], ("on", 0, note_to_freq(60)));
+        assert_eq!(events[1], ("on", 1, note_to_freq(64)));
+        assert!((events[2].2 - note_to_freq(60) * whole_tone).abs() < 1e-3);
+        assert!((events[3].2 - note_to_freq(64) * whole_tone).abs() < 1e-3);
+        assert_eq!(events[4], ("off", 0, 0.0));
+    }
+
+    #[test]
+    fn graph_control_ignores_stale_voices() {
+        let (queue, mut receiver) = ParamUpdateQueue::new();
+        let nodes = VoiceNodes {
+            oscillator: NodeId(1),
+            gate: Some(NodeId(2)),
+            amp: None,
+        };
+        let mut control = GraphVoiceControl::new(queue, vec![nodes]);
+        control.note_on(VoiceId(0, 1), 440.0, 100);
+        control.note_on(VoiceId(0, 2), 220.0, 100); // Stolen
+        control.note_off(VoiceId(0, 1));
+        control.set_frequency(VoiceId(0, 1), 880.0);
+
+        let mut applied = Vec::new();
+        receiver.drain(&mut |msg: ControlMsg| applied.push(msg));
+        assert_eq!(applied.len(), 4);
+        assert!(matches!(
+            applied[2],
+            ControlMsg::SetFrequency { node: NodeId(1), hz } if hz == 220.0
+        ));
+        assert!(matches!(
+            applied[3],
+            ControlMsg::TriggerGate { on: true, .. }
+        ));
+    }
+
+    #[test]
+    fn gain_scale_reaches_sounding_amps() {
+        let (queue, mut receiver) = ParamUpdateQueue::new();
+        let nodes = |base| VoiceNodes {
+            oscillator: NodeId(base),
+            gate: None,
+            amp: Some(NodeId(base + 1)),
+        };
+        let mut control = GraphVoiceControl::new(queue, vec![nodes(1), nodes(3)]);
+        control.note_on(VoiceId(0, 1), 440.0, 127);
+        assert!(control.set_gain_scale(0.5));
+        control.note_on(VoiceId(1, 1), 220.0, 127);
+
+        let mut gains = Vec::new();
+        receiver.drain(&mut |msg: ControlMsg| {
+            if let ControlMsg::SetGain { node, gain } = msg {
+                gains.push((node, gain));
+            }
+        });
+        assert_eq!(gains.len(), 3);
+        assert!((gains[1].1 - 0.5 * gains[0].1).abs() < 1e-6);
+        assert_eq!(gains[2], (NodeId(4), gains[1].1));
+    }
+}
@@ -0,0 +1,261 @@
+//! Voice subgraph templates: describe one voice's node chain once and build
+//! N copies, their mixing tree and parameter bindings
+//!
+//! ```rust
+//! use auxide::graph::NodeType;
+//! use auxide_midi::{VoiceGraphBuilder, VoiceRole};
+//!
+//! let voices = VoiceGraphBuilder::new(8)
+//!     .stage(VoiceRole::Oscillator, |graph| {
+//!         graph.add_node(NodeType::SineOsc { freq: 440.0 })
+//!     })
+//!     .stage(VoiceRole::Amp, |graph| graph.add_node(NodeType::Gain { gain: 0.0 }))
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(voices.voice_nodes().len(), 8);
+//! ```
+
+use crate::cc_mapping::ParamTarget;
+use crate::param_bridge::{NodeParam, ParamScale, ParamUpdateQueue};
+use crate::voice_control::VoiceNodes;
+use auxide::graph::{Edge, Graph, GraphError, NodeId, NodeType, PortId, Rate};
+
+/// What a stage of the voice chain does, so per-voice control knows where
+/// to send pitch, gate and velocity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceRole {
+    /// Receives note frequency
+    Oscillator,
+    Filter,
+    /// Receives the gate
+    Envelope,
+    /// Receives velocity gain
+    Amp,
+    /// Anything else (waveshapers, effects)
+    Other,
+}
+
+type StageFn<'a> = Box<dyn Fn(&mut Graph) -> NodeId + 'a>;
+
+/// Parameter binding applied to a stage in every voice
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct StageBinding {
+    stage: usize,
+    target: ParamTarget,
+    param: NodeParam,
+    scale: ParamScale,
+}
+
+/// Describes one voice as a chain of stages, each fed from the previous
+/// stage's first output into its first input
+pub struct VoiceGraphBuilder<'a> {
+    voice_count: usize,
+    stages: Vec<(VoiceRole, StageFn<'a>)>,
+    bindings: Vec<StageBinding>,
+}
+
+/// The built graph and the nodes of every voice
+#[derive(Debug)]
+pub struct VoiceGraph {
+    graph: Graph,
+    stages: Vec<Vec<NodeId>>, // [voice][stage]
+    roles: Vec<VoiceRole>,
+    bindings: Vec<StageBinding>,
+    output: NodeId,
+}
+
+impl<'a> VoiceGraphBuilder<'a> {
+    pub fn new(voice_count: usize) -> Self {
+        Self {
+            voice_count: voice_count.max(1),
+            stages: Vec::new(),
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Append a stage; `add` is called once per voice to create its node
+    pub fn stage(mut self, role: VoiceRole, add: impl Fn(&mut Graph) -> NodeId + 'a) -> Self {
+        self.stages.push((role, Box::new(add)));
+        self
+    }
+
+    /// Drive `param` of the most recently added stage from `target` in
+    /// every voice
+    pub fn bind(mut self, target: ParamTarget, param: NodeParam, scale: ParamScale) -> Self {
+        if let Some(stage) = self.stages.len().checked_sub(1) {
+            self.bindings.push(StageBinding {
+                stage,
+                target,
+                param,
+                scale,
+            });
+        }
+        self
+    }
+
+    /// Build every voice, sum them through a tree of two-input mixers and
+    /// terminate the graph in an output sink
+    pub fn build(self) -> Result<VoiceGraph, GraphError> {
+        let mut graph = Graph::new();
+        let mut stages = Vec::with_capacity(self.voice_count);
+        let mut outputs = Vec::with_capacity(self.voice_count);
+        for _ in 0..self.voice_count {
+            let nodes: Vec<NodeId> = self.stages.iter().map(|(_, add)| add(&mut graph)).collect();
+            for pair in nodes.windows(2) {
+                connect(&mut graph, pair[0], pair[1], PortId(0))?;
+            }
+            outputs.extend(nodes.last().copied());
+            stages.push(nodes);
+        }
+
+        // Mix pairwise until one signal is left; an odd one out moves up a level
+        while outputs.len() > 1 {
+            let mut mixed = Vec::with_capacity(outputs.len().div_ceil(2));
+            for pair in outputs.chunks(2) {
+                if let [left, right] = *pair {
+                    let mix = graph.add_node(NodeType::Mix);
+                    connect(&mut graph, left, mix, PortId(0))?;
+                    connect(&mut graph, right, mix, PortId(1))?;
+                    mixed.push(mix);
+                } else {
+                    mixed.push(pair[0]);
+                }
+            }
+            outputs = mixed;
+        }
+
+        let output = graph.add_node(NodeType::OutputSink);
+        if let Some(&last) = outputs.first() {
+            connect(&mut graph, last, output, PortId(0))?;
+        }
+        Ok(VoiceGraph {
+            graph,
+            stages,
+            roles: self.stages.iter().map(|(role, _)| *role).collect(),
+            bindings: self.bindings,
+            output,
+        })
+    }
+}
+
+impl VoiceGraph {
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    /// Take the graph for `Plan::compile` and `Runtime::new`
+    pub fn into_graph(self) -> Graph {
+        self.graph
+    }
+
+    pub fn output(&self) -> NodeId {
+        self.output
+    }
+
+    pub fn voice_count(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Node of `role` in every voice (the first stage with that role)
+    pub fn nodes_with_role(&self, role: VoiceRole) -> Vec<NodeId> {
+        match self.roles.iter().position(|r| *r == role) {
+            Some(stage) => self.stages.iter().map(|nodes| nodes[stage]).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Oscillator, envelope and amp of every voice, for `GraphVoiceControl`
+    /// Voices without an oscillator stage are skipped.
+    pub fn voice_nodes(&self) -> Vec<VoiceNodes> {
+        let stage = |role| self.roles.iter().position(|r| *r == role);
+        let (osc, env, amp) = (
+            stage(VoiceRole::Oscillator),
+            stage(VoiceRole::Envelope),
+            stage(VoiceRole::Amp),
+        );
+        self.stages
+            .iter()
+            .filter_map(|nodes| {
+                Some(VoiceNodes {
+                    oscillator: nodes[osc?],
+                    gate: env.map(|stage| nodes[stage]),
+                    amp: amp.map(|stage| nodes[stage]),
+                })
+            })
+            .collect()
+    }
+
+    /// Register the template's parameter bindings for every voice
+    pub fn bind_params(&self, queue: &mut ParamUpdateQueue) {
+        for binding in &self.bindings {
+            for nodes in &self.stages {
+                queue.bind(
+                    binding.target,
+                    nodes[binding.stage],
+                    binding.param,
+                    binding.scale,
+                );
+            }
+        }
+    }
+}
+
+fn connect(graph: &mut Graph, from: NodeId, to: NodeId, to_port: PortId) -> Result<(), GraphError> {
+    graph.add_edge(Edge {
+        from_node: from,
+        from_port: PortId(0),
+        to_node: to,
+        to_port,
+        rate: Rate::Audio,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auxide::plan::Plan;
+    use auxide::rt::Runtime;
+
+    fn sine_voices(voice_count: usize) -> VoiceGraph {
+        VoiceGraphBuilder::new(voice_count)
+            .stage(VoiceRole::Oscillator, |graph| {
+                graph.add_node(NodeType::SineOsc { freq: 440.0 })
+            })
+            .stage(VoiceRole::Amp, |graph| {
+                graph.add_node(NodeType::Gain { gain: 0.5 })
+            })
+            .bind(ParamTarget::Volume, NodeParam::Gain, ParamScale::Raw)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn builds_a_playable_graph_for_any_voice_count() {
+        for voice_count in [1, 3, 8] {
+            let voices = sine_voices(voice_count);
+            assert_eq!(voices.voice_count(), voice_count);
+            assert_eq!(voices.nodes_with_role(VoiceRole::Amp).len(), voice_count);
+            assert!(voices.nodes_with_role(VoiceRole::Filter).is_empty());
+
+            let plan = Plan::compile(voices.graph(), 64).unwrap();
+            let mut runtime = Runtime::new(plan, voices.graph(), 48000.0);
+            let mut out = [0.0; 64];
+            runtime.process_block(&mut out).unwrap();
+            assert!(out.iter().any(|sample| *sample != 0.0));
+        }
+    }
+
+    #[test]
+    fn voice_nodes_and_bindings_cover_every_voice() {
+        let voices = sine_voices(4);
+        let nodes = voices.voice_nodes();
+        assert_eq!(nodes.len(), 4);
+        assert!(nodes
+            .iter()
+            .all(|voice| voice.gate.is_none() && voice.amp.is_some()));
+
+        let (mut queue, _receiver) = ParamUpdateQueue::new();
+        voices.bind_params(&mut queue);
+        assert_eq!(queue.bindings(ParamTarget::Volume).count(), 4);
+    }
+}
@@ -0,0 +1,156 @@
+//! `MidiVoiceSource`: an auxide node emitting one voice's pitch and gate
+//!
+//! The MIDI thread writes note decisions into a [`MidiVoiceSender`]; each
+//! voice's [`MidiVoiceSource`] node reads them lock-free through per-voice [mailboxes](crate::mailbox) at the start of every
+//! block and outputs them as control signals, so oscillators and envelopes
+//! downstream follow the allocator without rebuilding the graph.
+
+use crate::mailbox::{voice_mailboxes, VoiceMailboxReader, VoiceMailboxWriter, VoiceParams};
+use auxide::graph::{Port, PortId, Rate};
+use auxide::node::NodeDef;
+
+/// Output port carrying the voice frequency in Hz
+pub const VOICE_FREQ_PORT: PortId = PortId(0);
+
+/// Output port carrying the gate, 1.0 while the note is held
+pub const VOICE_GATE_PORT: PortId = PortId(1);
+
+static OUTPUT_PORTS: [Port; 2] = [
+    Port {
+        id: VOICE_FREQ_PORT,
+        rate: Rate::Control,
+    },
+    Port {
+        id: VOICE_GATE_PORT,
+        rate: Rate::Control,
+    },
+];
+
+/// MIDI-side writer for a set of [`MidiVoiceSource`] nodes
+pub type MidiVoiceSender = VoiceMailboxWriter;
+
+/// Source node for one voice: port 0 is frequency in Hz, port 1 is the gate
+///
+/// A note-on that lands on an already open gate (a steal or legato retrigger)
+/// drops the gate for the first sample of the block so envelopes restart.
+#[derive(Debug, Clone)]
+pub struct MidiVoiceSource {
+    voice: usize,
+    mailboxes: VoiceMailboxReader,
+}
+
+/// Create a sender and one source node per voice
+pub fn midi_voice_sources(voice_count: usize) -> (MidiVoiceSender, Vec<MidiVoiceSource>) {
+    let (sender, reader) = voice_mailboxes(voice_count);
+    let sources = (0..voice_count)
+        .map(|voice| MidiVoiceSource {
+            voice,
+            mailboxes: reader.clone(),
+        })
+        .collect();
+    (sender, sources)
+}
+
+impl MidiVoiceSource {
+    /// Slot index of the voice this node follows
+    pub fn voice(&self) -> usize {
+        self.voice
+    }
+
+    fn params(&self) -> VoiceParams {
+        self.mailboxes.read(self.voice)
+    }
+}
+
+impl NodeDef for MidiVoiceSource {
+    /// Trigger count and gate seen at the last block
+    type State = (u32, bool);
+
+    fn input_ports(&self) -> &'static [Port] {
+        &[]
+    }
+
+    fn output_ports(&self) -> &'static [Port] {
+        &OUTPUT_PORTS
+    }
+
+    fn required_inputs(&self) -> usize {
+        0
+    }
+
+    fn init_state(&self, _sample_rate: f32, _block_size: usize) -> Self::State {
+        (self.params().triggers, false)
+    }
+
+    fn process_block(
+        &self,
+        state: &mut Self::State,
+        _inputs: &[&[f32]],
+        outputs: &mut [Vec<f32>],
+        _sample_rate: f32,
+    ) -> Result<(), &'static str> {
+        let VoiceParams {
+            freq,
+            gate,
+            triggers,
+            ..
+        } = self.params();
+        let (last_triggers, last_gate) = *state;
+        // A new note on a gate that never closed needs an edge of its own
+        let retrigger = gate && last_gate && triggers != last_triggers;
+        *state = (triggers, gate);
+
+        let [freq_out, gate_out, ..] = outputs else {
+            return Err("MidiVoiceSource needs frequency and gate outputs");
+        };
+        freq_out.fill(freq);
+        gate_out.fill(if gate { 1.0 } else { 0.0 });
+        if retrigger {
+            if let Some(first) = gate_out.first_mut() {
+                *first = 0.0;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voice_allocator::VoiceId;
+
+    fn run(source: &MidiVoiceSource, state: &mut (u32, bool)) -> (Vec<f32>, Vec<f32>) {
+        let mut outputs = vec![vec![0.0; 4], vec![0.0; 4]];
+        source
+            .process_block(state, &[], &mut outputs, 48000.0)
+            .unwrap();
+        let gate = outputs.pop().unwrap();
+        (outputs.pop().unwrap(), gate)
+    }
+
+    #[test]
+    fn source_follows_note_on_and_off() {
+        let (sender, sources) = midi_voice_sources(2);
+        let source = &sources[1];
+        let mut state = source.init_state(48000.0, 4);
+
+        sender.note_on(VoiceId(1, 1), 220.0, 1.0);
+        let (freq, gate) = run(source, &mut state);
+        assert_eq!(freq, [220.0; 4]);
+        assert_eq!(gate, [1.0; 4]);
+
+        sender.set_frequency(VoiceId(1, 1), 233.0);
+        assert_eq!(run(source, &mut state).0, [233.0; 4]);
+
+        // A stale handle from before a steal leaves the new note alone
+        sender.note_on(VoiceId(1, 2), 330.0, 1.0);
+        sender.note_off(VoiceId(1, 1));
+        let (freq, gate) = run(source, &mut state);
+        assert_eq!(freq, [330.0; 4]);
+        assert_eq!(gate, [0.0, 1.0, 1.0, 1.0]); // Retrigger edge
+
+        sender.note_off(VoiceId(1, 2));
+        assert_eq!(run(source, &mut state).1, [0.0; 4]);
+        assert_eq!(run(&sources[0], &mut (0, false)).1, [0.0; 4]);
+    }
+}
@@ -1,6 +1,29 @@
 //! Voice state for polyphonic synthesis
 
+use crate::cc_mapping::ParamTarget;
+use crate::conversions::pitch_bend_to_ratio_with_range;
+use crate::drift::VoiceDrift;
+use crate::lfo::LfoShape;
+use crate::mpe::{MpeMessage, MpeZoneConfig};
+use crate::voice_allocator::{VoiceAllocator, VoiceId, MAX_VOICES};
+
+/// Release velocity assumed when a Note Off carries none (MIDI 1.0 default)
+pub const DEFAULT_RELEASE_VELOCITY: u8 = 64;
+
+/// Per-note timbre (CC74) before the controller sends any, as MPE specifies
+pub const DEFAULT_TIMBRE: f32 = 0.5;
+
+/// Envelope level at or below which a releasing voice counts as finished
+pub const ENV_SILENCE_THRESHOLD: f32 = 1e-4;
+
+/// Time for a voice's tracked output level to fall by 1/e after a peak
+pub const LEVEL_RELEASE_SECONDS: f32 = 0.1;
+
+/// Default block size the pool preallocates its render scratch buffer for
+pub const DEFAULT_MAX_BLOCK_SIZE: usize = 1024;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum EnvStage {
     Idle,
     Attack,
@@ -10,6 +33,7 @@ pub enum EnvStage {
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VoiceState {
     pub osc_phase: f32,
     pub filter_z1: f32,
@@ -18,7 +42,20 @@ pub struct VoiceState {
     pub env_level: f32,
     pub note: u8,
     pub velocity: u8,
+    pub release_velocity: u8,
+    pub channel: u8,
     pub active: bool,
+    pub pitch: f32,           // Current pitch as a fractional MIDI note
+    pub target_pitch: f32,    // Pitch the glide is heading to
+    pub glide_remaining: u32, // Samples until the glide arrives
+    pub bend_ratio: f32,      // Per-note pitch bend as a frequency ratio
+    pub pressure: f32,        // Per-note pressure, 0.0-1.0
+    pub timbre: f32,          // Per-note timbre (CC74), 0.0-1.0
+    pub level: f32,           // Peak output level with decay, for stealing and meters
+    pub lfo_phase: f32,       // 0.0-1.0, restarted on trigger
+    pub lfo_rate: f32,        // Hz
+    pub lfo_depth: f32,       // 0.0 disables the LFO
+    pub lfo_shape: LfoShape,
 }
 
 impl VoiceState {
@@ -31,7 +68,20 @@ impl VoiceState {
             env_level: 0.0,
             note: 0,
             velocity: 0,
+            release_velocity: DEFAULT_RELEASE_VELOCITY,
+            channel: 0,
             active: false,
+            pitch: 0.0,
+            target_pitch: 0.0,
+            glide_remaining: 0,
+            bend_ratio: 1.0,
+            pressure: 0.0,
+            timbre: DEFAULT_TIMBRE,
+            level: 0.0,
+            lfo_phase: 0.0,
+            lfo_rate: 0.0,
+            lfo_depth: 0.0,
+            lfo_shape: LfoShape::Sine,
         }
     }
 
@@ -42,6 +92,7 @@ impl VoiceState {
         self.env_stage = EnvStage::Idle;
         self.env_level = 0.0;
         self.active = false;
+        self.level = 0.0;
     }
 
     pub fn trigger(&mut self, note: u8, velocity: u8) {
@@ -50,11 +101,115 @@ impl VoiceState {
         self.env_stage = EnvStage::Attack;
         self.env_level = 0.0;
         self.active = true;
+        self.pitch = note as f32;
+        self.target_pitch = note as f32;
+        self.glide_remaining = 0;
+        self.bend_ratio = 1.0;
+        self.pressure = 0.0;
+        self.timbre = DEFAULT_TIMBRE;
+        self.level = 0.0;
+        self.lfo_phase = 0.0;
+    }
+
+    /// Configure the per-voice LFO (depth 0.0 turns it off)
+    pub fn set_lfo(&mut self, rate_hz: f32, depth: f32, shape: LfoShape) {
+        self.lfo_rate = rate_hz;
+        self.lfo_depth = depth;
+        self.lfo_shape = shape;
+    }
+
+    pub fn lfo_enabled(&self) -> bool {
+        self.lfo_depth != 0.0
+    }
+
+    /// Get the LFO's next value (bipolar, scaled by depth) and advance its
+    /// phase by one sample; call once per block with `sample_rate / block_len`
+    /// for control-rate modulation
+    pub fn lfo_next(&mut self, sample_rate: f32) -> f32 {
+        if !self.lfo_enabled() {
+            return 0.0;
+        }
+        let value = self.lfo_shape.value(self.lfo_phase) * self.lfo_depth;
+        self.lfo_phase = (self.lfo_phase + self.lfo_rate / sample_rate).rem_euclid(1.0);
+        value
+    }
+
+    /// Check whether the voice has fallen silent: its renderer marked it
+    /// inactive, or its release stage has decayed to silence
+    pub fn is_finished(&self) -> bool {
+        !self.active
+            || (self.env_stage == EnvStage::Release && self.env_level <= ENV_SILENCE_THRESHOLD)
+    }
+
+    /// Update the running peak level from a block of this voice's output
+    pub fn track_level(&mut self, block: &[f32], sample_rate: f32) {
+        let peak = block
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        let decay = (-(block.len() as f32) / (LEVEL_RELEASE_SECONDS * sample_rate)).exp();
+        self.level = peak.max(self.level * decay);
+    }
+
+    /// Set the per-note pitch bend from a 14-bit bend value and the bend
+    /// range in semitones for the note's channel
+    pub fn set_pitch_bend(&mut self, bend: i16, range_semitones: f32) {
+        self.bend_ratio = pitch_bend_to_ratio_with_range(bend, range_semitones);
+    }
+
+    /// Set the per-note pressure from a 7-bit value
+    pub fn set_pressure(&mut self, value: u8) {
+        self.pressure = value.min(127) as f32 / 127.0;
+    }
+
+    /// Set the per-note timbre from a 7-bit CC74 value
+    pub fn set_timbre(&mut self, value: u8) {
+        self.timbre = value.min(127) as f32 / 127.0;
+    }
+
+    /// Glide from the current pitch to `note` over `glide_samples` samples
+    /// (linear in pitch, so exponential in frequency) without
+    /// retriggering the envelope. Zero samples jumps immediately.
+    pub fn glide_to(&mut self, note: u8, glide_samples: u32) {
+        self.note = note;
+        self.target_pitch = note as f32;
+        self.glide_remaining = glide_samples;
+        if glide_samples == 0 {
+            self.pitch = self.target_pitch;
+        }
+    }
+
+    /// Check whether a glide is still in progress
+    pub fn is_gliding(&self) -> bool {
+        self.glide_remaining > 0
+    }
+
+    /// Advance the glide by `samples` (1 per sample, or the block length per
+    /// block) and return the current pitch
+    pub fn advance_glide(&mut self, samples: u32) -> f32 {
+        if self.glide_remaining == 0 {
+            return self.pitch;
+        }
+        if samples >= self.glide_remaining {
+            self.pitch = self.target_pitch; // Exact arrival
+            self.glide_remaining = 0;
+        } else {
+            let step = (self.target_pitch - self.pitch) / self.glide_remaining as f32;
+            self.pitch += step * samples as f32;
+            self.glide_remaining -= samples;
+        }
+        self.pitch
     }
 
     pub fn release(&mut self) {
+        self.release_with_velocity(DEFAULT_RELEASE_VELOCITY);
+    }
+
+    /// Enter the release stage, recording the Note Off velocity so patches
+    /// can shorten or lengthen the release tail
+    pub fn release_with_velocity(&mut self, velocity: u8) {
         if self.active {
             self.env_stage = EnvStage::Release;
+            self.release_velocity = velocity;
         }
     }
 }
@@ -65,17 +220,256 @@ impl Default for VoiceState {
     }
 }
 
-pub struct VoicePool {
-    voices: [VoiceState; 8],
+/// Per-voice sound generator, stored as a `VoicePool`'s user data
+pub trait VoiceRenderer {
+    /// Render the next `out.len()` samples of `voice`, adding (mixing) them
+    /// into `out` rather than overwriting it. Called only for active voices;
+    /// set `voice.active = false` once the voice falls silent.
+    fn render(&mut self, voice: &mut VoiceState, out: &mut [f32], sample_rate: f32);
+
+    /// Receive the voice's analog drift offset in cents before each block
+    /// when drift is enabled on the pool
+    fn set_drift(&mut self, _cents: f32) {}
+
+    /// Receive a smoothed engine parameter (e.g. filter cutoff from the mod
+    /// wheel) before the block it applies to
+    fn set_param(&mut self, _target: ParamTarget, _value: f32) {}
+
+    /// Receive the sustain pedal depth (0.0 up to 1.0 fully down) when the
+    /// voice starts and whenever the pedal moves; piano-style patches can
+    /// scale their release by it for half-pedaling
+    fn set_sustain(&mut self, _depth: f32) {}
+}
+
+/// Fixed set of voices, each with an optional application-defined user data
+/// slot (`U`) for per-voice DSP state such as custom oscillators
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VoicePool<U = ()> {
+    voices: Vec<VoiceState>, // Preallocated at construction for RT-safety
+    generations: Vec<u32>,
+    user_data: Vec<U>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "default_scratch"))]
+    scratch: Vec<f32>, // Per-voice render buffer, for level tracking
+    drift: Vec<VoiceDrift>,
+    drift_amount: Option<(f32, f32)>, // (cents, rate Hz) when enabled
+    muted: Vec<bool>,
+    solo: Option<usize>,
 }
 
 impl VoicePool {
     pub fn new() -> Self {
+        Self::with_user_data()
+    }
+
+    /// Create a pool with a fixed number of voices
+    /// Match the allocator's voice count so every handle has a voice
+    pub fn with_voices(voice_count: usize) -> Self {
+        Self::with_user_data_voices(voice_count)
+    }
+}
+
+impl<U: Default> VoicePool<U> {
+    /// Create a pool whose voices each carry a default `U`
+    /// User data is not touched when voices are triggered or reset
+    pub fn with_user_data() -> Self {
+        Self::with_user_data_voices(MAX_VOICES)
+    }
+
+    /// Create a pool of `voice_count` voices, each carrying a default `U`
+    pub fn with_user_data_voices(voice_count: usize) -> Self {
         Self {
-            voices: [VoiceState::new(); 8],
+            voices: vec![VoiceState::new(); voice_count],
+            generations: vec![0; voice_count],
+            user_data: (0..voice_count).map(|_| U::default()).collect(),
+            scratch: vec![0.0; DEFAULT_MAX_BLOCK_SIZE],
+            drift: vec![VoiceDrift::disabled(); voice_count],
+            drift_amount: None,
+            muted: vec![false; voice_count],
+            solo: None,
+        }
+    }
+}
+
+impl<U> VoicePool<U> {
+    /// Trigger the voice behind an allocator handle, adopting its generation
+    pub fn trigger_voice(&mut self, voice_id: VoiceId, note: u8, velocity: u8) {
+        self.trigger_voice_on_channel(voice_id, 0, note, velocity);
+    }
+
+    /// Trigger a voice and record the channel its note arrived on
+    pub fn trigger_voice_on_channel(
+        &mut self,
+        voice_id: VoiceId,
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    ) {
+        self.generations[voice_id.0] = voice_id.1;
+        let voice = &mut self.voices[voice_id.0];
+        voice.trigger(note, velocity);
+        voice.channel = channel;
+        if let Some((cents, rate)) = self.drift_amount {
+            self.drift[voice_id.0] = VoiceDrift::for_voice(voice_id, cents, rate);
+        }
+    }
+
+    /// Mute a voice slot: it keeps running but is left out of the mix and
+    /// reports zero level. Returns false if the handle is stale
+    pub fn mute_voice(&mut self, voice_id: VoiceId) -> bool {
+        self.set_muted(voice_id, true)
+    }
+
+    pub fn unmute_voice(&mut self, voice_id: VoiceId) -> bool {
+        self.set_muted(voice_id, false)
+    }
+
+    /// Mix only this voice slot until `clear_solo`
+    /// Returns false if the handle is stale
+    pub fn solo_voice(&mut self, voice_id: VoiceId) -> bool {
+        if self.get_voice_checked(voice_id).is_none() {
+            return false;
+        }
+        self.solo = Some(voice_id.0);
+        true
+    }
+
+    pub fn clear_solo(&mut self) {
+        self.solo = None;
+    }
+
+    /// Check whether a voice slot is heard, given mute and solo
+    pub fn is_audible(&self, index: usize) -> bool {
+        let soloed_out = self.solo.is_some_and(|solo| solo != index);
+        !soloed_out && !self.muted.get(index).copied().unwrap_or(true)
+    }
+
+    fn set_muted(&mut self, voice_id: VoiceId, muted: bool) -> bool {
+        if self.get_voice_checked(voice_id).is_none() {
+            return false;
+        }
+        self.muted[voice_id.0] = muted;
+        true
+    }
+
+    /// Give every newly triggered voice its own analog drift of up to
+    /// `amount_cents`, wandering at `rate_hz` (None disables drift)
+    /// Drift is seeded from the voice handle, so it is reproducible
+    pub fn set_drift(&mut self, drift: Option<(f32, f32)>) {
+        self.drift_amount = drift;
+        if drift.is_none() {
+            self.drift.fill(VoiceDrift::disabled());
+        }
+    }
+
+    /// Current drift of a voice in cents, or None if the handle is stale
+    pub fn drift_cents(&self, voice_id: VoiceId) -> Option<f32> {
+        self.get_voice_checked(voice_id)?;
+        Some(self.drift[voice_id.0].cents())
+    }
+
+    /// Advance every active voice's drift by `samples`
+    /// `render` does this automatically
+    pub fn advance_drift(&mut self, samples: u32, sample_rate: f32) {
+        if self.drift_amount.is_none() {
+            return;
+        }
+        for (voice, drift) in self.voices.iter().zip(self.drift.iter_mut()) {
+            if voice.active {
+                drift.advance(samples, sample_rate);
+            }
+        }
+    }
+
+    /// Release the voice behind an allocator handle with the Note Off velocity
+    /// Returns false (and does nothing) if the handle is stale
+    pub fn release_voice(&mut self, voice_id: VoiceId, velocity: u8) -> bool {
+        match self.get_voice_checked_mut(voice_id) {
+            Some(voice) => {
+                voice.release_with_velocity(velocity);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply a routed MPE message to the voice it targets, using the zone
+    /// layout for per-channel bend ranges
+    /// Returns false if the message's voice handle is stale
+    pub fn apply_mpe_message(&mut self, message: &MpeMessage, zones: &MpeZoneConfig) -> bool {
+        match *message {
+            MpeMessage::NoteOn {
+                voice,
+                channel,
+                note,
+                velocity,
+            } => {
+                self.trigger_voice_on_channel(voice, channel, note, velocity);
+                true
+            }
+            MpeMessage::NoteOff {
+                voice, velocity, ..
+            } => self.release_voice(voice, velocity),
+            MpeMessage::PitchBend { voice, bend } => match self.get_voice_checked_mut(voice) {
+                Some(state) => {
+                    let range = zones.bend_range_for_channel(state.channel);
+                    state.set_pitch_bend(bend, range);
+                    true
+                }
+                None => false,
+            },
+            MpeMessage::Pressure { voice, value } => match self.get_voice_checked_mut(voice) {
+                Some(state) => {
+                    state.set_pressure(value);
+                    true
+                }
+                None => false,
+            },
+            MpeMessage::Timbre { voice, value } => match self.get_voice_checked_mut(voice) {
+                Some(state) => {
+                    state.set_timbre(value);
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    /// Get a voice by handle, or None if the handle is stale
+    pub fn get_voice_checked(&self, voice_id: VoiceId) -> Option<&VoiceState> {
+        if self.generations.get(voice_id.0) == Some(&voice_id.1) {
+            Some(&self.voices[voice_id.0])
+        } else {
+            None
+        }
+    }
+
+    /// Get a mutable voice by handle, or None if the handle is stale
+    pub fn get_voice_checked_mut(&mut self, voice_id: VoiceId) -> Option<&mut VoiceState> {
+        if self.generations.get(voice_id.0) == Some(&voice_id.1) {
+            Some(&mut self.voices[voice_id.0])
+        } else {
+            None
         }
     }
 
+    /// Get a voice's user data by handle, or None if the handle is stale
+    pub fn user_data(&self, voice_id: VoiceId) -> Option<&U> {
+        self.get_voice_checked(voice_id)?;
+        Some(&self.user_data[voice_id.0])
+    }
+
+    /// Get a voice's user data mutably by handle, or None if the handle is stale
+    pub fn user_data_mut(&mut self, voice_id: VoiceId) -> Option<&mut U> {
+        self.get_voice_checked(voice_id)?;
+        Some(&mut self.user_data[voice_id.0])
+    }
+
+    /// Iterate voices alongside their user data, for per-block processing
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&mut VoiceState, &mut U)> {
+        self.voices.iter_mut().zip(self.user_data.iter_mut())
+    }
+
     pub fn get_voice(&self, voice_id: usize) -> &VoiceState {
         &self.voices[voice_id]
     }
@@ -84,22 +478,102 @@ impl VoicePool {
         &mut self.voices[voice_id]
     }
 
-    pub fn voices(&self) -> &[VoiceState; 8] {
+    pub fn voices(&self) -> &[VoiceState] {
         &self.voices
     }
 
-    pub fn voices_mut(&mut self) -> &mut [VoiceState; 8] {
+    pub fn voices_mut(&mut self) -> &mut [VoiceState] {
         &mut self.voices
     }
 
+    pub fn voice_count(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// Preallocate rendering for blocks of up to `max_block_size` samples
+    /// Larger blocks still render, but allocate on the audio thread
+    pub fn set_max_block_size(&mut self, max_block_size: usize) {
+        self.scratch.resize(max_block_size, 0.0);
+    }
+
     pub fn active_voice_count(&self) -> usize {
         self.voices.iter().filter(|v| v.active).count()
     }
+
+    /// Sweep for voices whose sound has finished, resetting them here and
+    /// freeing them in `allocator` if it still holds them (e.g. a percussive
+    /// voice that decayed while its key was held). Call once per block.
+    /// Returns the number of voices freed
+    pub fn collect_finished(&mut self, allocator: &mut VoiceAllocator) -> usize {
+        let mut freed = 0;
+        for (idx, voice) in self.voices.iter_mut().enumerate() {
+            let voice_id = VoiceId(idx, self.generations[idx]);
+            let held = allocator.is_current(voice_id);
+            if !voice.is_finished() || !(voice.active || held) {
+                continue;
+            }
+            voice.reset();
+            if held {
+                allocator.release_voice_id(voice_id);
+            }
+            freed += 1;
+        }
+        freed
+    }
+
+    /// Hard reset every voice, cutting release tails (panic / All Sound Off)
+    pub fn kill_all(&mut self) {
+        for voice in &mut self.voices {
+            voice.reset();
+        }
+    }
+}
+
+impl<U: VoiceRenderer> VoicePool<U> {
+    /// Render all active voices and sum the audible ones into `out`, replacing
+    /// its contents. Each voice's `level` is updated from its own output
+    pub fn render(&mut self, out: &mut [f32], sample_rate: f32) {
+        if self.scratch.len() < out.len() {
+            self.scratch.resize(out.len(), 0.0);
+        }
+        out.fill(0.0);
+        self.advance_drift(out.len() as u32, sample_rate);
+        let scratch = &mut self.scratch[..out.len()];
+        let voices = self.voices.iter_mut().zip(self.user_data.iter_mut());
+        for (index, ((voice, renderer), drift)) in voices.zip(self.drift.iter()).enumerate() {
+            if !voice.active {
+                voice.level = 0.0;
+                continue;
+            }
+            if self.drift_amount.is_some() {
+                renderer.set_drift(drift.cents());
+            }
+            scratch.fill(0.0);
+            renderer.render(voice, scratch, sample_rate);
+
+            // Muted and soloed-out voices keep running so their envelopes
+            // finish, but are bypassed in the mix and the level meters
+            let soloed_out = self.solo.is_some_and(|solo| solo != index);
+            if soloed_out || self.muted[index] {
+                voice.level = 0.0;
+                continue;
+            }
+            voice.track_level(scratch, sample_rate);
+            for (out, sample) in out.iter_mut().zip(scratch.iter()) {
+                *out += *sample;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn default_scratch() -> Vec<f32> {
+    vec![0.0; DEFAULT_MAX_BLOCK_SIZE]
 }
 
-impl Default for VoicePool {
+impl<U: Default> Default for VoicePool<U> {
     fn default() -> Self {
-        Self::new()
+        Self::with_user_data()
     }
 }
 
@@ -119,6 +593,21 @@ mod tests {
         assert_eq!(pool.voices().len(), 8);
     }
 
+    #[test]
+    fn pool_size_matches_allocator() {
+        use crate::voice_allocator::VoiceAllocator;
+
+        let mut allocator = VoiceAllocator::with_voices(32);
+        let mut pool = VoicePool::with_voices(allocator.voice_count());
+        assert_eq!(pool.voice_count(), 32);
+
+        for note in 0..32 {
+            let voice_id = allocator.allocate_voice(note).unwrap();
+            pool.trigger_voice(voice_id, note, 100);
+        }
+        assert_eq!(pool.active_voice_count(), 32);
+    }
+
     #[test]
     fn voice_trigger_sets_active() {
         let mut voice = VoiceState::new();
@@ -140,6 +629,323 @@ mod tests {
         assert!(voice.active); // Still active until envelope finishes
     }
 
+    #[test]
+    fn release_velocity_recorded() {
+        let mut voice = VoiceState::new();
+        voice.trigger(60, 100);
+        voice.release_with_velocity(120);
+        assert_eq!(voice.release_velocity, 120);
+
+        voice.trigger(62, 100);
+        voice.release();
+        assert_eq!(voice.release_velocity, DEFAULT_RELEASE_VELOCITY);
+    }
+
+    #[test]
+    fn pool_release_threads_velocity() {
+        let mut pool = VoicePool::new();
+        let voice_id = VoiceId(3, 1);
+        pool.trigger_voice(voice_id, 60, 100);
+
+        assert!(pool.release_voice(voice_id, 10));
+        assert_eq!(pool.get_voice(3).env_stage, EnvStage::Release);
+        assert_eq!(pool.get_voice(3).release_velocity, 10);
+        assert!(!pool.release_voice(VoiceId(3, 0), 10));
+    }
+
+    #[test]
+    fn kill_all_silences_pool() {
+        let mut pool = VoicePool::new();
+        pool.trigger_voice(VoiceId(0, 1), 60, 100);
+        pool.trigger_voice(VoiceId(1, 1), 64, 100);
+        pool.release_voice(VoiceId(1, 1), 64);
+
+        pool.kill_all();
+        assert_eq!(pool.active_voice_count(), 0);
+        assert!(pool.voices().iter().all(|v| v.env_stage == EnvStage::Idle));
+    }
+
+    #[test]
+    fn stale_handle_ignored_by_pool() {
+        let mut pool = VoicePool::new();
+        let old = VoiceId(0, 1);
+        let new = VoiceId(0, 2);
+
+        pool.trigger_voice(old, 60, 100);
+        pool.trigger_voice(new, 72, 100);
+
+        assert!(pool.get_voice_checked_mut(old).is_none());
+        assert_eq!(pool.get_voice_checked(new).unwrap().note, 72);
+    }
+
+    #[test]
+    fn glide_arrives_after_glide_time() {
+        let mut voice = VoiceState::new();
+        voice.trigger(60, 100);
+        assert!(!voice.is_gliding());
+
+        voice.glide_to(64, 4);
+        assert_eq!(voice.note, 64);
+        assert_eq!(voice.advance_glide(1), 61.0);
+        assert_eq!(voice.advance_glide(2), 63.0);
+        assert!(voice.is_gliding());
+        assert_eq!(voice.advance_glide(64), 64.0);
+        assert!(!voice.is_gliding());
+        assert_eq!(voice.env_stage, EnvStage::Attack);
+
+        voice.glide_to(48, 0);
+        assert_eq!(voice.pitch, 48.0);
+    }
+
+    #[test]
+    fn mpe_expression_lands_on_voice() {
+        use crate::midi_input::{ChannelEvent, MidiEvent};
+        use crate::mpe::MpeRouter;
+
+        let mut router = MpeRouter::new();
+        let mut pool = VoicePool::new();
+        let events = [
+            (3, MidiEvent::NoteOn(60, 100)),
+            (3, MidiEvent::PitchBend(16383)),
+            (3, MidiEvent::ChannelPressure(127)),
+            (3, MidiEvent::ControlChange(74, 0)),
+        ];
+        for (channel, event) in events {
+            let message = router
+                .handle_event(&ChannelEvent { channel, event })
+                .unwrap();
+            assert!(pool.apply_mpe_message(&message, router.zones()));
+        }
+
+        let voice = pool.voices().iter().find(|v| v.active).unwrap();
+        let semitones = 12.0 * voice.bend_ratio.log2();
+        assert!((semitones - 48.0).abs() < 0.01); // MPE default member range
+        assert_eq!(voice.pressure, 1.0);
+        assert_eq!(voice.timbre, 0.0);
+    }
+
+    #[test]
+    fn trigger_resets_expression() {
+        let mut voice = VoiceState::new();
+        voice.trigger(60, 100);
+        voice.set_pitch_bend(0, 2.0);
+        voice.set_pressure(64);
+        voice.trigger(62, 100);
+        assert_eq!(voice.bend_ratio, 1.0);
+        assert_eq!(voice.pressure, 0.0);
+        assert_eq!(voice.timbre, DEFAULT_TIMBRE);
+    }
+
+    #[test]
+    fn user_data_travels_with_voice() {
+        #[derive(Default)]
+        struct Wavetable {
+            position: f32,
+        }
+
+        let mut pool: VoicePool<Wavetable> = VoicePool::with_user_data();
+        let voice_id = VoiceId(2, 1);
+        pool.trigger_voice(voice_id, 60, 100);
+        pool.user_data_mut(voice_id).unwrap().position = 0.25;
+
+        for (voice, table) in pool.iter_mut() {
+            if voice.active {
+                table.position += 0.5;
+            }
+        }
+        assert_eq!(pool.user_data(voice_id).unwrap().position, 0.75);
+        assert!(pool.user_data(VoiceId(2, 0)).is_none());
+    }
+
+    #[test]
+    fn pool_renders_and_sums_active_voices() {
+        // Outputs velocity as DC, dropping out after its first block in release
+        #[derive(Default)]
+        struct Dc;
+
+        impl VoiceRenderer for Dc {
+            fn render(&mut self, voice: &mut VoiceState, out: &mut [f32], _sample_rate: f32) {
+                for sample in out.iter_mut() {
+                    *sample += voice.velocity as f32 / 100.0;
+                }
+                if voice.env_stage == EnvStage::Release {
+                    voice.active = false;
+                }
+            }
+        }
+
+        let mut pool: VoicePool<Dc> = VoicePool::with_user_data();
+        pool.trigger_voice(VoiceId(0, 1), 60, 100);
+        pool.trigger_voice(VoiceId(1, 1), 64, 50);
+
+        let mut out = [9.0; 4];
+        pool.render(&mut out, 44100.0);
+        assert_eq!(out, [1.5; 4]);
+        assert_eq!(pool.get_voice(1).level, 0.5);
+
+        pool.release_voice(VoiceId(1, 1), 64);
+        pool.render(&mut out, 44100.0);
+        pool.render(&mut out, 44100.0);
+        assert_eq!(out, [1.0; 4]);
+    }
+
+    #[test]
+    fn mute_and_solo_bypass_mix() {
+        #[derive(Default)]
+        struct Dc;
+
+        impl VoiceRenderer for Dc {
+            fn render(&mut self, voice: &mut VoiceState, out: &mut [f32], _sample_rate: f32) {
+                for sample in out.iter_mut() {
+                    *sample += voice.velocity as f32;
+                }
+            }
+        }
+
+        let mut pool: VoicePool<Dc> = VoicePool::with_user_data();
+        let (a, b, c) = (VoiceId(0, 1), VoiceId(1, 1), VoiceId(2, 1));
+        pool.trigger_voice(a, 60, 1);
+        pool.trigger_voice(b, 62, 10);
+        pool.trigger_voice(c, 64, 100);
+        let mut out = [0.0; 2];
+
+        assert!(pool.mute_voice(c));
+        pool.render(&mut out, 44100.0);
+        assert_eq!(out, [11.0; 2]);
+        assert_eq!(pool.get_voice(2).level, 0.0);
+
+        assert!(pool.solo_voice(b));
+        pool.render(&mut out, 44100.0);
+        assert_eq!(out, [10.0; 2]);
+        assert!(!pool.is_audible(0));
+
+        pool.clear_solo();
+        pool.unmute_voice(c);
+        pool.render(&mut out, 44100.0);
+        assert_eq!(out, [111.0; 2]);
+        assert!(!pool.mute_voice(VoiceId(0, 7)));
+    }
+
+    #[test]
+    fn level_tracks_peak_and_decays() {
+        let mut voice = VoiceState::new();
+        voice.trigger(60, 100);
+        voice.track_level(&[0.1, -0.8, 0.3], 1000.0);
+        assert_eq!(voice.level, 0.8);
+
+        // 100 silent samples at 1kHz is one release time constant
+        voice.track_level(&[0.0; 100], 1000.0);
+        assert!((voice.level - 0.8 / std::f32::consts::E).abs() < 1e-4);
+
+        voice.trigger(62, 100);
+        assert_eq!(voice.level, 0.0);
+    }
+
+    #[test]
+    fn finished_voices_free_allocator_slots() {
+        let mut allocator = VoiceAllocator::with_voices(2);
+        let mut pool = VoicePool::with_voices(2);
+        let held = allocator.allocate_voice(60).unwrap();
+        let released = allocator.allocate_voice(64).unwrap();
+        pool.trigger_voice(held, 60, 100);
+        pool.trigger_voice(released, 64, 100);
+        pool.get_voice_checked_mut(held).unwrap().env_level = 0.5;
+        pool.get_voice_checked_mut(released).unwrap().env_level = 0.5;
+
+        allocator.release_voice(64);
+        pool.release_voice(released, 64);
+        assert_eq!(pool.collect_finished(&mut allocator), 0); // Tail still sounding
+
+        // Release tail fades out; the held note's renderer reports silence
+        pool.get_voice_checked_mut(released).unwrap().env_level = 0.0;
+        pool.get_voice_checked_mut(held).unwrap().active = false;
+        assert_eq!(pool.collect_finished(&mut allocator), 2);
+        assert_eq!(allocator.active_voice_count(), 0);
+        assert_eq!(pool.active_voice_count(), 0);
+        assert_eq!(pool.collect_finished(&mut allocator), 0);
+    }
+
+    #[test]
+    fn lfo_phase_is_per_voice() {
+        let mut early = VoiceState::new();
+        let mut late = VoiceState::new();
+        for voice in [&mut early, &mut late] {
+            voice.set_lfo(1.0, 0.5, LfoShape::SawUp);
+        }
+        early.trigger(60, 100);
+        for _ in 0..25 {
+            early.lfo_next(100.0);
+        }
+        late.trigger(64, 100);
+
+        assert!((early.lfo_next(100.0) + 0.25).abs() < 1e-5);
+        assert_eq!(late.lfo_next(100.0), -0.5);
+        assert_eq!(VoiceState::new().lfo_next(100.0), 0.0);
+    }
+
+    #[test]
+    fn pool_drift_reaches_renderer() {
+        #[derive(Default)]
+        struct Osc {
+            drift: f32,
+        }
+
+        impl VoiceRenderer for Osc {
+            fn render(&mut self, _voice: &mut VoiceState, _out: &mut [f32], _sample_rate: f32) {}
+
+            fn set_drift(&mut self, cents: f32) {
+                self.drift = cents;
+            }
+        }
+
+        let mut pool: VoicePool<Osc> = VoicePool::with_user_data();
+        pool.set_drift(Some((4.0, 1.0)));
+        let a = VoiceId(0, 1);
+        let b = VoiceId(1, 1);
+        pool.trigger_voice(a, 60, 100);
+        pool.trigger_voice(b, 60, 100);
+
+        let mut out = [0.0; 64];
+        pool.render(&mut out, 44100.0);
+        let drift_a = pool.drift_cents(a).unwrap();
+        assert_eq!(pool.user_data(a).unwrap().drift, drift_a);
+        assert_ne!(drift_a, pool.drift_cents(b).unwrap());
+        assert!(drift_a.abs() <= 4.0);
+
+        pool.set_drift(None);
+        assert_eq!(pool.drift_cents(a), Some(0.0));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshot_restores_running_state() {
+        let mut allocator = VoiceAllocator::with_voices(4);
+        let mut pool = VoicePool::with_voices(4);
+        for note in [60, 64, 67] {
+            let voice_id = allocator.allocate_voice(note).unwrap();
+            pool.trigger_voice(voice_id, note, 100);
+        }
+        pool.get_voice_mut(1).glide_to(72, 100);
+        let released = allocator.release_voice(60).unwrap();
+        pool.release_voice(released, 30);
+
+        let allocator_json = serde_json::to_string(&allocator).unwrap();
+        let pool_json = serde_json::to_string(&pool).unwrap();
+        let mut restored_allocator: VoiceAllocator = serde_json::from_str(&allocator_json).unwrap();
+        let restored_pool: VoicePool = serde_json::from_str(&pool_json).unwrap();
+
+        assert_eq!(restored_pool.get_voice(1).target_pitch, 72.0);
+        assert_eq!(restored_pool.get_voice(0).release_velocity, 30);
+        assert_eq!(
+            restored_allocator.allocate_voice(50),
+            allocator.allocate_voice(50)
+        );
+        assert_eq!(
+            restored_allocator.active_voices().collect::<Vec<_>>(),
+            allocator.active_voices().collect::<Vec<_>>()
+        );
+    }
+
     #[test]
     fn voice_reset_clears_state() {
         let mut voice = VoiceState::new();
@@ -1,6 +1,11 @@
 //! Tests for CC mapping
 
-use auxide_midi::{CCMap, ParamTarget};
+#![cfg(feature = "std")]
+
+use auxide_midi::{
+    CCMap, CCMapping, CCMode, ControllerProfile, MidiEvent, ModCurve, ModMatrix, ModRoute,
+    ModSource, NrpnMap, ParamTarget, ProfileLibrary,
+};
 use proptest::prelude::*;
 
 #[test]
@@ -94,9 +99,332 @@ fn unused_mappings_default_to_unused() {
     let map = CCMap::new();
     let mappings = map.get_mappings();
 
-    // Check that unmapped slots are Unused
-    assert_eq!(mappings[2], (0, ParamTarget::Unused));
-    assert_eq!(mappings[3], (0, ParamTarget::Unused));
+    // Check that slots after the defaults are Unused
+    assert_eq!(mappings[7], (0, ParamTarget::Unused));
+    assert_eq!(mappings[8], (0, ParamTarget::Unused));
+}
+
+#[test]
+fn remapping_does_not_duplicate() {
+    let mut map = CCMap::new();
+    map.set_mapping(20, ParamTarget::AttackTime);
+    map.set_mapping(20, ParamTarget::ReleaseTime);
+
+    let count = map.get_mappings().iter().filter(|m| m.0 == 20).count();
+    assert_eq!(count, 1);
+    assert_eq!(map.map_cc(20, 127), Some((ParamTarget::ReleaseTime, 1.0)));
+}
+
+#[test]
+fn removed_mapping_can_be_reassigned() {
+    let mut map = CCMap::new();
+    map.remove_mapping(1);
+    map.set_mapping(1, ParamTarget::AttackTime);
+
+    assert_eq!(map.map_cc(1, 127), Some((ParamTarget::AttackTime, 1.0)));
+}
+
+#[test]
+fn cleared_map_is_empty() {
+    let mut map = CCMap::new();
+    map.set_mapping(20, ParamTarget::ReleaseTime);
+    map.clear();
+
+    for cc in 0..=127 {
+        assert_eq!(map.map_cc(cc, 64), None);
+    }
+}
+
+#[test]
+fn cancelled_learn_binds_nothing() {
+    let mut map = CCMap::new();
+    map.begin_learn(ParamTarget::AttackTime);
+    map.cancel_learn();
+
+    assert_eq!(map.handle_cc(30, 64), None);
+    assert_eq!(map.map_cc(1, 127), Some((ParamTarget::FilterCutoff, 1.0)));
+}
+
+#[test]
+fn learn_overrides_existing_cc_binding() {
+    let mut map = CCMap::new();
+    map.begin_learn(ParamTarget::ReleaseTime);
+    map.handle_cc(74, 0);
+
+    assert_eq!(map.map_cc(74, 127), Some((ParamTarget::ReleaseTime, 1.0)));
+}
+
+#[test]
+fn removed_mapping_forgets_range() {
+    let mut map = CCMap::new();
+    map.set_mapping_with_range(20, ParamTarget::AttackTime, 0.001, 2.0);
+    map.remove_mapping(20);
+    map.set_mapping(20, ParamTarget::AttackTime);
+
+    assert_eq!(map.map_cc(20, 127), Some((ParamTarget::AttackTime, 1.0)));
+}
+
+#[test]
+fn lsb_of_seven_bit_mapping_ignored() {
+    let mut map = CCMap::new();
+
+    // CC 1 is a plain 7-bit mapping, so CC 33 means nothing
+    assert_eq!(map.handle_cc(33, 64), None);
+    assert!(!map.is_14bit(1));
+}
+
+#[test]
+fn fourteen_bit_mapping_uses_range() {
+    let mut map = CCMap::new();
+    map.set_mapping_14bit(7, ParamTarget::FilterCutoff);
+    map.set_range(7, 0.0, 16383.0);
+
+    map.handle_cc(7, 1);
+    assert_eq!(
+        map.handle_cc(39, 2),
+        Some((ParamTarget::FilterCutoff, 130.0))
+    );
+}
+
+#[test]
+fn nrpn_mapping_is_per_channel() {
+    let mut map = NrpnMap::new();
+    map.set_mapping(200, ParamTarget::ReleaseTime);
+
+    // Select NRPN 200 (MSB 1, LSB 72) on channel 3 only
+    map.handle_cc(3, 99, 1);
+    map.handle_cc(3, 98, 72);
+
+    assert_eq!(map.handle_cc(4, 6, 127), None);
+    let (target, value) = map.handle_cc(3, 6, 127).unwrap();
+    assert_eq!(target, ParamTarget::ReleaseTime);
+    assert!(value > 0.99);
+}
+
+#[test]
+fn learn_custom_target() {
+    let mut map = CCMap::new();
+    map.begin_learn(ParamTarget::Custom(42));
+
+    assert_eq!(map.handle_cc(16, 127), Some((ParamTarget::Custom(42), 1.0)));
+}
+
+#[test]
+fn fan_out_ranges_are_independent() {
+    let mut map = CCMap::new();
+    map.add_mapping(12, ParamTarget::FilterCutoff, 200.0, 8000.0);
+    map.add_mapping(12, ParamTarget::Custom(3), 1.0, 0.0);
+
+    let targets: Vec<_> = map.map_cc_all(12, 127).collect();
+    assert_eq!(
+        targets,
+        vec![
+            (ParamTarget::FilterCutoff, 8000.0),
+            (ParamTarget::Custom(3), 0.0)
+        ]
+    );
+}
+
+#[test]
+fn set_mapping_replaces_fan_out_target() {
+    let mut map = CCMap::new();
+    map.add_mapping(1, ParamTarget::AttackTime, 0.0, 1.0);
+    map.set_mapping(1, ParamTarget::ReleaseTime);
+
+    // set_mapping only replaces the first target of the CC
+    assert_eq!(map.map_cc_all(1, 0).count(), 2);
+    assert_eq!(map.map_cc(1, 0), Some((ParamTarget::ReleaseTime, 0.0)));
+}
+
+#[test]
+fn remapping_clears_inversion() {
+    let mut map = CCMap::new();
+    map.set_inverted(1, true);
+    map.set_mapping(1, ParamTarget::FilterCutoff);
+
+    assert!(!map.is_inverted(1));
+}
+
+#[test]
+fn endless_encoder_sweeps_range() {
+    let mut map = CCMap::new();
+    map.set_mapping_with_range(20, ParamTarget::FilterCutoff, 100.0, 5000.0);
+    map.set_mode(20, CCMode::TwosComplement);
+
+    // 127 single-step increments reach the top of the range
+    for _ in 0..127 {
+        map.handle_cc(20, 1);
+    }
+    let (_, cutoff) = map.handle_cc(20, 1).unwrap();
+    assert!((cutoff - 5000.0).abs() < 0.01);
+    assert_eq!(map.mode(20), Some(CCMode::TwosComplement));
+}
+
+#[test]
+fn pickup_engages_on_matching_value() {
+    let mut map = CCMap::new();
+    map.set_pickup(1, true);
+    map.set_target_value(ParamTarget::FilterCutoff, 0.5);
+
+    assert_eq!(map.handle_cc(1, 100), None);
+    assert!(map.handle_cc(1, 64).is_some());
+}
+
+#[test]
+fn without_pickup_knob_jumps() {
+    let mut map = CCMap::new();
+    map.set_target_value(ParamTarget::FilterCutoff, 0.5);
+
+    assert_eq!(
+        map.handle_cc(1, 127),
+        Some((ParamTarget::FilterCutoff, 1.0))
+    );
+}
+
+#[test]
+fn toggle_state_readable() {
+    let mut map = CCMap::new();
+    map.set_mapping(80, ParamTarget::Custom(9));
+    map.set_mode(80, CCMode::Toggle);
+
+    map.handle_cc(80, 127);
+    map.handle_cc(80, 0);
+    assert_eq!(map.value(80), Some(1.0));
+    assert!(CCMode::Toggle.is_switch());
+    assert!(!CCMode::Toggle.is_relative());
+}
+
+#[test]
+fn map_from_mappings_skips_invalid() {
+    let mappings = [
+        CCMapping::new(1, ParamTarget::FilterCutoff),
+        CCMapping::new(2, ParamTarget::Unused),
+        CCMapping {
+            high_res: true,
+            ..CCMapping::new(40, ParamTarget::AttackTime)
+        },
+    ];
+    let map = CCMap::from_mappings(&mappings);
+
+    assert_eq!(map.to_mappings(), vec![mappings[0].clone()]);
+    assert_eq!(map.map_cc(74, 64), None); // no defaults
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn preset_json_fills_defaults() {
+    let json = r#"{ "mappings": [ { "cc": 11, "target": { "Custom": 4 }, "max": 100.0 } ] }"#;
+    let map: CCMap = serde_json::from_str(json).unwrap();
+
+    assert_eq!(map.map_cc(11, 127), Some((ParamTarget::Custom(4), 100.0)));
+    assert_eq!(map.mode(11), Some(CCMode::Absolute));
+}
+
+#[test]
+fn generic_gm_profile_by_name() {
+    let library = ProfileLibrary::with_builtin();
+    let map = library.profile("Generic GM").unwrap().cc_map();
+
+    assert_eq!(map.map_cc(74, 127), Some((ParamTarget::FilterCutoff, 1.0)));
+    assert_eq!(map.map_cc(71, 0), Some((ParamTarget::FilterResonance, 0.0)));
+}
+
+#[test]
+fn registering_same_name_replaces_profile() {
+    let mut library = ProfileLibrary::new();
+    library.register(ControllerProfile::new("Pad", Some("pad"), Vec::new()));
+    library.register(ControllerProfile::new("Pad", Some("launch"), Vec::new()));
+
+    assert_eq!(library.profiles().len(), 1);
+    assert!(library.profile_for_device("Launchpad").is_some());
+    assert!(library.cc_map_for_device("Unknown Keys").is_none());
+}
+
+#[test]
+fn snap_values_survive_round_trip() {
+    let mut map = CCMap::new();
+    map.set_snap_values(1, &[0.0, 0.5, 1.0]);
+    map.set_steps(74, 8);
+
+    let restored = CCMap::from_mappings(&map.to_mappings());
+    assert_eq!(restored.to_mappings(), map.to_mappings());
+    assert_eq!(
+        restored.map_cc(1, 70),
+        Some((ParamTarget::FilterCutoff, 0.5))
+    );
+}
+
+#[test]
+fn zero_hysteresis_passes_every_change() {
+    let mut map = CCMap::new();
+
+    assert!(map.handle_cc(1, 10).is_some());
+    assert!(map.handle_cc(1, 11).is_some());
+    assert!(map.handle_cc(1, 11).is_some()); // repeats still reported
+}
+
+#[test]
+fn current_value_follows_preset_value() {
+    let mut map = CCMap::new();
+    map.set_target_value(ParamTarget::FilterCutoff, 0.25);
+
+    assert_eq!(
+        map.current_value(1),
+        Some((ParamTarget::FilterCutoff, 0.25))
+    );
+}
+
+#[test]
+fn current_value_of_fourteen_bit_mapping() {
+    let mut map = CCMap::new();
+    map.set_mapping_14bit(2, ParamTarget::FilterResonance);
+    map.handle_cc(2, 127);
+    map.handle_cc(34, 127);
+
+    assert_eq!(
+        map.current_value(2),
+        Some((ParamTarget::FilterResonance, 1.0))
+    );
+}
+
+#[test]
+fn smoothing_survives_round_trip() {
+    let mut map = CCMap::new();
+    map.set_smoothing(1, Some(0.05));
+
+    let restored = CCMap::from_mappings(&map.to_mappings());
+    assert_eq!(restored.smoothing(1), Some(0.05));
+}
+
+#[test]
+fn len_counts_fan_out_targets() {
+    let mut map = CCMap::new();
+    map.add_mapping(1, ParamTarget::Custom(2), 0.0, 1.0);
+
+    assert_eq!(map.len(), 8);
+    assert_eq!(map.iter_active().filter(|(cc, _)| *cc == 1).count(), 2);
+}
+
+#[test]
+fn default_routings_can_be_overridden() {
+    let mut map = CCMap::new();
+    map.set_mapping(7, ParamTarget::Custom(0));
+    map.set_range(10, -1.0, 1.0);
+
+    assert_eq!(map.map_cc(7, 127), Some((ParamTarget::Custom(0), 1.0)));
+    assert_eq!(map.map_cc(10, 0), Some((ParamTarget::Pan, -1.0)));
+}
+
+#[test]
+fn more_than_sixteen_mappings() {
+    let mut map = CCMap::new();
+    for cc in 20..60 {
+        map.set_mapping(cc, ParamTarget::ReleaseTime);
+    }
+
+    for cc in 20..60 {
+        assert_eq!(map.map_cc(cc, 0), Some((ParamTarget::ReleaseTime, 0.0)));
+    }
 }
 
 #[test]
@@ -112,6 +440,70 @@ fn cc_values_clamped_to_valid_range() {
 }
 
 proptest! {
+    #[test]
+    fn mod_route_bounded_by_depth(value in 0u8..=127, depth in -10.0f32..10.0, curve in 0usize..4) {
+        let curve = [ModCurve::Linear, ModCurve::Exponential, ModCurve::Logarithmic, ModCurve::SCurve][curve];
+        let mut matrix = ModMatrix::new();
+        matrix.add_route(ModRoute::new(ModSource::ControlChange(7), ParamTarget::Custom(0), depth).with_curve(curve));
+        matrix.handle_event(&MidiEvent::ControlChange(7, value));
+
+        let out = matrix.evaluate(ParamTarget::Custom(0), None);
+        prop_assert!(out.abs() <= depth.abs() + 1e-5);
+    }
+
+    #[test]
+    fn stepped_output_is_on_grid(value in 0u8..=127, steps in 2u16..16) {
+        let mut map = CCMap::new();
+        map.set_steps(1, steps);
+
+        let (_, out) = map.map_cc(1, value).unwrap();
+        let scaled = out * (steps - 1) as f32;
+        prop_assert!((scaled - scaled.round()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn relative_value_stays_normalized(steps in proptest::collection::vec(0u8..=127, 0..64)) {
+        let mut map = CCMap::new();
+        map.set_mode(1, CCMode::SignMagnitude);
+        for step in steps {
+            map.handle_cc(1, step);
+        }
+
+        let value = map.value(1).unwrap();
+        prop_assert!((0.0..=1.0).contains(&value));
+    }
+
+    #[test]
+    fn inverted_mirrors_normal(value in 0u8..=127) {
+        let mut map = CCMap::new();
+        let (_, normal) = map.map_cc(1, value).unwrap();
+        map.set_inverted(1, true);
+        let (_, inverted) = map.map_cc(1, value).unwrap();
+
+        prop_assert!((normal + inverted - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fourteen_bit_values_monotonic(msb in 0u8..=127, lsb in 0u8..127) {
+        let mut map = CCMap::new();
+        map.set_mapping_14bit(2, ParamTarget::FilterResonance);
+
+        map.handle_cc(2, msb);
+        let (_, low) = map.handle_cc(34, lsb).unwrap();
+        let (_, high) = map.handle_cc(34, lsb + 1).unwrap();
+        prop_assert!(high > low);
+        prop_assert!((0.0..=1.0).contains(&high));
+    }
+
+    #[test]
+    fn ranged_values_stay_in_range(value in 0u8..=127, min in -1000.0f32..1000.0, span in 0.0f32..10000.0) {
+        let mut map = CCMap::new();
+        map.set_mapping_with_range(10, ParamTarget::FilterCutoff, min, min + span);
+
+        let (_, mapped) = map.map_cc(10, value).unwrap();
+        prop_assert!(mapped >= min - 1e-3 && mapped <= min + span + 1e-3);
+    }
+
     #[test]
     fn cc_map_no_panic(cc_num in 0u8..128, value in 0u8..128) {
         let map = CCMap::new();
@@ -125,6 +517,12 @@ proptest! {
                 | ParamTarget::FilterResonance
                 | ParamTarget::AttackTime
                 | ParamTarget::ReleaseTime
+                | ParamTarget::Volume
+                | ParamTarget::Pan
+                | ParamTarget::Expression
+                | ParamTarget::Breath
+                | ParamTarget::PortamentoTime
+                | ParamTarget::Custom(_)
                 | ParamTarget::Unused => {} // Valid
             }
             // Normalized value should be in [0, 1]
@@ -148,13 +546,14 @@ proptest! {
     }
 
     #[test]
-    fn set_mapping_no_panic(cc_num in 0u8..128, target_int in 0u8..5) {
+    fn set_mapping_no_panic(cc_num in 0u8..128, target_int in 0u8..6) {
         let mut map = CCMap::new();
         let target = match target_int {
             0 => ParamTarget::FilterCutoff,
             1 => ParamTarget::FilterResonance,
             2 => ParamTarget::AttackTime,
             3 => ParamTarget::ReleaseTime,
+            4 => ParamTarget::Custom(cc_num as u32),
             _ => ParamTarget::Unused,
         };
 
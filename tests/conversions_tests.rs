@@ -1,6 +1,13 @@
 //! Tests for MIDI conversions
 
-use auxide_midi::{note_to_freq, pitch_bend_to_ratio, velocity_to_gain};
+#![cfg(feature = "std")]
+
+use auxide_midi::{
+    cents_to_ratio, db_to_gain, freq_to_normalized, gain_to_db, normalized_to_freq, note_to_freq,
+    note_with_cents_to_freq, pitch_bend_to_ratio, pitch_bend_to_ratio_with_range, ratio_to_cents,
+    velocity_to_db, velocity_to_gain, KeyboardMapping, NoteDivision, NoteLength, Scale, Tuning,
+    VelocityCurve,
+};
 use proptest::prelude::*;
 
 #[test]
@@ -99,6 +106,43 @@ fn note_range() {
     assert!(high_note < 100000.0); // Reasonable upper bound
 }
 
+#[test]
+fn alternate_concert_pitches() {
+    for reference in [432.0, 442.0, 443.0] {
+        let tuning = Tuning::new(reference);
+        assert!((tuning.note_to_freq(69) - reference).abs() < 0.01);
+        // Intervals are unchanged, only the reference moves
+        let ratio = tuning.note_to_freq(60) / note_to_freq(60);
+        assert!((ratio - reference / 440.0).abs() < 1e-5);
+    }
+}
+
+#[test]
+fn scala_12_tet_matches_standard_tuning() {
+    let tuning = Tuning::from_scale(&Scale::equal_temperament(12), &KeyboardMapping::default());
+    assert!(tuning.is_microtonal());
+    for note in 0..128u8 {
+        let expected = note_to_freq(note);
+        assert!((tuning.note_to_freq(note) - expected).abs() / expected < 1e-5);
+    }
+}
+
+#[test]
+fn scala_19_edo_from_file_text() {
+    let text = "! 19edo.scl\n19 tone equal\n19\n".to_string()
+        + &(1..=19)
+            .map(|i| format!("{:.5}\n", i as f64 * 1200.0 / 19.0))
+            .collect::<String>();
+    let scale = Scale::parse(&text).unwrap();
+    let tuning = Tuning::from_scale(&scale, &KeyboardMapping::linear(60, 60, 261.6256));
+
+    assert!((tuning.note_to_freq(60) - 261.6256).abs() < 1e-3);
+    assert!((tuning.note_to_freq(79) - 2.0 * 261.6256).abs() < 1e-2);
+    let step = tuning.note_to_freq(61) / tuning.note_to_freq(60);
+    assert!((step - 2.0f32.powf(1.0 / 19.0)).abs() < 1e-5);
+    assert!((tuning.freq_to_note(tuning.note_to_freq(70)) - 70.0).abs() < 1e-3);
+}
+
 proptest! {
     #[test]
     fn note_to_freq_no_panic(note in 0u8..128) {
@@ -127,6 +171,89 @@ proptest! {
         prop_assert!((ratio - 2.0).abs() < 0.001);
     }
 
+    #[test]
+    fn tuning_round_trips_note(note in 0u8..128, reference in 400.0f32..480.0) {
+        let tuning = Tuning::new(reference);
+        let back = tuning.freq_to_note(tuning.note_to_freq(note));
+        prop_assert!((back - note as f32).abs() < 1e-3);
+    }
+
+    #[test]
+    fn cents_ratio_round_trip(cents in -4800.0f32..4800.0) {
+        let back = ratio_to_cents(cents_to_ratio(cents));
+        prop_assert!((back - cents).abs() < 0.01);
+    }
+
+    #[test]
+    fn detune_stays_between_neighbours(note in 1u8..127, cents in -99.0f32..99.0) {
+        let freq = note_with_cents_to_freq(note, cents);
+        prop_assert!(freq > note_to_freq(note - 1));
+        prop_assert!(freq < note_to_freq(note + 1));
+    }
+
+    #[test]
+    fn wide_bend_range_scales_linearly(bend in 0i16..16384, range in 1.0f32..48.0) {
+        let ratio = pitch_bend_to_ratio_with_range(bend, range);
+        let semitones = 12.0 * ratio.log2();
+        let expected = (bend - 8192) as f32 / 8192.0 * range;
+        prop_assert!((semitones - expected).abs() < 1e-3);
+        prop_assert!(semitones.abs() <= range);
+    }
+
+    #[test]
+    fn velocity_curves_stay_in_unit_range(velocity in 0u8..=255) {
+        let curves = [
+            VelocityCurve::Linear,
+            VelocityCurve::Soft,
+            VelocityCurve::Hard,
+            VelocityCurve::Squared,
+            VelocityCurve::Custom(vec![0.0, 0.3, 0.9, 1.0]),
+        ];
+        for curve in &curves {
+            let gain = curve.gain(velocity);
+            prop_assert!((0.0..=1.0).contains(&gain));
+        }
+    }
+
+    #[test]
+    fn n_tet_octave_spans_divisions(divisions in 5u16..53, note in 0u8..64) {
+        let tuning = Tuning::equal_temperament(divisions, 60, 261.63);
+        prop_assume!(note as u16 + divisions < 128);
+        let ratio = tuning.note_to_freq(note + divisions as u8) / tuning.note_to_freq(note);
+        prop_assert!((ratio - 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn db_gain_round_trip(db in -120.0f32..24.0) {
+        let back = gain_to_db(db_to_gain(db));
+        prop_assert!((back - db).abs() < 1e-3);
+    }
+
+    #[test]
+    fn velocity_db_monotonic(vel1 in 1u8..127, vel2 in 2u8..128) {
+        prop_assume!(vel1 < vel2);
+        prop_assert!(velocity_to_db(vel1) < velocity_to_db(vel2));
+        prop_assert!(velocity_to_db(vel2) <= 0.0);
+    }
+
+    #[test]
+    fn note_lengths_scale_with_tempo(bpm in 20.0f64..300.0) {
+        let eighth = NoteLength::straight(NoteDivision::Eighth);
+        let dotted = NoteLength::dotted(NoteDivision::Eighth);
+        let triplet = NoteLength::triplet(NoteDivision::Quarter);
+        prop_assert!((dotted.to_ms(bpm) - 1.5 * eighth.to_ms(bpm)).abs() < 1e-9);
+        prop_assert!((triplet.to_ms(bpm) * 3.0 - NoteLength::from(NoteDivision::Half).to_ms(bpm)).abs() < 1e-6);
+        prop_assert!((eighth.to_ms(bpm * 2.0) * 2.0 - eighth.to_ms(bpm)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn normalized_freq_round_trip(norm in 0.0f32..=1.0, min in 10.0f32..200.0, span in 2.0f32..1000.0) {
+        let max = min * span;
+        let freq = normalized_to_freq(norm, min, max);
+        prop_assert!(freq >= min * 0.9999 && freq <= max * 1.0001);
+        prop_assert!((freq_to_normalized(freq, min, max) - norm).abs() < 1e-3);
+    }
+
     #[test]
     fn velocity_to_gain_no_panic(velocity in 0u8..128) {
         let gain = velocity_to_gain(velocity);
@@ -1,11 +1,13 @@
 //! Integration tests for auxide-midi
 
+#![cfg(feature = "std")]
+
 use auxide::graph::{Graph, NodeType};
 use auxide::plan::Plan;
 use auxide::rt::Runtime;
 use auxide_dsp::envelopes::AdsrEnvelope;
 use auxide_dsp::oscillators::SawOsc;
-use auxide_midi::{CCMap, MidiEvent, MidiInputHandler, ParamTarget, VoiceAllocator};
+use auxide_midi::{CCMap, MidiEvent, ParamTarget, VoiceAllocator};
 use proptest::prelude::*;
 
 #[test]
@@ -91,7 +93,7 @@ fn midi_parser_integration() {
     ];
 
     for (bytes, expected) in test_cases {
-        let result = MidiInputHandler::parse_message(&bytes);
+        let result = MidiEvent::from_bytes(&bytes);
         assert_eq!(result, expected, "Failed for bytes: {:?}", bytes);
     }
 }
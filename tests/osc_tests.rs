@@ -0,0 +1,35 @@
+//! Property tests for OSC packet decoding
+
+#![cfg(feature = "osc")]
+
+use auxide_midi::{OscArg, OscMessage};
+use proptest::prelude::*;
+
+proptest! {
+    #[test]
+    fn arbitrary_packets_never_panic(bytes in prop::collection::vec(any::<u8>(), 0..512)) {
+        let _ = OscMessage::parse_packet(&bytes);
+    }
+
+    #[test]
+    fn corrupted_messages_never_panic(
+        data in prop::collection::vec(any::<u8>(), 0..64),
+        position in any::<usize>(),
+        byte in any::<u8>(),
+    ) {
+        let message = OscMessage::new("/blob", vec![OscArg::Int(1), OscArg::Blob(data)]);
+        let mut bytes = message.to_bytes();
+        prop_assert_eq!(OscMessage::parse_packet(&bytes).unwrap(), vec![message]);
+
+        let index = position % bytes.len();
+        bytes[index] = byte;
+        let _ = OscMessage::parse_packet(&bytes);
+
+        // The same message as a bundle element, with a corrupted size
+        let mut bundle = b"#bundle\0".to_vec();
+        bundle.extend([0; 8]);
+        bundle.extend((byte as i32 - 128).to_be_bytes());
+        bundle.extend(&bytes);
+        let _ = OscMessage::parse_packet(&bundle);
+    }
+}
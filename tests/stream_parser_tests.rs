@@ -0,0 +1,137 @@
+//! Property tests for the byte-stream parser
+
+use auxide_midi::{ChannelEvent, MidiEvent, StreamMessage, StreamParser};
+use proptest::prelude::*;
+
+/// Channel events, Real-Time bytes and SysEx messages parsed from `bytes`
+fn parse(parser: &mut StreamParser, bytes: &[u8]) -> (Vec<ChannelEvent>, Vec<u8>, Vec<Vec<u8>>) {
+    let (mut events, mut real_time, mut sysex) = (Vec::new(), Vec::new(), Vec::new());
+    parser.feed(bytes, 0, |message| match message {
+        StreamMessage::Channel(event) => events.push(event),
+        StreamMessage::System(system) if system.bytes()[0] >= 0xF8 => {
+            real_time.push(system.bytes()[0])
+        }
+        StreamMessage::System(_) => {}
+        StreamMessage::SysEx(bytes) => sysex.push(bytes.to_vec()),
+    });
+    (events, real_time, sysex)
+}
+
+fn channel_event() -> impl Strategy<Value = ChannelEvent> {
+    let event = prop_oneof![
+        (0u8..128, 1u8..128).prop_map(|(note, vel)| MidiEvent::NoteOn(note, vel)),
+        (0u8..128, 0u8..128).prop_map(|(note, vel)| MidiEvent::NoteOff(note, vel)),
+        (0u8..128, 0u8..128).prop_map(|(cc, value)| MidiEvent::ControlChange(cc, value)),
+        (0i16..16384).prop_map(MidiEvent::PitchBend),
+        (0u8..128).prop_map(MidiEvent::ChannelPressure),
+        (0u8..128).prop_map(MidiEvent::ProgramChange),
+    ];
+    (0u8..16, event).prop_map(|(channel, event)| ChannelEvent { channel, event })
+}
+
+/// Encode with running status, as a sender on a serial line would
+fn encode(events: &[ChannelEvent]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut running = None;
+    for event in events {
+        let (message, len) = event.to_bytes();
+        if running != Some(message[0]) {
+            bytes.push(message[0]);
+            running = Some(message[0]);
+        }
+        bytes.extend_from_slice(&message[1..len]);
+    }
+    bytes
+}
+
+/// Insert real-time bytes at the given positions (taken modulo the length)
+fn interleave(bytes: &[u8], real_time: &[(usize, u8)]) -> Vec<u8> {
+    let mut out = bytes.to_vec();
+    for &(position, byte) in real_time {
+        out.insert(position % (out.len() + 1), byte);
+    }
+    out
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_bytes_never_panic(bytes in prop::collection::vec(any::<u8>(), 0..2048)) {
+        let mut parser = StreamParser::new();
+        let (events, _, sysex) = parse(&mut parser, &bytes);
+        // Everything emitted is well-formed
+        for event in events {
+            let (message, len) = event.to_bytes();
+            prop_assert!(message[1..len].iter().all(|&b| b < 0x80));
+        }
+        for message in sysex {
+            prop_assert_eq!(message[0], 0xF0);
+            prop_assert_eq!(*message.last().unwrap(), 0xF7);
+            prop_assert!(message[1..message.len() - 1].iter().all(|&b| b < 0x80));
+        }
+    }
+
+    #[test]
+    fn running_status_round_trips_around_real_time(
+        events in prop::collection::vec(channel_event(), 0..64),
+        real_time in prop::collection::vec((any::<usize>(), 0xF8u8..=0xFF), 0..32),
+    ) {
+        let stream = interleave(&encode(&events), &real_time);
+        let mut parser = StreamParser::new();
+        let (parsed, clocks, _) = parse(&mut parser, &stream);
+        // Note On with velocity 0 never occurs in `events`, so they compare equal
+        prop_assert_eq!(parsed, events);
+        prop_assert_eq!(clocks.len(), real_time.len());
+        prop_assert_eq!(parser.discarded(), 0);
+    }
+
+    #[test]
+    fn resyncs_after_garbage(
+        garbage in prop::collection::vec(any::<u8>(), 0..256),
+        events in prop::collection::vec(channel_event(), 1..16),
+    ) {
+        let mut parser = StreamParser::new();
+        parse(&mut parser, &garbage);
+        // The first message after the garbage carries its status byte
+        let (parsed, _, _) = parse(&mut parser, &encode(&events));
+        prop_assert_eq!(parsed, events);
+    }
+
+    #[test]
+    fn truncated_messages_are_dropped(
+        events in prop::collection::vec((channel_event(), any::<bool>()), 1..32),
+    ) {
+        // Cut some messages short by their last data byte
+        let mut stream = Vec::new();
+        let mut expected = Vec::new();
+        for (event, truncate) in &events {
+            let (message, len) = event.to_bytes();
+            if *truncate {
+                stream.extend_from_slice(&message[..len - 1]);
+            } else {
+                stream.extend_from_slice(&message[..len]);
+                expected.push(event.clone());
+            }
+        }
+        let mut parser = StreamParser::new();
+        let (parsed, _, _) = parse(&mut parser, &stream);
+        let truncated = events.len() - expected.len();
+        prop_assert_eq!(parsed, expected);
+        // A trailing partial message is still pending, not yet discarded
+        let pending = events.last().is_some_and(|(_, truncate)| *truncate);
+        prop_assert_eq!(parser.discarded() as usize, truncated - pending as usize);
+    }
+
+    #[test]
+    fn sysex_survives_real_time(
+        payload in prop::collection::vec(0u8..0x80, 0..400),
+        real_time in prop::collection::vec((any::<usize>(), 0xF8u8..=0xFF), 0..16),
+    ) {
+        let mut message = vec![0xF0];
+        message.extend_from_slice(&payload);
+        message.push(0xF7);
+        let mut parser = StreamParser::new();
+        let (_, clocks, sysex) = parse(&mut parser, &interleave(&message, &real_time));
+        prop_assert_eq!(sysex, vec![message]);
+        prop_assert_eq!(clocks.len(), real_time.len());
+    }
+}
@@ -1,5 +1,7 @@
 //! Tests for voice allocator
 
+#![cfg(feature = "std")]
+
 use auxide_midi::{VoiceAllocator, VoiceId};
 use proptest::prelude::*;
 
@@ -97,6 +99,70 @@ fn release_nonexistent_voice() {
     assert_eq!(allocator.active_voice_count(), 1);
 }
 
+#[test]
+fn release_voice_returns_released_id() {
+    let mut allocator = VoiceAllocator::new();
+    let voice_id = allocator.allocate_voice(60).unwrap();
+
+    assert_eq!(allocator.release_voice(60), Some(voice_id));
+    assert_eq!(allocator.release_voice(60), None);
+}
+
+#[test]
+fn release_by_id_with_retriggered_note() {
+    let mut allocator = VoiceAllocator::new();
+
+    let voice1 = allocator.allocate_voice(60).unwrap();
+    let voice2 = allocator.allocate_voice(60).unwrap();
+
+    // Releasing the later voice must leave the earlier one sounding
+    assert!(allocator.release_voice_id(voice2));
+    let active: Vec<_> = allocator.active_voices().collect();
+    assert_eq!(active, vec![(voice1, 60)]);
+}
+
+#[test]
+fn release_by_invalid_id_is_ignored() {
+    let mut allocator = VoiceAllocator::new();
+    allocator.allocate_voice(60).unwrap();
+
+    assert!(!allocator.release_voice_id(VoiceId(100, 0)));
+    assert_eq!(allocator.active_voice_count(), 1);
+}
+
+#[test]
+fn reused_slot_gets_new_generation() {
+    let mut allocator = VoiceAllocator::with_voices(1);
+
+    let first = allocator.allocate_voice(60).unwrap();
+    allocator.release_voice(60);
+    let second = allocator.allocate_voice(62).unwrap();
+
+    assert_eq!(first.index(), second.index());
+    assert_ne!(first, second);
+    assert!(!allocator.is_valid(first));
+    assert!(allocator.is_valid(second));
+}
+
+#[test]
+fn voice_for_note_lookup() {
+    let mut allocator = VoiceAllocator::new();
+
+    let voice1 = allocator.allocate_voice(60).unwrap();
+    allocator.allocate_voice(64).unwrap();
+    let voice2 = allocator.allocate_voice(60).unwrap();
+
+    assert_eq!(allocator.voice_for_note(60), Some(voice1));
+    assert_eq!(allocator.voice_for_note(61), None);
+
+    let voices: Vec<_> = allocator.voices_for_note(60).collect();
+    assert_eq!(voices, vec![voice1, voice2]);
+
+    // voice_for_note agrees with what release_voice frees
+    assert_eq!(allocator.release_voice(60), Some(voice1));
+    assert_eq!(allocator.voice_for_note(60), Some(voice2));
+}
+
 proptest! {
     #[test]
     fn voice_allocator_no_panic_random_notes(notes in prop::collection::vec(0u8..128, 1..20)) {
@@ -139,4 +205,31 @@ proptest! {
         // Should have stolen voice 0 (oldest)
         prop_assert_eq!(stolen_voice.0, 0);
     }
+
+    #[test]
+    fn note_index_consistent_with_voices(ops in prop::collection::vec((any::<bool>(), 0u8..16), 1..100)) {
+        let mut allocator = VoiceAllocator::with_voices(4);
+
+        for (allocate, note) in ops {
+            if allocate {
+                allocator.allocate_voice(note).unwrap();
+            } else {
+                allocator.release_voice(note);
+            }
+
+            let active: Vec<_> = allocator.active_voices().collect();
+            prop_assert_eq!(allocator.active_voice_count(), active.len());
+            for note in 0u8..16 {
+                let mut indexed: Vec<_> = allocator.voices_for_note(note).collect();
+                let mut scanned: Vec<_> = active
+                    .iter()
+                    .filter(|(_, n)| *n == note)
+                    .map(|(id, _)| *id)
+                    .collect();
+                indexed.sort_by_key(|id| id.index());
+                scanned.sort_by_key(|id| id.index());
+                prop_assert_eq!(indexed, scanned);
+            }
+        }
+    }
 }